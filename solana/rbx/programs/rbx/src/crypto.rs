@@ -0,0 +1,247 @@
+use anchor_lang::prelude::*;
+use solana_program::secp256k1_recover::secp256k1_recover;
+
+use crate::eip712::keccak256;
+use crate::errors::RbxError;
+
+pub const SOLANA_WITHDRAWAL_DOMAIN: &[u8] = b"RBX_SOL_WITHDRAWAL_V1";
+
+// Withdrawal payload signed under SignatureScheme::SolanaNative
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SolanaWithdrawalMessage {
+    pub program_state: Pubkey, // State PDA, binding the signature to one deployment
+    pub id: u64,
+    pub token: Pubkey,
+    pub trader: Pubkey,
+    pub amount: u64, // Raw token units, never scaled to 18 decimals
+    pub relayer_tip: u64,
+}
+
+// sha256(SOLANA_WITHDRAWAL_DOMAIN || borsh(message))
+pub fn solana_withdrawal_digest(message: &SolanaWithdrawalMessage) -> [u8; 32] {
+    let payload = message.try_to_vec().unwrap();
+    solana_program::hash::hashv(&[SOLANA_WITHDRAWAL_DOMAIN, &payload]).to_bytes()
+}
+
+pub fn verify_secp256k1_signature(
+    digest: &[u8; 32],
+    v: u8,
+    r: &[u8; 32],
+    s: &[u8; 32],
+    expected_signer: &[u8; 20],
+) -> Result<bool> {
+    let recovered_signer_address = recover_eth_address(digest, v, r, s)?;
+
+    // Compare the Ethereum addresses directly
+    let result = recovered_signer_address == *expected_signer;
+
+    Ok(result)
+}
+
+// Recover the Ethereum address that produced a signature over the digest.
+// v may be given as 27/28 (Ethereum) or as the raw recovery id 0/1.
+pub fn recover_eth_address(
+    digest: &[u8; 32],
+    v: u8,
+    r: &[u8; 32],
+    s: &[u8; 32],
+) -> Result<[u8; 20]> {
+    // Adjust recovery ID for Ethereum compatibility (v should be 27 or 28)
+    let recovery_id = if v >= 27 { v - 27 } else { v };
+
+    // Validate recovery_id is either 0 or 1
+    require!(recovery_id <= 1, RbxError::InvalidSignatureFormat);
+
+    // Combine r and s into a single signature array
+    let mut signature = [0u8; 64];
+    signature[0..32].copy_from_slice(r);
+    signature[32..64].copy_from_slice(s);
+
+    let recovered_pubkey = match secp256k1_recover(digest, recovery_id, &signature) {
+        Ok(pubkey) => pubkey,
+        Err(err) => {
+            // Convert the error to a string and include it in the error message
+            msg!("Signature recovery error: {:?}", err);
+            return Err(error!(RbxError::InvalidSignature));
+        }
+    };
+
+    // The syscall always returns the 64-byte uncompressed key
+    derive_eth_address(&recovered_pubkey.to_bytes()).ok_or(error!(RbxError::InvalidSignature))
+}
+
+// Derive the Ethereum address of a secp256k1 public key: the last 20 bytes of the
+// keccak256 of its 64-byte uncompressed encoding. The key is accepted either as
+// those 64 bytes or with the 0x04 SEC1 prefix. Compressed keys and any other
+// length or prefix return None rather than hashing an arbitrary 64-byte window.
+pub fn derive_eth_address(pubkey: &[u8]) -> Option<[u8; 20]> {
+    let key_to_hash = match pubkey.len() {
+        64 => pubkey,
+        65 if pubkey[0] == 0x04 => &pubkey[1..],
+        _ => return None,
+    };
+
+    // Take the last 20 bytes of the hash result
+    let hash = keccak256(key_to_hash);
+    let mut address = [0u8; 20];
+    address.copy_from_slice(&hash[12..32]);
+
+    Some(address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Hardhat account #0, the withdrawal signer used by the TypeScript tests
+    const HARDHAT_PUBKEY: &str = "8318535b54105d4a7aae60c08fc45f9687181b4fdfc625bd1a753fa7397fed753547f11ca8696646f2f3acb08e31016afac23e630c5d11f59f61fef57b0d2aa5";
+    const HARDHAT_ADDRESS: &str = "f39fd6e51aad88f6f4ce6ab8827279cfffb92266";
+
+    // Public key of private key 1, i.e. the secp256k1 generator point
+    const GENERATOR_PUBKEY: &str = "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8";
+    const GENERATOR_ADDRESS: &str = "7e5f4552091a69125d5dfcb7b8c2659029395bdf";
+
+    // Signature by Hardhat account #0 over keccak256("RabbitX withdrawal test vector")
+    const DIGEST: &str = "46694591d79b323c14e82958c22a1bcb08066ffc463011bc92452abe08834a73";
+    const SIG_R: &str = "bb50e2d89a4ed70663d080659fe0ad4b9bc3e06c17a227433966cb59ceee020d";
+    const SIG_S: &str = "56826b755a5b8ea3627e8f8b4d45592c8fbe75e4529ce87896768fb0be6c7fdd";
+    const SIG_V: u8 = 27;
+
+    fn bytes<const N: usize>(hex_str: &str) -> [u8; N] {
+        hex::decode(hex_str).unwrap().try_into().unwrap()
+    }
+
+    #[test]
+    fn derives_address_of_known_keys() {
+        let hardhat: [u8; 64] = bytes(HARDHAT_PUBKEY);
+        assert_eq!(derive_eth_address(&hardhat), Some(bytes(HARDHAT_ADDRESS)));
+
+        let generator: [u8; 64] = bytes(GENERATOR_PUBKEY);
+        assert_eq!(derive_eth_address(&generator), Some(bytes(GENERATOR_ADDRESS)));
+    }
+
+    #[test]
+    fn accepts_sec1_prefixed_key() {
+        let mut prefixed = vec![0x04];
+        prefixed.extend_from_slice(&bytes::<64>(HARDHAT_PUBKEY));
+        assert_eq!(derive_eth_address(&prefixed), Some(bytes(HARDHAT_ADDRESS)));
+    }
+
+    #[test]
+    fn rejects_other_key_encodings() {
+        let raw: [u8; 64] = bytes(HARDHAT_PUBKEY);
+
+        // Compressed key
+        let mut compressed = vec![0x02 | (raw[63] & 1)];
+        compressed.extend_from_slice(&raw[..32]);
+        assert_eq!(derive_eth_address(&compressed), None);
+
+        // 65 bytes with a prefix other than 0x04
+        let mut wrong_prefix = vec![0x00];
+        wrong_prefix.extend_from_slice(&raw);
+        assert_eq!(derive_eth_address(&wrong_prefix), None);
+
+        // Lengths that previously fell back to the trailing 64 bytes, or panicked
+        let mut too_long = vec![0x04, 0x04];
+        too_long.extend_from_slice(&raw);
+        assert_eq!(derive_eth_address(&too_long), None);
+        assert_eq!(derive_eth_address(&raw[..63]), None);
+        assert_eq!(derive_eth_address(&[]), None);
+    }
+
+    #[test]
+    fn recovers_known_signature() {
+        let digest: [u8; 32] = bytes(DIGEST);
+        let r: [u8; 32] = bytes(SIG_R);
+        let s: [u8; 32] = bytes(SIG_S);
+        let expected: [u8; 20] = bytes(HARDHAT_ADDRESS);
+
+        assert_eq!(recover_eth_address(&digest, SIG_V, &r, &s).unwrap(), expected);
+        // The raw recovery id is accepted as well
+        assert_eq!(recover_eth_address(&digest, SIG_V - 27, &r, &s).unwrap(), expected);
+        assert!(verify_secp256k1_signature(&digest, SIG_V, &r, &s, &expected).unwrap());
+    }
+
+    #[test]
+    fn rejects_other_signer_and_digest() {
+        let digest: [u8; 32] = bytes(DIGEST);
+        let r: [u8; 32] = bytes(SIG_R);
+        let s: [u8; 32] = bytes(SIG_S);
+
+        let other: [u8; 20] = bytes(GENERATOR_ADDRESS);
+        assert!(!verify_secp256k1_signature(&digest, SIG_V, &r, &s, &other).unwrap());
+
+        let mut tampered = digest;
+        tampered[0] ^= 1;
+        let expected: [u8; 20] = bytes(HARDHAT_ADDRESS);
+        assert!(!verify_secp256k1_signature(&tampered, SIG_V, &r, &s, &expected).unwrap_or(false));
+    }
+
+    #[test]
+    fn rejects_invalid_recovery_id() {
+        let digest: [u8; 32] = bytes(DIGEST);
+        let r: [u8; 32] = bytes(SIG_R);
+        let s: [u8; 32] = bytes(SIG_S);
+
+        assert!(recover_eth_address(&digest, 29, &r, &s).is_err());
+        assert!(recover_eth_address(&digest, 2, &r, &s).is_err());
+    }
+
+    #[test]
+    fn solana_withdrawal_digest_layout() {
+        let message = SolanaWithdrawalMessage {
+            program_state: Pubkey::new_from_array([1; 32]),
+            id: 7,
+            token: Pubkey::new_from_array([2; 32]),
+            trader: Pubkey::new_from_array([3; 32]),
+            amount: 1_000,
+            relayer_tip: 5,
+        };
+
+        // Borsh encodes integers little-endian and pubkeys as their 32 bytes
+        let mut expected = SOLANA_WITHDRAWAL_DOMAIN.to_vec();
+        expected.extend_from_slice(&[1; 32]);
+        expected.extend_from_slice(&7u64.to_le_bytes());
+        expected.extend_from_slice(&[2; 32]);
+        expected.extend_from_slice(&[3; 32]);
+        expected.extend_from_slice(&1_000u64.to_le_bytes());
+        expected.extend_from_slice(&5u64.to_le_bytes());
+
+        assert_eq!(
+            solana_withdrawal_digest(&message),
+            solana_program::hash::hash(&expected).to_bytes()
+        );
+    }
+
+    // Cross-check recovery and address derivation against libsecp256k1, over keys and
+    // digests derived from a counter so every run covers the same cases
+    #[test]
+    fn matches_libsecp256k1() {
+        for i in 0u64..64 {
+            let key = keccak256(&i.to_le_bytes());
+            let digest = keccak256(&key);
+            let secret_key = match libsecp256k1::SecretKey::parse(&key) {
+                Ok(secret_key) => secret_key,
+                Err(_) => continue,
+            };
+
+            let (signature, recovery_id) =
+                libsecp256k1::sign(&libsecp256k1::Message::parse(&digest), &secret_key);
+            let point = libsecp256k1::PublicKey::from_secret_key(&secret_key).serialize();
+
+            let expected = derive_eth_address(&point).unwrap();
+            assert_eq!(derive_eth_address(&point[1..]), Some(expected));
+            assert_eq!(&expected[..], &keccak256(&point[1..])[12..]);
+
+            let signature = signature.serialize();
+            let mut r = [0u8; 32];
+            let mut s = [0u8; 32];
+            r.copy_from_slice(&signature[..32]);
+            s.copy_from_slice(&signature[32..]);
+
+            let recovered =
+                recover_eth_address(&digest, 27 + recovery_id.serialize(), &r, &s).unwrap();
+            assert_eq!(recovered, expected);
+        }
+    }
+}
@@ -0,0 +1,344 @@
+use anchor_lang::prelude::*;
+use sha3::{Digest, Keccak256};
+
+use crate::{DepositReceipt, State, TraderEncoding};
+
+pub const WITHDRAWAL_TYPEHASH: [u8; 32] = [
+    167, 69, 94, 218, 166, 15, 227, 162, 173, 23, 189, 249, 11, 198, 237, 102, 6, 5, 183, 189, 69,
+    157, 74, 166, 94, 139, 214, 92, 182, 237, 67, 161,
+]; // keccak256("Withdrawal(uint256 id,address token,address trader,uint256 amount)")
+
+pub const RELAYED_WITHDRAWAL_TYPEHASH: [u8; 32] = [
+    65, 112, 107, 197, 220, 228, 100, 0, 151, 134, 182, 29, 32, 247, 179, 74, 78, 107, 135, 201,
+    221, 219, 234, 161, 22, 70, 102, 153, 113, 74, 17, 62,
+]; // keccak256("RelayedWithdrawal(uint256 id,address token,address trader,uint256 amount,uint256 tip)")
+
+// Its own type name, so it cannot be confused with the version 1 Withdrawal struct
+pub const RECIPIENT_WITHDRAWAL_TYPEHASH: [u8; 32] = [
+    240, 152, 112, 209, 231, 199, 40, 129, 162, 3, 109, 106, 218, 33, 2, 22, 52, 56, 140, 165, 61,
+    145, 230, 96, 233, 244, 77, 99, 101, 132, 50, 180,
+]; // keccak256("RecipientWithdrawal(uint256 id,address token,address trader,address recipient,uint256 amount)")
+
+pub const WITHDRAWAL_RELEASE_TYPEHASH: [u8; 32] = [
+    27, 218, 13, 71, 104, 142, 37, 218, 75, 155, 217, 99, 96, 198, 127, 44, 148, 242, 199, 180,
+    124, 93, 134, 183, 218, 231, 51, 108, 135, 183, 135, 193,
+]; // keccak256("WithdrawalRelease(address token,address trader,uint256 amount,uint256 requestedAt)")
+
+pub const EMERGENCY_PAUSE_TYPEHASH: [u8; 32] = [
+    170, 41, 78, 44, 195, 162, 204, 37, 24, 1, 3, 21, 5, 182, 190, 183, 59, 178, 13, 104, 20, 34,
+    112, 184, 229, 139, 169, 158, 233, 51, 114, 242,
+]; // keccak256("EmergencyPause(uint256 nonce,uint256 deadline)")
+
+pub const EMERGENCY_UNPAUSE_TYPEHASH: [u8; 32] = [
+    76, 129, 54, 4, 4, 224, 102, 29, 205, 21, 244, 153, 80, 15, 255, 176, 52, 112, 137, 128, 22,
+    158, 153, 54, 124, 246, 92, 150, 40, 58, 205, 59,
+]; // keccak256("EmergencyUnpause(uint256 nonce,uint256 deadline)")
+
+pub const DEPOSIT_APPROVAL_TYPEHASH: [u8; 32] = [
+    202, 141, 226, 135, 133, 151, 162, 208, 214, 227, 150, 192, 140, 149, 160, 121, 51, 132, 129,
+    189, 12, 205, 54, 129, 14, 65, 88, 131, 250, 59, 65, 69,
+]; // keccak256("DepositApproval(address trader,address token,uint256 amount,uint256 nonce)")
+
+pub const DEPOSIT_POLICY_UPDATE_TYPEHASH: [u8; 32] = [
+    220, 255, 40, 109, 77, 149, 232, 70, 19, 35, 170, 222, 195, 154, 178, 168, 235, 229, 124, 61,
+    212, 179, 173, 95, 34, 137, 233, 57, 230, 178, 129, 94,
+]; // keccak256("DepositPolicyUpdate(address trader,address approver,uint256 threshold,uint256 nonce)")
+
+pub const PROFILE_BINDING_TYPEHASH: [u8; 32] = [
+    168, 15, 43, 108, 179, 201, 168, 41, 33, 139, 14, 189, 83, 89, 219, 53, 201, 226, 115, 59,
+    123, 113, 84, 204, 161, 85, 75, 44, 134, 66, 181, 224,
+]; // keccak256("ProfileBinding(address wallet,uint256 profileId)")
+
+pub const EIP712_DOMAIN_TYPEHASH: [u8; 32] = [
+    139, 115, 195, 198, 155, 184, 254, 61, 81, 46, 204, 76, 247, 89, 204, 121, 35, 159, 123, 23,
+    155, 15, 250, 202, 169, 167, 93, 82, 43, 57, 64, 15,
+]; // keccak256("EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)")
+
+pub const DOMAIN_NAME: &[u8] = b"RabbitXWithdrawal";
+pub const DOMAIN_VERSION: &[u8] = b"1";
+
+pub const EVM_DECIMALS: u8 = 18;
+
+pub fn get_domain_separator(state: &mut Account<State>) -> [u8; 32] {
+    // First check if we have a cached value in the state
+    if let Some(cached) = state.domain_separator {
+        return cached;
+    }
+
+    // If no cached value, compute it
+    let result = compute_domain_separator(&state.key());
+
+    // Cache the result in the state for future use
+    state.domain_separator = Some(result);
+
+    result
+}
+
+// The verifying contract is the State PDA, so off-chain signers can derive it
+// from the program id without reading the account
+pub fn compute_domain_separator(verifying_contract: &Pubkey) -> [u8; 32] {
+    // Compute the domain separator components
+    let name_hash = keccak256(DOMAIN_NAME);
+    let version_hash = keccak256(DOMAIN_VERSION);
+    // Use fixed chain ID value 0x534f4c414e41 (hex for "SOLANA" in ASCII)
+    let chain_id: u64 = 0x534f4c414e41;
+    // Need to pad to 32 bytes (pad with zeros)
+    let mut chain_id_bytes = [0u8; 32];
+    chain_id_bytes[24..32].copy_from_slice(&chain_id.to_be_bytes());
+    let contract_bytes = verifying_contract.to_bytes();
+
+    // Perform the hashing
+    let mut hasher = Keccak256::new();
+    hasher.update(EIP712_DOMAIN_TYPEHASH);
+    hasher.update(name_hash);
+    hasher.update(version_hash);
+    hasher.update(chain_id_bytes);
+    hasher.update(contract_bytes);
+
+    hasher.finalize().into()
+}
+
+// The `address trader` word of the withdrawal structs. EvmAddress20 maps the pubkey to the
+// EVM-style address keccak256(pubkey)[12..32], left-padded with zeros like an ABI-encoded address
+pub fn encode_trader(trader: &Pubkey, encoding: TraderEncoding) -> [u8; 32] {
+    match encoding {
+        TraderEncoding::SolanaPubkey => trader.to_bytes(),
+        TraderEncoding::EvmAddress20 => {
+            let mut word = keccak256(&trader.to_bytes());
+            word[..12].fill(0);
+            word
+        }
+    }
+}
+
+pub fn get_withdrawal_hash(
+    typehash: [u8; 32],
+    id: u64,
+    token: Pubkey,
+    trader: [u8; 32], // Encoded with encode_trader
+    amount: u64,
+) -> [u8; 32] {
+    let mut hasher = Keccak256::new();
+    hasher.update(typehash);
+    hasher.update(id.to_be_bytes());
+    hasher.update(token.to_bytes());
+    hasher.update(trader);
+    hasher.update(amount.to_be_bytes());
+    hasher.finalize().into()
+}
+
+pub fn get_recipient_withdrawal_hash(
+    typehash: [u8; 32],
+    id: u64,
+    token: Pubkey,
+    trader: [u8; 32], // Encoded with encode_trader
+    recipient: Pubkey,
+    amount: u64,
+) -> [u8; 32] {
+    let mut hasher = Keccak256::new();
+    hasher.update(typehash);
+    hasher.update(id.to_be_bytes());
+    hasher.update(token.to_bytes());
+    hasher.update(trader);
+    hasher.update(recipient.to_bytes());
+    hasher.update(amount.to_be_bytes());
+    hasher.finalize().into()
+}
+
+pub fn get_relayed_withdrawal_hash(
+    typehash: [u8; 32],
+    id: u64,
+    token: Pubkey,
+    trader: [u8; 32], // Encoded with encode_trader
+    amount: u64,
+    tip: u64,
+) -> [u8; 32] {
+    let mut hasher = Keccak256::new();
+    hasher.update(typehash);
+    hasher.update(id.to_be_bytes());
+    hasher.update(token.to_bytes());
+    hasher.update(trader);
+    hasher.update(amount.to_be_bytes());
+    hasher.update(tip.to_be_bytes());
+    hasher.finalize().into()
+}
+
+// Commits to requested_at so a release cannot be replayed against a later request
+pub fn get_withdrawal_release_hash(
+    token: Pubkey,
+    trader: [u8; 32], // Encoded with encode_trader
+    amount: u64,
+    requested_at: i64,
+) -> [u8; 32] {
+    let mut hasher = Keccak256::new();
+    hasher.update(WITHDRAWAL_RELEASE_TYPEHASH);
+    hasher.update(token.to_bytes());
+    hasher.update(trader);
+    hasher.update(amount.to_be_bytes());
+    hasher.update(requested_at.to_be_bytes());
+    hasher.finalize().into()
+}
+
+// keccak256(deposit_num, trader, token, amount), with integers big-endian like the withdrawal hash
+pub fn get_deposit_attestation_hash(receipt: &DepositReceipt) -> [u8; 32] {
+    let mut hasher = Keccak256::new();
+    hasher.update(receipt.deposit_num.to_be_bytes());
+    hasher.update(receipt.trader.to_bytes());
+    hasher.update(receipt.token.to_bytes());
+    hasher.update(receipt.amount.to_be_bytes());
+    hasher.finalize().into()
+}
+
+pub fn get_pause_hash(typehash: [u8; 32], nonce: u64, deadline: i64) -> [u8; 32] {
+    let mut hasher = Keccak256::new();
+    hasher.update(typehash);
+    hasher.update(nonce.to_be_bytes());
+    hasher.update(deadline.to_be_bytes());
+    hasher.finalize().into()
+}
+
+pub fn get_profile_binding_hash(
+    wallet: [u8; 32], // Encoded with encode_trader
+    profile_id: u64,
+) -> [u8; 32] {
+    let mut hasher = Keccak256::new();
+    hasher.update(PROFILE_BINDING_TYPEHASH);
+    hasher.update(wallet);
+    hasher.update(profile_id.to_be_bytes());
+    hasher.finalize().into()
+}
+
+pub fn hash_typed_data(domain_separator: &[u8; 32], struct_hash: &[u8; 32]) -> [u8; 32] {
+    // Create a prefixed message following EIP-712 spec
+    let mut message = Vec::with_capacity(66); // 2 bytes prefix + 32 bytes domain_separator + 32 bytes struct_hash
+    message.push(0x19);
+    message.push(0x01);
+    message.extend_from_slice(domain_separator);
+    message.extend_from_slice(struct_hash);
+
+    keccak256(&message)
+}
+
+pub fn get_withdrawal_hash_e18(
+    typehash: [u8; 32],
+    id: u64,
+    token: Pubkey,
+    trader: [u8; 32], // Encoded with encode_trader
+    amount_e18: u128,
+) -> [u8; 32] {
+    // Encode the amount as a full 32-byte uint256
+    let mut amount_bytes = [0u8; 32];
+    amount_bytes[16..32].copy_from_slice(&amount_e18.to_be_bytes());
+
+    let mut hasher = Keccak256::new();
+    hasher.update(typehash);
+    hasher.update(id.to_be_bytes());
+    hasher.update(token.to_bytes());
+    hasher.update(trader);
+    hasher.update(amount_bytes);
+    hasher.finalize().into()
+}
+
+pub fn get_recipient_withdrawal_hash_e18(
+    typehash: [u8; 32],
+    id: u64,
+    token: Pubkey,
+    trader: [u8; 32], // Encoded with encode_trader
+    recipient: Pubkey,
+    amount_e18: u128,
+) -> [u8; 32] {
+    // Encode the amount as a full 32-byte uint256
+    let mut amount_bytes = [0u8; 32];
+    amount_bytes[16..32].copy_from_slice(&amount_e18.to_be_bytes());
+
+    let mut hasher = Keccak256::new();
+    hasher.update(typehash);
+    hasher.update(id.to_be_bytes());
+    hasher.update(token.to_bytes());
+    hasher.update(trader);
+    hasher.update(recipient.to_bytes());
+    hasher.update(amount_bytes);
+    hasher.finalize().into()
+}
+
+pub fn get_relayed_withdrawal_hash_e18(
+    typehash: [u8; 32],
+    id: u64,
+    token: Pubkey,
+    trader: [u8; 32], // Encoded with encode_trader
+    amount_e18: u128,
+    tip_e18: u128,
+) -> [u8; 32] {
+    // Encode the amount and tip as full 32-byte uint256 words
+    let mut amount_bytes = [0u8; 32];
+    amount_bytes[16..32].copy_from_slice(&amount_e18.to_be_bytes());
+    let mut tip_bytes = [0u8; 32];
+    tip_bytes[16..32].copy_from_slice(&tip_e18.to_be_bytes());
+
+    let mut hasher = Keccak256::new();
+    hasher.update(typehash);
+    hasher.update(id.to_be_bytes());
+    hasher.update(token.to_bytes());
+    hasher.update(trader);
+    hasher.update(amount_bytes);
+    hasher.update(tip_bytes);
+    hasher.finalize().into()
+}
+
+// Scale a raw token amount to the 18-decimal representation used by the EVM backend
+pub fn to_e18(amount: u64, decimals: u8) -> u128 {
+    if decimals <= EVM_DECIMALS {
+        amount as u128 * 10u128.pow((EVM_DECIMALS - decimals) as u32)
+    } else {
+        amount as u128 / 10u128.pow((decimals - EVM_DECIMALS) as u32)
+    }
+}
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = sha3::Keccak256::new();
+    hasher.update(data);
+    let result = hasher.finalize();
+    let mut output = [0u8; 32];
+    output.copy_from_slice(&result);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRADER: Pubkey = Pubkey::new_from_array([7u8; 32]);
+    const TOKEN: Pubkey = Pubkey::new_from_array([9u8; 32]);
+
+    #[test]
+    fn solana_pubkey_encoding_is_the_raw_key() {
+        assert_eq!(
+            encode_trader(&TRADER, TraderEncoding::SolanaPubkey),
+            TRADER.to_bytes()
+        );
+    }
+
+    #[test]
+    fn evm_address_encoding_is_a_padded_keccak_tail() {
+        let word = encode_trader(&TRADER, TraderEncoding::EvmAddress20);
+        assert_eq!(word[..12], [0u8; 12]);
+        assert_eq!(word[12..], keccak256(&TRADER.to_bytes())[12..]);
+    }
+
+    #[test]
+    fn withdrawal_hash_depends_on_encoding() {
+        let hash = |encoding| {
+            get_withdrawal_hash(
+                WITHDRAWAL_TYPEHASH,
+                1,
+                TOKEN,
+                encode_trader(&TRADER, encoding),
+                1_000,
+            )
+        };
+        assert_ne!(
+            hash(TraderEncoding::SolanaPubkey),
+            hash(TraderEncoding::EvmAddress20)
+        );
+    }
+}
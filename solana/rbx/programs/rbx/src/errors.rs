@@ -0,0 +1,271 @@
+use anchor_lang::prelude::*;
+
+#[error_code]
+pub enum RbxError {
+    #[msg("Amount too small")]
+    AmountTooSmall,
+    #[msg("Invalid signature")]
+    InvalidSignature,
+    #[msg("Already processed")]
+    AlreadyProcessed,
+    #[msg("Wrong amount")]
+    WrongAmount,
+    #[msg("Unsupported token")]
+    UnsupportedToken,
+    #[msg("Too many tokens")]
+    TooManyTokens,
+    #[msg("Invalid token mint")]
+    InvalidToken,
+    #[msg("Invalid signature format")]
+    InvalidSignatureFormat,
+    #[msg("Reentrancy detected")]
+    ReentrancyDetected,
+    #[msg("Insufficient funds")]
+    InsufficientFunds,
+    #[msg("Invalid signer")]
+    InvalidSigner,
+    #[msg("Unauthorized access")]
+    UnauthorizedAccess,
+    #[msg("Invalid operation index")]
+    InvalidOperationIndex,
+    #[msg("Timelock delay not met")]
+    TimelockDelayNotMet,
+    #[msg("Invalid operation type")]
+    InvalidOperationType,
+    #[msg("Invalid operation data")]
+    InvalidOperationData,
+    #[msg("Invalid timelock delay")]
+    InvalidTimelockDelay,
+    #[msg("Invalid authority")]
+    InvalidAuthority,
+    #[msg("Withdrawal already processed")]
+    WithdrawalAlreadyProcessed,
+    #[msg("Authority already exists")]
+    AuthorityAlreadyExists,
+    #[msg("Authority not found")]
+    AuthorityNotFound,
+    #[msg("Cannot remove the last authority")]
+    CannotRemoveLastAuthority,
+    #[msg("No authorities provided")]
+    NoAuthoritiesProvided,
+    #[msg("Too many authorities")]
+    TooManyAuthorities,
+    #[msg("Duplicate authority")]
+    DuplicateAuthority,
+    #[msg("Invalid treasury")]
+    InvalidTreasury,
+    #[msg("No surplus to sweep")]
+    NoSurplus,
+    #[msg("Trader ledger does not match trader or token")]
+    InvalidTraderLedger,
+    #[msg("Trader ledger required for full-balance withdrawal")]
+    TraderLedgerRequired,
+    #[msg("Withdrawals are paused")]
+    WithdrawalsPaused,
+    #[msg("Invalid nonce")]
+    InvalidNonce,
+    #[msg("Signature expired")]
+    SignatureExpired,
+    #[msg("Deposit requires approval")]
+    DepositApprovalRequired,
+    #[msg("Invalid wSOL account")]
+    InvalidWsolAccount,
+    #[msg("Invalid vault account")]
+    InvalidVaultAccount,
+    #[msg("Batch is empty")]
+    EmptyBatch,
+    #[msg("Batch exceeds the configured maximum size")]
+    BatchTooLarge,
+    #[msg("Mint has a freeze authority")]
+    FreezableMint,
+    #[msg("Current slot is outside the requested window")]
+    SlotWindowViolated,
+    #[msg("Forced withdrawals are disabled")]
+    ForcedWithdrawalsDisabled,
+    #[msg("Withdrawal request is not stale yet")]
+    WithdrawalRequestNotStale,
+    #[msg("Withdrawal request was already answered")]
+    WithdrawalRequestAnswered,
+    #[msg("Destination is not an active entry in the withdrawal allowlist")]
+    DestinationNotAllowlisted,
+    #[msg("Destination is already in the withdrawal allowlist")]
+    DestinationAlreadyAllowlisted,
+    #[msg("Withdrawal allowlist is full")]
+    TooManyAllowlistEntries,
+    #[msg("Withdrawal does not use the configured replay protection")]
+    ReplayProtectionMismatch,
+    #[msg("Deposit receipt account does not match create_receipt")]
+    InvalidDepositReceipt,
+    #[msg("Token is not active for deposits")]
+    TokenNotActive,
+    #[msg("Token is paused")]
+    TokenPaused,
+    #[msg("Signer is not a deposit delegate of the trader")]
+    NotADepositDelegate,
+    #[msg("Deposit delegate already added")]
+    DuplicateDepositDelegate,
+    #[msg("Too many deposit delegates")]
+    TooManyDepositDelegates,
+    #[msg("Third-party deposits are disabled")]
+    ThirdPartyDepositsDisabled,
+    #[msg("Signer is not an allowed third-party deposit sponsor")]
+    UnauthorizedSponsor,
+    #[msg("Sponsor already allowed")]
+    DuplicateSponsor,
+    #[msg("Too many third-party deposit sponsors")]
+    TooManySponsors,
+    #[msg("Sponsor not found")]
+    SponsorNotFound,
+    #[msg("Watermark can only move forward")]
+    InvalidWatermark,
+    #[msg("Withdrawal record is not fully below the watermark")]
+    WithdrawalRecordInUse,
+    #[msg("Withdrawal id is below the watermark")]
+    WithdrawalIdBelowWatermark,
+    #[msg("Program upgrade authority does not match the expected authority")]
+    UpgradeAuthorityMismatch,
+    #[msg("Withdrawal record accounts do not match the withdrawal ids")]
+    InvalidWithdrawalRecord,
+    #[msg("Decommissioning has not been approved through the timelock")]
+    DecommissionNotApproved,
+    #[msg("Traders still have funds tracked by the program")]
+    OutstandingLiabilities,
+    #[msg("Stake position does not belong to this trader and token")]
+    InvalidStakePosition,
+    #[msg("Not enough unlocked stake")]
+    InsufficientStake,
+    #[msg("Not enough locked collateral")]
+    InsufficientCollateral,
+    #[msg("Vault balance is below recorded liabilities")]
+    Insolvent,
+    #[msg("Relayer tip must be less than the withdrawal amount")]
+    RelayerTipTooLarge,
+    #[msg("Relayer token account must belong to the payer and match the mint")]
+    InvalidRelayerAccount,
+    #[msg("Withdrawal signature version is not enabled")]
+    SignatureVersionDisabled,
+    #[msg("Signature version does not cover the withdrawal's fields")]
+    SignatureVersionMismatch,
+    #[msg("Unknown signature version or empty typehash")]
+    InvalidSignatureVersion,
+    #[msg("Payout would take the SOL account below its reserve")]
+    SolReserveBreached,
+    #[msg("Withdrawal amount is below the token's minimum")]
+    WithdrawalTooSmall,
+    #[msg("Address is blocked")]
+    AddressBlocked,
+    #[msg("Signer-released withdrawal requests are disabled")]
+    WithdrawalReleaseDisabled,
+    #[msg("Withdrawal request cannot be released yet")]
+    WithdrawalReleaseNotReady,
+    #[msg("Bridge receipt is not owned by the configured bridge program or is malformed")]
+    InvalidBridgeReceipt,
+    #[msg("A partial withdrawal account for the trader and token is required")]
+    PartialWithdrawalAccountRequired,
+    #[msg("Operation cannot be executed by the permissionless crank")]
+    OperationNotAutoExecutable,
+    #[msg("Payout account must belong to the trader or be the recipient's associated token account")]
+    InvalidRecipientAccount,
+    #[msg("Deposits of this token are paused")]
+    TokenDepositsPaused,
+    #[msg("Withdrawals of this token are paused")]
+    TokenWithdrawalsPaused,
+    #[msg("Unpausing this token has not been approved through the timelock")]
+    TokenUnpauseNotApproved,
+    #[msg("SOL vault migration has not been approved through the timelock")]
+    SolVaultMigrationNotApproved,
+    #[msg("SOL vault has already been migrated")]
+    SolVaultAlreadyMigrated,
+    #[msg("Deposit amount is not a multiple of the token's dust modulus")]
+    InvalidPrecision,
+    #[msg("Third-party deposits of this size must be made through a deposit claim")]
+    DepositClaimRequired,
+    #[msg("Deposit claims are disabled")]
+    DepositClaimsDisabled,
+    #[msg("Deposit claim has expired")]
+    DepositClaimExpired,
+    #[msg("Deposit claim has not expired yet")]
+    DepositClaimNotExpired,
+    #[msg("Deposit leg accounts do not match the mint or its vault")]
+    InvalidDepositLeg,
+    #[msg("Deposit would exceed the token's hourly deposit cap")]
+    DepositCapExceeded,
+    #[msg("Rent treasury cannot cover the account's rent")]
+    RentTreasuryDepleted,
+    #[msg("Rebalancing to the cold vault is disabled for this token")]
+    ColdVaultDisabled,
+    #[msg("Hot vault holds nothing above its float")]
+    NothingToRebalance,
+    #[msg("Moving this amount out of the cold vault has not been approved through the timelock")]
+    ColdReleaseNotApproved,
+    #[msg("Advancing the counters has not been approved through the timelock")]
+    CounterAdvanceNotApproved,
+    #[msg("Native SOL support needs native_min_deposit together with the wSOL mint, config and vault accounts")]
+    NativeSupportAccountsRequired,
+    #[msg("Deposit fee exceeds the maximum")]
+    DepositFeeTooHigh,
+    #[msg("Fee destination does not match the token's configured fee account")]
+    InvalidFeeDestination,
+    #[msg("Profile binding belongs to another wallet")]
+    InvalidProfileBinding,
+    #[msg("Retiring withdrawal shards is disabled")]
+    ShardRetentionDisabled,
+    #[msg("Withdrawal shard retention period has not passed")]
+    ShardRetentionNotElapsed,
+    #[msg("Deposit tag must be 1 to 64 bytes and the memo program must be passed")]
+    InvalidDepositTag,
+    #[msg("Only the program upgrade authority can initialize")]
+    NotUpgradeAuthority,
+    #[msg("Emission schedule segments must be sorted by start, at most 8")]
+    InvalidEmissionSchedule,
+    #[msg("Emission schedule has not been approved through the timelock")]
+    EmissionScheduleNotApproved,
+    #[msg("Vault token account is frozen by the mint's freeze authority")]
+    VaultFrozen,
+    #[msg("Activity counter does not belong to the trader")]
+    InvalidActivityCounter,
+    #[msg("Governance is frozen")]
+    GovernanceFrozen,
+    #[msg("Vault balance does not cover the withdrawal")]
+    InsufficientVaultLiquidity,
+    #[msg("Token was already checkpointed today")]
+    CheckpointTooSoon,
+    #[msg("Invalid or full address lookup table")]
+    InvalidLookupTable,
+    #[msg("Counter would overflow")]
+    CounterOverflow,
+    #[msg("Timestamp or slot arithmetic would overflow")]
+    TimestampOverflow,
+    #[msg("Deployed program is older than the version recorded in state")]
+    ProgramVersionDowngrade,
+    #[msg("Event authority is not this program's event CPI signer")]
+    InvalidEventAuthority,
+    #[msg("Token does not accept funds for this purpose")]
+    PurposeNotAllowed,
+    #[msg("Allowed purposes were not approved through the timelock")]
+    AllowedPurposesNotApproved,
+    #[msg("Withdrawal record is not the shard derived from the withdrawal id")]
+    WithdrawalRecordMismatch,
+    #[msg("Bridged transfer was not emitted by the configured token bridge")]
+    BridgeEmitterMismatch,
+    #[msg("Bridged transfer does not deliver this mint into the bridge inbox")]
+    BridgeDestinationMismatch,
+    #[msg("Token config is not the PDA of the supported token at this position")]
+    TokenConfigMismatch,
+    #[msg("Custodied lamports have not been reconciled since tracking began")]
+    CustodyNotReconciled,
+    #[msg("Too many pending timelock operations")]
+    TooManyPendingOperations,
+    #[msg("Token is already supported")]
+    TokenAlreadySupported,
+    #[msg("Retire a token with unsupport_token so its slot is released")]
+    RetireThroughUnsupport,
+    #[msg("Amount arithmetic would overflow")]
+    AmountOverflow,
+    #[msg("Adopting this vault has not been approved through the timelock")]
+    VaultAdoptionNotApproved,
+    #[msg("This deposit fee has not been approved through the timelock")]
+    DepositFeeNotApproved,
+    #[msg("Blacklist entry does not belong to the credited trader")]
+    InvalidBlacklistEntry,
+}
@@ -0,0 +1,848 @@
+use anchor_lang::prelude::*;
+
+use crate::{TokenStatus, TraderEncoding};
+
+// Bumped whenever a V2 event changes shape, so indexers can dispatch on it
+pub const EVENT_SCHEMA_VERSION: u8 = 4;
+
+#[event]
+pub struct DepositEvent {
+    #[index]
+    pub id: String,
+    #[index]
+    pub trader: Pubkey,
+    pub amount: u64,
+    pub amount_e18: u128,
+    pub token: Pubkey,
+    pub slot: u64,
+    pub unix_timestamp: i64,
+}
+
+#[event]
+pub struct StakeEvent {
+    #[index]
+    pub id: String,
+    #[index]
+    pub trader: Pubkey,
+    pub amount: u64,
+    pub token: Pubkey,
+    pub slot: u64,
+    pub unix_timestamp: i64,
+}
+
+#[event]
+pub struct DepositEventV2 {
+    pub schema_version: u8,
+    #[index]
+    pub id: String,
+    #[index]
+    pub trader: Pubkey,
+    pub amount: u64,
+    pub amount_e18: u128,
+    pub token: Pubkey,
+    pub slot: u64,
+    pub unix_timestamp: i64,
+    pub profile_id: Option<u64>, // Set when the deposit passed the trader's ProfileBinding
+}
+
+#[event]
+pub struct StakeEventV2 {
+    pub schema_version: u8,
+    #[index]
+    pub id: String,
+    #[index]
+    pub trader: Pubkey,
+    pub amount: u64,
+    pub token: Pubkey,
+    pub slot: u64,
+    pub unix_timestamp: i64,
+}
+
+#[event]
+pub struct RelayerTipEvent {
+    #[index]
+    pub id: u64,
+    pub relayer: Pubkey,
+    pub token: Pubkey,
+    pub tip: u64,
+}
+
+#[event]
+pub struct WithdrawalEvent {
+    #[index]
+    pub id: u64,
+    #[index]
+    pub trader: Pubkey,
+    pub recipient: Pubkey, // Wallet the funds were paid to, the trader unless signed otherwise
+    pub amount: u64,
+    pub amount_e18: u128,
+    pub token: Pubkey,
+    pub slot: u64,
+    pub unix_timestamp: i64,
+}
+
+#[event]
+pub struct WithdrawalEventV2 {
+    pub schema_version: u8,
+    #[index]
+    pub id: u64,
+    #[index]
+    pub trader: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub amount_e18: u128,
+    pub token: Pubkey,
+    pub slot: u64,
+    pub unix_timestamp: i64,
+}
+
+#[event]
+pub struct WithdrawalRejectedEvent {
+    #[index]
+    pub id: u64,
+    #[index]
+    pub trader: Pubkey,
+    pub reason_code: u8,
+}
+
+#[event]
+pub struct WithdrawalRequestedEvent {
+    #[index]
+    pub trader: Pubkey,
+    pub token: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ForcedWithdrawalEvent {
+    #[index]
+    pub trader: Pubkey,
+    pub token: Pubkey,
+    pub requested_amount: u64,
+    pub amount: u64,
+}
+
+#[event]
+pub struct SetForcedWithdrawalDelayEvent {
+    pub delay: i64,
+}
+
+#[event]
+pub struct AllowlistDestinationAddedEvent {
+    #[index]
+    pub trader: Pubkey,
+    pub destination: Pubkey,
+    pub active_at: i64,
+}
+
+#[event]
+pub struct AllowlistDestinationRemovedEvent {
+    #[index]
+    pub trader: Pubkey,
+    pub destination: Pubkey,
+}
+
+#[event]
+pub struct SetWithdrawalReceiptsEvent {
+    pub use_withdrawal_receipts: bool,
+    pub withdrawal_receipt_floor: u64,
+}
+
+#[event]
+pub struct SetTokenSignerEvent {
+    pub token: Pubkey,
+    pub signer: [u8; 20],
+}
+
+#[event]
+pub struct SetTokenStatusEvent {
+    #[index]
+    pub token: Pubkey,
+    pub status: TokenStatus,
+}
+
+#[event]
+pub struct DepositDelegateEvent {
+    #[index]
+    pub trader: Pubkey,
+    pub delegate: Pubkey,
+    pub added: bool,
+}
+
+#[event]
+pub struct SetThirdPartyDepositsEvent {
+    pub enabled: bool,
+}
+
+#[event]
+pub struct ThirdPartySponsorEvent {
+    pub sponsor: Pubkey,
+    pub added: bool,
+}
+
+#[event]
+pub struct WithdrawalWatermarkEvent {
+    pub id: u64,
+}
+
+#[event]
+pub struct WithdrawalRecordClosedEvent {
+    pub index: u64,
+    pub lamports: u64,
+}
+
+#[event]
+pub struct SetUpgradeAuthorityEvent {
+    pub upgrade_authority: Pubkey,
+}
+
+#[event]
+pub struct UpgradeAuthorityCheckEvent {
+    pub expected: Pubkey,
+    pub actual: Option<Pubkey>,
+    pub matches: bool,
+}
+
+#[event]
+pub struct DecommissionApprovedEvent {}
+
+#[event]
+pub struct DecommissionedEvent {
+    pub owner: Pubkey,
+    pub vaults_closed: u8,
+    pub lamports: u64,
+}
+
+#[event]
+pub struct DepositAttestationEvent {
+    #[index]
+    pub deposit_num: u64,
+    pub trader: Pubkey,
+    pub token: Pubkey,
+    pub amount: u64,
+    pub hash: [u8; 32],
+}
+
+#[event]
+pub struct SetMarginEngineEvent {
+    pub margin_engine: Pubkey,
+}
+
+#[event]
+pub struct CollateralLockedEvent {
+    #[index]
+    pub trader: Pubkey,
+    pub token: Pubkey,
+    pub amount: u64,
+    pub locked: u64,
+}
+
+#[event]
+pub struct CollateralReleasedEvent {
+    #[index]
+    pub trader: Pubkey,
+    pub token: Pubkey,
+    pub amount: u64,
+    pub locked: u64,
+}
+
+#[event]
+pub struct SolReserveEvent {
+    pub reserved_lamports: u64,
+    pub top_up: u64,
+    pub balance: u64, // SOL account balance after the top-up
+}
+
+#[event]
+pub struct SolvencyCheckEvent {
+    #[index]
+    pub token: Pubkey,
+    pub liabilities: u64,
+    pub vault_balance: u64,
+}
+
+#[event]
+pub struct SupportTokenEvent {
+    #[index]
+    pub token: Pubkey,
+    pub min_deposit: u64,
+}
+
+#[event]
+pub struct UnsupportTokenEvent {
+    #[index]
+    pub token: Pubkey,
+}
+
+#[event]
+pub struct SetSignerEvent {
+    #[index]
+    pub signer: [u8; 20],
+    pub previous_signer: [u8; 20],
+    pub rotation_deadline: i64,
+}
+
+#[event]
+pub struct PreviousSignerRevokedEvent {
+    pub previous_signer: [u8; 20],
+    pub authority: Pubkey,
+}
+
+#[event]
+pub struct SetMinWithdrawalEvent {
+    #[index]
+    pub token: Pubkey,
+    pub amount: u64, // 0 when the minimum was removed
+}
+
+#[event]
+pub struct SetSignatureVersionEvent {
+    pub version: u8,
+    pub typehash: [u8; 32],
+    pub enabled: bool,
+}
+
+#[event]
+pub struct SetSignerRotationOverlapEvent {
+    pub overlap: i64,
+}
+
+#[event]
+pub struct QueueOperationEvent {
+    pub operation_id: u64,
+    pub operation_type: u8,
+    pub execute_time: i64,
+    pub execute_slot: u64,
+    pub auto_executable: bool,
+}
+
+#[event]
+pub struct ExecuteOperationEvent {
+    pub operation_type: u8,
+}
+
+#[event]
+pub struct CancelOperationEvent {
+    pub operation_type: u8,
+    pub authority: Pubkey,
+    pub operation_hash: [u8; 32], // keccak256 of the Borsh-encoded operation
+}
+
+#[event]
+pub struct SetTimelockDelayEvent {
+    pub delay: i64,
+}
+
+#[event]
+pub struct SetTimelockAuthorityEvent {
+    pub authority: Pubkey,
+}
+
+#[event]
+pub struct InitializeEvent {
+    pub owner: Pubkey,
+    pub signer: [u8; 20],
+    pub timelock_authorities: Vec<Pubkey>,
+    pub timelock_delay: i64,
+    pub timelock_in_slots: bool,
+    pub default_token: Pubkey,
+    pub min_deposit: u64,
+}
+
+#[event]
+pub struct SetOwnerEvent {
+    #[index]
+    pub owner: Pubkey,
+}
+
+#[event]
+pub struct AddAuthorityEvent {
+    #[index]
+    pub authority: Pubkey,
+}
+
+#[event]
+pub struct RemoveAuthorityEvent {
+    #[index]
+    pub authority: Pubkey,
+}
+
+#[event]
+pub struct SetTreasuryEvent {
+    #[index]
+    pub treasury: Pubkey,
+}
+
+#[event]
+pub struct SolSurplusSweptEvent {
+    #[index]
+    pub treasury: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct CustodyReconciledEvent {
+    pub custodied_lamports: u64,
+}
+
+#[event]
+pub struct WithdrawalsPausedEvent {
+    pub nonce: u64,
+    pub submitter: Pubkey,
+}
+
+#[event]
+pub struct WithdrawalsUnpausedEvent {
+    pub nonce: u64,
+    pub submitter: Pubkey,
+}
+
+#[event]
+pub struct DepositPolicyEvent {
+    #[index]
+    pub trader: Pubkey,
+    pub approver: [u8; 20],
+    pub threshold: u64,
+}
+
+#[event]
+pub struct DepositApprovedEvent {
+    #[index]
+    pub trader: Pubkey,
+    pub token: Pubkey,
+    pub amount: u64,
+    pub nonce: u64,
+}
+
+#[event]
+pub struct SetAmountEncodingEvent {
+    pub sign_amounts_e18: bool,
+}
+
+#[event]
+pub struct TokenAccountingEvent {
+    #[index]
+    pub token: Pubkey,
+    pub supported: bool,
+    pub min_deposit: u64,
+    pub next_deposit_num: u64,
+    pub next_stake_num: u64,
+    pub vault_balance: u64,
+    pub sol_vault_balance: u64,
+    pub reserved_lamports: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SetMaxBatchSizesEvent {
+    pub max_batch_deposits: u8,
+    pub max_batch_withdrawals: u8,
+}
+
+#[event]
+pub struct SetRejectFreezableMintsEvent {
+    pub reject_freezable_mints: bool,
+}
+
+#[event]
+pub struct DomainSeparatorRefreshedEvent {
+    pub domain_separator: [u8; 32],
+}
+
+#[event]
+pub struct SetComplianceAuthorityEvent {
+    pub compliance_authority: Pubkey,
+}
+
+#[event]
+pub struct AddressBlockedEvent {
+    #[index]
+    pub address: Pubkey,
+    pub authority: Pubkey,
+}
+
+#[event]
+pub struct AddressUnblockedEvent {
+    #[index]
+    pub address: Pubkey,
+    pub authority: Pubkey,
+}
+
+#[event]
+pub struct SetWithdrawalReleaseDelayEvent {
+    pub delay: i64,
+}
+
+#[event]
+pub struct WithdrawalReleasedEvent {
+    #[index]
+    pub trader: Pubkey,
+    pub token: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct SetBridgeProgramEvent {
+    pub bridge_program: Pubkey,
+    pub emitter_chain: u16,
+    pub emitter: [u8; 32],
+}
+
+#[event]
+pub struct BridgedDepositEvent {
+    #[index]
+    pub receipt: Pubkey,
+    pub trader: Pubkey,
+    pub token: Pubkey,
+    pub amount: u64,
+    pub deposit_num: u64,
+}
+
+#[event]
+pub struct PartialWithdrawalEvent {
+    #[index]
+    pub id: u64,
+    pub trader: Pubkey,
+    pub token: Pubkey,
+    pub paid: u64,
+    pub deferred: u64,
+}
+
+#[event]
+pub struct PartialWithdrawalClaimedEvent {
+    #[index]
+    pub trader: Pubkey,
+    pub token: Pubkey,
+    pub amount: u64,
+    pub remaining: u64,
+}
+
+#[event]
+pub struct TokenPauseEvent {
+    #[index]
+    pub token: Pubkey,
+    pub paused_deposits: bool,
+    pub paused_withdrawals: bool,
+}
+
+#[event]
+pub struct TokenUnpauseApprovedEvent {
+    pub token: Pubkey,
+}
+
+#[event]
+pub struct SolVaultMigrationApprovedEvent {}
+
+#[event]
+pub struct SolVaultMigratedEvent {
+    pub old_sol_account: Pubkey,
+    pub new_sol_account: Pubkey,
+    pub lamports: u64,
+}
+
+#[event]
+pub struct DepositFeeApprovedEvent {
+    #[index]
+    pub token: Pubkey,
+    pub deposit_fee_bps: u16,
+    pub fee_destination: Pubkey,
+}
+
+#[event]
+pub struct SetDepositFeeEvent {
+    #[index]
+    pub token: Pubkey,
+    pub deposit_fee_bps: u16,
+    pub fee_destination: Pubkey,
+}
+
+// Emitted next to the deposit event, whose amount is the net credited to the trader
+#[event]
+pub struct DepositFeeEvent {
+    pub id: String,
+    #[index]
+    pub trader: Pubkey,
+    pub token: Pubkey,
+    pub gross_amount: u64,
+    pub fee: u64,
+    pub net_amount: u64,
+}
+
+#[event]
+pub struct SetDustModulusEvent {
+    #[index]
+    pub token: Pubkey,
+    pub dust_modulus: u64,
+}
+
+#[event]
+pub struct SetDepositClaimConfigEvent {
+    pub threshold: u64,
+    pub window: i64,
+}
+
+#[event]
+pub struct DepositClaimCreatedEvent {
+    pub claim: Pubkey,
+    pub sponsor: Pubkey,
+    #[index]
+    pub trader: Pubkey,
+    pub token: Pubkey,
+    pub amount: u64,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct DepositClaimAcceptedEvent {
+    pub claim: Pubkey,
+    #[index]
+    pub trader: Pubkey,
+    pub deposit_num: u64,
+}
+
+#[event]
+pub struct DepositReclaimedEvent {
+    pub claim: Pubkey,
+    #[index]
+    pub sponsor: Pubkey,
+    pub token: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct SetEmitLegacyEventsEvent {
+    pub enabled: bool,
+}
+
+#[event]
+pub struct SetEventModeEvent {
+    pub mode: u8,
+}
+
+#[event]
+pub struct SetSolReserveEvent {
+    pub reserved_lamports: u64,
+}
+
+#[event]
+pub struct HeartbeatEvent {
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SetHeartbeatConfigEvent {
+    pub heartbeat_authority: Pubkey,
+    pub timeout: i64,
+}
+
+#[event]
+pub struct SetDepositCapEvent {
+    #[index]
+    pub token: Pubkey,
+    pub deposit_cap: u64,
+}
+
+#[event]
+pub struct SetTraderEncodingEvent {
+    pub trader_encoding: TraderEncoding,
+}
+
+#[event]
+pub struct WithdrawalRecordSponsoredEvent {
+    pub index: u64,
+    pub rent: u64,
+    pub treasury_balance: u64,
+}
+
+#[event]
+pub struct RentTreasuryToppedUpEvent {
+    pub amount: u64,
+    pub balance: u64,
+}
+
+#[event]
+pub struct SetTimelockRolesEvent {
+    pub authority: Pubkey,
+    pub roles: u8,
+}
+
+#[event]
+pub struct SetHotFloatEvent {
+    #[index]
+    pub token: Pubkey,
+    pub hot_float: u64,
+}
+
+#[event]
+pub struct ColdReleaseApprovedEvent {
+    pub token: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct VaultRebalancedEvent {
+    #[index]
+    pub token: Pubkey,
+    pub to_cold: bool,
+    pub amount: u64,
+}
+
+// Emitted with the new value each time a deposit or stake counter advances, so monitoring
+// can spot regressions or replays across upgrades without parsing the string ids
+#[event]
+pub struct CounterAdvancedEvent {
+    pub kind: u8, // COUNTER_DEPOSIT_NUM or COUNTER_STAKE_NUM
+    pub new_value: u64,
+}
+
+#[event]
+pub struct CounterAdvanceApprovedEvent {
+    pub min_deposit_num: u64,
+    pub min_stake_num: u64,
+}
+
+#[event]
+pub struct CountersAdvancedEvent {
+    pub next_deposit_num: u64,
+    pub next_stake_num: u64,
+}
+
+#[event]
+pub struct ProfileBoundEvent {
+    #[index]
+    pub wallet: Pubkey,
+    pub profile_id: u64,
+}
+
+#[event]
+pub struct SetShardRetentionPeriodEvent {
+    pub retention_period: i64,
+}
+
+// Emitted after the deposit event of `deposit_native_with_tag`
+#[event]
+pub struct DepositTaggedEvent {
+    #[index]
+    pub id: String,
+    #[index]
+    pub trader: Pubkey,
+    pub tag: String,
+}
+
+#[event]
+pub struct EmissionScheduleApprovedEvent {
+    pub token: Pubkey,
+    pub schedule_hash: [u8; 32],
+}
+
+#[event]
+pub struct AllowedPurposesApprovedEvent {
+    pub token: Pubkey,
+    pub allowed_purposes: u8,
+}
+
+#[event]
+pub struct SetAllowedPurposesEvent {
+    #[index]
+    pub token: Pubkey,
+    pub allowed_purposes: u8,
+}
+
+#[event]
+pub struct SetEmissionScheduleEvent {
+    #[index]
+    pub token: Pubkey,
+    pub schedule_hash: [u8; 32],
+}
+
+// A trader topped up or partially unstaked a position; `staked` is the position after the change
+#[event]
+pub struct StakeAdjustedEvent {
+    #[index]
+    pub trader: Pubkey,
+    pub token: Pubkey,
+    pub increased: bool,
+    pub amount: u64,
+    pub staked: u64,
+}
+
+// Alert raised before rejecting a transfer because the mint's freeze authority froze the vault
+#[event]
+pub struct VaultFrozenEvent {
+    #[index]
+    pub token: Pubkey,
+    pub vault: Pubkey,
+}
+
+#[event]
+pub struct SetMinDepositEvent {
+    #[index]
+    pub token: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct WithdrawalTicketPostedEvent {
+    #[index]
+    pub id: u64,
+    #[index]
+    pub trader: Pubkey,
+    pub token: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct GovernanceFrozenEvent {
+    pub frozen_until: i64,
+}
+
+// Alert raised when a withdrawal defers the part the vault cannot cover
+#[event]
+pub struct VaultShortfallEvent {
+    #[index]
+    pub token: Pubkey,
+    pub requested: u64,
+    pub available: u64,
+}
+
+// Once-a-day snapshot of a token for analytics, see checkpoint
+#[event]
+pub struct DailyCheckpointEvent {
+    #[index]
+    pub token: Pubkey,
+    pub day: i64, // Days since the Unix epoch
+    pub vault_balance: u64,
+    pub liabilities: u64,
+    pub deposit_count: u64,
+    pub deposit_volume: u128,
+    pub withdrawal_count: u64,
+    pub withdrawal_volume: u128,
+    pub stake_count: u64,
+    pub stake_volume: u128,
+    pub next_deposit_num: u64,
+    pub next_stake_num: u64,
+    pub slot: u64,
+    pub unix_timestamp: i64,
+}
+
+#[event]
+pub struct VaultAdoptionApprovedEvent {
+    #[index]
+    pub token: Pubkey,
+    pub vault: Pubkey,
+}
+
+#[event]
+pub struct VaultAdoptedEvent {
+    #[index]
+    pub token: Pubkey,
+    pub vault: Pubkey,
+    pub previous: Pubkey,
+}
+
+#[event]
+pub struct UpgradeFinalizedEvent {
+    pub previous_version: [u16; 3],
+    pub version: [u16; 3],
+}
@@ -1,34 +1,162 @@
+// Instruction arguments mirror the signed EVM payloads, and the SBF toolchain predates
+// is_multiple_of and inspect_err
+#![allow(clippy::too_many_arguments, clippy::manual_is_multiple_of, clippy::manual_inspect)]
+
 use anchor_lang::prelude::*;
-use anchor_spl::associated_token::AssociatedToken;
-use anchor_spl::token::{self, Token, Transfer};
+use anchor_spl::associated_token::{self, get_associated_token_address, AssociatedToken};
+use anchor_spl::token::{self, CloseAccount, Mint, SyncNative, Token, TokenAccount, Transfer};
 use sha3::{Digest, Keccak256};
-use solana_program::secp256k1_recover::secp256k1_recover;
+use solana_program::address_lookup_table::instruction::extend_lookup_table;
+use solana_program::address_lookup_table::state::AddressLookupTable;
+
+pub mod crypto;
+pub mod eip712;
+pub mod errors;
+pub mod events;
+#[cfg(any(test, feature = "signing"))]
+pub mod signing;
+pub mod state;
+
+use crypto::*;
+use eip712::*;
+pub use errors::*;
+pub use events::*;
+pub use state::*;
 
 declare_id!("CZBh9LezU7rC2vpxCBs8w1TSFYmHDjU2WmWYkkcocq9W");
 
 // Define constants at module level
 const MAX_SUPPORTED_TOKENS: usize = 10;
 const MAX_AUTHORITIES: usize = 5;
+const MAX_THIRD_PARTY_SPONSORS: usize = 10;
 pub const WITHDRAWALS_PER_ACCOUNT: usize = 4_000;
+pub const WITHDRAW_ALL: u64 = u64::MAX; // Signed amount meaning "the trader's full ledger balance"
 const WITHDRAWAL_BITMAP_SIZE: usize = 500; // 500 bytes * 8 bits = 4,000 withdrawals
-
-const WITHDRAWAL_TYPEHASH: [u8; 32] = [
-    167, 69, 94, 218, 166, 15, 227, 162, 173, 23, 189, 249, 11, 198, 237, 102, 6, 5, 183, 189, 69,
-    157, 74, 166, 94, 139, 214, 92, 182, 237, 67, 161,
-]; // keccak256("Withdrawal(uint256 id,address token,address trader,uint256 amount)")
-
-const EIP712_DOMAIN_TYPEHASH: [u8; 32] = [
-    139, 115, 195, 198, 155, 184, 254, 61, 81, 46, 204, 76, 247, 89, 204, 121, 35, 159, 123, 23,
-    155, 15, 250, 202, 169, 167, 93, 82, 43, 57, 64, 15,
-]; // keccak256("EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)")
-
-const DOMAIN_NAME: &[u8] = b"RabbitXWithdrawal";
-const DOMAIN_VERSION: &[u8] = b"1";
+const MAX_OPERATION_TYPE: u8 = 41;
+const OPERATION_SET_MIN_DEPOSIT: u8 = 35;
+const OPERATION_FREEZE_GOVERNANCE: u8 = 36;
+const OPERATION_SET_EVENT_MODE: u8 = 37;
+const OPERATION_APPROVE_ALLOWED_PURPOSES: u8 = 38;
+const OPERATION_SET_SOL_RESERVE: u8 = 39;
+const OPERATION_APPROVE_VAULT_ADOPTION: u8 = 40;
+const OPERATION_APPROVE_DEPOSIT_FEE: u8 = 41;
+const MAX_SOL_RESERVE: u64 = 100 * solana_program::native_token::LAMPORTS_PER_SOL;
+// Operations the permissionless crank may execute: limits and publishing settings that move
+// neither authority nor funds. Everything else stays executable only by a timelock authority
+const CRANK_SAFE_OPERATION_TYPES: [u8; 9] =
+    [8, 9, 10, 20, 22, 26, 27, 33, OPERATION_SET_EVENT_MODE];
+const MAX_PENDING_OPERATIONS: usize = 10; // Bounds State.pending_operations to its allocation
+const MAX_OPERATION_DATA_LEN: usize = 66; // Longest operation payload (set_bridge_program)
+const MAX_GOVERNANCE_FREEZE: i64 = 90 * 24 * 60 * 60; // Longest a governance freeze may last
+const DEFAULT_MAX_BATCH_SIZE: u8 = 4;
+const NATIVE_DECIMALS: u8 = 9;
+const WORMHOLE_CHAIN_SOLANA: u16 = 1;
+// How deposit, withdrawal and stake events are published, see State.event_mode
+pub const EVENT_MODE_LOGS: u8 = 0; // Program logs only (emit!)
+pub const EVENT_MODE_LOGS_AND_CPI: u8 = 1; // Program logs plus Anchor's event CPI
+pub const EVENT_MODE_CPI: u8 = 2; // Event CPI only, logs when the CPI accounts are not passed
+const EVENT_AUTHORITY_SEED: &[u8] = b"__event_authority";
+// TokenConfig.allowed_purposes bits, one per FundingPurpose
+pub const PURPOSE_DEPOSIT: u8 = 1 << 0;
+pub const PURPOSE_STAKE: u8 = 1 << 1;
+pub const PURPOSES_ALL: u8 = PURPOSE_DEPOSIT | PURPOSE_STAKE;
+const MAX_ALLOWLIST_ENTRIES: usize = 10;
+const MAX_DEPOSIT_DELEGATES: usize = 5;
+const ALLOWLIST_ADD_DELAY: i64 = 24 * 60 * 60; // New destinations become usable after 24 hours
+const DEPOSIT_CAP_WINDOW: i64 = 60 * 60; // TokenConfig.deposit_cap applies per hour
+const MAX_SPONSORED_SHARDS_AHEAD: u64 = 4; // Sponsored records may run this far past the watermark
+const MAX_DEPOSIT_FEE_BPS: u16 = 500; // Campaign deposit fees are capped at 5%
+const MAX_FILLED_SHARDS: usize = 8; // Fully processed shards tracked for retire_withdrawal_shard
+const MAX_EMISSION_SEGMENTS: usize = 8;
+const LOOKUP_TABLE_MAX_ADDRESSES: usize = 256;
+const CHECKPOINT_INTERVAL: i64 = 24 * 60 * 60; // checkpoint runs once per UTC day per token
+const SIG_VERSION_WITHDRAWAL: u8 = 1; // Withdrawal(id, token, trader, amount)
+const SIG_VERSION_RELAYED: u8 = 2; // RelayedWithdrawal(id, token, trader, amount, tip)
+const SIG_VERSION_RECIPIENT: u8 = 3; // RecipientWithdrawal(id, token, trader, recipient, amount)
+const MAX_SIG_VERSION: u8 = 3;
+
+// Seeds of the program SOL account, selected by State.sol_account_version
+pub const MEMO_PROGRAM_ID: Pubkey =
+    solana_program::pubkey!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+const MAX_DEPOSIT_TAG_LEN: usize = 64;
+
+pub const SOL_ACCOUNT_SEED: &[u8] = b"sol_account";
+pub const SOL_ACCOUNT_SEED_V2: &[u8] = b"sol_account_v2";
+
+// Timelock authority roles, authorities without an entry in timelock_authority_roles hold all
+pub const TIMELOCK_ROLE_PROPOSER: u8 = 1; // May queue and cancel operations
+pub const TIMELOCK_ROLE_EXECUTOR: u8 = 2; // May execute matured operations
+pub const TIMELOCK_ROLES_ALL: u8 = TIMELOCK_ROLE_PROPOSER | TIMELOCK_ROLE_EXECUTOR;
 
 pub const UNLOCKED: u8 = 1;
 pub const LOCKED: u8 = 2;
 
-pub const PROGRAM_VERSION: &str = "1.0.1";
+pub const PROGRAM_VERSION: &str = "1.1.0";
+
+// Machine-readable reasons reported in WithdrawalRejectedEvent
+pub const REJECT_WRONG_AMOUNT: u8 = 1;
+pub const REJECT_PAUSED: u8 = 2;
+pub const REJECT_ALREADY_PROCESSED: u8 = 3;
+pub const REJECT_INVALID_SIGNATURE: u8 = 4;
+pub const REJECT_INVALID_LEDGER: u8 = 5;
+pub const REJECT_INVALID_RECORD: u8 = 6;
+
+// Counters reported in CounterAdvancedEvent
+pub const COUNTER_DEPOSIT_NUM: u8 = 1;
+pub const COUNTER_STAKE_NUM: u8 = 2;
+
+// require! that first logs the instruction and error, plus the token, amount and withdrawal id
+// when given, as compact JSON when built with `verbose-errors`, so failures can be diagnosed
+// from the transaction logs
+macro_rules! require_logged {
+    ($cond:expr, $error:expr, $ix:expr $(,)?) => {
+        if !($cond) {
+            return Err(logged_error!($error, $ix));
+        }
+    };
+    ($cond:expr, $error:expr, $ix:expr, $token:expr, $amount:expr, $id:expr $(,)?) => {
+        if !($cond) {
+            #[cfg(feature = "verbose-errors")]
+            msg!(
+                "{{\"ix\":\"{}\",\"error\":\"{}\",\"token\":\"{}\",\"amount\":{},\"id\":{}}}",
+                $ix,
+                $error.name(),
+                $token,
+                $amount,
+                $id
+            );
+            #[cfg(not(feature = "verbose-errors"))]
+            let _ = ($ix, $token, $amount, $id);
+            return Err(error!($error));
+        }
+    };
+}
+
+// The error require_logged! returns, for failures that are not a plain condition such as
+// .ok_or_else(|| logged_error!(RbxError::CounterOverflow, ix))
+macro_rules! logged_error {
+    ($error:expr, $ix:expr) => {{
+        #[cfg(feature = "verbose-errors")]
+        msg!(
+            "{{\"ix\":\"{}\",\"error\":\"{}\"}}",
+            $ix,
+            $error.name()
+        );
+        #[cfg(not(feature = "verbose-errors"))]
+        let _ = $ix;
+        error!($error)
+    }};
+}
+
+// Like require!, but logs a structured WithdrawalRejectedEvent for monitoring before erroring.
+// Logs of failed transactions are retained, so the event is visible to indexers
+macro_rules! require_withdrawal {
+    ($cond:expr, $id:expr, $trader:expr, $reason:expr, $error:expr) => {
+        if !($cond) {
+            return Err(reject_withdrawal($id, $trader, $reason, $error));
+        }
+    };
+}
 
 #[program]
 pub mod rbx {
@@ -41,25 +169,31 @@ pub mod rbx {
         timelock_delay: i64,
         withdrawal_signer: [u8; 20],
         initial_authorities: Vec<Pubkey>,
+        timelock_in_slots: bool,
+        native_min_deposit: Option<u64>,
     ) -> Result<()> {
+
         let state = &mut ctx.accounts.state;
 
         // Validate initial authorities
-        require!(
+        require_logged!(
             !initial_authorities.is_empty(),
-            RbxError::NoAuthoritiesProvided
+            RbxError::NoAuthoritiesProvided,
+            "initialize"
         );
-        require!(
+        require_logged!(
             initial_authorities.len() <= MAX_AUTHORITIES,
-            RbxError::TooManyAuthorities
+            RbxError::TooManyAuthorities,
+            "initialize"
         );
 
         // Check for duplicates using a simple n^2 approach (since MAX_AUTHORITIES is small)
         for i in 0..initial_authorities.len() {
             for j in i + 1..initial_authorities.len() {
-                require!(
+                require_logged!(
                     initial_authorities[i] != initial_authorities[j],
-                    RbxError::DuplicateAuthority
+                    RbxError::DuplicateAuthority,
+                    "initialize"
                 );
             }
         }
@@ -67,10 +201,20 @@ pub mod rbx {
         state.owner = ctx.accounts.owner.key();
         state.timelock_authorities = initial_authorities;
         state.timelock_delay = timelock_delay;
+        state.timelock_in_slots = timelock_in_slots;
         state.withdrawal_signer = withdrawal_signer;
-        state.next_deposit_num = 1000;
-        state.next_stake_num = 1000;
-        state.reentry_lock_status = UNLOCKED;
+
+        let counters = &mut ctx.accounts.counters;
+        counters.next_deposit_num = 1000;
+        counters.next_stake_num = 1000;
+        counters.reentry_lock_status = UNLOCKED;
+        // Nothing is custodied yet, so the SOL surplus is known from the start
+        state.custody_reconciled = true;
+        state.max_batch_deposits = DEFAULT_MAX_BATCH_SIZE;
+        state.max_batch_withdrawals = DEFAULT_MAX_BATCH_SIZE;
+        state.third_party_deposits_enabled = true;
+        state.emit_legacy_events = true;
+        state.event_mode = EVENT_MODE_LOGS;
 
         // Store the token account authority bump
         state.token_account_bump = ctx.bumps.program_token_authority;
@@ -81,21 +225,91 @@ pub mod rbx {
         // Initialize domain separator cache as None (will be computed on first use)
         state.domain_separator = None;
 
-        // Verify the default token exists
-        require!(
-            ctx.accounts.default_token_mint.key() != Pubkey::default(),
-            RbxError::InvalidToken
+        // A fresh deployment has nothing to migrate
+        state.program_version = parse_version(PROGRAM_VERSION);
+
+        // Verify the default token exists and matches the supplied mint account
+        require_logged!(
+            ctx.accounts.default_token_mint.key() != Pubkey::default()
+                && ctx.accounts.default_token_mint.key() == default_token,
+            RbxError::InvalidToken,
+            "initialize"
         );
 
+        init_token_config(
+            &mut ctx.accounts.default_token_config,
+            &ctx.accounts.default_token_mint,
+            false,
+            "initialize",
+        )?;
+
+        // Create the vault ATA so the first deposits find it; idempotent if someone already did
+        associated_token::create_idempotent(CpiContext::new(
+            ctx.accounts.associated_token_program.to_account_info(),
+            associated_token::Create {
+                payer: ctx.accounts.owner.to_account_info(),
+                associated_token: ctx.accounts.program_token_account.to_account_info(),
+                authority: ctx.accounts.program_token_authority.to_account_info(),
+                mint: ctx.accounts.default_token_mint.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+            },
+        ))?;
+
         // Initialize with default token
         state.supported_tokens.push(default_token);
         state.set_min_deposit(default_token, min_deposit);
 
+        // Optionally support native SOL (the wSOL mint) as part of the deployment
+        require_logged!(
+            native_min_deposit.is_some() == ctx.accounts.native_token_config.is_some(),
+            RbxError::NativeSupportAccountsRequired,
+            "initialize"
+        );
+        if let Some(native_min_deposit) = native_min_deposit {
+            let (Some(native_mint), Some(native_token_config), Some(native_token_account)) = (
+                ctx.accounts.native_mint.as_ref(),
+                ctx.accounts.native_token_config.as_mut(),
+                ctx.accounts.native_program_token_account.as_ref(),
+            ) else {
+                return Err(error!(RbxError::NativeSupportAccountsRequired));
+            };
+            let native_token = native_mint.key();
+            require_logged!(
+                native_token != default_token,
+                RbxError::InvalidToken,
+                "initialize"
+            );
+
+            init_token_config(native_token_config, native_mint, false, "initialize")?;
+
+            associated_token::create_idempotent(CpiContext::new(
+                ctx.accounts.associated_token_program.to_account_info(),
+                associated_token::Create {
+                    payer: ctx.accounts.owner.to_account_info(),
+                    associated_token: native_token_account.to_account_info(),
+                    authority: ctx.accounts.program_token_authority.to_account_info(),
+                    mint: native_mint.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    token_program: ctx.accounts.token_program.to_account_info(),
+                },
+            ))?;
+
+            state.supported_tokens.push(native_token);
+            state.set_min_deposit(native_token, native_min_deposit);
+
+            emit!(SupportTokenEvent {
+                token: native_token,
+                min_deposit: native_min_deposit,
+            });
+        }
+
         emit!(InitializeEvent {
             owner: state.owner,
             signer: state.withdrawal_signer,
             timelock_authorities: state.timelock_authorities.clone(),
             timelock_delay,
+            timelock_in_slots,
             default_token,
             min_deposit,
         });
@@ -103,88 +317,350 @@ pub mod rbx {
         Ok(())
     }
 
-    pub fn deposit_token(ctx: Context<DepositToken>, amount: u64) -> Result<()> {
-        require!(
-            ctx.accounts.state.reentry_lock_status == UNLOCKED,
-            RbxError::ReentrancyDetected
-        );
-
-        ctx.accounts.state.reentry_lock_status = LOCKED;
+    pub fn deposit_token(
+        ctx: Context<DepositToken>,
+        amount: u64,
+        create_receipt: bool,
+    ) -> Result<u64> {
+        // Returned so programs depositing through CPI learn the deposit id
+        let (deposit_num, _) = fund_transfer_in(
+            deposit_token_funding(ctx.accounts),
+            amount,
+            FundingPurpose::Deposit,
+            create_receipt,
+            "deposit_token",
+        )?;
+        Ok(deposit_num)
+    }
 
-        let state = &ctx.accounts.state;
-        let token = ctx.accounts.mint.key();
+    // Deposit or stake through one entrypoint, returning the deposit or stake num. A deposit
+    // receipt is created when its account is passed
+    pub fn deposit_v2(
+        ctx: Context<DepositToken>,
+        amount: u64,
+        purpose: FundingPurpose,
+    ) -> Result<u64> {
+        let create_receipt = ctx.accounts.deposit_receipt.is_some();
+        let (num, _) = fund_transfer_in(
+            deposit_token_funding(ctx.accounts),
+            amount,
+            purpose,
+            create_receipt,
+            "deposit_v2",
+        )?;
+        Ok(num)
+    }
 
-        // Verify token is supported
-        require!(
-            ctx.accounts.state.supported_tokens.contains(&token),
-            RbxError::UnsupportedToken
+    pub fn deposit_token_for(
+        ctx: Context<DepositToken>,
+        amount: u64,
+        for_trader: Pubkey,
+    ) -> Result<()> {
+        check_third_party_deposit(
+            &ctx.accounts.state,
+            &ctx.accounts.user.key(),
+            "deposit_token_for",
+        )?;
+        // The credited trader is only known from the arguments, so derive its blacklist PDA here
+        let (trader_blacklist_key, _) =
+            Pubkey::find_program_address(&[b"blacklist".as_ref(), for_trader.as_ref()], &crate::ID);
+        let trader_blacklist_entry = ctx
+            .accounts
+            .trader_blacklist_entry
+            .as_ref()
+            .filter(|entry| entry.key() == trader_blacklist_key)
+            .ok_or_else(|| logged_error!(RbxError::InvalidBlacklistEntry, "deposit_token_for"))?;
+        enforce_not_blocked(trader_blacklist_entry, "deposit_token_for")?;
+        require_logged!(
+            !requires_deposit_claim(&ctx.accounts.state, amount),
+            RbxError::DepositClaimRequired,
+            "deposit_token_for",
+            ctx.accounts.mint.key(),
+            amount,
+            0
         );
-        let min_deposit = state
-            .get_min_deposit(&token)
-            .ok_or(RbxError::UnsupportedToken)?;
-
-        require!(amount >= min_deposit, RbxError::AmountTooSmall);
 
-        let deposit_num = ctx.accounts.state.next_deposit_num;
-        ctx.accounts.state.next_deposit_num += 1;
+        // The sponsor funds the deposit, the named trader is credited
+        fund_transfer_in(
+            TokenFundingAccounts {
+                trader: for_trader,
+                ..deposit_token_funding(ctx.accounts)
+            },
+            amount,
+            FundingPurpose::Deposit,
+            false,
+            "deposit_token_for",
+        )?;
 
-        // Create deposit ID string with _rbx_sol suffix
-        let mut deposit_id = String::with_capacity(20); // Pre-allocate to avoid reallocation
-        deposit_id.push_str("d_");
-        deposit_id.push_str(&deposit_num.to_string());
-        deposit_id.push_str("_rbx_sol");
+        Ok(())
+    }
 
-        // Transfer tokens from user to program token account
-        let transfer_ctx = CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.user_token_account.to_account_info(),
-                to: ctx.accounts.program_token_account.to_account_info(),
-                authority: ctx.accounts.user.to_account_info(),
-            },
+    // Deposit from a wallet the trader has named as a delegate, credited to the trader
+    pub fn deposit_token_as_delegate(ctx: Context<DepositTokenAsDelegate>, amount: u64) -> Result<()> {
+        require_logged!(
+            ctx.accounts
+                .deposit_delegate
+                .delegates
+                .contains(&ctx.accounts.delegate.key()),
+            RbxError::NotADepositDelegate,
+            "deposit_token_as_delegate",
+            ctx.accounts.mint.key(),
+            amount,
+            0
         );
-        token::transfer(transfer_ctx, amount)?;
 
-        emit!(DepositEvent {
-            id: deposit_id,
-            trader: ctx.accounts.user.key(),
+        let accounts = ctx.accounts;
+        fund_transfer_in(
+            TokenFundingAccounts {
+                state: &accounts.state,
+                counters: &mut accounts.counters,
+                stats: &mut accounts.stats,
+                token_config: &mut accounts.token_config,
+                mint: &accounts.mint,
+                program_token_account: &accounts.program_token_account,
+                funder_token_account: &accounts.delegate_token_account,
+                funder: accounts.delegate.to_account_info(),
+                // Credit the principal trader, not the delegate
+                trader: accounts.trader.key(),
+                blacklist_entry: &accounts.blacklist_entry,
+                deposit_policy: &accounts.deposit_policy,
+                trader_ledger: &mut accounts.trader_ledger,
+                stake_position: &mut None,
+                deposit_receipt: &mut None,
+                fee_destination: &accounts.fee_destination,
+                token_program: accounts.token_program.to_account_info(),
+                profile_binding: &accounts.profile_binding,
+                activity_counter: &mut accounts.activity_counter,
+                event_authority: None,
+            },
             amount,
-            token,
-        });
-
-        ctx.accounts.state.reentry_lock_status = UNLOCKED;
+            FundingPurpose::Deposit,
+            false,
+            "deposit_token_as_delegate",
+        )?;
 
         Ok(())
     }
 
-    pub fn deposit_token_for(
-        ctx: Context<DepositToken>,
+    // Idempotent deposit: the marker PDA can only be created once per user and
+    // client_id, so a retry after an ambiguous timeout fails instead of depositing twice
+    pub fn deposit_token_with_client_id(
+        ctx: Context<DepositTokenWithClientId>,
         amount: u64,
-        for_trader: Pubkey,
+        client_id: [u8; 16],
+    ) -> Result<u64> {
+        let accounts = ctx.accounts;
+        let (deposit_num, amount) = fund_transfer_in(
+            TokenFundingAccounts {
+                state: &accounts.state,
+                counters: &mut accounts.counters,
+                stats: &mut accounts.stats,
+                token_config: &mut accounts.token_config,
+                mint: &accounts.mint,
+                program_token_account: &accounts.program_token_account,
+                funder_token_account: &accounts.user_token_account,
+                funder: accounts.user.to_account_info(),
+                trader: accounts.user.key(),
+                blacklist_entry: &accounts.blacklist_entry,
+                deposit_policy: &accounts.deposit_policy,
+                trader_ledger: &mut accounts.trader_ledger,
+                stake_position: &mut None,
+                deposit_receipt: &mut None,
+                fee_destination: &accounts.fee_destination,
+                token_program: accounts.token_program.to_account_info(),
+                profile_binding: &accounts.profile_binding,
+                activity_counter: &mut accounts.activity_counter,
+                event_authority: None,
+            },
+            amount,
+            FundingPurpose::Deposit,
+            false,
+            "deposit_token_with_client_id",
+        )?;
+
+        // The client reads the marker to learn which deposit its first attempt produced
+        let marker = &mut accounts.client_deposit_marker;
+        marker.client_id = client_id;
+        marker.trader = accounts.user.key();
+        marker.token = accounts.mint.key();
+        marker.amount = amount;
+        marker.deposit_num = deposit_num;
+
+        Ok(deposit_num)
+    }
+
+    // Deposit several tokens at once. remaining_accounts holds a (mint, token config, user token
+    // account, vault, trader ledger, fee destination) group for every entry of `amounts`, each
+    // crediting the ledger and emitting its own deposit event. Pass the program id for an
+    // omitted ledger or fee destination.
+    pub fn deposit_multi<'info>(
+        ctx: Context<'_, '_, 'info, 'info, DepositMulti<'info>>,
+        amounts: Vec<u64>,
     ) -> Result<()> {
-        require!(
-            ctx.accounts.state.reentry_lock_status == UNLOCKED,
-            RbxError::ReentrancyDetected
+        enforce_not_blocked(&ctx.accounts.blacklist_entry, "deposit_multi")?;
+        check_batch_size(amounts.len(), ctx.accounts.state.max_batch_deposits, "deposit_multi")?;
+        require_logged!(
+            ctx.remaining_accounts.len() == amounts.len() * 6,
+            RbxError::InvalidDepositLeg,
+            "deposit_multi"
         );
 
-        ctx.accounts.state.reentry_lock_status = LOCKED;
+        let accounts = ctx.accounts;
+        let authority = accounts.program_token_authority.key();
+
+        for (leg, &amount) in ctx.remaining_accounts.chunks(6).zip(amounts.iter()) {
+            let token = leg[0].key();
+            let mut token_config = Account::<TokenConfig>::try_from(&leg[1])?;
+            require_logged!(
+                token_config.mint == token
+                    && leg[3].key() == vault_address(&token_config, &authority),
+                RbxError::InvalidDepositLeg,
+                "deposit_multi",
+                token,
+                amount,
+                0
+            );
+            require_logged!(
+                token_config.status == TokenStatus::Active,
+                RbxError::TokenNotActive,
+                "deposit_multi",
+                token,
+                amount,
+                0
+            );
+            require_logged!(
+                !token_config.paused_deposits,
+                RbxError::TokenDepositsPaused,
+                "deposit_multi",
+                token,
+                amount,
+                0
+            );
+
+            // Each leg credits the trader's ledger for its own token
+            let mut trader_ledger = optional_trader_ledger(&leg[4])?;
+            let fee_destination = optional_token_account(&leg[5])?;
+            fund_transfer_in(
+                TokenFundingAccounts {
+                    state: &accounts.state,
+                    counters: &mut accounts.counters,
+                    stats: &mut accounts.stats,
+                    token_config: &mut token_config,
+                    mint: &leg[0],
+                    program_token_account: &leg[3],
+                    funder_token_account: &leg[2],
+                    funder: accounts.user.to_account_info(),
+                    trader: accounts.user.key(),
+                    blacklist_entry: &accounts.blacklist_entry,
+                    deposit_policy: &accounts.deposit_policy,
+                    trader_ledger: &mut trader_ledger,
+                    stake_position: &mut None,
+                    deposit_receipt: &mut None,
+                    fee_destination: &fee_destination,
+                    token_program: accounts.token_program.to_account_info(),
+                    profile_binding: &accounts.profile_binding,
+                    activity_counter: &mut accounts.activity_counter,
+                    event_authority: None,
+                },
+                amount,
+                FundingPurpose::Deposit,
+                false,
+                "deposit_multi",
+            )?;
+            if let Some(ledger) = trader_ledger {
+                ledger.exit(&crate::ID)?;
+            }
+            token_config.exit(&crate::ID)?;
+        }
+
+        Ok(())
+    }
+
+    // Move bridged tokens from the inbox into the vault, crediting the trader named in the receipt
+    pub fn deposit_bridged(ctx: Context<DepositBridged>) -> Result<u64> {
+        require_logged!(
+            ctx.accounts.counters.reentry_lock_status == UNLOCKED,
+            RbxError::ReentrancyDetected,
+            "deposit_bridged",
+            ctx.accounts.mint.key(),
+            0,
+            0
+        );
 
         let state = &ctx.accounts.state;
         let token = ctx.accounts.mint.key();
+        require_logged!(
+            state.bridge_program != Pubkey::default()
+                && ctx.accounts.bridge_receipt.owner == &state.bridge_program,
+            RbxError::InvalidBridgeReceipt,
+            "deposit_bridged",
+            ctx.accounts.mint.key(),
+            0,
+            0
+        );
+        require_logged!(
+            state.supported_tokens.contains(&token),
+            RbxError::UnsupportedToken,
+            "deposit_bridged",
+            ctx.accounts.mint.key(),
+            0,
+            0
+        );
 
-        // Verify token is supported
-        require!(
-            ctx.accounts.state.supported_tokens.contains(&token),
-            RbxError::UnsupportedToken
+        check_funding_purpose(
+            &ctx.accounts.token_config,
+            FundingPurpose::Deposit,
+            "deposit_bridged",
+        )?;
+
+        let payload = BridgedDepositPayload::from_receipt(&ctx.accounts.bridge_receipt)?;
+        require_logged!(
+            payload.emitter_chain == state.bridge_emitter_chain
+                && payload.emitter_address == state.bridge_emitter,
+            RbxError::BridgeEmitterMismatch,
+            "deposit_bridged",
+            token,
+            0,
+            0
         );
-        let min_deposit = state
-            .get_min_deposit(&token)
-            .ok_or(RbxError::UnsupportedToken)?;
+        // The token bridge only redeems into an account of the transferred mint, so naming the
+        // inbox binds wrapped mints too. Native mints must also match the transfer's token
+        require_logged!(
+            payload.to_chain == WORMHOLE_CHAIN_SOLANA
+                && payload.to == ctx.accounts.bridge_inbox.key()
+                && (payload.token_chain != WORMHOLE_CHAIN_SOLANA
+                    || payload.token_address == token.to_bytes()),
+            RbxError::BridgeDestinationMismatch,
+            "deposit_bridged",
+            token,
+            0,
+            0
+        );
+        let trader = payload.trader;
+        let amount = payload.denormalized_amount(mint_decimals(&ctx.accounts.mint)?)?;
+        require_logged!(
+            amount > 0,
+            RbxError::WrongAmount,
+            "deposit_bridged",
+            ctx.accounts.mint.key(),
+            0,
+            0
+        );
+
+        // The destination trader is only known from the payload, so derive its blacklist PDA here
+        let (blacklist_key, _) =
+            Pubkey::find_program_address(&[b"blacklist".as_ref(), trader.as_ref()], &crate::ID);
+        require_keys_eq!(
+            ctx.accounts.blacklist_entry.key(),
+            blacklist_key,
+            RbxError::InvalidBridgeReceipt
+        );
+        enforce_not_blocked(&ctx.accounts.blacklist_entry, "deposit_bridged")?;
+        record_deposit_inflow(&mut ctx.accounts.token_config, amount, "deposit_bridged")?;
 
-        require!(amount >= min_deposit, RbxError::AmountTooSmall);
+        ctx.accounts.counters.reentry_lock_status = LOCKED;
 
-        let deposit_num = ctx.accounts.state.next_deposit_num;
-        ctx.accounts.state.next_deposit_num += 1;
+        let deposit_num = take_deposit_num(&mut ctx.accounts.counters, "deposit_bridged")?;
 
         // Create deposit ID string with _rbx_sol suffix
         let mut deposit_id = String::with_capacity(20); // Pre-allocate to avoid reallocation
@@ -192,52 +668,225 @@ pub mod rbx {
         deposit_id.push_str(&deposit_num.to_string());
         deposit_id.push_str("_rbx_sol");
 
-        // Transfer tokens from user to program token account
-        let transfer_ctx = CpiContext::new(
+        // Transfer tokens from the bridge inbox to program token account
+        let seeds = &[
+            b"bridge_authority".as_ref(),
+            &[ctx.bumps.bridge_authority],
+        ];
+        let signer = &[&seeds[..]];
+
+        ensure_vault_not_frozen(&ctx.accounts.program_token_account.to_account_info())?;
+        let transfer_ctx = CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
             Transfer {
-                from: ctx.accounts.user_token_account.to_account_info(),
+                from: ctx.accounts.bridge_inbox.to_account_info(),
                 to: ctx.accounts.program_token_account.to_account_info(),
-                authority: ctx.accounts.user.to_account_info(),
+                authority: ctx.accounts.bridge_authority.to_account_info(),
             },
+            signer,
         );
         token::transfer(transfer_ctx, amount)?;
 
-        emit!(DepositEvent {
-            id: deposit_id,
-            trader: for_trader, // Use the provided for_trader parameter instead of the sender
+        credit_trader_ledger(
+            &mut ctx.accounts.trader_ledger,
+            trader,
+            token,
             amount,
+            "deposit_bridged",
+        )?;
+
+        ctx.accounts.token_config.add_liability(amount);
+
+        ctx.accounts.stats.record_deposit(token, amount);
+
+        // The marker's existence rejects a second deposit from the same receipt
+        let marker = &mut ctx.accounts.bridged_deposit_marker;
+        marker.receipt = ctx.accounts.bridge_receipt.key();
+        marker.emitter_chain = payload.emitter_chain;
+        marker.sequence = payload.sequence;
+        marker.trader = trader;
+        marker.token = token;
+        marker.amount = amount;
+        marker.deposit_num = deposit_num;
+
+        let clock = Clock::get()?;
+        emit_deposit_event(
+            &ctx.accounts.state,
+            &ctx.accounts.profile_binding,
+            &None,
+            DepositEvent {
+                id: deposit_id,
+                trader,
+                amount,
+                amount_e18: to_e18(amount, mint_decimals(&ctx.accounts.mint)?),
+                token,
+                slot: clock.slot,
+                unix_timestamp: clock.unix_timestamp,
+            },
+            "deposit_bridged",
+        )?;
+
+        emit!(BridgedDepositEvent {
+            receipt: ctx.accounts.bridge_receipt.key(),
+            trader,
             token,
+            amount,
+            deposit_num,
+        });
+
+        ctx.accounts.counters.reentry_lock_status = UNLOCKED;
+
+        Ok(deposit_num)
+    }
+
+    // Name the wallets allowed to deposit on the trader's behalf
+    pub fn open_deposit_delegate(ctx: Context<OpenDepositDelegate>) -> Result<()> {
+        let deposit_delegate = &mut ctx.accounts.deposit_delegate;
+        deposit_delegate.trader = ctx.accounts.trader.key();
+        deposit_delegate.delegates = Vec::new();
+
+        Ok(())
+    }
+
+    pub fn add_deposit_delegate(ctx: Context<ManageDepositDelegate>, delegate: Pubkey) -> Result<()> {
+        let deposit_delegate = &mut ctx.accounts.deposit_delegate;
+        require_logged!(
+            !deposit_delegate.delegates.contains(&delegate),
+            RbxError::DuplicateDepositDelegate,
+            "add_deposit_delegate"
+        );
+        require_logged!(
+            deposit_delegate.delegates.len() < MAX_DEPOSIT_DELEGATES,
+            RbxError::TooManyDepositDelegates,
+            "add_deposit_delegate"
+        );
+        deposit_delegate.delegates.push(delegate);
+
+        emit!(DepositDelegateEvent {
+            trader: deposit_delegate.trader,
+            delegate,
+            added: true,
         });
 
-        ctx.accounts.state.reentry_lock_status = UNLOCKED;
+        Ok(())
+    }
+
+    pub fn remove_deposit_delegate(
+        ctx: Context<ManageDepositDelegate>,
+        delegate: Pubkey,
+    ) -> Result<()> {
+        let deposit_delegate = &mut ctx.accounts.deposit_delegate;
+        let position = deposit_delegate
+            .delegates
+            .iter()
+            .position(|d| d == &delegate)
+            .ok_or_else(|| {
+                logged_error!(RbxError::NotADepositDelegate, "remove_deposit_delegate")
+            })?;
+        deposit_delegate.delegates.remove(position);
+
+        emit!(DepositDelegateEvent {
+            trader: deposit_delegate.trader,
+            delegate,
+            added: false,
+        });
 
         Ok(())
     }
 
     pub fn support_token(ctx: Context<SupportToken>, min_deposit: u64) -> Result<()> {
-        require!(
+        require_logged!(
             ctx.accounts
                 .state
                 .timelock_authorities
                 .contains(&ctx.accounts.authority.key()),
-            RbxError::UnauthorizedAccess
+            RbxError::UnauthorizedAccess,
+            "support_token",
+            ctx.accounts.token_mint.key(),
+            0,
+            0
         );
 
         let state = &mut ctx.accounts.state;
         let token = ctx.accounts.token_mint.key();
 
         // Verify the token exists
-        require!(
+        require_logged!(
             ctx.accounts.token_mint.key() != Pubkey::default(),
-            RbxError::InvalidToken
+            RbxError::InvalidToken,
+            "support_token",
+            ctx.accounts.token_mint.key(),
+            0,
+            0
+        );
+
+        require_logged!(
+            state.supported_tokens.len() < MAX_SUPPORTED_TOKENS,
+            RbxError::TooManyTokens,
+            "support_token",
+            ctx.accounts.token_mint.key(),
+            0,
+            0
         );
 
-        require!(
+        init_token_config(
+            &mut ctx.accounts.token_config,
+            &ctx.accounts.token_mint,
+            state.reject_freezable_mints,
+            "support_token",
+        )?;
+
+        // Create the vault ATA so the first deposits find it; idempotent if someone already did
+        associated_token::create_idempotent(CpiContext::new(
+            ctx.accounts.associated_token_program.to_account_info(),
+            associated_token::Create {
+                payer: ctx.accounts.authority.to_account_info(),
+                associated_token: ctx.accounts.program_token_account.to_account_info(),
+                authority: ctx.accounts.program_token_authority.to_account_info(),
+                mint: ctx.accounts.token_mint.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+            },
+        ))?;
+
+        state.supported_tokens.push(token);
+        state.set_min_deposit(token, min_deposit);
+
+        emit!(SupportTokenEvent { token, min_deposit });
+        Ok(())
+    }
+
+    // A retired token keeps its config, so supporting it again reactivates it with its
+    // history, liabilities and vault intact
+    pub fn reactivate_token(ctx: Context<ReactivateToken>, min_deposit: u64) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+        let token = ctx.accounts.token_config.mint;
+        require_logged!(
+            state.timelock_authorities.contains(&ctx.accounts.authority.key()),
+            RbxError::UnauthorizedAccess,
+            "reactivate_token",
+            token,
+            0,
+            0
+        );
+        require_logged!(
+            ctx.accounts.token_config.status == TokenStatus::Retired,
+            RbxError::TokenAlreadySupported,
+            "reactivate_token",
+            token,
+            0,
+            0
+        );
+        require_logged!(
             state.supported_tokens.len() < MAX_SUPPORTED_TOKENS,
-            RbxError::TooManyTokens
+            RbxError::TooManyTokens,
+            "reactivate_token",
+            token,
+            0,
+            0
         );
 
+        ctx.accounts.token_config.status = TokenStatus::Active;
         state.supported_tokens.push(token);
         state.set_min_deposit(token, min_deposit);
 
@@ -246,27 +895,32 @@ pub mod rbx {
     }
 
     pub fn unsupport_token(ctx: Context<UnsupportToken>, token: Pubkey) -> Result<()> {
-        require!(
+        require_logged!(
             ctx.accounts
                 .state
                 .timelock_authorities
                 .contains(&ctx.accounts.authority.key()),
-            RbxError::UnauthorizedAccess
+            RbxError::UnauthorizedAccess,
+            "unsupport_token",
+            token,
+            0,
+            0
         );
 
-        let state = &mut ctx.accounts.state;
-
-        // Find the token in the supported tokens list
-        let position = state
-            .supported_tokens
-            .iter()
-            .position(|&t| t == token)
-            .ok_or(RbxError::UnsupportedToken)?;
-
-        // Remove the token from the supported tokens list
-        state.supported_tokens.remove(position);
+        require_logged!(
+            ctx.accounts.state.supported_tokens.contains(&token),
+            RbxError::UnsupportedToken,
+            "unsupport_token",
+            token,
+            0,
+            0
+        );
 
-        // Remove the token from the min deposits list
+        // Retire rather than close the config so the token keeps its history and traders can
+        // still withdraw. Its slot is released, and support_token reactivates it
+        ctx.accounts.token_config.status = TokenStatus::Retired;
+        let state = &mut ctx.accounts.state;
+        state.supported_tokens.retain(|t| t != &token);
         state.remove_min_deposit(&token);
 
         emit!(UnsupportTokenEvent { token });
@@ -274,528 +928,975 @@ pub mod rbx {
         Ok(())
     }
 
-    // Native SOL deposit
-    pub fn deposit_native(ctx: Context<DepositNative>, amount: u64) -> Result<()> {
-        require!(
-            ctx.accounts.state.reentry_lock_status == UNLOCKED,
-            RbxError::ReentrancyDetected
+    // Move a supported token between Active and Paused. Retiring goes through
+    // unsupport_token, which also releases the token's slot
+    pub fn set_token_status(ctx: Context<SetTokenStatus>, status: TokenStatus) -> Result<()> {
+        require_logged!(
+            ctx.accounts
+                .state
+                .timelock_authorities
+                .contains(&ctx.accounts.authority.key()),
+            RbxError::UnauthorizedAccess,
+            "set_token_status"
         );
 
-        ctx.accounts.state.reentry_lock_status = LOCKED;
-
-        // Verify amount meets minimum
-        let state = &ctx.accounts.state;
-        let wrapped_sol = ctx.accounts.wrapped_sol_mint.key();
-
-        let min_deposit = state
-            .get_min_deposit(&wrapped_sol)
-            .ok_or(RbxError::UnsupportedToken)?;
-
-        require!(amount >= min_deposit, RbxError::AmountTooSmall);
-        require!(
-            amount <= ctx.accounts.user.lamports(),
-            RbxError::InsufficientFunds
+        let token_config = &mut ctx.accounts.token_config;
+        require_logged!(
+            ctx.accounts.state.supported_tokens.contains(&token_config.mint),
+            RbxError::UnsupportedToken,
+            "set_token_status"
         );
+        require_logged!(
+            status != TokenStatus::Retired,
+            RbxError::RetireThroughUnsupport,
+            "set_token_status"
+        );
+        token_config.status = status;
 
-        let deposit_num = ctx.accounts.state.next_deposit_num;
-        ctx.accounts.state.next_deposit_num += 1;
+        emit!(SetTokenStatusEvent {
+            token: token_config.mint,
+            status,
+        });
 
-        // Create deposit ID string with _rbx_sol suffix
-        let mut deposit_id = String::with_capacity(20); // Pre-allocate to avoid reallocation
-        deposit_id.push_str("d_");
-        deposit_id.push_str(&deposit_num.to_string());
-        deposit_id.push_str("_rbx_sol");
+        Ok(())
+    }
 
-        // Transfer SOL from user to program
-        let ix = solana_program::system_instruction::transfer(
-            &ctx.accounts.user.key(),
-            &ctx.accounts.program_sol_account.key(),
-            amount,
+    // Fast path for risk: limit a token's deposit inflow per hour without the timelock, 0 lifts the cap
+    pub fn set_deposit_cap(ctx: Context<SetTokenStatus>, deposit_cap: u64) -> Result<()> {
+        require_logged!(
+            ctx.accounts
+                .state
+                .timelock_authorities
+                .contains(&ctx.accounts.authority.key()),
+            RbxError::UnauthorizedAccess,
+            "set_deposit_cap"
         );
 
-        solana_program::program::invoke(
-            &ix,
-            &[
-                ctx.accounts.user.to_account_info(),
-                ctx.accounts.program_sol_account.to_account_info(),
-                ctx.accounts.system_program.to_account_info(),
-            ],
-        )?;
+        let token_config = &mut ctx.accounts.token_config;
+        token_config.deposit_cap = deposit_cap;
 
-        emit!(DepositEvent {
-            id: deposit_id,
-            trader: ctx.accounts.user.key(),
-            amount,
-            token: wrapped_sol,
+        emit!(SetDepositCapEvent {
+            token: token_config.mint,
+            deposit_cap,
         });
 
-        ctx.accounts.state.reentry_lock_status = UNLOCKED;
-
         Ok(())
     }
 
-    // Native SOL deposit on behalf of another trader
-    pub fn deposit_native_for(
-        ctx: Context<DepositNative>,
-        amount: u64,
-        for_trader: Pubkey,
-    ) -> Result<()> {
-        require!(
-            ctx.accounts.state.reentry_lock_status == UNLOCKED,
-            RbxError::ReentrancyDetected
+    // Require deposits of a token to be multiples of dust_modulus, so amounts the backend
+    // cannot represent are rejected on-chain instead of stranded. 0 disables the check
+    pub fn set_dust_modulus(ctx: Context<SetTokenStatus>, dust_modulus: u64) -> Result<()> {
+        require_logged!(
+            ctx.accounts
+                .state
+                .timelock_authorities
+                .contains(&ctx.accounts.authority.key()),
+            RbxError::UnauthorizedAccess,
+            "set_dust_modulus"
         );
 
-        ctx.accounts.state.reentry_lock_status = LOCKED;
+        let token_config = &mut ctx.accounts.token_config;
+        token_config.dust_modulus = dust_modulus;
 
-        // Verify amount meets minimum
-        let state = &ctx.accounts.state;
-        let wrapped_sol = ctx.accounts.wrapped_sol_mint.key();
+        emit!(SetDustModulusEvent {
+            token: token_config.mint,
+            dust_modulus,
+        });
 
-        let min_deposit = state
-            .get_min_deposit(&wrapped_sol)
-            .ok_or(RbxError::UnsupportedToken)?;
+        Ok(())
+    }
 
-        require!(amount >= min_deposit, RbxError::AmountTooSmall);
-        require!(
-            amount <= ctx.accounts.user.lamports(),
-            RbxError::InsufficientFunds
+    // Apply the campaign fee approved through the timelock for the token. The fee is taken from
+    // every token deposit and paid to a rewards pool token account; 0 bps turns it off
+    pub fn set_deposit_fee(
+        ctx: Context<SetDepositFee>,
+        deposit_fee_bps: u16,
+        fee_destination: Pubkey,
+    ) -> Result<()> {
+        require_logged!(
+            ctx.accounts
+                .state
+                .timelock_authorities
+                .contains(&ctx.accounts.authority.key()),
+            RbxError::UnauthorizedAccess,
+            "set_deposit_fee"
         );
 
-        let deposit_num = ctx.accounts.state.next_deposit_num;
-        ctx.accounts.state.next_deposit_num += 1;
+        let token = ctx.accounts.token_config.mint;
+        let state = &mut ctx.accounts.state;
+        let position = state
+            .approved_deposit_fees
+            .iter()
+            .position(|approval| *approval == (token, deposit_fee_bps, fee_destination))
+            .ok_or_else(|| logged_error!(RbxError::DepositFeeNotApproved, "set_deposit_fee"))?;
+        // Approvals are single use
+        state.approved_deposit_fees.remove(position);
+
+        let token_config = &mut ctx.accounts.token_config;
+        token_config.deposit_fee_bps = deposit_fee_bps;
+        token_config.fee_destination = fee_destination;
+
+        emit!(SetDepositFeeEvent {
+            token: token_config.mint,
+            deposit_fee_bps,
+            fee_destination,
+        });
 
-        // Create deposit ID string with _rbx_sol suffix
-        let mut deposit_id = String::with_capacity(20); // Pre-allocate to avoid reallocation
-        deposit_id.push_str("d_");
-        deposit_id.push_str(&deposit_num.to_string());
-        deposit_id.push_str("_rbx_sol");
+        Ok(())
+    }
 
-        // Transfer SOL from user to program
-        let ix = solana_program::system_instruction::transfer(
-            &ctx.accounts.user.key(),
-            &ctx.accounts.program_sol_account.key(),
-            amount,
+    // Fast path for a compromised outgoing signer: stop accepting it before the rotation overlap
+    // closes, without waiting for the timelock
+    pub fn revoke_previous_signer(ctx: Context<RevokePreviousSigner>) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+        require_logged!(
+            state
+                .timelock_authorities
+                .contains(&ctx.accounts.authority.key()),
+            RbxError::UnauthorizedAccess,
+            "revoke_previous_signer"
+        );
+        require_logged!(
+            state.previous_signer != [0u8; 20],
+            RbxError::InvalidSigner,
+            "revoke_previous_signer"
         );
 
-        solana_program::program::invoke(
-            &ix,
-            &[
-                ctx.accounts.user.to_account_info(),
-                ctx.accounts.program_sol_account.to_account_info(),
-                ctx.accounts.system_program.to_account_info(),
-            ],
-        )?;
+        let previous_signer = state.previous_signer;
+        state.previous_signer = [0u8; 20];
+        state.rotation_deadline = 0;
 
-        emit!(DepositEvent {
-            id: deposit_id,
-            trader: for_trader, // Use the provided for_trader parameter instead of the sender
-            amount,
-            token: wrapped_sol,
+        emit!(PreviousSignerRevokedEvent {
+            previous_signer,
+            authority: ctx.accounts.authority.key(),
         });
 
-        ctx.accounts.state.reentry_lock_status = UNLOCKED;
-
         Ok(())
     }
 
-    pub fn withdraw_token(
-        ctx: Context<WithdrawToken>,
-        id: u64,
-        amount: u64,
-        v: u8,
-        r: [u8; 32],
-        s: [u8; 32],
+    // Fast path for risk: halt deposits and/or withdrawals of one token without the timelock.
+    // Clearing the flags needs a timelock approval, see `unpause_token`
+    pub fn pause_token(
+        ctx: Context<SetTokenPause>,
+        pause_deposits: bool,
+        pause_withdrawals: bool,
     ) -> Result<()> {
-        require!(
-            ctx.accounts.state.reentry_lock_status == UNLOCKED,
-            RbxError::ReentrancyDetected
+        require_logged!(
+            ctx.accounts
+                .state
+                .timelock_authorities
+                .contains(&ctx.accounts.authority.key()),
+            RbxError::UnauthorizedAccess,
+            "pause_token"
         );
-        ctx.accounts.state.reentry_lock_status = LOCKED;
-
-        // Process common withdrawal logic
-        process_withdrawal(
-            &ctx.program_id,
-            &mut ctx.accounts.state,
-            &mut ctx.accounts.withdrawal_record,
-            id,
-            amount,
-            ctx.accounts.mint.key(),
-            ctx.accounts.trader.key(),
-            v,
-            r,
-            s,
-        )?;
-
-        // Transfer tokens from program to user
-        let seeds = &[
-            b"token_authority".as_ref(),
-            &[ctx.accounts.state.token_account_bump],
-        ];
-        let signer = &[&seeds[..]];
-
-        let transfer_ctx = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.program_token_account.to_account_info(),
-                to: ctx.accounts.trader_token_account.to_account_info(),
-                authority: ctx.accounts.program_token_authority.to_account_info(),
-            },
-            signer,
+        require_logged!(
+            pause_deposits || pause_withdrawals,
+            RbxError::InvalidOperationData,
+            "pause_token"
         );
-        token::transfer(transfer_ctx, amount)?;
 
-        // Unlock reentrancy lock
-        ctx.accounts.state.reentry_lock_status = UNLOCKED;
+        let token_config = &mut ctx.accounts.token_config;
+        token_config.paused_deposits |= pause_deposits;
+        token_config.paused_withdrawals |= pause_withdrawals;
 
-        emit!(WithdrawalEvent {
-            id,
-            trader: ctx.accounts.trader.key(),
-            amount,
-            token: ctx.accounts.mint.key(),
+        emit!(TokenPauseEvent {
+            token: token_config.mint,
+            paused_deposits: token_config.paused_deposits,
+            paused_withdrawals: token_config.paused_withdrawals,
         });
 
         Ok(())
     }
 
-    pub fn withdraw_native(
-        ctx: Context<WithdrawNative>,
-        id: u64,
-        amount: u64,
-        v: u8,
-        r: [u8; 32],
-        s: [u8; 32],
-    ) -> Result<()> {
-        require!(
-            ctx.accounts.state.reentry_lock_status == UNLOCKED,
-            RbxError::ReentrancyDetected
-        );
-        ctx.accounts.state.reentry_lock_status = LOCKED;
-
-        // Process common withdrawal logic
-        process_withdrawal(
-            &ctx.program_id,
-            &mut ctx.accounts.state,
-            &mut ctx.accounts.withdrawal_record,
-            id,
-            amount,
-            ctx.accounts.wrapped_sol_mint.key(),
-            ctx.accounts.trader.key(),
-            v,
-            r,
-            s,
-        )?;
-
-        // Transfer SOL from program to user
-        let seeds = &[
-            b"sol_account".as_ref(),
-            &[ctx.accounts.state.sol_account_bump],
-        ];
-        let signer = &[&seeds[..]];
-
-        let transfer_ix = solana_program::system_instruction::transfer(
-            &ctx.accounts.program_sol_account.key(),
-            &ctx.accounts.trader.key(),
-            amount,
+    // Clear both pause flags of a token once timelock operation 24 has approved it
+    pub fn unpause_token(ctx: Context<SetTokenPause>) -> Result<()> {
+        require_logged!(
+            ctx.accounts
+                .state
+                .timelock_authorities
+                .contains(&ctx.accounts.authority.key()),
+            RbxError::UnauthorizedAccess,
+            "unpause_token"
         );
 
-        solana_program::program::invoke_signed(
-            &transfer_ix,
-            &[
-                ctx.accounts.program_sol_account.to_account_info(),
-                ctx.accounts.trader.to_account_info(),
-                ctx.accounts.system_program.to_account_info(),
-            ],
-            signer,
-        )?;
+        let token = ctx.accounts.token_config.mint;
+        let state = &mut ctx.accounts.state;
+        let position = state
+            .approved_token_unpauses
+            .iter()
+            .position(|t| t == &token)
+            .ok_or_else(|| logged_error!(RbxError::TokenUnpauseNotApproved, "unpause_token"))?;
+        // Approvals are single use
+        state.approved_token_unpauses.remove(position);
 
-        ctx.accounts.state.reentry_lock_status = UNLOCKED;
+        let token_config = &mut ctx.accounts.token_config;
+        token_config.paused_deposits = false;
+        token_config.paused_withdrawals = false;
 
-        emit!(WithdrawalEvent {
-            id,
-            trader: ctx.accounts.trader.key(),
-            amount,
-            token: ctx.accounts.wrapped_sol_mint.key(),
+        emit!(TokenPauseEvent {
+            token,
+            paused_deposits: false,
+            paused_withdrawals: false,
         });
 
         Ok(())
     }
 
-    pub fn get_version(_ctx: Context<GetVersion>) -> Result<String> {
-        Ok(PROGRAM_VERSION.to_string())
-    }
-
-    pub fn get_eip712_verifying_contract(
-        ctx: Context<GetEip712VerifyingContract>,
-    ) -> Result<String> {
-        // Get the full 32-byte state PDA pubkey
-        let pubkey = ctx.accounts.state.key();
-
-        // Convert the pubkey to a 32-byte hex string
-        let bytes = pubkey.to_bytes();
-        let hex_string = format!("0x{}", hex::encode(bytes));
-
-        // Return the hex string representation (with 0x prefix)
-        Ok(hex_string)
-    }
-
-    // Queue a timelock operation
-    pub fn queue_operation(
-        ctx: Context<QueueOperation>,
-        operation_type: u8,
-        data: Vec<u8>,
-    ) -> Result<()> {
-        let state = &mut ctx.accounts.state;
-
-        // Only timelock authority can queue operations
-        require!(
-            state
+    // Raise the deposit and stake counters to the minimums approved through the timelock,
+    // e.g. past ids the backend already ingested before a state restore. Never moves them back
+    pub fn advance_counters(ctx: Context<AdvanceCounters>) -> Result<()> {
+        require_logged!(
+            ctx.accounts
+                .state
                 .timelock_authorities
                 .contains(&ctx.accounts.authority.key()),
-            RbxError::UnauthorizedAccess
+            RbxError::UnauthorizedAccess,
+            "advance_counters"
         );
 
-        // Validate operation type
-        require!(
-            operation_type >= 1 && operation_type <= 5,
-            RbxError::InvalidOperationType
+        let state = &mut ctx.accounts.state;
+        require_logged!(
+            state.approved_min_deposit_num > 0 || state.approved_min_stake_num > 0,
+            RbxError::CounterAdvanceNotApproved,
+            "advance_counters"
         );
+        // Approvals are single use
+        let min_deposit_num = std::mem::take(&mut state.approved_min_deposit_num);
+        let min_stake_num = std::mem::take(&mut state.approved_min_stake_num);
+
+        let counters = &mut ctx.accounts.counters;
+        if min_deposit_num > counters.next_deposit_num {
+            counters.next_deposit_num = min_deposit_num;
+            emit!(CounterAdvancedEvent {
+                kind: COUNTER_DEPOSIT_NUM,
+                new_value: min_deposit_num,
+            });
+        }
+        if min_stake_num > counters.next_stake_num {
+            counters.next_stake_num = min_stake_num;
+            emit!(CounterAdvancedEvent {
+                kind: COUNTER_STAKE_NUM,
+                new_value: min_stake_num,
+            });
+        }
 
-        let clock = Clock::get()?;
-        let current_time = clock.unix_timestamp;
-        let execute_time = current_time + state.timelock_delay;
-
-        let operation = TimelockOperation {
-            operation_type,
-            data,
-            queued_at: current_time,
-            can_execute_at: execute_time,
-        };
-
-        state.pending_operations.push(operation);
-
-        emit!(QueueOperationEvent {
-            operation_type,
-            execute_time,
+        emit!(CountersAdvancedEvent {
+            next_deposit_num: counters.next_deposit_num,
+            next_stake_num: counters.next_stake_num,
         });
 
         Ok(())
     }
 
-    pub fn execute_operation(ctx: Context<ExecuteOperation>, operation_index: u8) -> Result<()> {
-        let state = &mut ctx.accounts.state;
-
-        // Only timelock authority can execute operations
-        require!(
-            state
+    // Register an existing token account of the token authority as the mint's vault, e.g. when
+    // funds were sent to a manually created account instead of the canonical ATA. The token and
+    // vault must have been approved through the timelock
+    pub fn adopt_vault_account(ctx: Context<AdoptVaultAccount>) -> Result<()> {
+        require_logged!(
+            ctx.accounts
+                .state
                 .timelock_authorities
                 .contains(&ctx.accounts.authority.key()),
-            RbxError::UnauthorizedAccess
+            RbxError::UnauthorizedAccess,
+            "adopt_vault_account"
         );
 
-        // Check if operation index is valid
-        require!(
-            (operation_index as usize) < state.pending_operations.len(),
-            RbxError::InvalidOperationIndex
+        // Only the token authority may move the funds, now and later
+        let vault = &ctx.accounts.vault_account;
+        let token = ctx.accounts.token_config.mint;
+        require_logged!(
+            vault.mint == token
+                && vault.owner == ctx.accounts.program_token_authority.key()
+                && vault.delegate.is_none()
+                && vault.close_authority.is_none()
+                && !vault.is_frozen(),
+            RbxError::InvalidVaultAccount,
+            "adopt_vault_account"
         );
 
-        // Clone the operation to avoid borrow issues
-        let operation = state.pending_operations[operation_index as usize].clone();
+        let state = &mut ctx.accounts.state;
+        let position = state
+            .approved_vault_adoptions
+            .iter()
+            .position(|approval| *approval == (token, vault.key()))
+            .ok_or_else(|| {
+                logged_error!(RbxError::VaultAdoptionNotApproved, "adopt_vault_account")
+            })?;
+        // Approvals are single use
+        state.approved_vault_adoptions.remove(position);
+
+        let token_config = &mut ctx.accounts.token_config;
+        let previous = vault_address(token_config, &ctx.accounts.program_token_authority.key());
+        token_config.vault = vault.key();
+
+        emit!(VaultAdoptedEvent {
+            token,
+            vault: vault.key(),
+            previous,
+        });
+
+        Ok(())
+    }
 
-        // Check if timelock delay has passed
-        let current_time = Clock::get()?.unix_timestamp;
-        require!(
-            current_time >= operation.can_execute_at,
-            RbxError::TimelockDelayNotMet
+    // Working float kept in the hot vault; anything above it can be moved to the cold vault.
+    // 0 disables rebalancing to cold
+    pub fn set_hot_float(ctx: Context<SetTokenStatus>, hot_float: u64) -> Result<()> {
+        require_logged!(
+            ctx.accounts
+                .state
+                .timelock_authorities
+                .contains(&ctx.accounts.authority.key()),
+            RbxError::UnauthorizedAccess,
+            "set_hot_float"
         );
 
-        // Execute operation based on type
-        match operation.operation_type {
-            1 => {
-                // Change owner
-                require!(operation.data.len() == 32, RbxError::InvalidOperationData);
-                let new_owner = Pubkey::try_from_slice(&operation.data[0..32])?;
-                state.owner = new_owner;
+        let token_config = &mut ctx.accounts.token_config;
+        token_config.hot_float = hot_float;
 
-                emit!(SetOwnerEvent { owner: new_owner });
-            }
-            2 => {
-                // Change signer - which is a 20-byte Ethereum address
-                require!(operation.data.len() == 20, RbxError::InvalidOperationData);
-                let mut new_signer = [0u8; 20];
-                new_signer.copy_from_slice(&operation.data[0..20]);
+        emit!(SetHotFloatEvent {
+            token: token_config.mint,
+            hot_float,
+        });
 
-                // Check if signer is all zeros
-                let is_zero = new_signer.iter().all(|&b| b == 0);
-                require!(!is_zero, RbxError::InvalidSigner);
+        Ok(())
+    }
 
-                state.withdrawal_signer = new_signer;
+    // Permissionless: move whatever the hot vault holds above its float into the cold vault
+    pub fn rebalance_to_cold(ctx: Context<RebalanceToCold>) -> Result<()> {
+        let token = ctx.accounts.mint.key();
+        let hot_float = ctx.accounts.token_config.hot_float;
+        require_logged!(
+            hot_float > 0,
+            RbxError::ColdVaultDisabled,
+            "rebalance_to_cold",
+            token,
+            0,
+            0
+        );
+        let amount = ctx.accounts.program_token_account.amount.saturating_sub(hot_float);
+        require_logged!(
+            amount > 0,
+            RbxError::NothingToRebalance,
+            "rebalance_to_cold",
+            token,
+            0,
+            0
+        );
 
-                emit!(SetSignerEvent { signer: new_signer });
-            }
-            3 => {
-                // Set timelock delay
-                require!(operation.data.len() == 8, RbxError::InvalidOperationData);
-                let new_delay = i64::from_le_bytes(operation.data[0..8].try_into().unwrap());
-                require!(new_delay >= 0, RbxError::InvalidTimelockDelay);
-                state.timelock_delay = new_delay;
-
-                emit!(SetTimelockDelayEvent { delay: new_delay });
-            }
-            4 => {
-                // Add timelock authority
-                require!(operation.data.len() == 32, RbxError::InvalidOperationData);
-                let new_authority = Pubkey::try_from_slice(&operation.data[0..32])?;
-
-                // Validate the new authority
-                require!(
-                    new_authority != Pubkey::default(),
-                    RbxError::InvalidAuthority
-                );
+        // The cold vault is created by the first rebalance of each token
+        associated_token::create_idempotent(CpiContext::new(
+            ctx.accounts.associated_token_program.to_account_info(),
+            associated_token::Create {
+                payer: ctx.accounts.payer.to_account_info(),
+                associated_token: ctx.accounts.cold_vault.to_account_info(),
+                authority: ctx.accounts.cold_authority.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+            },
+        ))?;
 
-                // Check if already an authority
-                require!(
-                    !state.timelock_authorities.contains(&new_authority),
-                    RbxError::AuthorityAlreadyExists
-                );
+        let seeds = &[
+            b"token_authority".as_ref(),
+            &[ctx.accounts.state.token_account_bump],
+        ];
+        let signer = &[&seeds[..]];
 
-                // Check max limit
-                require!(
-                    state.timelock_authorities.len() < MAX_AUTHORITIES,
-                    RbxError::TooManyAuthorities
-                );
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.program_token_account.to_account_info(),
+                to: ctx.accounts.cold_vault.to_account_info(),
+                authority: ctx.accounts.program_token_authority.to_account_info(),
+            },
+            signer,
+        );
+        token::transfer(transfer_ctx, amount)?;
 
-                // Add the new authority
-                state.timelock_authorities.push(new_authority);
+        emit!(VaultRebalancedEvent {
+            token,
+            to_cold: true,
+            amount,
+        });
 
-                emit!(AddAuthorityEvent {
-                    authority: new_authority
-                });
-            }
-            5 => {
-                // Remove timelock authority
-                require!(operation.data.len() == 32, RbxError::InvalidOperationData);
-                let authority_to_remove = Pubkey::try_from_slice(&operation.data[0..32])?;
-
-                // Prevent removing non-existent authority
-                let position = state
-                    .timelock_authorities
-                    .iter()
-                    .position(|&a| a == authority_to_remove)
-                    .ok_or(RbxError::AuthorityNotFound)?;
-
-                // Prevent removing the last authority
-                require!(
-                    state.timelock_authorities.len() > 1,
-                    RbxError::CannotRemoveLastAuthority
-                );
+        Ok(())
+    }
 
-                // Remove the authority
-                state.timelock_authorities.remove(position);
+    // Move tokens from the cold vault back to the hot vault, up to what the timelock approved
+    pub fn rebalance_to_hot(ctx: Context<RebalanceToHot>, amount: u64) -> Result<()> {
+        require_logged!(
+            ctx.accounts
+                .state
+                .timelock_authorities
+                .contains(&ctx.accounts.authority.key()),
+            RbxError::UnauthorizedAccess,
+            "rebalance_to_hot"
+        );
 
-                emit!(RemoveAuthorityEvent {
-                    authority: authority_to_remove
-                });
-            }
-            _ => return Err(error!(RbxError::InvalidOperationType)),
+        let token = ctx.accounts.mint.key();
+        let state = &mut ctx.accounts.state;
+        let position = state
+            .approved_cold_releases
+            .iter()
+            .position(|(t, approved)| t == &token && *approved >= amount)
+            .ok_or_else(|| logged_error!(RbxError::ColdReleaseNotApproved, "rebalance_to_hot"))?;
+        state.approved_cold_releases[position].1 -= amount;
+        if state.approved_cold_releases[position].1 == 0 {
+            state.approved_cold_releases.remove(position);
         }
 
-        // Remove the operation from the pending list
-        state.pending_operations.remove(operation_index as usize);
+        let seeds = &[b"cold_authority".as_ref(), &[ctx.bumps.cold_authority]];
+        let signer = &[&seeds[..]];
 
-        emit!(ExecuteOperationEvent {
-            operation_type: operation.operation_type,
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.cold_vault.to_account_info(),
+                to: ctx.accounts.program_token_account.to_account_info(),
+                authority: ctx.accounts.cold_authority.to_account_info(),
+            },
+            signer,
+        );
+        token::transfer(transfer_ctx, amount)?;
+
+        emit!(VaultRebalancedEvent {
+            token,
+            to_cold: false,
+            amount,
         });
 
         Ok(())
     }
 
-    pub fn get_withdrawal_signer(ctx: Context<GetWithdrawalSigner>) -> Result<[u8; 20]> {
-        Ok(ctx.accounts.state.withdrawal_signer)
-    }
-    
-    pub fn get_owner(ctx: Context<GetOwner>) -> Result<Pubkey> {
-        Ok(ctx.accounts.state.owner)
+    // Create the config of a token supported before TokenConfig accounts existed
+    pub fn register_token_config(ctx: Context<RegisterTokenConfig>) -> Result<()> {
+        require_logged!(
+            ctx.accounts
+                .state
+                .timelock_authorities
+                .contains(&ctx.accounts.authority.key()),
+            RbxError::UnauthorizedAccess,
+            "register_token_config",
+            ctx.accounts.token_mint.key(),
+            0,
+            0
+        );
+        require_logged!(
+            ctx.accounts
+                .state
+                .supported_tokens
+                .contains(&ctx.accounts.token_mint.key()),
+            RbxError::UnsupportedToken,
+            "register_token_config",
+            ctx.accounts.token_mint.key(),
+            0,
+            0
+        );
+
+        // The mint was accepted when it was first supported, so it is not re-checked here
+        init_token_config(
+            &mut ctx.accounts.token_config,
+            &ctx.accounts.token_mint,
+            false,
+            "register_token_config",
+        )
     }
-    
-    pub fn get_next_stake_num(ctx: Context<GetNextStakeNum>) -> Result<u64> {
-        Ok(ctx.accounts.state.next_stake_num)
-    }
-    
-    pub fn get_next_deposit_num(ctx: Context<GetNextDepositNum>) -> Result<u64> {
-        Ok(ctx.accounts.state.next_deposit_num)
+
+    pub fn deposit_native(ctx: Context<DepositNative>, amount: u64) -> Result<u64> {
+        require_logged!(
+            ctx.accounts.counters.reentry_lock_status == UNLOCKED,
+            RbxError::ReentrancyDetected,
+            "deposit_native",
+            ctx.accounts.wrapped_sol_mint.key(),
+            amount,
+            0
+        );
+        enforce_not_blocked(&ctx.accounts.blacklist_entry, "deposit_native")?;
+
+        ctx.accounts.counters.reentry_lock_status = LOCKED;
+
+        // Verify amount meets minimum
+        let state = &ctx.accounts.state;
+        let wrapped_sol = ctx.accounts.wrapped_sol_mint.key();
+
+        let min_deposit = state
+            .get_min_deposit(&wrapped_sol)
+            .ok_or_else(|| logged_error!(RbxError::UnsupportedToken, "deposit_native"))?;
+
+        require_logged!(
+            amount > 0 && amount >= min_deposit,
+            RbxError::AmountTooSmall,
+            "deposit_native",
+            ctx.accounts.wrapped_sol_mint.key(),
+            amount,
+            0
+        );
+        enforce_deposit_policy(
+            &ctx.accounts.deposit_policy,
+            wrapped_sol,
+            amount,
+            "deposit_native",
+        )?;
+        check_deposit_precision(&ctx.accounts.token_config, amount, "deposit_native")?;
+        check_funding_purpose(
+            &ctx.accounts.token_config,
+            FundingPurpose::Deposit,
+            "deposit_native",
+        )?;
+        record_deposit_inflow(&mut ctx.accounts.token_config, amount, "deposit_native")?;
+        require_logged!(
+            amount <= ctx.accounts.user.lamports(),
+            RbxError::InsufficientFunds,
+            "deposit_native",
+            ctx.accounts.wrapped_sol_mint.key(),
+            amount,
+            0
+        );
+
+        let deposit_num = take_deposit_num(&mut ctx.accounts.counters, "deposit_native")?;
+        ctx.accounts.counters.custodied_lamports += amount;
+
+        // Create deposit ID string with _rbx_sol suffix
+        let mut deposit_id = String::with_capacity(20); // Pre-allocate to avoid reallocation
+        deposit_id.push_str("d_");
+        deposit_id.push_str(&deposit_num.to_string());
+        deposit_id.push_str("_rbx_sol");
+
+        // Transfer SOL from user to program
+        let ix = solana_program::system_instruction::transfer(
+            &ctx.accounts.user.key(),
+            &ctx.accounts.program_sol_account.key(),
+            amount,
+        );
+
+        solana_program::program::invoke(
+            &ix,
+            &[
+                ctx.accounts.user.to_account_info(),
+                ctx.accounts.program_sol_account.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+        )?;
+
+        credit_trader_ledger(
+            &mut ctx.accounts.trader_ledger,
+            ctx.accounts.user.key(),
+            wrapped_sol,
+            amount,
+            "deposit_native",
+        )?;
+
+        ctx.accounts.token_config.add_liability(amount);
+
+        ctx.accounts.stats.record_deposit(wrapped_sol, amount);
+
+        let clock = Clock::get()?;
+        emit_deposit_event(
+            &ctx.accounts.state,
+            &ctx.accounts.profile_binding,
+            &event_cpi_authority(&ctx.accounts.event_authority, &ctx.accounts.event_program),
+            DepositEvent {
+                id: deposit_id,
+                trader: ctx.accounts.user.key(),
+                amount,
+                amount_e18: to_e18(amount, NATIVE_DECIMALS),
+                token: wrapped_sol,
+                slot: clock.slot,
+                unix_timestamp: clock.unix_timestamp,
+            },
+            "deposit_native",
+        )?;
+
+        ctx.accounts.counters.reentry_lock_status = UNLOCKED;
+
+        record_activity(
+            &mut ctx.accounts.activity_counter,
+            ctx.accounts.user.key(),
+            "deposit_native",
+        )?;
+
+        // Returned so programs depositing through CPI learn the deposit id
+        Ok(deposit_num)
     }
-    
-    pub fn get_timelock_delay(ctx: Context<GetTimelockDelay>) -> Result<i64> {
-        Ok(ctx.accounts.state.timelock_delay)
+
+    // deposit_native that also writes `tag` as an SPL Memo, for exchanges that identify
+    // deposits by memo, and reports it next to the deposit event
+    pub fn deposit_native_with_tag(
+        ctx: Context<DepositNative>,
+        amount: u64,
+        tag: String,
+    ) -> Result<u64> {
+        require_logged!(
+            !tag.is_empty() && tag.len() <= MAX_DEPOSIT_TAG_LEN,
+            RbxError::InvalidDepositTag,
+            "deposit_native_with_tag",
+            ctx.accounts.wrapped_sol_mint.key(),
+            amount,
+            0
+        );
+        let memo_program = ctx
+            .accounts
+            .memo_program
+            .clone()
+            .ok_or_else(|| logged_error!(RbxError::InvalidDepositTag, "deposit_native_with_tag"))?;
+        let user = ctx.accounts.user.to_account_info();
+
+        let ix = solana_program::instruction::Instruction {
+            program_id: MEMO_PROGRAM_ID,
+            accounts: vec![solana_program::instruction::AccountMeta::new_readonly(
+                user.key(),
+                true,
+            )],
+            data: tag.as_bytes().to_vec(),
+        };
+        solana_program::program::invoke(&ix, &[user.clone(), memo_program])?;
+
+        let deposit_num = deposit_native(ctx, amount)?;
+
+        emit!(DepositTaggedEvent {
+            id: format!("d_{}_rbx_sol", deposit_num),
+            trader: user.key(),
+            tag,
+        });
+
+        Ok(deposit_num)
     }
-    
-    pub fn get_domain_separator(ctx: Context<GetDomainSeparator>) -> Result<Option<[u8; 32]>> {
-        Ok(ctx.accounts.state.domain_separator)
+
+    // Native SOL deposit on behalf of another trader
+    pub fn deposit_native_for(
+        ctx: Context<DepositNative>,
+        amount: u64,
+        for_trader: Pubkey,
+    ) -> Result<()> {
+        require_logged!(
+            ctx.accounts.counters.reentry_lock_status == UNLOCKED,
+            RbxError::ReentrancyDetected,
+            "deposit_native_for",
+            ctx.accounts.wrapped_sol_mint.key(),
+            amount,
+            0
+        );
+        enforce_not_blocked(&ctx.accounts.blacklist_entry, "deposit_native_for")?;
+        check_third_party_deposit(
+            &ctx.accounts.state,
+            &ctx.accounts.user.key(),
+            "deposit_native_for",
+        )?;
+
+        ctx.accounts.counters.reentry_lock_status = LOCKED;
+
+        // Verify amount meets minimum
+        let state = &ctx.accounts.state;
+        let wrapped_sol = ctx.accounts.wrapped_sol_mint.key();
+
+        let min_deposit = state
+            .get_min_deposit(&wrapped_sol)
+            .ok_or_else(|| logged_error!(RbxError::UnsupportedToken, "deposit_native_for"))?;
+
+        require_logged!(
+            amount > 0 && amount >= min_deposit,
+            RbxError::AmountTooSmall,
+            "deposit_native_for",
+            ctx.accounts.wrapped_sol_mint.key(),
+            amount,
+            0
+        );
+        enforce_deposit_policy(
+            &ctx.accounts.deposit_policy,
+            wrapped_sol,
+            amount,
+            "deposit_native_for",
+        )?;
+        check_deposit_precision(&ctx.accounts.token_config, amount, "deposit_native_for")?;
+        check_funding_purpose(
+            &ctx.accounts.token_config,
+            FundingPurpose::Deposit,
+            "deposit_native_for",
+        )?;
+        record_deposit_inflow(&mut ctx.accounts.token_config, amount, "deposit_native_for")?;
+        require_logged!(
+            !requires_deposit_claim(state, amount),
+            RbxError::DepositClaimRequired,
+            "deposit_native_for",
+            ctx.accounts.wrapped_sol_mint.key(),
+            amount,
+            0
+        );
+        require_logged!(
+            amount <= ctx.accounts.user.lamports(),
+            RbxError::InsufficientFunds,
+            "deposit_native_for",
+            ctx.accounts.wrapped_sol_mint.key(),
+            amount,
+            0
+        );
+
+        let deposit_num = take_deposit_num(&mut ctx.accounts.counters, "deposit_native_for")?;
+        ctx.accounts.counters.custodied_lamports += amount;
+
+        // Create deposit ID string with _rbx_sol suffix
+        let mut deposit_id = String::with_capacity(20); // Pre-allocate to avoid reallocation
+        deposit_id.push_str("d_");
+        deposit_id.push_str(&deposit_num.to_string());
+        deposit_id.push_str("_rbx_sol");
+
+        // Transfer SOL from user to program
+        let ix = solana_program::system_instruction::transfer(
+            &ctx.accounts.user.key(),
+            &ctx.accounts.program_sol_account.key(),
+            amount,
+        );
+
+        solana_program::program::invoke(
+            &ix,
+            &[
+                ctx.accounts.user.to_account_info(),
+                ctx.accounts.program_sol_account.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+        )?;
+
+        credit_trader_ledger(
+            &mut ctx.accounts.trader_ledger,
+            for_trader,
+            wrapped_sol,
+            amount,
+            "deposit_native_for",
+        )?;
+
+        ctx.accounts.token_config.add_liability(amount);
+
+        ctx.accounts.stats.record_deposit(wrapped_sol, amount);
+
+        let clock = Clock::get()?;
+        emit_deposit_event(
+            &ctx.accounts.state,
+            &ctx.accounts.profile_binding,
+            &event_cpi_authority(&ctx.accounts.event_authority, &ctx.accounts.event_program),
+            DepositEvent {
+                id: deposit_id,
+                trader: for_trader, // Use the provided for_trader parameter instead of the sender
+                amount,
+                amount_e18: to_e18(amount, NATIVE_DECIMALS),
+                token: wrapped_sol,
+                slot: clock.slot,
+                unix_timestamp: clock.unix_timestamp,
+            },
+            "deposit_native_for",
+        )?;
+
+        ctx.accounts.counters.reentry_lock_status = UNLOCKED;
+
+        record_activity(&mut ctx.accounts.activity_counter, for_trader, "deposit_native_for")?;
+
+        Ok(())
     }
 
-    pub fn cancel_operation(ctx: Context<CancelOperation>, operation_index: u8) -> Result<()> {
-        let state = &mut ctx.accounts.state;
+    // SOL deposit for program-owned depositors (DAOs, smart wallets) whose PDAs carry data and
+    // so cannot fund a system transfer. The caller moves `amount` lamports into
+    // program_sol_account itself, then invokes this with its PDA as the signing `user`. The
+    // lamports are taken from the balance not yet attributed to anyone, so the move and the
+    // invocation must happen in the same instruction or anyone may claim them first
+    pub fn deposit_native_from_pda(
+        ctx: Context<DepositNative>,
+        amount: u64,
+        for_trader: Pubkey,
+    ) -> Result<()> {
+        require_logged!(
+            ctx.accounts.counters.reentry_lock_status == UNLOCKED,
+            RbxError::ReentrancyDetected,
+            "deposit_native_from_pda",
+            ctx.accounts.wrapped_sol_mint.key(),
+            amount,
+            0
+        );
+        enforce_not_blocked(&ctx.accounts.blacklist_entry, "deposit_native_from_pda")?;
+        if for_trader != ctx.accounts.user.key() {
+            check_third_party_deposit(
+                &ctx.accounts.state,
+                &ctx.accounts.user.key(),
+                "deposit_native_from_pda",
+            )?;
+        }
 
-        // Only timelock authority can cancel operations
-        require!(
-            state
-                .timelock_authorities
-                .contains(&ctx.accounts.authority.key()),
-            RbxError::UnauthorizedAccess
+        ctx.accounts.counters.reentry_lock_status = LOCKED;
+
+        let state = &ctx.accounts.state;
+        let wrapped_sol = ctx.accounts.wrapped_sol_mint.key();
+
+        let min_deposit = state
+            .get_min_deposit(&wrapped_sol)
+            .ok_or_else(|| logged_error!(RbxError::UnsupportedToken, "deposit_native_from_pda"))?;
+
+        require_logged!(
+            amount > 0 && amount >= min_deposit,
+            RbxError::AmountTooSmall,
+            "deposit_native_from_pda",
+            wrapped_sol,
+            amount,
+            0
+        );
+        enforce_deposit_policy(
+            &ctx.accounts.deposit_policy,
+            wrapped_sol,
+            amount,
+            "deposit_native_from_pda",
+        )?;
+        check_deposit_precision(&ctx.accounts.token_config, amount, "deposit_native_from_pda")?;
+        check_funding_purpose(
+            &ctx.accounts.token_config,
+            FundingPurpose::Deposit,
+            "deposit_native_from_pda",
+        )?;
+        record_deposit_inflow(&mut ctx.accounts.token_config, amount, "deposit_native_from_pda")?;
+        require_logged!(
+            for_trader == ctx.accounts.user.key() || !requires_deposit_claim(state, amount),
+            RbxError::DepositClaimRequired,
+            "deposit_native_from_pda",
+            wrapped_sol,
+            amount,
+            0
         );
 
-        // Check if operation index is valid
-        require!(
-            (operation_index as usize) < state.pending_operations.len(),
-            RbxError::InvalidOperationIndex
+        // The pre-transferred lamports are the balance above what is owed and the reserve
+        require_logged!(
+            state.custody_reconciled,
+            RbxError::CustodyNotReconciled,
+            "deposit_native_from_pda",
+            wrapped_sol,
+            amount,
+            0
+        );
+        let unattributed = ctx
+            .accounts
+            .program_sol_account
+            .lamports()
+            .saturating_sub(ctx.accounts.counters.custodied_lamports)
+            .saturating_sub(sol_reserve_floor(state)?);
+        require_logged!(
+            amount <= unattributed,
+            RbxError::InsufficientFunds,
+            "deposit_native_from_pda",
+            wrapped_sol,
+            amount,
+            0
         );
 
-        // Clone the operation to access its details for the event
-        let operation = state.pending_operations[operation_index as usize].clone();
+        let deposit_num = take_deposit_num(&mut ctx.accounts.counters, "deposit_native_from_pda")?;
+        ctx.accounts.counters.custodied_lamports += amount;
+
+        let mut deposit_id = String::with_capacity(20);
+        deposit_id.push_str("d_");
+        deposit_id.push_str(&deposit_num.to_string());
+        deposit_id.push_str("_rbx_sol");
 
-        // Remove the operation from the pending list
-        state.pending_operations.remove(operation_index as usize);
+        credit_trader_ledger(
+            &mut ctx.accounts.trader_ledger,
+            for_trader,
+            wrapped_sol,
+            amount,
+            "deposit_native_from_pda",
+        )?;
 
-        emit!(CancelOperationEvent {
-            operation_type: operation.operation_type,
-            authority: ctx.accounts.authority.key(),
-        });
+        ctx.accounts.token_config.add_liability(amount);
+
+        ctx.accounts.stats.record_deposit(wrapped_sol, amount);
+
+        let clock = Clock::get()?;
+        emit_deposit_event(
+            &ctx.accounts.state,
+            &ctx.accounts.profile_binding,
+            &event_cpi_authority(&ctx.accounts.event_authority, &ctx.accounts.event_program),
+            DepositEvent {
+                id: deposit_id,
+                trader: for_trader,
+                amount,
+                amount_e18: to_e18(amount, NATIVE_DECIMALS),
+                token: wrapped_sol,
+                slot: clock.slot,
+                unix_timestamp: clock.unix_timestamp,
+            },
+            "deposit_native_from_pda",
+        )?;
+
+        ctx.accounts.counters.reentry_lock_status = UNLOCKED;
+
+        record_activity(&mut ctx.accounts.activity_counter, for_trader, "deposit_native_from_pda")?;
 
         Ok(())
     }
 
-    pub fn stake_token(ctx: Context<DepositToken>, amount: u64) -> Result<()> {
-        require!(
-            ctx.accounts.state.reentry_lock_status == UNLOCKED,
-            RbxError::ReentrancyDetected
+    // Deposit on behalf of another trader into an escrow the trader must accept before expiry
+    pub fn deposit_token_for_claim(
+        ctx: Context<DepositTokenForClaim>,
+        amount: u64,
+        for_trader: Pubkey,
+        claim_id: u64,
+    ) -> Result<()> {
+        require_logged!(
+            ctx.accounts.counters.reentry_lock_status == UNLOCKED,
+            RbxError::ReentrancyDetected,
+            "deposit_token_for_claim",
+            ctx.accounts.mint.key(),
+            amount,
+            0
         );
+        enforce_not_blocked(&ctx.accounts.blacklist_entry, "deposit_token_for_claim")?;
+        check_third_party_deposit(
+            &ctx.accounts.state,
+            &ctx.accounts.user.key(),
+            "deposit_token_for_claim",
+        )?;
 
-        ctx.accounts.state.reentry_lock_status = LOCKED;
+        ctx.accounts.counters.reentry_lock_status = LOCKED;
 
         let state = &ctx.accounts.state;
         let token = ctx.accounts.mint.key();
 
-        // Verify token is supported
-        require!(
-            ctx.accounts.state.supported_tokens.contains(&token),
-            RbxError::UnsupportedToken
+        require_logged!(
+            state.deposit_claim_threshold != 0,
+            RbxError::DepositClaimsDisabled,
+            "deposit_token_for_claim",
+            token,
+            amount,
+            0
+        );
+        require_logged!(
+            state.supported_tokens.contains(&token),
+            RbxError::UnsupportedToken,
+            "deposit_token_for_claim",
+            token,
+            amount,
+            0
         );
         let min_deposit = state
             .get_min_deposit(&token)
-            .ok_or(RbxError::UnsupportedToken)?;
-
-        require!(amount >= min_deposit, RbxError::AmountTooSmall);
+            .ok_or_else(|| logged_error!(RbxError::UnsupportedToken, "deposit_token_for_claim"))?;
 
-        let stake_num = ctx.accounts.state.next_stake_num;
-        ctx.accounts.state.next_stake_num += 1;
-
-        // Create stake ID string with _rbx_sol suffix
-        let mut stake_id = String::with_capacity(20); // Pre-allocate to avoid reallocation
-        stake_id.push_str("s_");
-        stake_id.push_str(&stake_num.to_string());
-        stake_id.push_str("_rbx_sol");
+        require_logged!(
+            amount > 0 && amount >= min_deposit,
+            RbxError::AmountTooSmall,
+            "deposit_token_for_claim",
+            token,
+            amount,
+            0
+        );
+        enforce_deposit_policy(
+            &ctx.accounts.deposit_policy,
+            token,
+            amount,
+            "deposit_token_for_claim",
+        )?;
+        check_deposit_precision(&ctx.accounts.token_config, amount, "deposit_token_for_claim")?;
+        check_funding_purpose(
+            &ctx.accounts.token_config,
+            FundingPurpose::Deposit,
+            "deposit_token_for_claim",
+        )?;
+        record_deposit_inflow(&mut ctx.accounts.token_config, amount, "deposit_token_for_claim")?;
 
         // Transfer tokens from user to program token account
+        ensure_vault_not_frozen(&ctx.accounts.program_token_account.to_account_info())?;
         let transfer_ctx = CpiContext::new(
             ctx.accounts.token_program.to_account_info(),
             Transfer {
@@ -806,49 +1907,104 @@ pub mod rbx {
         );
         token::transfer(transfer_ctx, amount)?;
 
-        emit!(StakeEvent {
-            id: stake_id,
-            trader: ctx.accounts.user.key(),
-            amount,
+        // Escrowed funds sit in the vault but are only credited once accepted
+        ctx.accounts.token_config.add_liability(amount);
+
+        let expires_at = Clock::get()?
+            .unix_timestamp
+            .checked_add(ctx.accounts.state.deposit_claim_window)
+            .ok_or_else(|| logged_error!(RbxError::TimestampOverflow, "deposit_token_for_claim"))?;
+        let claim = &mut ctx.accounts.deposit_claim;
+        claim.sponsor = ctx.accounts.user.key();
+        claim.claim_id = claim_id;
+        claim.trader = for_trader;
+        claim.token = token;
+        claim.amount = amount;
+        claim.expires_at = expires_at;
+
+        emit!(DepositClaimCreatedEvent {
+            claim: claim.key(),
+            sponsor: claim.sponsor,
+            trader: for_trader,
             token,
+            amount,
+            expires_at,
         });
 
-        ctx.accounts.state.reentry_lock_status = UNLOCKED;
+        ctx.accounts.counters.reentry_lock_status = UNLOCKED;
 
         Ok(())
     }
 
-    pub fn stake_native(ctx: Context<DepositNative>, amount: u64) -> Result<()> {
-        require!(
-            ctx.accounts.state.reentry_lock_status == UNLOCKED,
-            RbxError::ReentrancyDetected
+    // Native SOL deposit on behalf of another trader into an escrow the trader must accept
+    pub fn deposit_native_for_claim(
+        ctx: Context<DepositNativeForClaim>,
+        amount: u64,
+        for_trader: Pubkey,
+        claim_id: u64,
+    ) -> Result<()> {
+        require_logged!(
+            ctx.accounts.counters.reentry_lock_status == UNLOCKED,
+            RbxError::ReentrancyDetected,
+            "deposit_native_for_claim",
+            ctx.accounts.wrapped_sol_mint.key(),
+            amount,
+            0
         );
+        enforce_not_blocked(&ctx.accounts.blacklist_entry, "deposit_native_for_claim")?;
+        check_third_party_deposit(
+            &ctx.accounts.state,
+            &ctx.accounts.user.key(),
+            "deposit_native_for_claim",
+        )?;
 
-        ctx.accounts.state.reentry_lock_status = LOCKED;
+        ctx.accounts.counters.reentry_lock_status = LOCKED;
 
-        // Verify amount meets minimum
         let state = &ctx.accounts.state;
         let wrapped_sol = ctx.accounts.wrapped_sol_mint.key();
 
+        require_logged!(
+            state.deposit_claim_threshold != 0,
+            RbxError::DepositClaimsDisabled,
+            "deposit_native_for_claim",
+            wrapped_sol,
+            amount,
+            0
+        );
         let min_deposit = state
             .get_min_deposit(&wrapped_sol)
-            .ok_or(RbxError::UnsupportedToken)?;
+            .ok_or_else(|| logged_error!(RbxError::UnsupportedToken, "deposit_native_for_claim"))?;
 
-        require!(amount >= min_deposit, RbxError::AmountTooSmall);
-        require!(
+        require_logged!(
+            amount > 0 && amount >= min_deposit,
+            RbxError::AmountTooSmall,
+            "deposit_native_for_claim",
+            wrapped_sol,
+            amount,
+            0
+        );
+        enforce_deposit_policy(
+            &ctx.accounts.deposit_policy,
+            wrapped_sol,
+            amount,
+            "deposit_native_for_claim",
+        )?;
+        check_deposit_precision(&ctx.accounts.token_config, amount, "deposit_native_for_claim")?;
+        check_funding_purpose(
+            &ctx.accounts.token_config,
+            FundingPurpose::Deposit,
+            "deposit_native_for_claim",
+        )?;
+        record_deposit_inflow(&mut ctx.accounts.token_config, amount, "deposit_native_for_claim")?;
+        require_logged!(
             amount <= ctx.accounts.user.lamports(),
-            RbxError::InsufficientFunds
+            RbxError::InsufficientFunds,
+            "deposit_native_for_claim",
+            wrapped_sol,
+            amount,
+            0
         );
 
-        let stake_num = ctx.accounts.state.next_stake_num;
-        ctx.accounts.state.next_stake_num += 1;
-
-        // Create stake ID string with _rbx_sol suffix
-        let mut stake_id = String::with_capacity(20); // Pre-allocate to avoid reallocation
-        stake_id.push_str("s_");
-        stake_id.push_str(&stake_num.to_string());
-        stake_id.push_str("_rbx_sol");
-
         // Transfer SOL from user to program
         let ix = solana_program::system_instruction::transfer(
             &ctx.accounts.user.key(),
@@ -865,97 +2021,9110 @@ pub mod rbx {
             ],
         )?;
 
-        emit!(StakeEvent {
-            id: stake_id,
-            trader: ctx.accounts.user.key(),
-            amount,
+        // Escrowed lamports are owed back to the sponsor or the trader, never surplus
+        ctx.accounts.counters.custodied_lamports += amount;
+        ctx.accounts.token_config.add_liability(amount);
+
+        let expires_at = Clock::get()?
+            .unix_timestamp
+            .checked_add(ctx.accounts.state.deposit_claim_window)
+            .ok_or_else(|| logged_error!(RbxError::TimestampOverflow, "deposit_native_for_claim"))?;
+        let claim = &mut ctx.accounts.deposit_claim;
+        claim.sponsor = ctx.accounts.user.key();
+        claim.claim_id = claim_id;
+        claim.trader = for_trader;
+        claim.token = wrapped_sol;
+        claim.amount = amount;
+        claim.expires_at = expires_at;
+
+        emit!(DepositClaimCreatedEvent {
+            claim: claim.key(),
+            sponsor: claim.sponsor,
+            trader: for_trader,
             token: wrapped_sol,
+            amount,
+            expires_at,
         });
 
-        ctx.accounts.state.reentry_lock_status = UNLOCKED;
+        ctx.accounts.counters.reentry_lock_status = UNLOCKED;
 
         Ok(())
     }
-}
+
+    // Named trader accepts an escrowed deposit, crediting it like a regular deposit
+    pub fn accept_deposit(ctx: Context<AcceptDeposit>) -> Result<u64> {
+        let claim = &ctx.accounts.deposit_claim;
+        require_logged!(
+            Clock::get()?.unix_timestamp <= claim.expires_at,
+            RbxError::DepositClaimExpired,
+            "accept_deposit",
+            claim.token,
+            claim.amount,
+            0
+        );
+        // The claim was escrowed before the trader may have been blocked
+        enforce_not_blocked(&ctx.accounts.blacklist_entry, "accept_deposit")?;
+        let token = claim.token;
+        let amount = claim.amount;
+
+        let deposit_num = take_deposit_num(&mut ctx.accounts.counters, "accept_deposit")?;
+
+        // Create deposit ID string with _rbx_sol suffix
+        let mut deposit_id = String::with_capacity(20); // Pre-allocate to avoid reallocation
+        deposit_id.push_str("d_");
+        deposit_id.push_str(&deposit_num.to_string());
+        deposit_id.push_str("_rbx_sol");
+
+        credit_trader_ledger(
+            &mut ctx.accounts.trader_ledger,
+            ctx.accounts.trader.key(),
+            token,
+            amount,
+            "accept_deposit",
+        )?;
+
+        ctx.accounts.stats.record_deposit(token, amount);
+
+        let clock = Clock::get()?;
+        emit_deposit_event(
+            &ctx.accounts.state,
+            &ctx.accounts.profile_binding,
+            &None,
+            DepositEvent {
+                id: deposit_id,
+                trader: ctx.accounts.trader.key(),
+                amount,
+                amount_e18: to_e18(amount, ctx.accounts.token_config.decimals),
+                token,
+                slot: clock.slot,
+                unix_timestamp: clock.unix_timestamp,
+            },
+            "accept_deposit",
+        )?;
+
+        emit!(DepositClaimAcceptedEvent {
+            claim: ctx.accounts.deposit_claim.key(),
+            trader: ctx.accounts.trader.key(),
+            deposit_num,
+        });
+
+        Ok(deposit_num)
+    }
+
+    // Sponsor takes back a token deposit its named trader did not accept in time
+    pub fn reclaim_deposit(ctx: Context<ReclaimDeposit>) -> Result<()> {
+        let claim = &ctx.accounts.deposit_claim;
+        require_logged!(
+            Clock::get()?.unix_timestamp > claim.expires_at,
+            RbxError::DepositClaimNotExpired,
+            "reclaim_deposit",
+            claim.token,
+            claim.amount,
+            0
+        );
+        let amount = claim.amount;
+
+        // Transfer tokens from program back to the sponsor
+        let seeds = &[
+            b"token_authority".as_ref(),
+            &[ctx.accounts.state.token_account_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.program_token_account.to_account_info(),
+                to: ctx.accounts.sponsor_token_account.to_account_info(),
+                authority: ctx.accounts.program_token_authority.to_account_info(),
+            },
+            signer,
+        );
+        token::transfer(transfer_ctx, amount)?;
+
+        ctx.accounts.token_config.remove_liability(amount);
+
+        emit!(DepositReclaimedEvent {
+            claim: ctx.accounts.deposit_claim.key(),
+            sponsor: ctx.accounts.sponsor.key(),
+            token: ctx.accounts.mint.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    // Sponsor takes back a native SOL deposit its named trader did not accept in time
+    pub fn reclaim_deposit_native(ctx: Context<ReclaimDepositNative>) -> Result<()> {
+        let claim = &ctx.accounts.deposit_claim;
+        require_logged!(
+            Clock::get()?.unix_timestamp > claim.expires_at,
+            RbxError::DepositClaimNotExpired,
+            "reclaim_deposit_native",
+            claim.token,
+            claim.amount,
+            0
+        );
+        let amount = claim.amount;
+
+        // Transfer SOL from program back to the sponsor
+        let seeds = &[
+            ctx.accounts.state.sol_account_seed(),
+            &[ctx.accounts.state.sol_account_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        enforce_sol_rent_floor(
+            ctx.accounts.program_sol_account.lamports(),
+            amount,
+            "reclaim_deposit_native",
+        )?;
+
+        let transfer_ix = solana_program::system_instruction::transfer(
+            &ctx.accounts.program_sol_account.key(),
+            &ctx.accounts.sponsor.key(),
+            amount,
+        );
+
+        solana_program::program::invoke_signed(
+            &transfer_ix,
+            &[
+                ctx.accounts.program_sol_account.to_account_info(),
+                ctx.accounts.sponsor.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+            signer,
+        )?;
+
+        ctx.accounts.counters.custodied_lamports =
+            ctx.accounts.counters.custodied_lamports.saturating_sub(amount);
+
+        ctx.accounts.token_config.remove_liability(amount);
+
+        emit!(DepositReclaimedEvent {
+            claim: ctx.accounts.deposit_claim.key(),
+            sponsor: ctx.accounts.sponsor.key(),
+            token: ctx.accounts.wrapped_sol_mint.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    // Wrapped SOL deposit, optionally unwrapped into the program's SOL account
+    pub fn deposit_wsol(ctx: Context<DepositWsol>, amount: u64, unwrap: bool) -> Result<()> {
+        require_logged!(
+            ctx.accounts.counters.reentry_lock_status == UNLOCKED,
+            RbxError::ReentrancyDetected,
+            "deposit_wsol",
+            ctx.accounts.wrapped_sol_mint.key(),
+            amount,
+            0
+        );
+        enforce_not_blocked(&ctx.accounts.blacklist_entry, "deposit_wsol")?;
+
+        ctx.accounts.counters.reentry_lock_status = LOCKED;
+
+        // Verify amount meets minimum
+        let state = &ctx.accounts.state;
+        let wrapped_sol = ctx.accounts.wrapped_sol_mint.key();
+
+        let min_deposit = state
+            .get_min_deposit(&wrapped_sol)
+            .ok_or_else(|| logged_error!(RbxError::UnsupportedToken, "deposit_wsol"))?;
+
+        require_logged!(
+            amount > 0 && amount >= min_deposit,
+            RbxError::AmountTooSmall,
+            "deposit_wsol",
+            ctx.accounts.wrapped_sol_mint.key(),
+            amount,
+            0
+        );
+        enforce_deposit_policy(&ctx.accounts.deposit_policy, wrapped_sol, amount, "deposit_wsol")?;
+        check_deposit_precision(&ctx.accounts.token_config, amount, "deposit_wsol")?;
+        check_funding_purpose(&ctx.accounts.token_config, FundingPurpose::Deposit, "deposit_wsol")?;
+        record_deposit_inflow(&mut ctx.accounts.token_config, amount, "deposit_wsol")?;
+        require_logged!(
+            amount <= ctx.accounts.user_wsol_account.amount,
+            RbxError::InsufficientFunds,
+            "deposit_wsol",
+            ctx.accounts.wrapped_sol_mint.key(),
+            amount,
+            0
+        );
+
+        let deposit_num = take_deposit_num(&mut ctx.accounts.counters, "deposit_wsol")?;
+
+        // Create deposit ID string with _rbx_sol suffix
+        let mut deposit_id = String::with_capacity(20); // Pre-allocate to avoid reallocation
+        deposit_id.push_str("d_");
+        deposit_id.push_str(&deposit_num.to_string());
+        deposit_id.push_str("_rbx_sol");
+
+        if unwrap {
+            // The temporary account must hold exactly the deposit so closing it doesn't strand wSOL
+            require_logged!(
+                ctx.accounts.user_wsol_account.amount == amount,
+                RbxError::WrongAmount,
+                "deposit_wsol",
+                ctx.accounts.wrapped_sol_mint.key(),
+                amount,
+                0
+            );
+
+            // Close the wSOL account back to the user, releasing its lamports
+            let close_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: ctx.accounts.user_wsol_account.to_account_info(),
+                    destination: ctx.accounts.user.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            );
+            token::close_account(close_ctx)?;
+
+            // Route the unwrapped lamports into the program's SOL account
+            let ix = solana_program::system_instruction::transfer(
+                &ctx.accounts.user.key(),
+                &ctx.accounts.program_sol_account.key(),
+                amount,
+            );
+
+            solana_program::program::invoke(
+                &ix,
+                &[
+                    ctx.accounts.user.to_account_info(),
+                    ctx.accounts.program_sol_account.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+            )?;
+
+            ctx.accounts.counters.custodied_lamports += amount;
+        } else {
+            // Keep the deposit wrapped in the program's wSOL token account
+            ensure_vault_not_frozen(&ctx.accounts.program_token_account.to_account_info())?;
+            let transfer_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.user_wsol_account.to_account_info(),
+                    to: ctx.accounts.program_token_account.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            );
+            token::transfer(transfer_ctx, amount)?;
+        }
+
+        credit_trader_ledger(
+            &mut ctx.accounts.trader_ledger,
+            ctx.accounts.user.key(),
+            wrapped_sol,
+            amount,
+            "deposit_wsol",
+        )?;
+
+        ctx.accounts.token_config.add_liability(amount);
+
+        ctx.accounts.stats.record_deposit(wrapped_sol, amount);
+
+        let clock = Clock::get()?;
+        emit_deposit_event(
+            &ctx.accounts.state,
+            &ctx.accounts.profile_binding,
+            &None,
+            DepositEvent {
+                id: deposit_id,
+                trader: ctx.accounts.user.key(),
+                amount,
+                amount_e18: to_e18(amount, NATIVE_DECIMALS),
+                token: wrapped_sol,
+                slot: clock.slot,
+                unix_timestamp: clock.unix_timestamp,
+            },
+            "deposit_wsol",
+        )?;
+
+        ctx.accounts.counters.reentry_lock_status = UNLOCKED;
+
+        Ok(())
+    }
+
+    pub fn withdraw_token(
+        ctx: Context<WithdrawToken>,
+        id: u64,
+        amount: u64,
+        v: u8,
+        r: [u8; 32],
+        s: [u8; 32],
+        sig_version: u8,
+        scheme: SignatureScheme,
+        allow_partial: bool,
+    ) -> Result<()> {
+        let event_authority =
+            event_cpi_authority(&ctx.accounts.event_authority, &ctx.accounts.event_program);
+
+        // Pay the trader's own account, or the ATA of a recipient named in the signature
+        let trader = ctx.accounts.trader.key();
+        let recipient = ctx.accounts.recipient.as_ref().map_or(trader, |r| r.key());
+        execute_token_withdrawal(
+            ctx.program_id,
+            TokenWithdrawalAccounts {
+                state: &mut ctx.accounts.state,
+                counters: &mut ctx.accounts.counters,
+                stats: &mut ctx.accounts.stats,
+                token_config: &mut ctx.accounts.token_config,
+                withdrawal_record: &mut ctx.accounts.withdrawal_record,
+                trader_ledger: ctx.accounts.trader_ledger.as_mut(),
+                partial_withdrawal: ctx.accounts.partial_withdrawal.as_mut(),
+                mint: &ctx.accounts.mint,
+                program_token_account: &ctx.accounts.program_token_account,
+                program_token_authority: &ctx.accounts.program_token_authority,
+                trader_token_account: &ctx.accounts.trader_token_account,
+                trader: &ctx.accounts.trader,
+                recipient,
+                blacklist_entry: &ctx.accounts.blacklist_entry,
+                withdrawal_allowlist: &ctx.accounts.withdrawal_allowlist,
+                token_program: &ctx.accounts.token_program,
+                event_authority,
+            },
+            id,
+            amount,
+            v,
+            r,
+            s,
+            sig_version,
+            scheme,
+            allow_partial,
+            "withdraw_token",
+        )?;
+
+        record_activity(
+            &mut ctx.accounts.activity_counter,
+            ctx.accounts.trader.key(),
+            "withdraw_token",
+        )?;
+
+        Ok(())
+    }
+
+    // Post a signed withdrawal as a ticket for the trader to claim later. The signature is
+    // verified and the id marked processed here, so several tickets can share a transaction
+    // posted off-peak and claiming needs no secp256k1 recovery
+    pub fn post_withdrawal_ticket(
+        ctx: Context<PostWithdrawalTicket>,
+        id: u64,
+        amount: u64,
+        v: u8,
+        r: [u8; 32],
+        s: [u8; 32],
+        sig_version: u8,
+        scheme: SignatureScheme,
+    ) -> Result<()> {
+        enforce_not_blocked(&ctx.accounts.blacklist_entry, "post_withdrawal_ticket")?;
+
+        let decimals = mint_decimals(&ctx.accounts.mint)?;
+        let trader = ctx.accounts.trader.key();
+        let token = ctx.accounts.mint.key();
+
+        // Tickets always pay the trader, so recipient-bound signatures are not accepted
+        let amount = process_withdrawal(
+            ctx.program_id,
+            &mut ctx.accounts.state,
+            Some(&mut ctx.accounts.withdrawal_record),
+            ctx.accounts.trader_ledger.as_mut(),
+            id,
+            amount,
+            token,
+            decimals,
+            trader,
+            trader,
+            v,
+            r,
+            s,
+            sig_version,
+            scheme,
+            0,
+        )?;
+
+        let ticket = &mut ctx.accounts.withdrawal_ticket;
+        ticket.id = id;
+        ticket.trader = trader;
+        ticket.token = token;
+        ticket.amount = amount;
+        ticket.poster = ctx.accounts.poster.key();
+        ticket.posted_at = Clock::get()?.unix_timestamp;
+
+        emit!(WithdrawalTicketPostedEvent {
+            id,
+            trader,
+            token,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    // Redeem a posted ticket, paying the trader and refunding the ticket rent to its poster
+    pub fn claim_ticket(ctx: Context<ClaimTicket>) -> Result<()> {
+        let id = ctx.accounts.withdrawal_ticket.id;
+        let amount = ctx.accounts.withdrawal_ticket.amount;
+        let trader = ctx.accounts.trader.key();
+        let token = ctx.accounts.mint.key();
+
+        require_logged!(
+            ctx.accounts.counters.reentry_lock_status == UNLOCKED,
+            RbxError::ReentrancyDetected,
+            "claim_ticket",
+            token,
+            amount,
+            id
+        );
+        require_logged!(
+            !ctx.accounts.state.withdrawals_paused,
+            RbxError::WithdrawalsPaused,
+            "claim_ticket",
+            token,
+            amount,
+            id
+        );
+        enforce_not_blocked(&ctx.accounts.blacklist_entry, "claim_ticket")?;
+        enforce_withdrawal_allowlist(
+            &ctx.accounts.withdrawal_allowlist,
+            &ctx.accounts.trader_token_account,
+            ctx.accounts.trader.key(),
+            "claim_ticket",
+        )?;
+        ctx.accounts.counters.reentry_lock_status = LOCKED;
+
+        let decimals = mint_decimals(&ctx.accounts.mint)?;
+
+        let seeds = &[
+            b"token_authority".as_ref(),
+            &[ctx.accounts.state.token_account_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        ensure_vault_not_frozen(&ctx.accounts.program_token_account.to_account_info())?;
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.program_token_account.to_account_info(),
+                to: ctx.accounts.trader_token_account.to_account_info(),
+                authority: ctx.accounts.program_token_authority.to_account_info(),
+            },
+            signer,
+        );
+        token::transfer(transfer_ctx, amount)?;
+
+        ctx.accounts.counters.reentry_lock_status = UNLOCKED;
+
+        ctx.accounts.token_config.remove_liability(amount);
+
+        ctx.accounts.stats.record_withdrawal(token, amount);
+
+        let clock = Clock::get()?;
+        emit_withdrawal_event(
+            &ctx.accounts.state,
+            &None,
+            WithdrawalEvent {
+                id,
+                trader,
+                recipient: trader,
+                amount,
+                amount_e18: to_e18(amount, decimals),
+                token,
+                slot: clock.slot,
+                unix_timestamp: clock.unix_timestamp,
+            },
+        )?;
+
+        Ok(())
+    }
+
+    pub fn withdraw_native(
+        ctx: Context<WithdrawNative>,
+        id: u64,
+        amount: u64,
+        v: u8,
+        r: [u8; 32],
+        s: [u8; 32],
+        wrap: bool,
+        sig_version: u8,
+        scheme: SignatureScheme,
+    ) -> Result<()> {
+        let event_authority =
+            event_cpi_authority(&ctx.accounts.event_authority, &ctx.accounts.event_program);
+        execute_native_withdrawal(
+            ctx.program_id,
+            NativeWithdrawalAccounts {
+                state: &mut ctx.accounts.state,
+                counters: &mut ctx.accounts.counters,
+                stats: &mut ctx.accounts.stats,
+                token_config: &mut ctx.accounts.token_config,
+                withdrawal_record: &mut ctx.accounts.withdrawal_record,
+                trader_ledger: ctx.accounts.trader_ledger.as_mut(),
+                wrapped_sol_mint: &ctx.accounts.wrapped_sol_mint,
+                program_sol_account: &ctx.accounts.program_sol_account,
+                trader: &ctx.accounts.trader,
+                blacklist_entry: &ctx.accounts.blacklist_entry,
+                trader_wsol_account: ctx.accounts.trader_wsol_account.as_ref(),
+                token_program: ctx.accounts.token_program.as_ref(),
+                system_program: &ctx.accounts.system_program,
+                event_authority,
+            },
+            id,
+            amount,
+            v,
+            r,
+            s,
+            wrap,
+            sig_version,
+            scheme,
+            "withdraw_native",
+        )?;
+
+        record_activity(
+            &mut ctx.accounts.activity_counter,
+            ctx.accounts.trader.key(),
+            "withdraw_native",
+        )?;
+
+        Ok(())
+    }
+
+    // Require approver signatures for the trader's deposits above a threshold
+    pub fn register_deposit_policy(
+        ctx: Context<RegisterDepositPolicy>,
+        approver: [u8; 20],
+        threshold: u64,
+    ) -> Result<()> {
+        require_logged!(approver != [0u8; 20], RbxError::InvalidSigner, "register_deposit_policy");
+
+        let policy = &mut ctx.accounts.deposit_policy;
+        policy.trader = ctx.accounts.trader.key();
+        policy.approver = approver;
+        policy.threshold = threshold;
+        policy.nonce = 0;
+        policy.approved_token = Pubkey::default();
+        policy.approved_amount = 0;
+
+        emit!(DepositPolicyEvent {
+            trader: policy.trader,
+            approver,
+            threshold,
+        });
+
+        Ok(())
+    }
+
+    // Link the wallet to its off-chain profile id, attested by the backend signer.
+    // Deposits passing the binding report the profile id in their events
+    pub fn bind_profile(
+        ctx: Context<BindProfile>,
+        profile_id: u64,
+        v: u8,
+        r: [u8; 32],
+        s: [u8; 32],
+    ) -> Result<()> {
+        let wallet = ctx.accounts.wallet.key();
+        let wallet_word = encode_trader(&wallet, ctx.accounts.state.trader_encoding);
+        let struct_hash = get_profile_binding_hash(wallet_word, profile_id);
+        let domain_separator = eip712::get_domain_separator(&mut ctx.accounts.state);
+        let digest = hash_typed_data(&domain_separator, &struct_hash);
+        let sig_result = verify_secp256k1_signature(
+            &digest,
+            v,
+            &r,
+            &s,
+            &ctx.accounts.state.withdrawal_signer,
+        )?;
+        require_logged!(sig_result, RbxError::InvalidSignature, "bind_profile");
+
+        let binding = &mut ctx.accounts.profile_binding;
+        binding.wallet = wallet;
+        binding.profile_id = profile_id;
+        binding.bound_at = Clock::get()?.unix_timestamp;
+
+        emit!(ProfileBoundEvent { wallet, profile_id });
+
+        Ok(())
+    }
+
+    // Change the approver or threshold, authorized by the current approver
+    pub fn update_deposit_policy(
+        ctx: Context<UpdateDepositPolicy>,
+        approver: [u8; 20],
+        threshold: u64,
+        nonce: u64,
+        v: u8,
+        r: [u8; 32],
+        s: [u8; 32],
+    ) -> Result<()> {
+        require_logged!(approver != [0u8; 20], RbxError::InvalidSigner, "update_deposit_policy");
+
+        let policy = &mut ctx.accounts.deposit_policy;
+        require_logged!(nonce == policy.nonce, RbxError::InvalidNonce, "update_deposit_policy");
+
+        let mut hasher = Keccak256::new();
+        hasher.update(DEPOSIT_POLICY_UPDATE_TYPEHASH);
+        hasher.update(policy.trader.to_bytes());
+        hasher.update(approver);
+        hasher.update(threshold.to_be_bytes());
+        hasher.update(nonce.to_be_bytes());
+        let struct_hash: [u8; 32] = hasher.finalize().into();
+
+        let domain_separator = eip712::get_domain_separator(&mut ctx.accounts.state);
+        let digest = hash_typed_data(&domain_separator, &struct_hash);
+        let sig_result = verify_secp256k1_signature(&digest, v, &r, &s, &policy.approver)?;
+        require_logged!(sig_result, RbxError::InvalidSignature, "update_deposit_policy");
+
+        policy.approver = approver;
+        policy.threshold = threshold;
+        policy.nonce = policy
+            .nonce
+            .checked_add(1)
+            .ok_or_else(|| logged_error!(RbxError::CounterOverflow, "update_deposit_policy"))?;
+
+        emit!(DepositPolicyEvent {
+            trader: policy.trader,
+            approver,
+            threshold,
+        });
+
+        Ok(())
+    }
+
+    // Record an approver-signed authorization consumed by the trader's next matching deposit
+    pub fn approve_deposit(
+        ctx: Context<ApproveDeposit>,
+        token: Pubkey,
+        amount: u64,
+        nonce: u64,
+        v: u8,
+        r: [u8; 32],
+        s: [u8; 32],
+    ) -> Result<()> {
+        let policy = &mut ctx.accounts.deposit_policy;
+        require_logged!(
+            nonce == policy.nonce,
+            RbxError::InvalidNonce,
+            "approve_deposit",
+            token,
+            amount,
+            0
+        );
+
+        let mut hasher = Keccak256::new();
+        hasher.update(DEPOSIT_APPROVAL_TYPEHASH);
+        hasher.update(policy.trader.to_bytes());
+        hasher.update(token.to_bytes());
+        hasher.update(amount.to_be_bytes());
+        hasher.update(nonce.to_be_bytes());
+        let struct_hash: [u8; 32] = hasher.finalize().into();
+
+        let domain_separator = eip712::get_domain_separator(&mut ctx.accounts.state);
+        let digest = hash_typed_data(&domain_separator, &struct_hash);
+        let sig_result = verify_secp256k1_signature(&digest, v, &r, &s, &policy.approver)?;
+        require_logged!(
+            sig_result,
+            RbxError::InvalidSignature,
+            "approve_deposit",
+            token,
+            amount,
+            0
+        );
+
+        policy.approved_token = token;
+        policy.approved_amount = amount;
+        policy.nonce = policy
+            .nonce
+            .checked_add(1)
+            .ok_or_else(|| logged_error!(RbxError::CounterOverflow, "approve_deposit"))?;
+
+        emit!(DepositApprovedEvent {
+            trader: policy.trader,
+            token,
+            amount,
+            nonce,
+        });
+
+        Ok(())
+    }
+
+    // Censorship-evident withdrawal request channel for when the exchange API is unavailable
+    pub fn request_withdrawal(ctx: Context<RequestWithdrawal>, token: Pubkey, amount: u64) -> Result<()> {
+        require_logged!(amount > 0, RbxError::WrongAmount, "request_withdrawal", token, amount, 0);
+
+        emit!(WithdrawalRequestedEvent {
+            trader: ctx.accounts.trader.key(),
+            token,
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    // Record a withdrawal request on-chain so it can become a forced claim if left unanswered
+    pub fn file_withdrawal_request(
+        ctx: Context<FileWithdrawalRequest>,
+        amount: u64,
+    ) -> Result<()> {
+        require_logged!(
+            amount > 0,
+            RbxError::WrongAmount,
+            "file_withdrawal_request",
+            ctx.accounts.mint.key(),
+            amount,
+            0
+        );
+        let token = ctx.accounts.mint.key();
+
+        let timestamp = Clock::get()?.unix_timestamp;
+        let request = &mut ctx.accounts.withdrawal_request;
+        request.trader = ctx.accounts.trader.key();
+        request.token = token;
+        request.amount = amount;
+        request.requested_at = timestamp;
+
+        emit!(WithdrawalRequestedEvent {
+            trader: request.trader,
+            token,
+            amount,
+            timestamp,
+        });
+
+        Ok(())
+    }
+
+    // Backstop exit: pay out a stale request, capped by the trader's ledger balance
+    pub fn force_withdraw_token(ctx: Context<ForceWithdrawToken>) -> Result<()> {
+        require_logged!(
+            ctx.accounts.counters.reentry_lock_status == UNLOCKED,
+            RbxError::ReentrancyDetected,
+            "force_withdraw_token",
+            ctx.accounts.mint.key(),
+            0,
+            0
+        );
+        enforce_not_blocked(&ctx.accounts.blacklist_entry, "force_withdraw_token")?;
+        ctx.accounts.counters.reentry_lock_status = LOCKED;
+
+        enforce_withdrawal_allowlist(
+            &ctx.accounts.withdrawal_allowlist,
+            &ctx.accounts.trader_token_account,
+            ctx.accounts.trader.key(),
+            "force_withdraw_token",
+        )?;
+
+        let amount = process_forced_withdrawal(
+            &ctx.accounts.state,
+            &ctx.accounts.withdrawal_request,
+            &mut ctx.accounts.trader_ledger,
+            "force_withdraw_token",
+        )?;
+
+        // Transfer tokens from program to trader
+        let seeds = &[
+            b"token_authority".as_ref(),
+            &[ctx.accounts.state.token_account_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        ensure_vault_not_frozen(&ctx.accounts.program_token_account.to_account_info())?;
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.program_token_account.to_account_info(),
+                to: ctx.accounts.trader_token_account.to_account_info(),
+                authority: ctx.accounts.program_token_authority.to_account_info(),
+            },
+            signer,
+        );
+        token::transfer(transfer_ctx, amount)?;
+
+        ctx.accounts.counters.reentry_lock_status = UNLOCKED;
+
+        ctx.accounts.token_config.remove_liability(amount);
+
+        ctx.accounts.stats.record_withdrawal(ctx.accounts.mint.key(), amount);
+
+        emit!(ForcedWithdrawalEvent {
+            trader: ctx.accounts.trader.key(),
+            token: ctx.accounts.mint.key(),
+            requested_amount: ctx.accounts.withdrawal_request.amount,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn force_withdraw_native(ctx: Context<ForceWithdrawNative>) -> Result<()> {
+        require_logged!(
+            ctx.accounts.counters.reentry_lock_status == UNLOCKED,
+            RbxError::ReentrancyDetected,
+            "force_withdraw_native",
+            ctx.accounts.wrapped_sol_mint.key(),
+            0,
+            0
+        );
+        enforce_not_blocked(&ctx.accounts.blacklist_entry, "force_withdraw_native")?;
+        ctx.accounts.counters.reentry_lock_status = LOCKED;
+
+        let amount = process_forced_withdrawal(
+            &ctx.accounts.state,
+            &ctx.accounts.withdrawal_request,
+            &mut ctx.accounts.trader_ledger,
+            "force_withdraw_native",
+        )?;
+
+        // Transfer SOL from program to trader
+        let seeds = &[
+            ctx.accounts.state.sol_account_seed(),
+            &[ctx.accounts.state.sol_account_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        // Keep the SOL account rent exempt, ignoring the reserve on this escape path
+        enforce_sol_rent_floor(
+            ctx.accounts.program_sol_account.lamports(),
+            amount,
+            "force_withdraw_native",
+        )?;
+
+        let transfer_ix = solana_program::system_instruction::transfer(
+            &ctx.accounts.program_sol_account.key(),
+            &ctx.accounts.trader.key(),
+            amount,
+        );
+
+        solana_program::program::invoke_signed(
+            &transfer_ix,
+            &[
+                ctx.accounts.program_sol_account.to_account_info(),
+                ctx.accounts.trader.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+            signer,
+        )?;
+
+        ctx.accounts.counters.custodied_lamports =
+            ctx.accounts.counters.custodied_lamports.saturating_sub(amount);
+
+        ctx.accounts.counters.reentry_lock_status = UNLOCKED;
+
+        ctx.accounts.token_config.remove_liability(amount);
+
+        ctx.accounts.stats.record_withdrawal(ctx.accounts.wrapped_sol_mint.key(), amount);
+
+        emit!(ForcedWithdrawalEvent {
+            trader: ctx.accounts.trader.key(),
+            token: ctx.accounts.wrapped_sol_mint.key(),
+            requested_amount: ctx.accounts.withdrawal_request.amount,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    // Pay out a filed request co-signed by the withdrawal signer, available before it goes stale
+    pub fn release_withdrawal_request(
+        ctx: Context<ForceWithdrawToken>,
+        v: u8,
+        r: [u8; 32],
+        s: [u8; 32],
+    ) -> Result<()> {
+        require_logged!(
+            ctx.accounts.counters.reentry_lock_status == UNLOCKED,
+            RbxError::ReentrancyDetected,
+            "release_withdrawal_request",
+            ctx.accounts.mint.key(),
+            0,
+            0
+        );
+        enforce_not_blocked(&ctx.accounts.blacklist_entry, "release_withdrawal_request")?;
+        ctx.accounts.counters.reentry_lock_status = LOCKED;
+
+        enforce_withdrawal_allowlist(
+            &ctx.accounts.withdrawal_allowlist,
+            &ctx.accounts.trader_token_account,
+            ctx.accounts.trader.key(),
+            "release_withdrawal_request",
+        )?;
+
+        let amount = process_released_withdrawal(
+            &mut ctx.accounts.state,
+            &ctx.accounts.withdrawal_request,
+            &mut ctx.accounts.trader_ledger,
+            v,
+            r,
+            s,
+            "release_withdrawal_request",
+        )?;
+
+        // Transfer tokens from program to trader
+        let seeds = &[
+            b"token_authority".as_ref(),
+            &[ctx.accounts.state.token_account_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        ensure_vault_not_frozen(&ctx.accounts.program_token_account.to_account_info())?;
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.program_token_account.to_account_info(),
+                to: ctx.accounts.trader_token_account.to_account_info(),
+                authority: ctx.accounts.program_token_authority.to_account_info(),
+            },
+            signer,
+        );
+        token::transfer(transfer_ctx, amount)?;
+
+        ctx.accounts.counters.reentry_lock_status = UNLOCKED;
+
+        ctx.accounts.token_config.remove_liability(amount);
+
+        ctx.accounts.stats.record_withdrawal(ctx.accounts.mint.key(), amount);
+
+        emit!(WithdrawalReleasedEvent {
+            trader: ctx.accounts.trader.key(),
+            token: ctx.accounts.mint.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn release_withdrawal_request_native(
+        ctx: Context<ForceWithdrawNative>,
+        v: u8,
+        r: [u8; 32],
+        s: [u8; 32],
+    ) -> Result<()> {
+        require_logged!(
+            ctx.accounts.counters.reentry_lock_status == UNLOCKED,
+            RbxError::ReentrancyDetected,
+            "release_withdrawal_request_native",
+            ctx.accounts.wrapped_sol_mint.key(),
+            0,
+            0
+        );
+        enforce_not_blocked(&ctx.accounts.blacklist_entry, "release_withdrawal_request_native")?;
+        ctx.accounts.counters.reentry_lock_status = LOCKED;
+
+        let amount = process_released_withdrawal(
+            &mut ctx.accounts.state,
+            &ctx.accounts.withdrawal_request,
+            &mut ctx.accounts.trader_ledger,
+            v,
+            r,
+            s,
+            "release_withdrawal_request_native",
+        )?;
+
+        // Transfer SOL from program to trader
+        let seeds = &[
+            ctx.accounts.state.sol_account_seed(),
+            &[ctx.accounts.state.sol_account_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        // Keep the SOL account rent exempt, ignoring the reserve on this escape path
+        enforce_sol_rent_floor(
+            ctx.accounts.program_sol_account.lamports(),
+            amount,
+            "release_withdrawal_request_native",
+        )?;
+
+        let transfer_ix = solana_program::system_instruction::transfer(
+            &ctx.accounts.program_sol_account.key(),
+            &ctx.accounts.trader.key(),
+            amount,
+        );
+
+        solana_program::program::invoke_signed(
+            &transfer_ix,
+            &[
+                ctx.accounts.program_sol_account.to_account_info(),
+                ctx.accounts.trader.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+            signer,
+        )?;
+
+        ctx.accounts.counters.custodied_lamports =
+            ctx.accounts.counters.custodied_lamports.saturating_sub(amount);
+
+        ctx.accounts.counters.reentry_lock_status = UNLOCKED;
+
+        ctx.accounts.token_config.remove_liability(amount);
+
+        ctx.accounts.stats.record_withdrawal(ctx.accounts.wrapped_sol_mint.key(), amount);
+
+        emit!(WithdrawalReleasedEvent {
+            trader: ctx.accounts.trader.key(),
+            token: ctx.accounts.wrapped_sol_mint.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    // Opt in to restricting token withdrawals to a list of destination accounts
+    pub fn open_withdrawal_allowlist(ctx: Context<OpenWithdrawalAllowlist>) -> Result<()> {
+        let allowlist = &mut ctx.accounts.withdrawal_allowlist;
+        allowlist.trader = ctx.accounts.trader.key();
+        allowlist.entries = Vec::new();
+
+        Ok(())
+    }
+
+    pub fn add_allowlisted_destination(
+        ctx: Context<ManageWithdrawalAllowlist>,
+        destination: Pubkey,
+    ) -> Result<()> {
+        let allowlist = &mut ctx.accounts.withdrawal_allowlist;
+        require_logged!(
+            !allowlist.entries.iter().any(|e| e.destination == destination),
+            RbxError::DestinationAlreadyAllowlisted,
+            "add_allowlisted_destination"
+        );
+        require_logged!(
+            allowlist.entries.len() < MAX_ALLOWLIST_ENTRIES,
+            RbxError::TooManyAllowlistEntries,
+            "add_allowlisted_destination"
+        );
+
+        // Delay activation so a compromised trader key cannot redirect funds immediately
+        let active_at = Clock::get()?
+            .unix_timestamp
+            .checked_add(ALLOWLIST_ADD_DELAY)
+            .ok_or_else(|| {
+                logged_error!(RbxError::TimestampOverflow, "add_allowlisted_destination")
+            })?;
+        allowlist.entries.push(AllowlistEntry {
+            destination,
+            active_at,
+        });
+
+        emit!(AllowlistDestinationAddedEvent {
+            trader: allowlist.trader,
+            destination,
+            active_at,
+        });
+
+        Ok(())
+    }
+
+    pub fn remove_allowlisted_destination(
+        ctx: Context<ManageWithdrawalAllowlist>,
+        destination: Pubkey,
+    ) -> Result<()> {
+        let allowlist = &mut ctx.accounts.withdrawal_allowlist;
+        let position = allowlist
+            .entries
+            .iter()
+            .position(|e| e.destination == destination)
+            .ok_or_else(|| {
+                logged_error!(
+                    RbxError::DestinationNotAllowlisted,
+                    "remove_allowlisted_destination"
+                )
+            })?;
+        allowlist.entries.remove(position);
+
+        emit!(AllowlistDestinationRemovedEvent {
+            trader: allowlist.trader,
+            destination,
+        });
+
+        Ok(())
+    }
+
+    // Block an address from depositing, staking and withdrawing
+    pub fn block_address(ctx: Context<BlockAddress>, address: Pubkey) -> Result<()> {
+        let entry = &mut ctx.accounts.blacklist_entry;
+        entry.address = address;
+        entry.blocked_at = Clock::get()?.unix_timestamp;
+
+        emit!(AddressBlockedEvent {
+            address,
+            authority: ctx.accounts.compliance_authority.key(),
+        });
+
+        Ok(())
+    }
+
+    pub fn unblock_address(ctx: Context<UnblockAddress>, address: Pubkey) -> Result<()> {
+        emit!(AddressUnblockedEvent {
+            address,
+            authority: ctx.accounts.compliance_authority.key(),
+        });
+
+        Ok(())
+    }
+
+    // Re-emit the attestation hash of a receipted deposit for backends that verify deposits
+    pub fn attest_deposit(ctx: Context<AttestDeposit>, deposit_num: u64) -> Result<()> {
+        let receipt = &ctx.accounts.deposit_receipt;
+        emit!(DepositAttestationEvent {
+            deposit_num,
+            trader: receipt.trader,
+            token: receipt.token,
+            amount: receipt.amount,
+            hash: get_deposit_attestation_hash(receipt),
+        });
+
+        Ok(())
+    }
+
+    pub fn get_deposit_attestation(
+        ctx: Context<AttestDeposit>,
+        _deposit_num: u64,
+    ) -> Result<[u8; 32]> {
+        Ok(get_deposit_attestation_hash(&ctx.accounts.deposit_receipt))
+    }
+
+    // Close a deposit receipt that is no longer needed and reclaim its rent
+    pub fn close_deposit_receipt(_ctx: Context<CloseDepositReceipt>, _deposit_num: u64) -> Result<()> {
+        Ok(())
+    }
+
+    // Create the on-chain balance ledger of a trader and token, paid for by anyone. Deposits
+    // and withdrawals require it
+    pub fn open_trader_ledger(ctx: Context<OpenTraderLedger>) -> Result<()> {
+        let ledger = &mut ctx.accounts.trader_ledger;
+        ledger.trader = ctx.accounts.trader.key();
+        ledger.token = ctx.accounts.mint.key();
+        ledger.balance = 0;
+        ledger.last_withdrawal_at = 0;
+        Ok(())
+    }
+
+    // Create the activity counter of a trader, paid for by anyone
+    pub fn open_activity_counter(ctx: Context<OpenActivityCounter>) -> Result<()> {
+        let counter = &mut ctx.accounts.activity_counter;
+        counter.trader = ctx.accounts.trader.key();
+        counter.count = 0;
+        counter.last_slot = 0;
+        Ok(())
+    }
+
+    // Opt in to on-chain tracking of a trader's stakes in a token
+    pub fn open_stake_position(ctx: Context<OpenStakePosition>) -> Result<()> {
+        let position = &mut ctx.accounts.stake_position;
+        position.trader = ctx.accounts.trader.key();
+        position.token = ctx.accounts.mint.key();
+        position.staked = 0;
+        position.locked = 0;
+        Ok(())
+    }
+
+    // Create the empty reward emission schedule of a stake token
+    pub fn init_emission_schedule(ctx: Context<InitEmissionSchedule>) -> Result<()> {
+        let schedule = &mut ctx.accounts.schedule;
+        schedule.token = ctx.accounts.mint.key();
+        schedule.segments = Vec::new();
+        Ok(())
+    }
+
+    // Replace a token's emission schedule with the segments approved through the timelock
+    pub fn set_emission_schedule(
+        ctx: Context<SetEmissionSchedule>,
+        segments: Vec<EmissionSegment>,
+    ) -> Result<()> {
+        require_logged!(
+            ctx.accounts
+                .state
+                .timelock_authorities
+                .contains(&ctx.accounts.authority.key()),
+            RbxError::UnauthorizedAccess,
+            "set_emission_schedule"
+        );
+        require_logged!(
+            segments.len() <= MAX_EMISSION_SEGMENTS
+                && segments.windows(2).all(|pair| pair[0].start < pair[1].start),
+            RbxError::InvalidEmissionSchedule,
+            "set_emission_schedule"
+        );
+
+        let token = ctx.accounts.schedule.token;
+        let schedule_hash: [u8; 32] = Keccak256::digest(segments.try_to_vec()?).into();
+        let state = &mut ctx.accounts.state;
+        let position = state
+            .approved_emission_schedules
+            .iter()
+            .position(|approval| *approval == (token, schedule_hash))
+            .ok_or_else(|| {
+                logged_error!(
+                    RbxError::EmissionScheduleNotApproved,
+                    "set_emission_schedule"
+                )
+            })?;
+        // Approvals are single use
+        state.approved_emission_schedules.remove(position);
+
+        ctx.accounts.schedule.segments = segments;
+
+        emit!(SetEmissionScheduleEvent {
+            token,
+            schedule_hash,
+        });
+
+        Ok(())
+    }
+
+    // Apply the allowed_purposes mask approved through the timelock for the token
+    pub fn set_allowed_purposes(
+        ctx: Context<SetAllowedPurposes>,
+        allowed_purposes: u8,
+    ) -> Result<()> {
+        require_logged!(
+            ctx.accounts
+                .state
+                .timelock_authorities
+                .contains(&ctx.accounts.authority.key()),
+            RbxError::UnauthorizedAccess,
+            "set_allowed_purposes"
+        );
+
+        let token = ctx.accounts.token_config.mint;
+        let state = &mut ctx.accounts.state;
+        let position = state
+            .approved_allowed_purposes
+            .iter()
+            .position(|approval| *approval == (token, allowed_purposes))
+            .ok_or_else(|| {
+                logged_error!(RbxError::AllowedPurposesNotApproved, "set_allowed_purposes")
+            })?;
+        // Approvals are single use
+        state.approved_allowed_purposes.remove(position);
+
+        ctx.accounts.token_config.allowed_purposes = allowed_purposes;
+
+        emit!(SetAllowedPurposesEvent {
+            token,
+            allowed_purposes,
+        });
+
+        Ok(())
+    }
+
+    // Rewards the schedule emits over [from, to), summed segment by segment
+    pub fn get_emitted_rewards(ctx: Context<GetEmittedRewards>, from: i64, to: i64) -> Result<u64> {
+        let emitted = ctx.accounts.schedule.emitted_between(from, to);
+        Ok(u64::try_from(emitted).unwrap_or(u64::MAX))
+    }
+
+    // Top up an open stake position; a stake_token that requires the position
+    pub fn increase_stake(ctx: Context<DepositToken>, amount: u64) -> Result<()> {
+        let position = ctx
+            .accounts
+            .stake_position
+            .as_ref()
+            .ok_or_else(|| logged_error!(RbxError::InvalidStakePosition, "increase_stake"))?;
+        let (trader, token) = (position.trader, position.token);
+        let staked = position
+            .staked
+            .checked_add(amount)
+            .ok_or_else(|| logged_error!(RbxError::InsufficientStake, "increase_stake"))?;
+
+        stake_token(ctx, amount)?;
+
+        emit!(StakeAdjustedEvent {
+            trader,
+            token,
+            increased: true,
+            amount,
+            staked,
+        });
+
+        Ok(())
+    }
+
+    // Unstake part of a position. Only the portion not locked as collateral can be
+    // released; the tokens return to the trader's exchange balance, not their wallet
+    pub fn decrease_stake(ctx: Context<DecreaseStake>, amount: u64) -> Result<()> {
+        let position = &mut ctx.accounts.stake_position;
+        require_logged!(
+            amount > 0,
+            RbxError::WrongAmount,
+            "decrease_stake",
+            position.token,
+            amount,
+            0
+        );
+        require_logged!(
+            amount <= position.staked.saturating_sub(position.locked),
+            RbxError::InsufficientStake,
+            "decrease_stake",
+            position.token,
+            amount,
+            0
+        );
+        position.staked -= amount;
+
+        emit!(StakeAdjustedEvent {
+            trader: position.trader,
+            token: position.token,
+            increased: false,
+            amount,
+            staked: position.staked,
+        });
+
+        Ok(())
+    }
+
+    // Margin engine marks part of a stake as collateral for trading credit
+    pub fn lock_collateral(ctx: Context<ManageCollateral>, amount: u64) -> Result<()> {
+        require_logged!(
+            amount > 0,
+            RbxError::WrongAmount,
+            "lock_collateral",
+            Pubkey::default(),
+            amount,
+            0
+        );
+        let position = &mut ctx.accounts.stake_position;
+        let locked = position
+            .locked
+            .checked_add(amount)
+            .ok_or_else(|| logged_error!(RbxError::InsufficientStake, "lock_collateral"))?;
+        require_logged!(
+            locked <= position.staked,
+            RbxError::InsufficientStake,
+            "lock_collateral",
+            Pubkey::default(),
+            amount,
+            0
+        );
+        position.locked = locked;
+
+        emit!(CollateralLockedEvent {
+            trader: position.trader,
+            token: position.token,
+            amount,
+            locked,
+        });
+
+        Ok(())
+    }
+
+    pub fn release_collateral(ctx: Context<ManageCollateral>, amount: u64) -> Result<()> {
+        require_logged!(
+            amount > 0,
+            RbxError::WrongAmount,
+            "release_collateral",
+            Pubkey::default(),
+            amount,
+            0
+        );
+        let position = &mut ctx.accounts.stake_position;
+        require_logged!(
+            amount <= position.locked,
+            RbxError::InsufficientCollateral,
+            "release_collateral",
+            Pubkey::default(),
+            amount,
+            0
+        );
+        position.locked -= amount;
+
+        emit!(CollateralReleasedEvent {
+            trader: position.trader,
+            token: position.token,
+            amount,
+            locked: position.locked,
+        });
+
+        Ok(())
+    }
+
+    // Create the account that holds deferred remainders of partial withdrawals
+    pub fn open_partial_withdrawal(ctx: Context<OpenPartialWithdrawal>) -> Result<()> {
+        let partial = &mut ctx.accounts.partial_withdrawal;
+        partial.trader = ctx.accounts.trader.key();
+        partial.token = ctx.accounts.mint.key();
+        partial.remaining = 0;
+        Ok(())
+    }
+
+    // Pay out a deferred remainder, again capped by what the vault holds
+    pub fn claim_partial_withdrawal(ctx: Context<ClaimPartialWithdrawal>) -> Result<()> {
+        require_logged!(
+            ctx.accounts.counters.reentry_lock_status == UNLOCKED,
+            RbxError::ReentrancyDetected,
+            "claim_partial_withdrawal",
+            ctx.accounts.mint.key(),
+            0,
+            0
+        );
+        enforce_not_blocked(&ctx.accounts.blacklist_entry, "claim_partial_withdrawal")?;
+        ctx.accounts.counters.reentry_lock_status = LOCKED;
+
+        enforce_withdrawal_allowlist(
+            &ctx.accounts.withdrawal_allowlist,
+            &ctx.accounts.trader_token_account,
+            ctx.accounts.trader.key(),
+            "claim_partial_withdrawal",
+        )?;
+
+        let partial = &mut ctx.accounts.partial_withdrawal;
+        let amount = partial.remaining.min(ctx.accounts.program_token_account.amount);
+        require_logged!(
+            amount > 0,
+            RbxError::InsufficientFunds,
+            "claim_partial_withdrawal",
+            ctx.accounts.mint.key(),
+            0,
+            0
+        );
+        partial.remaining -= amount;
+        let remaining = partial.remaining;
+
+        // Transfer tokens from program to trader
+        let seeds = &[
+            b"token_authority".as_ref(),
+            &[ctx.accounts.state.token_account_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        ensure_vault_not_frozen(&ctx.accounts.program_token_account.to_account_info())?;
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.program_token_account.to_account_info(),
+                to: ctx.accounts.trader_token_account.to_account_info(),
+                authority: ctx.accounts.program_token_authority.to_account_info(),
+            },
+            signer,
+        );
+        token::transfer(transfer_ctx, amount)?;
+
+        ctx.accounts.counters.reentry_lock_status = UNLOCKED;
+
+        ctx.accounts.token_config.remove_liability(amount);
+
+        ctx.accounts.stats.record_withdrawal(ctx.accounts.mint.key(), amount);
+
+        emit!(PartialWithdrawalClaimedEvent {
+            trader: ctx.accounts.trader.key(),
+            token: ctx.accounts.mint.key(),
+            amount,
+            remaining,
+        });
+
+        Ok(())
+    }
+
+    // Create the withdrawal record shard up front so withdrawals don't need init_if_needed
+    pub fn create_withdrawal_record(ctx: Context<CreateWithdrawalRecord>, index: u64) -> Result<()> {
+        ctx.accounts.withdrawal_record.index = index;
+        Ok(())
+    }
+
+    // Create a withdrawal record shard with rent drawn from the rent treasury instead of the caller.
+    // Limited to shards near the watermark so the treasury can't be drained with far-off indices
+    pub fn create_withdrawal_record_sponsored(
+        ctx: Context<CreateWithdrawalRecordSponsored>,
+        index: u64,
+    ) -> Result<()> {
+        let watermark_shard = ctx.accounts.state.high_watermark_id / WITHDRAWALS_PER_ACCOUNT as u64;
+        require_logged!(
+            index <= watermark_shard + MAX_SPONSORED_SHARDS_AHEAD,
+            RbxError::InvalidWithdrawalRecord,
+            "create_withdrawal_record_sponsored"
+        );
+        require_logged!(
+            ctx.accounts.withdrawal_record.data_is_empty(),
+            RbxError::InvalidWithdrawalRecord,
+            "create_withdrawal_record_sponsored"
+        );
+
+        // The treasury has no data, but must stay above its own rent-exempt minimum
+        let space = 8 + WithdrawalRecord::SIZE;
+        let rent = Rent::get()?.minimum_balance(space);
+        require_logged!(
+            ctx.accounts.rent_treasury.lamports() >= rent + Rent::get()?.minimum_balance(0),
+            RbxError::RentTreasuryDepleted,
+            "create_withdrawal_record_sponsored"
+        );
+
+        let index_bytes = index.to_le_bytes();
+        let treasury_seeds = &[b"rent_treasury".as_ref(), &[ctx.bumps.rent_treasury]];
+        let record_seeds = &[
+            b"withdrawal_account".as_ref(),
+            index_bytes.as_ref(),
+            &[ctx.bumps.withdrawal_record],
+        ];
+
+        solana_program::program::invoke_signed(
+            &solana_program::system_instruction::create_account(
+                &ctx.accounts.rent_treasury.key(),
+                &ctx.accounts.withdrawal_record.key(),
+                rent,
+                space as u64,
+                &crate::ID,
+            ),
+            &[
+                ctx.accounts.rent_treasury.to_account_info(),
+                ctx.accounts.withdrawal_record.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+            &[&treasury_seeds[..], &record_seeds[..]],
+        )?;
+
+        let record = WithdrawalRecord {
+            index,
+            processed_bits: [0; WITHDRAWAL_BITMAP_SIZE],
+        };
+        let mut data = ctx.accounts.withdrawal_record.try_borrow_mut_data()?;
+        record.try_serialize(&mut &mut data[..])?;
+
+        ctx.accounts.state.sponsored_rent += rent;
+
+        emit!(WithdrawalRecordSponsoredEvent {
+            index,
+            rent,
+            treasury_balance: ctx.accounts.rent_treasury.lamports(),
+        });
+
+        Ok(())
+    }
+
+    // Fund the rent treasury that pays for sponsored withdrawal records
+    pub fn top_up_rent_treasury(ctx: Context<TopUpRentTreasury>, amount: u64) -> Result<()> {
+        let transfer_ix = solana_program::system_instruction::transfer(
+            &ctx.accounts.owner.key(),
+            &ctx.accounts.rent_treasury.key(),
+            amount,
+        );
+        solana_program::program::invoke(
+            &transfer_ix,
+            &[
+                ctx.accounts.owner.to_account_info(),
+                ctx.accounts.rent_treasury.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+        )?;
+
+        ctx.accounts.state.rent_treasury_funded += amount;
+
+        emit!(RentTreasuryToppedUpEvent {
+            amount,
+            balance: ctx.accounts.rent_treasury.lamports(),
+        });
+
+        Ok(())
+    }
+
+    // Declare every withdrawal id below `id` finished, so shards under it can be closed
+    pub fn advance_withdrawal_watermark(
+        ctx: Context<AdvanceWithdrawalWatermark>,
+        id: u64,
+    ) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+        require_logged!(
+            state
+                .timelock_authorities
+                .contains(&ctx.accounts.authority.key()),
+            RbxError::UnauthorizedAccess,
+            "advance_withdrawal_watermark",
+            Pubkey::default(),
+            0,
+            id
+        );
+        require_logged!(
+            id > state.high_watermark_id,
+            RbxError::InvalidWatermark,
+            "advance_withdrawal_watermark",
+            Pubkey::default(),
+            0,
+            id
+        );
+
+        state.high_watermark_id = id;
+
+        emit!(WithdrawalWatermarkEvent { id });
+
+        Ok(())
+    }
+
+    // Permissionless: close a shard lying entirely below the watermark, rent to the treasury
+    pub fn gc_withdrawal_record(ctx: Context<GcWithdrawalRecord>, index: u64) -> Result<()> {
+        let state = &ctx.accounts.state;
+        require_logged!(
+            state.treasury != Pubkey::default(),
+            RbxError::InvalidTreasury,
+            "gc_withdrawal_record"
+        );
+        require_logged!(
+            index
+                .saturating_add(1)
+                .saturating_mul(WITHDRAWALS_PER_ACCOUNT as u64)
+                <= state.high_watermark_id,
+            RbxError::WithdrawalRecordInUse,
+            "gc_withdrawal_record"
+        );
+
+        emit!(WithdrawalRecordClosedEvent {
+            index,
+            lamports: ctx.accounts.withdrawal_record.to_account_info().lamports(),
+        });
+
+        Ok(())
+    }
+
+    // Permissionless: close the watermark shard once every id in it has been processed and the
+    // retention period has passed, moving the watermark past it. Rent goes to the treasury
+    pub fn retire_withdrawal_shard(ctx: Context<RetireWithdrawalShard>, index: u64) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+        require_logged!(
+            state.treasury != Pubkey::default(),
+            RbxError::InvalidTreasury,
+            "retire_withdrawal_shard"
+        );
+        require_logged!(
+            state.shard_retention_period > 0,
+            RbxError::ShardRetentionDisabled,
+            "retire_withdrawal_shard"
+        );
+        // Shards retire in order, so the watermark never skips ids that are still open
+        require_logged!(
+            index == state.high_watermark_id / WITHDRAWALS_PER_ACCOUNT as u64,
+            RbxError::WithdrawalRecordInUse,
+            "retire_withdrawal_shard"
+        );
+        let position = state
+            .filled_shards
+            .iter()
+            .position(|(i, _)| *i == index)
+            .ok_or_else(|| {
+                logged_error!(RbxError::WithdrawalRecordInUse, "retire_withdrawal_shard")
+            })?;
+        let filled_at = state.filled_shards[position].1;
+        require_logged!(
+            Clock::get()?.unix_timestamp >= filled_at.saturating_add(state.shard_retention_period),
+            RbxError::ShardRetentionNotElapsed,
+            "retire_withdrawal_shard"
+        );
+
+        state.filled_shards.remove(position);
+        state.high_watermark_id = (index + 1) * WITHDRAWALS_PER_ACCOUNT as u64;
+
+        emit!(WithdrawalWatermarkEvent {
+            id: state.high_watermark_id,
+        });
+        emit!(WithdrawalRecordClosedEvent {
+            index,
+            lamports: ctx.accounts.withdrawal_record.to_account_info().lamports(),
+        });
+
+        Ok(())
+    }
+
+    pub fn withdraw_token_existing_record(
+        ctx: Context<WithdrawTokenExistingRecord>,
+        id: u64,
+        amount: u64,
+        v: u8,
+        r: [u8; 32],
+        s: [u8; 32],
+        sig_version: u8,
+        scheme: SignatureScheme,
+        allow_partial: bool,
+    ) -> Result<()> {
+        let event_authority = None;
+        execute_token_withdrawal(
+            ctx.program_id,
+            TokenWithdrawalAccounts {
+                state: &mut ctx.accounts.state,
+                counters: &mut ctx.accounts.counters,
+                stats: &mut ctx.accounts.stats,
+                token_config: &mut ctx.accounts.token_config,
+                withdrawal_record: &mut ctx.accounts.withdrawal_record,
+                trader_ledger: ctx.accounts.trader_ledger.as_mut(),
+                partial_withdrawal: ctx.accounts.partial_withdrawal.as_mut(),
+                mint: &ctx.accounts.mint,
+                program_token_account: &ctx.accounts.program_token_account,
+                program_token_authority: &ctx.accounts.program_token_authority,
+                trader_token_account: &ctx.accounts.trader_token_account,
+                trader: &ctx.accounts.trader,
+                recipient: ctx.accounts.trader.key(),
+                blacklist_entry: &ctx.accounts.blacklist_entry,
+                withdrawal_allowlist: &ctx.accounts.withdrawal_allowlist,
+                token_program: &ctx.accounts.token_program,
+                event_authority,
+            },
+            id,
+            amount,
+            v,
+            r,
+            s,
+            sig_version,
+            scheme,
+            allow_partial,
+            "withdraw_token_existing_record",
+        )?;
+
+        Ok(())
+    }
+
+    // Token withdrawal submitted by a third-party relayer, who is paid the signed
+    // tip out of the withdrawn amount so traders without SOL can still withdraw
+    pub fn withdraw_token_relayed(
+        ctx: Context<WithdrawTokenRelayed>,
+        id: u64,
+        amount: u64,
+        relayer_tip: u64,
+        v: u8,
+        r: [u8; 32],
+        s: [u8; 32],
+        sig_version: u8,
+        scheme: SignatureScheme,
+    ) -> Result<()> {
+        require_logged!(
+            ctx.accounts.counters.reentry_lock_status == UNLOCKED,
+            RbxError::ReentrancyDetected,
+            "withdraw_token_relayed",
+            ctx.accounts.mint.key(),
+            amount,
+            id
+        );
+        enforce_not_blocked(&ctx.accounts.blacklist_entry, "withdraw_token_relayed")?;
+        ctx.accounts.counters.reentry_lock_status = LOCKED;
+
+        let decimals = mint_decimals(&ctx.accounts.mint)?;
+
+        enforce_withdrawal_allowlist(
+            &ctx.accounts.withdrawal_allowlist,
+            &ctx.accounts.trader_token_account,
+            ctx.accounts.trader.key(),
+            "withdraw_token_relayed",
+        )?;
+
+        // Process common withdrawal logic, resolving the amount actually paid out
+        let amount = process_withdrawal(
+            ctx.program_id,
+            &mut ctx.accounts.state,
+            Some(&mut ctx.accounts.withdrawal_record),
+            ctx.accounts.trader_ledger.as_mut(),
+            id,
+            amount,
+            ctx.accounts.mint.key(),
+            decimals,
+            ctx.accounts.trader.key(),
+            ctx.accounts.trader.key(),
+            v,
+            r,
+            s,
+            sig_version,
+            scheme,
+            relayer_tip,
+        )?;
+
+        let seeds = &[
+            b"token_authority".as_ref(),
+            &[ctx.accounts.state.token_account_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        // The trader receives the amount less the tip
+        ensure_vault_not_frozen(&ctx.accounts.program_token_account.to_account_info())?;
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.program_token_account.to_account_info(),
+                to: ctx.accounts.trader_token_account.to_account_info(),
+                authority: ctx.accounts.program_token_authority.to_account_info(),
+            },
+            signer,
+        );
+        token::transfer(transfer_ctx, amount - relayer_tip)?;
+
+        if relayer_tip > 0 {
+            let tip_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.program_token_account.to_account_info(),
+                    to: ctx.accounts.relayer_token_account.to_account_info(),
+                    authority: ctx.accounts.program_token_authority.to_account_info(),
+                },
+                signer,
+            );
+            token::transfer(tip_ctx, relayer_tip)?;
+        }
+
+        // Unlock reentrancy lock
+        ctx.accounts.counters.reentry_lock_status = UNLOCKED;
+
+        ctx.accounts.token_config.remove_liability(amount);
+
+        ctx.accounts.stats.record_withdrawal(ctx.accounts.mint.key(), amount);
+
+        let clock = Clock::get()?;
+        emit_withdrawal_event(
+            &ctx.accounts.state,
+            &None,
+            WithdrawalEvent {
+                id,
+                trader: ctx.accounts.trader.key(),
+                recipient: ctx.accounts.trader.key(),
+                amount,
+                amount_e18: to_e18(amount, decimals),
+                token: ctx.accounts.mint.key(),
+                slot: clock.slot,
+                unix_timestamp: clock.unix_timestamp,
+            },
+        )?;
+
+        if relayer_tip > 0 {
+            emit!(RelayerTipEvent {
+                id,
+                relayer: ctx.accounts.payer.key(),
+                token: ctx.accounts.mint.key(),
+                tip: relayer_tip,
+            });
+        }
+
+        Ok(())
+    }
+
+    // Withdrawal protected by a per-id receipt instead of a bitmap shard, for sparse ids
+    pub fn withdraw_token_nonced(
+        ctx: Context<WithdrawTokenNonced>,
+        id: u64,
+        amount: u64,
+        v: u8,
+        r: [u8; 32],
+        s: [u8; 32],
+        sig_version: u8,
+        scheme: SignatureScheme,
+    ) -> Result<()> {
+        require_logged!(
+            ctx.accounts.counters.reentry_lock_status == UNLOCKED,
+            RbxError::ReentrancyDetected,
+            "withdraw_token_nonced",
+            ctx.accounts.mint.key(),
+            amount,
+            id
+        );
+        enforce_not_blocked(&ctx.accounts.blacklist_entry, "withdraw_token_nonced")?;
+        ctx.accounts.counters.reentry_lock_status = LOCKED;
+
+        let decimals = mint_decimals(&ctx.accounts.mint)?;
+
+        enforce_withdrawal_allowlist(
+            &ctx.accounts.withdrawal_allowlist,
+            &ctx.accounts.trader_token_account,
+            ctx.accounts.trader.key(),
+            "withdraw_token_nonced",
+        )?;
+
+        // Ids below the floor belong to the bitmap shards, which may already have marked them
+        let floor = ctx.accounts.state.withdrawal_receipt_floor;
+        require_logged!(
+            ctx.accounts.state.use_withdrawal_receipts && floor != 0 && id >= floor,
+            RbxError::ReplayProtectionMismatch,
+            "withdraw_token_nonced",
+            ctx.accounts.mint.key(),
+            amount,
+            id
+        );
+
+        // Replay is prevented by the receipt's `init`, so no bitmap record is passed
+        let amount = process_withdrawal(
+            ctx.program_id,
+            &mut ctx.accounts.state,
+            None,
+            ctx.accounts.trader_ledger.as_mut(),
+            id,
+            amount,
+            ctx.accounts.mint.key(),
+            decimals,
+            ctx.accounts.trader.key(),
+            ctx.accounts.trader.key(),
+            v,
+            r,
+            s,
+            sig_version,
+            scheme,
+            0,
+        )?;
+
+        let receipt = &mut ctx.accounts.withdrawal_receipt;
+        receipt.id = id;
+        receipt.trader = ctx.accounts.trader.key();
+        receipt.token = ctx.accounts.mint.key();
+        receipt.amount = amount;
+
+        // Transfer tokens from program to user
+        let seeds = &[
+            b"token_authority".as_ref(),
+            &[ctx.accounts.state.token_account_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        ensure_vault_not_frozen(&ctx.accounts.program_token_account.to_account_info())?;
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.program_token_account.to_account_info(),
+                to: ctx.accounts.trader_token_account.to_account_info(),
+                authority: ctx.accounts.program_token_authority.to_account_info(),
+            },
+            signer,
+        );
+        token::transfer(transfer_ctx, amount)?;
+
+        // Unlock reentrancy lock
+        ctx.accounts.counters.reentry_lock_status = UNLOCKED;
+
+        ctx.accounts.token_config.remove_liability(amount);
+
+        ctx.accounts.stats.record_withdrawal(ctx.accounts.mint.key(), amount);
+
+        let clock = Clock::get()?;
+        emit_withdrawal_event(
+            &ctx.accounts.state,
+            &None,
+            WithdrawalEvent {
+                id,
+                trader: ctx.accounts.trader.key(),
+                recipient: ctx.accounts.trader.key(),
+                amount,
+                amount_e18: to_e18(amount, decimals),
+                token: ctx.accounts.mint.key(),
+                slot: clock.slot,
+                unix_timestamp: clock.unix_timestamp,
+            },
+        )?;
+
+        Ok(())
+    }
+
+    pub fn withdraw_native_existing_record(
+        ctx: Context<WithdrawNativeExistingRecord>,
+        id: u64,
+        amount: u64,
+        v: u8,
+        r: [u8; 32],
+        s: [u8; 32],
+        wrap: bool,
+        sig_version: u8,
+        scheme: SignatureScheme,
+    ) -> Result<()> {
+        let event_authority = None;
+        execute_native_withdrawal(
+            ctx.program_id,
+            NativeWithdrawalAccounts {
+                state: &mut ctx.accounts.state,
+                counters: &mut ctx.accounts.counters,
+                stats: &mut ctx.accounts.stats,
+                token_config: &mut ctx.accounts.token_config,
+                withdrawal_record: &mut ctx.accounts.withdrawal_record,
+                trader_ledger: ctx.accounts.trader_ledger.as_mut(),
+                wrapped_sol_mint: &ctx.accounts.wrapped_sol_mint,
+                program_sol_account: &ctx.accounts.program_sol_account,
+                trader: &ctx.accounts.trader,
+                blacklist_entry: &ctx.accounts.blacklist_entry,
+                trader_wsol_account: ctx.accounts.trader_wsol_account.as_ref(),
+                token_program: ctx.accounts.token_program.as_ref(),
+                system_program: &ctx.accounts.system_program,
+                event_authority,
+            },
+            id,
+            amount,
+            v,
+            r,
+            s,
+            wrap,
+            sig_version,
+            scheme,
+            "withdraw_native_existing_record",
+        )?;
+
+        Ok(())
+    }
+
+    // Withdraw an SPL token and native SOL together, each under its own signature.
+    // Both legs succeed or the whole transaction fails.
+    // The SPL leg uses the named accounts. The native leg's accounts follow in
+    // remaining_accounts: wSOL mint, its TokenConfig, the SOL account, the trader's wSOL
+    // ledger (the program id when omitted) and, only when the native id is in another shard,
+    // its withdrawal record
+    pub fn withdraw_multi<'info>(
+        ctx: Context<'_, '_, 'info, 'info, WithdrawMulti<'info>>,
+        token_payload: WithdrawalPayload,
+        native_payload: WithdrawalPayload,
+    ) -> Result<()> {
+        require_logged!(
+            ctx.accounts.counters.reentry_lock_status == UNLOCKED,
+            RbxError::ReentrancyDetected,
+            "withdraw_multi",
+            ctx.accounts.mint.key(),
+            token_payload.amount,
+            token_payload.id
+        );
+        // A token and a native leg, so a withdrawal batch limit below two rejects it
+        check_batch_size(2, ctx.accounts.state.max_batch_withdrawals, "withdraw_multi")?;
+        enforce_not_blocked(&ctx.accounts.blacklist_entry, "withdraw_multi")?;
+        ctx.accounts.counters.reentry_lock_status = LOCKED;
+
+        // Ids sharing a shard are tracked in the token leg's record
+        let native_shard = native_payload.id / WITHDRAWALS_PER_ACCOUNT as u64;
+        let same_shard = token_payload.id / WITHDRAWALS_PER_ACCOUNT as u64 == native_shard;
+        let native_accounts = ctx.remaining_accounts;
+        require_logged!(
+            native_accounts.len() == if same_shard { 4 } else { 5 },
+            RbxError::InvalidWithdrawalRecord,
+            "withdraw_multi",
+            ctx.accounts.mint.key(),
+            token_payload.amount,
+            token_payload.id
+        );
+
+        let wrapped_sol_mint = native_accounts[0].key();
+        let mut native_token_config = Account::<TokenConfig>::try_from(&native_accounts[1])?;
+        let program_sol_account = &native_accounts[2];
+        let mut native_trader_ledger = optional_trader_ledger(&native_accounts[3])?;
+        let (sol_account_key, _) =
+            Pubkey::find_program_address(&[ctx.accounts.state.sol_account_seed()], &crate::ID);
+        require_logged!(
+            wrapped_sol_mint == token::spl_token::native_mint::ID
+                && native_token_config.mint == wrapped_sol_mint
+                && program_sol_account.key() == sol_account_key,
+            RbxError::InvalidWsolAccount,
+            "withdraw_multi",
+            wrapped_sol_mint,
+            native_payload.amount,
+            native_payload.id
+        );
+        require_logged!(
+            native_token_config.status != TokenStatus::Paused,
+            RbxError::TokenPaused,
+            "withdraw_multi",
+            wrapped_sol_mint,
+            native_payload.amount,
+            native_payload.id
+        );
+        require_logged!(
+            !native_token_config.paused_withdrawals,
+            RbxError::TokenWithdrawalsPaused,
+            "withdraw_multi",
+            wrapped_sol_mint,
+            native_payload.amount,
+            native_payload.id
+        );
+        let mut native_withdrawal_record = match native_accounts.get(4) {
+            Some(info) => {
+                let (record_key, _) = Pubkey::find_program_address(
+                    &[b"withdrawal_account".as_ref(), &native_shard.to_le_bytes()],
+                    &crate::ID,
+                );
+                require_logged!(
+                    info.key() == record_key,
+                    RbxError::WithdrawalRecordMismatch,
+                    "withdraw_multi",
+                    wrapped_sol_mint,
+                    native_payload.amount,
+                    native_payload.id
+                );
+                Some(Account::<WithdrawalRecord>::try_from(info)?)
+            }
+            None => None,
+        };
+
+        let decimals = mint_decimals(&ctx.accounts.mint)?;
+
+        enforce_withdrawal_allowlist(
+            &ctx.accounts.withdrawal_allowlist,
+            &ctx.accounts.trader_token_account,
+            ctx.accounts.trader.key(),
+            "withdraw_multi",
+        )?;
+
+        let token_amount = process_withdrawal(
+            ctx.program_id,
+            &mut ctx.accounts.state,
+            Some(&mut ctx.accounts.withdrawal_record),
+            ctx.accounts.trader_ledger.as_mut(),
+            token_payload.id,
+            token_payload.amount,
+            ctx.accounts.mint.key(),
+            decimals,
+            ctx.accounts.trader.key(),
+            ctx.accounts.trader.key(),
+            token_payload.v,
+            token_payload.r,
+            token_payload.s,
+            token_payload.sig_version,
+            token_payload.scheme,
+            0,
+        )?;
+
+        let native_record = match native_withdrawal_record.as_mut() {
+            Some(record) => record,
+            None => &mut ctx.accounts.withdrawal_record,
+        };
+        let native_amount = process_withdrawal(
+            ctx.program_id,
+            &mut ctx.accounts.state,
+            Some(native_record),
+            native_trader_ledger.as_mut(),
+            native_payload.id,
+            native_payload.amount,
+            wrapped_sol_mint,
+            NATIVE_DECIMALS,
+            ctx.accounts.trader.key(),
+            ctx.accounts.trader.key(),
+            native_payload.v,
+            native_payload.r,
+            native_payload.s,
+            native_payload.sig_version,
+            native_payload.scheme,
+            0,
+        )?;
+
+        // Transfer tokens from program to user
+        let seeds = &[
+            b"token_authority".as_ref(),
+            &[ctx.accounts.state.token_account_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        ensure_vault_not_frozen(&ctx.accounts.program_token_account.to_account_info())?;
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.program_token_account.to_account_info(),
+                to: ctx.accounts.trader_token_account.to_account_info(),
+                authority: ctx.accounts.program_token_authority.to_account_info(),
+            },
+            signer,
+        );
+        token::transfer(transfer_ctx, token_amount)?;
+
+        // Transfer SOL from program to user
+        let seeds = &[
+            ctx.accounts.state.sol_account_seed(),
+            &[ctx.accounts.state.sol_account_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        // Keep the SOL account above its rent-exempt minimum and reserve floor
+        enforce_sol_reserve(
+            &ctx.accounts.state,
+            program_sol_account.lamports(),
+            native_amount,
+            "withdraw_multi",
+        )?;
+
+        let transfer_ix = solana_program::system_instruction::transfer(
+            &program_sol_account.key(),
+            &ctx.accounts.trader.key(),
+            native_amount,
+        );
+
+        solana_program::program::invoke_signed(
+            &transfer_ix,
+            &[
+                program_sol_account.clone(),
+                ctx.accounts.trader.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+            signer,
+        )?;
+
+        // Withdrawn lamports are no longer owed to traders
+        ctx.accounts.counters.custodied_lamports = ctx
+            .accounts
+            .counters
+            .custodied_lamports
+            .saturating_sub(native_amount);
+
+        ctx.accounts.counters.reentry_lock_status = UNLOCKED;
+
+        ctx.accounts.token_config.remove_liability(token_amount);
+        native_token_config.remove_liability(native_amount);
+        ctx.accounts.stats.record_withdrawal(ctx.accounts.mint.key(), token_amount);
+        ctx.accounts.stats.record_withdrawal(wrapped_sol_mint, native_amount);
+
+        // Accounts from remaining_accounts are not written back by Anchor
+        native_token_config.exit(&crate::ID)?;
+        if let Some(ledger) = native_trader_ledger {
+            ledger.exit(&crate::ID)?;
+        }
+        if let Some(record) = native_withdrawal_record {
+            record.exit(&crate::ID)?;
+        }
+
+        let clock = Clock::get()?;
+        emit_withdrawal_event(
+            &ctx.accounts.state,
+            &None,
+            WithdrawalEvent {
+                id: token_payload.id,
+                trader: ctx.accounts.trader.key(),
+                recipient: ctx.accounts.trader.key(),
+                amount: token_amount,
+                amount_e18: to_e18(token_amount, decimals),
+                token: ctx.accounts.mint.key(),
+                slot: clock.slot,
+                unix_timestamp: clock.unix_timestamp,
+            },
+        )?;
+        emit_withdrawal_event(
+            &ctx.accounts.state,
+            &None,
+            WithdrawalEvent {
+                id: native_payload.id,
+                trader: ctx.accounts.trader.key(),
+                recipient: ctx.accounts.trader.key(),
+                amount: native_amount,
+                amount_e18: to_e18(native_amount, NATIVE_DECIMALS),
+                token: wrapped_sol_mint,
+                slot: clock.slot,
+                unix_timestamp: clock.unix_timestamp,
+            },
+        )?;
+
+        Ok(())
+    }
+
+    // Guard for transactions built offline with a durable nonce, which never expire on their own:
+    // the relayer bounds the slots in which the withdrawal may land
+    pub fn assert_slot_window(ctx: Context<AssertSlotWindow>, min_slot: u64, max_slot: u64) -> Result<()> {
+        let slot = ctx.accounts.clock.slot;
+        require_logged!(
+            slot >= min_slot && slot <= max_slot,
+            RbxError::SlotWindowViolated,
+            "assert_slot_window"
+        );
+        Ok(())
+    }
+
+    // Lets relayers check whether a durable-nonce withdrawal already landed before retrying it
+    pub fn is_withdrawal_processed(ctx: Context<IsWithdrawalProcessed>, id: u64) -> Result<bool> {
+        let record_info = &ctx.accounts.withdrawal_record;
+        if record_info.owner != &crate::ID || record_info.data_is_empty() {
+            return Ok(false);
+        }
+
+        let data = record_info.try_borrow_data()?;
+        let record = WithdrawalRecord::try_deserialize(&mut &data[..])?;
+        Ok(record.is_processed(id))
+    }
+
+    pub fn get_version(_ctx: Context<GetVersion>) -> Result<String> {
+        Ok(PROGRAM_VERSION.to_string())
+    }
+
+    pub fn get_eip712_verifying_contract(
+        ctx: Context<GetEip712VerifyingContract>,
+    ) -> Result<String> {
+        // Get the full 32-byte state PDA pubkey
+        let pubkey = ctx.accounts.state.key();
+
+        // Convert the pubkey to a 32-byte hex string
+        let bytes = pubkey.to_bytes();
+        let hex_string = format!("0x{}", hex::encode(bytes));
+
+        // Return the hex string representation (with 0x prefix)
+        Ok(hex_string)
+    }
+
+    // Queue a timelock operation
+    pub fn queue_operation(
+        ctx: Context<QueueOperation>,
+        operation_type: u8,
+        data: Vec<u8>,
+        auto_executable: bool,
+    ) -> Result<()> {
+        queue_pending_operation(
+            &mut ctx.accounts.state,
+            &ctx.accounts.authority.key(),
+            operation_type,
+            data,
+            auto_executable,
+            "queue_operation",
+        )
+    }
+
+    // Typed wrapper for operation 35, changing a supported token's min deposit without the
+    // unsupport/resupport round trip that would drop in-flight deposits
+    pub fn queue_set_min_deposit(
+        ctx: Context<QueueOperation>,
+        token: Pubkey,
+        amount: u64,
+        auto_executable: bool,
+    ) -> Result<()> {
+        require_logged!(
+            ctx.accounts.state.supported_tokens.contains(&token),
+            RbxError::UnsupportedToken,
+            "queue_set_min_deposit"
+        );
+
+        let mut data = token.to_bytes().to_vec();
+        data.extend_from_slice(&amount.to_le_bytes());
+        queue_pending_operation(
+            &mut ctx.accounts.state,
+            &ctx.accounts.authority.key(),
+            OPERATION_SET_MIN_DEPOSIT,
+            data,
+            auto_executable,
+            "queue_set_min_deposit",
+        )
+    }
+
+    // Typed wrapper for operation 38, approving which deposit purposes a token accepts. The
+    // approval takes effect through set_allowed_purposes
+    pub fn queue_set_allowed_purposes(
+        ctx: Context<QueueOperation>,
+        token: Pubkey,
+        allowed_purposes: u8,
+        auto_executable: bool,
+    ) -> Result<()> {
+        require_logged!(
+            ctx.accounts.state.supported_tokens.contains(&token),
+            RbxError::UnsupportedToken,
+            "queue_set_allowed_purposes"
+        );
+        require_logged!(
+            allowed_purposes != 0 && allowed_purposes & !PURPOSES_ALL == 0,
+            RbxError::InvalidOperationData,
+            "queue_set_allowed_purposes"
+        );
+
+        let mut data = token.to_bytes().to_vec();
+        data.push(allowed_purposes);
+        queue_pending_operation(
+            &mut ctx.accounts.state,
+            &ctx.accounts.authority.key(),
+            OPERATION_APPROVE_ALLOWED_PURPOSES,
+            data,
+            auto_executable,
+            "queue_set_allowed_purposes",
+        )
+    }
+
+    // Typed wrapper for operation 36, blocking queueing and executing of timelock operations
+    // until the deadline, e.g. ahead of a planned migration
+    pub fn queue_freeze_governance_until(
+        ctx: Context<QueueOperation>,
+        timestamp: i64,
+    ) -> Result<()> {
+        queue_pending_operation(
+            &mut ctx.accounts.state,
+            &ctx.accounts.authority.key(),
+            OPERATION_FREEZE_GOVERNANCE,
+            timestamp.to_le_bytes().to_vec(),
+            false,
+            "queue_freeze_governance_until",
+        )
+    }
+
+    pub fn execute_operation(ctx: Context<ExecuteOperation>, operation_index: u8) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+
+        // Only timelock authorities with the executor role can execute operations
+        require_logged!(
+            state.has_timelock_role(&ctx.accounts.authority.key(), TIMELOCK_ROLE_EXECUTOR),
+            RbxError::UnauthorizedAccess,
+            "execute_operation"
+        );
+
+        record_executed_operation(
+            &mut ctx.accounts.receipt,
+            state,
+            operation_index as usize,
+            ctx.accounts.authority.key(),
+            "execute_operation",
+        )?;
+        execute_pending_operation(state, operation_index, "execute_operation")
+    }
+
+    // Execute by operation id rather than index, which shifts as operations are removed.
+    // The receipt PDA makes a repeated attempt fail and records the executed action
+    pub fn execute_operation_by_id(
+        ctx: Context<ExecuteOperationById>,
+        operation_id: u64,
+    ) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+
+        require_logged!(
+            state.has_timelock_role(&ctx.accounts.authority.key(), TIMELOCK_ROLE_EXECUTOR),
+            RbxError::UnauthorizedAccess,
+            "execute_operation_by_id"
+        );
+
+        let index = state.operation_index(operation_id).ok_or_else(|| {
+            logged_error!(RbxError::InvalidOperationIndex, "execute_operation_by_id")
+        })?;
+
+        record_executed_operation(
+            &mut ctx.accounts.receipt,
+            state,
+            index,
+            ctx.accounts.authority.key(),
+            "execute_operation_by_id",
+        )?;
+        execute_pending_operation(state, index as u8, "execute_operation_by_id")
+    }
+
+    // Permissionless crank for matured operations flagged auto_executable when queued
+    pub fn execute_matured(ctx: Context<ExecuteMatured>, operation_index: u8) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+
+        require_logged!(
+            (operation_index as usize) < state.pending_operations.len(),
+            RbxError::InvalidOperationIndex,
+            "execute_matured"
+        );
+        // Re-checked here so operations flagged under an older list cannot run by crank
+        let operation = &state.pending_operations[operation_index as usize];
+        require_logged!(
+            operation.auto_executable
+                && CRANK_SAFE_OPERATION_TYPES.contains(&operation.operation_type),
+            RbxError::OperationNotAutoExecutable,
+            "execute_matured"
+        );
+
+        record_executed_operation(
+            &mut ctx.accounts.receipt,
+            state,
+            operation_index as usize,
+            ctx.accounts.cranker.key(),
+            "execute_matured",
+        )?;
+        execute_pending_operation(state, operation_index, "execute_matured")
+    }
+
+    // Execute an operation on behalf of an SPL Governance DAO, whose native treasury PDA
+    // is registered as a timelock authority and signs through the governance program's CPI
+    pub fn execute_operation_via_governance(
+        ctx: Context<ExecuteOperationViaGovernance>,
+        operation_index: u8,
+    ) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+
+        require_logged!(
+            state.has_timelock_role(&ctx.accounts.native_treasury.key(), TIMELOCK_ROLE_EXECUTOR),
+            RbxError::UnauthorizedAccess,
+            "execute_operation_via_governance"
+        );
+
+        record_executed_operation(
+            &mut ctx.accounts.receipt,
+            state,
+            operation_index as usize,
+            ctx.accounts.native_treasury.key(),
+            "execute_operation_via_governance",
+        )?;
+        execute_pending_operation(state, operation_index, "execute_operation_via_governance")
+    }
+
+    // All configuration in one read, instead of one round trip per getter
+    pub fn get_config(ctx: Context<GetConfig>) -> Result<ConfigView> {
+        let state = &ctx.accounts.state;
+        Ok(ConfigView {
+            owner: state.owner,
+            withdrawal_signer: state.withdrawal_signer,
+            timelock_delay: state.timelock_delay,
+            timelock_in_slots: state.timelock_in_slots,
+            supported_tokens: state
+                .supported_tokens
+                .iter()
+                .map(|token| TokenMinDeposit {
+                    token: *token,
+                    min_deposit: state.get_min_deposit(token).unwrap_or(0),
+                })
+                .collect(),
+            timelock_authorities: state.timelock_authorities.clone(),
+            version: PROGRAM_VERSION.to_string(),
+        })
+    }
+
+    pub fn get_withdrawal_signer(ctx: Context<GetWithdrawalSigner>) -> Result<[u8; 20]> {
+        Ok(ctx.accounts.state.withdrawal_signer)
+    }
+
+    // Signer whose signatures are accepted for withdrawals of the token
+    pub fn get_token_withdrawal_signer(
+        ctx: Context<GetWithdrawalSigner>,
+        token: Pubkey,
+    ) -> Result<[u8; 20]> {
+        Ok(ctx.accounts.state.get_withdrawal_signer(&token))
+    }
+    
+    pub fn get_owner(ctx: Context<GetOwner>) -> Result<Pubkey> {
+        Ok(ctx.accounts.state.owner)
+    }
+    
+    pub fn get_next_stake_num(ctx: Context<GetNextStakeNum>) -> Result<u64> {
+        Ok(ctx.accounts.counters.next_stake_num)
+    }
+    
+    pub fn get_next_deposit_num(ctx: Context<GetNextDepositNum>) -> Result<u64> {
+        Ok(ctx.accounts.counters.next_deposit_num)
+    }
+    
+    pub fn get_timelock_delay(ctx: Context<GetTimelockDelay>) -> Result<i64> {
+        Ok(ctx.accounts.state.timelock_delay)
+    }
+    
+    // Time until which timelock operations can be neither queued nor executed, 0 if never frozen
+    pub fn get_governance_frozen_until(ctx: Context<GetTimelockDelay>) -> Result<i64> {
+        Ok(ctx.accounts.state.governance_frozen_until)
+    }
+
+    pub fn get_domain_separator(ctx: Context<GetDomainSeparator>) -> Result<Option<[u8; 32]>> {
+        Ok(ctx.accounts.state.domain_separator)
+    }
+
+    // Dry run of a withdrawal signature for frontends, without touching any bitmap or ledger.
+    // Payouts to the trader only, with no relayer tip
+    pub fn validate_withdrawal_signature(
+        ctx: Context<ValidateWithdrawalSignature>,
+        id: u64,
+        amount: u64,
+        token: Pubkey,
+        trader: Pubkey,
+        v: u8,
+        r: [u8; 32],
+        s: [u8; 32],
+        sig_version: u8,
+        scheme: SignatureScheme,
+    ) -> Result<SignatureCheckView> {
+        let decimals = ctx.accounts.token_config.decimals;
+        let digest = withdrawal_digest(
+            &mut ctx.accounts.state,
+            id,
+            amount,
+            token,
+            decimals,
+            trader,
+            trader,
+            sig_version,
+            scheme,
+            0,
+        )?;
+
+        // A malformed signature recovers nothing rather than failing the view
+        let Ok(recovered_signer) = recover_eth_address(&digest, v, &r, &s) else {
+            return Ok(SignatureCheckView {
+                valid: false,
+                recovered_signer: [0; 20],
+            });
+        };
+        let valid = ctx.accounts.state.accepts_withdrawal_signer(
+            &token,
+            &recovered_signer,
+            Clock::get()?.unix_timestamp,
+        );
+
+        Ok(SignatureCheckView {
+            valid,
+            recovered_signer,
+        })
+    }
+
+    // Run every check of `deposit_token` without moving funds, returning the deposit number the
+    // deposit would be assigned. Checks that update state run on copies that are never written back
+    pub fn simulate_deposit(ctx: Context<SimulateDeposit>, amount: u64) -> Result<u64> {
+        let token = ctx.accounts.mint.key();
+        require_logged!(
+            ctx.accounts.counters.reentry_lock_status == UNLOCKED,
+            RbxError::ReentrancyDetected,
+            "simulate_deposit",
+            token,
+            amount,
+            0
+        );
+        enforce_not_blocked(&ctx.accounts.blacklist_entry, "simulate_deposit")?;
+
+        let state = &ctx.accounts.state;
+        require_logged!(
+            state.supported_tokens.contains(&token),
+            RbxError::UnsupportedToken,
+            "simulate_deposit",
+            token,
+            amount,
+            0
+        );
+        let min_deposit = state
+            .get_min_deposit(&token)
+            .ok_or_else(|| logged_error!(RbxError::UnsupportedToken, "simulate_deposit"))?;
+        require_logged!(
+            amount > 0 && amount >= min_deposit,
+            RbxError::AmountTooSmall,
+            "simulate_deposit",
+            token,
+            amount,
+            0
+        );
+        check_deposit_policy(&ctx.accounts.deposit_policy, token, amount, "simulate_deposit")?;
+        check_deposit_precision(&ctx.accounts.token_config, amount, "simulate_deposit")?;
+        check_funding_purpose(
+            &ctx.accounts.token_config,
+            FundingPurpose::Deposit,
+            "simulate_deposit",
+        )?;
+        record_deposit_inflow(&mut ctx.accounts.token_config.clone(), amount, "simulate_deposit")?;
+        require_logged!(
+            ctx.accounts.user_token_account.amount >= amount,
+            RbxError::InsufficientFunds,
+            "simulate_deposit",
+            token,
+            amount,
+            0
+        );
+
+        Ok(ctx.accounts.counters.next_deposit_num)
+    }
+
+    // Run every check of `withdraw_token`, including signature verification, without moving funds
+    // or marking the id processed. Returns the amount that would be paid out and the signed digest
+    pub fn simulate_withdrawal(
+        ctx: Context<SimulateWithdrawal>,
+        id: u64,
+        amount: u64,
+        trader: Pubkey,
+        recipient: Pubkey,
+        v: u8,
+        r: [u8; 32],
+        s: [u8; 32],
+        sig_version: u8,
+        scheme: SignatureScheme,
+        relayer_tip: u64,
+    ) -> Result<WithdrawalSimulationView> {
+        let token = ctx.accounts.mint.key();
+        require_logged!(
+            ctx.accounts.counters.reentry_lock_status == UNLOCKED,
+            RbxError::ReentrancyDetected,
+            "simulate_withdrawal",
+            token,
+            amount,
+            id
+        );
+        let decimals = ctx.accounts.token_config.decimals;
+
+        // Copies of the accounts process_withdrawal updates, dropped without being written back
+        let mut state = ctx.accounts.state.clone();
+        let mut withdrawal_record = ctx.accounts.withdrawal_record.clone();
+        let mut trader_ledger = ctx.accounts.trader_ledger.clone();
+
+        let floor = state.withdrawal_receipt_floor;
+        if floor != 0 && id >= floor {
+            require_logged!(
+                state.use_withdrawal_receipts,
+                RbxError::ReplayProtectionMismatch,
+                "simulate_withdrawal",
+                token,
+                amount,
+                id
+            );
+            // withdraw_token_nonced fails to create the receipt of a processed id
+            require_logged!(
+                ctx.accounts.withdrawal_receipt.data_is_empty(),
+                RbxError::WithdrawalAlreadyProcessed,
+                "simulate_withdrawal",
+                token,
+                amount,
+                id
+            );
+            withdrawal_record = None;
+        } else if withdrawal_record.is_none() {
+            // A shard that has not been created yet has processed nothing, but may not be re-created
+            // below the watermark
+            require_logged!(
+                id / WITHDRAWALS_PER_ACCOUNT as u64
+                    >= state.high_watermark_id / WITHDRAWALS_PER_ACCOUNT as u64,
+                RbxError::WithdrawalIdBelowWatermark,
+                "simulate_withdrawal",
+                token,
+                amount,
+                id
+            );
+        }
+
+        let digest = withdrawal_digest(
+            &mut state,
+            id,
+            amount,
+            token,
+            decimals,
+            trader,
+            recipient,
+            sig_version,
+            scheme,
+            relayer_tip,
+        )?;
+        let amount = process_withdrawal(
+            ctx.program_id,
+            &mut state,
+            withdrawal_record.as_mut(),
+            trader_ledger.as_mut(),
+            id,
+            amount,
+            token,
+            decimals,
+            trader,
+            recipient,
+            v,
+            r,
+            s,
+            sig_version,
+            scheme,
+            relayer_tip,
+        )?;
+
+        Ok(WithdrawalSimulationView { amount, digest })
+    }
+
+    // Queued timelock operations, with a keccak256 hash standing in for each operation's data
+    pub fn get_pending_operations(
+        ctx: Context<GetPendingOperations>,
+    ) -> Result<Vec<PendingOperationView>> {
+        Ok(ctx
+            .accounts
+            .state
+            .pending_operations
+            .iter()
+            .enumerate()
+            .map(|(index, operation)| PendingOperationView {
+                index: index as u8,
+                operation_id: ctx.accounts.state.operation_id(index),
+                operation_type: operation.operation_type,
+                queued_at: operation.queued_at,
+                can_execute_at: operation.can_execute_at,
+                data_hash: Keccak256::digest(&operation.data).into(),
+            })
+            .collect())
+    }
+
+    // Full data bytes of one queued timelock operation
+    pub fn get_pending_operation(
+        ctx: Context<GetPendingOperations>,
+        operation_index: u8,
+    ) -> Result<Vec<u8>> {
+        let state = &ctx.accounts.state;
+        require_logged!(
+            (operation_index as usize) < state.pending_operations.len(),
+            RbxError::InvalidOperationIndex,
+            "get_pending_operation"
+        );
+        Ok(state.pending_operations[operation_index as usize].data.clone())
+    }
+
+    // Emit a token's vault balance and cumulative totals at most once per UTC day, so analytics
+    // can build balance time series without archive-node account history. Anyone may call it
+    pub fn checkpoint(ctx: Context<Checkpoint>) -> Result<()> {
+        let clock = Clock::get()?;
+        let day = clock.unix_timestamp / CHECKPOINT_INTERVAL;
+        require_logged!(
+            day > ctx.accounts.token_config.last_checkpoint_day,
+            RbxError::CheckpointTooSoon,
+            "checkpoint"
+        );
+        ctx.accounts.token_config.last_checkpoint_day = day;
+
+        let token = ctx.accounts.token_config.mint;
+        let vault_balance = custodied_balance(
+            token,
+            ctx.accounts.program_token_account.as_ref(),
+            ctx.accounts.cold_vault.as_ref(),
+            &ctx.accounts.program_token_authority.key(),
+            &ctx.accounts.program_sol_account,
+            "checkpoint",
+        )?;
+
+        // Tokens without deposits or withdrawals yet have no stats entry
+        let totals = ctx.accounts.stats.tokens.iter().find(|t| t.token == token);
+        emit!(DailyCheckpointEvent {
+            token,
+            day,
+            vault_balance,
+            liabilities: ctx.accounts.token_config.liabilities,
+            deposit_count: totals.map_or(0, |t| t.deposit_count),
+            deposit_volume: totals.map_or(0, |t| t.deposit_volume),
+            withdrawal_count: totals.map_or(0, |t| t.withdrawal_count),
+            withdrawal_volume: totals.map_or(0, |t| t.withdrawal_volume),
+            stake_count: totals.map_or(0, |t| t.stake_count),
+            stake_volume: totals.map_or(0, |t| t.stake_volume),
+            next_deposit_num: ctx.accounts.counters.next_deposit_num,
+            next_stake_num: ctx.accounts.counters.next_stake_num,
+            slot: clock.slot,
+            unix_timestamp: clock.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    // Add the program's static accounts to a lookup table the caller owns, so withdrawal
+    // clients can build compact v0 transactions. Returns where each account sits in the table.
+    // Token configs passed as remaining accounts supply adopted vaults; other tokens use the
+    // canonical ATA
+    pub fn register_lookup_table_accounts<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RegisterLookupTableAccounts<'info>>,
+    ) -> Result<Vec<LookupTableEntry>> {
+        let state = &ctx.accounts.state;
+        let authority = ctx.accounts.program_token_authority.key();
+
+        let mut addresses = vec![
+            state.key(),
+            ctx.accounts.stats.key(),
+            ctx.accounts.counters.key(),
+            authority,
+            ctx.accounts.program_sol_account.key(),
+            token::ID,
+            associated_token::ID,
+            System::id(),
+        ];
+        for token in state.supported_tokens.iter() {
+            let (token_config, _) = Pubkey::find_program_address(
+                &[b"token_config".as_ref(), token.as_ref()],
+                &crate::ID,
+            );
+            let vault = match ctx.remaining_accounts.iter().find(|a| a.key() == token_config) {
+                Some(info) => {
+                    let config = Account::<TokenConfig>::try_from(info)?;
+                    vault_address(&config, &authority)
+                }
+                None => get_associated_token_address(&authority, token),
+            };
+            addresses.push(token_config);
+            addresses.push(vault);
+        }
+
+        // Only append what the table does not hold yet
+        let existing = {
+            let data = ctx.accounts.lookup_table.try_borrow_data()?;
+            AddressLookupTable::deserialize(&data)
+                .map_err(|_| error!(RbxError::InvalidLookupTable))?
+                .addresses
+                .to_vec()
+        };
+        let new_addresses: Vec<Pubkey> = addresses
+            .iter()
+            .filter(|a| !existing.contains(a))
+            .copied()
+            .collect();
+        require_logged!(
+            existing.len() + new_addresses.len() <= LOOKUP_TABLE_MAX_ADDRESSES,
+            RbxError::InvalidLookupTable,
+            "register_lookup_table_accounts"
+        );
+
+        if !new_addresses.is_empty() {
+            let ix = extend_lookup_table(
+                ctx.accounts.lookup_table.key(),
+                ctx.accounts.table_authority.key(),
+                Some(ctx.accounts.payer.key()),
+                new_addresses.clone(),
+            );
+            solana_program::program::invoke(
+                &ix,
+                &[
+                    ctx.accounts.lookup_table.to_account_info(),
+                    ctx.accounts.table_authority.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                    ctx.accounts.payer.to_account_info(),
+                ],
+            )?;
+        }
+
+        let table: Vec<Pubkey> = existing.into_iter().chain(new_addresses).collect();
+        Ok(addresses
+            .into_iter()
+            .map(|address| LookupTableEntry {
+                address,
+                index: table.iter().position(|a| *a == address).unwrap() as u8,
+            })
+            .collect())
+    }
+
+    // Cumulative deposit, withdrawal and stake totals per token
+    pub fn get_stats(ctx: Context<GetStats>) -> Result<Vec<TokenStats>> {
+        Ok(ctx.accounts.stats.tokens.clone())
+    }
+
+    // Move the deposit counters out of State for deployments initialized before Counters existed
+    pub fn init_counters(ctx: Context<InitCounters>) -> Result<()> {
+        let state = &ctx.accounts.state;
+        let counters = &mut ctx.accounts.counters;
+        counters.next_deposit_num = state.next_deposit_num;
+        counters.next_stake_num = state.next_stake_num;
+        counters.custodied_lamports = state.custodied_lamports;
+        counters.reentry_lock_status = UNLOCKED;
+        Ok(())
+    }
+
+    // Record the lamports owed to traders on deployments that held SOL before it was tracked.
+    // Until this runs the surplus is unknown, so sweeps and pre-funded deposits stay blocked
+    pub fn reconcile_custodied_lamports(
+        ctx: Context<ReconcileCustodiedLamports>,
+        custodied_lamports: u64,
+    ) -> Result<()> {
+        require_logged!(
+            custodied_lamports <= ctx.accounts.program_sol_account.lamports(),
+            RbxError::InsufficientFunds,
+            "reconcile_custodied_lamports"
+        );
+
+        ctx.accounts.counters.custodied_lamports = custodied_lamports;
+        ctx.accounts.state.custody_reconciled = true;
+
+        emit!(CustodyReconciledEvent { custodied_lamports });
+
+        Ok(())
+    }
+
+    // Top the SOL account up from the owner towards the reserve set by timelock operation 39
+    pub fn top_up_sol_account(ctx: Context<TopUpSolAccount>, top_up: u64) -> Result<()> {
+        if top_up > 0 {
+            let transfer_ix = solana_program::system_instruction::transfer(
+                &ctx.accounts.owner.key(),
+                &ctx.accounts.program_sol_account.key(),
+                top_up,
+            );
+            solana_program::program::invoke(
+                &transfer_ix,
+                &[
+                    ctx.accounts.owner.to_account_info(),
+                    ctx.accounts.program_sol_account.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+            )?;
+        }
+
+        emit!(SolReserveEvent {
+            reserved_lamports: ctx.accounts.state.reserved_lamports,
+            top_up,
+            balance: ctx.accounts.program_sol_account.lamports(),
+        });
+
+        Ok(())
+    }
+
+    // Create the statistics account for deployments initialized before it existed
+    pub fn init_stats(_ctx: Context<InitStats>) -> Result<()> {
+        Ok(())
+    }
+
+    // Permissionless check that the BPF upgrade authority is the one recorded in State
+    pub fn verify_upgrade_authority(ctx: Context<VerifyUpgradeAuthority>) -> Result<()> {
+        let expected = ctx.accounts.state.expected_upgrade_authority;
+        let actual = ctx.accounts.program_data.upgrade_authority_address;
+        let matches = actual == Some(expected);
+
+        // Emitted before failing so the mismatch is visible in the transaction logs
+        emit!(UpgradeAuthorityCheckEvent {
+            expected,
+            actual,
+            matches,
+        });
+
+        require_logged!(matches, RbxError::UpgradeAuthorityMismatch, "verify_upgrade_authority");
+
+        Ok(())
+    }
+
+    // Permissionless; run by the deploy pipeline after each upgrade. Once the deployed
+    // PROGRAM_VERSION is newer than the version recorded in State, run the pending in-place
+    // migrations and record the new version
+    pub fn post_upgrade_check<'info>(
+        ctx: Context<'_, '_, '_, 'info, PostUpgradeCheck<'info>>,
+    ) -> Result<()> {
+        // A 1.0.1 State cannot be loaded until it is converted to the current layout
+        if ctx.accounts.state.data_len() == 8 + LegacyState::SIZE {
+            convert_legacy_state(
+                &ctx.accounts.state,
+                &ctx.accounts.payer,
+                &ctx.accounts.system_program,
+                "post_upgrade_check",
+            )?;
+        }
+
+        let mut state = State::try_deserialize(&mut &ctx.accounts.state.try_borrow_data()?[..])?;
+        let previous_version = state.program_version;
+        let version = parse_version(PROGRAM_VERSION);
+        require_logged!(
+            version >= previous_version,
+            RbxError::ProgramVersionDowngrade,
+            "post_upgrade_check"
+        );
+        if version == previous_version {
+            return Ok(());
+        }
+
+        run_migrations(&mut state, previous_version);
+        state.program_version = version;
+        state.try_serialize(&mut &mut ctx.accounts.state.try_borrow_mut_data()?[..])?;
+
+        emit!(UpgradeFinalizedEvent {
+            previous_version,
+            version,
+        });
+
+        Ok(())
+    }
+
+    // Permissionless proof-of-reserves: compare recorded liabilities with the vault balance
+    pub fn assert_solvency(ctx: Context<AssertSolvency>) -> Result<()> {
+        let token = ctx.accounts.token_config.mint;
+        let liabilities = ctx.accounts.token_config.liabilities;
+
+        let vault_balance = custodied_balance(
+            token,
+            ctx.accounts.program_token_account.as_ref(),
+            ctx.accounts.cold_vault.as_ref(),
+            &ctx.accounts.program_token_authority.key(),
+            &ctx.accounts.program_sol_account,
+            "assert_solvency",
+        )?;
+
+        // Emitted before failing so a shortfall is visible in the transaction logs
+        emit!(SolvencyCheckEvent {
+            token,
+            liabilities,
+            vault_balance,
+        });
+
+        require_logged!(vault_balance >= liabilities, RbxError::Insolvent, "assert_solvency");
+
+        Ok(())
+    }
+
+    // Returns (max_batch_deposits, max_batch_withdrawals)
+    pub fn get_max_batch_sizes(ctx: Context<GetMaxBatchSizes>) -> Result<(u8, u8)> {
+        Ok((
+            ctx.accounts.state.max_batch_deposits,
+            ctx.accounts.state.max_batch_withdrawals,
+        ))
+    }
+
+    // Sweep lamports held by the SOL account beyond trader deposits and rent to the treasury
+    pub fn sweep_sol_surplus(ctx: Context<SweepSolSurplus>) -> Result<()> {
+        require_logged!(
+            ctx.accounts
+                .state
+                .timelock_authorities
+                .contains(&ctx.accounts.authority.key()),
+            RbxError::UnauthorizedAccess,
+            "sweep_sol_surplus"
+        );
+        require_logged!(
+            ctx.accounts.state.treasury != Pubkey::default(),
+            RbxError::InvalidTreasury,
+            "sweep_sol_surplus"
+        );
+        require_logged!(
+            ctx.accounts.state.custody_reconciled,
+            RbxError::CustodyNotReconciled,
+            "sweep_sol_surplus"
+        );
+
+        let floor = sol_reserve_floor(&ctx.accounts.state)?;
+        let surplus = ctx
+            .accounts
+            .program_sol_account
+            .lamports()
+            .saturating_sub(ctx.accounts.counters.custodied_lamports)
+            .saturating_sub(floor);
+
+        require_logged!(surplus > 0, RbxError::NoSurplus, "sweep_sol_surplus");
+
+        let seeds = &[
+            ctx.accounts.state.sol_account_seed(),
+            &[ctx.accounts.state.sol_account_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let transfer_ix = solana_program::system_instruction::transfer(
+            &ctx.accounts.program_sol_account.key(),
+            &ctx.accounts.treasury.key(),
+            surplus,
+        );
+
+        solana_program::program::invoke_signed(
+            &transfer_ix,
+            &[
+                ctx.accounts.program_sol_account.to_account_info(),
+                ctx.accounts.treasury.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+            signer,
+        )?;
+
+        emit!(SolSurplusSweptEvent {
+            treasury: ctx.accounts.treasury.key(),
+            amount: surplus,
+        });
+
+        Ok(())
+    }
+
+    // Liveness signal from the backend signer's hot wallet, see `heartbeat_lapsed`
+    pub fn heartbeat(ctx: Context<Heartbeat>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        ctx.accounts.state.last_heartbeat = now;
+
+        emit!(HeartbeatEvent { timestamp: now });
+
+        Ok(())
+    }
+
+    // Pause withdrawals with an EmergencyPause message signed by the withdrawal signer
+    pub fn emergency_pause(
+        ctx: Context<EmergencyPause>,
+        nonce: u64,
+        deadline: i64,
+        v: u8,
+        r: [u8; 32],
+        s: [u8; 32],
+    ) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+
+        verify_pause_signature(
+            state,
+            EMERGENCY_PAUSE_TYPEHASH,
+            nonce,
+            deadline,
+            v,
+            r,
+            s,
+            "emergency_pause",
+        )?;
+        state.withdrawals_paused = true;
+
+        emit!(WithdrawalsPausedEvent {
+            nonce,
+            submitter: ctx.accounts.submitter.key(),
+        });
+
+        Ok(())
+    }
+
+    // Resume withdrawals with an EmergencyUnpause message signed by the withdrawal signer
+    pub fn emergency_unpause(
+        ctx: Context<EmergencyPause>,
+        nonce: u64,
+        deadline: i64,
+        v: u8,
+        r: [u8; 32],
+        s: [u8; 32],
+    ) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+
+        verify_pause_signature(
+            state,
+            EMERGENCY_UNPAUSE_TYPEHASH,
+            nonce,
+            deadline,
+            v,
+            r,
+            s,
+            "emergency_unpause",
+        )?;
+        state.withdrawals_paused = false;
+
+        emit!(WithdrawalsUnpausedEvent {
+            nonce,
+            submitter: ctx.accounts.submitter.key(),
+        });
+
+        Ok(())
+    }
+
+    // Move the whole SOL balance to the v2 PDA and point deposits and withdrawals at it
+    pub fn migrate_sol_vault(ctx: Context<MigrateSolVault>) -> Result<()> {
+        require_logged!(
+            ctx.accounts
+                .state
+                .timelock_authorities
+                .contains(&ctx.accounts.authority.key()),
+            RbxError::UnauthorizedAccess,
+            "migrate_sol_vault"
+        );
+        require_logged!(
+            ctx.accounts.state.sol_vault_migration_approved,
+            RbxError::SolVaultMigrationNotApproved,
+            "migrate_sol_vault"
+        );
+
+        let lamports = ctx.accounts.old_sol_account.lamports();
+        let seeds = &[SOL_ACCOUNT_SEED, &[ctx.accounts.state.sol_account_bump]];
+        let signer = &[&seeds[..]];
+
+        solana_program::program::invoke_signed(
+            &solana_program::system_instruction::transfer(
+                &ctx.accounts.old_sol_account.key(),
+                &ctx.accounts.new_sol_account.key(),
+                lamports,
+            ),
+            &[
+                ctx.accounts.old_sol_account.to_account_info(),
+                ctx.accounts.new_sol_account.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+            signer,
+        )?;
+
+        let state = &mut ctx.accounts.state;
+        state.sol_account_version = 1;
+        state.sol_account_bump = ctx.bumps.new_sol_account;
+        state.sol_vault_migration_approved = false;
+
+        emit!(SolVaultMigratedEvent {
+            old_sol_account: ctx.accounts.old_sol_account.key(),
+            new_sol_account: ctx.accounts.new_sol_account.key(),
+            lamports,
+        });
+
+        Ok(())
+    }
+
+    // Permissionless snapshot of a token's accounting, intended for periodic emission
+    pub fn emit_token_accounting(ctx: Context<EmitTokenAccounting>, token: Pubkey) -> Result<()> {
+        let state = &ctx.accounts.state;
+
+        // Vault token balance, if the caller supplied the program's token account for this mint
+        let vault_balance = match &ctx.accounts.program_token_account {
+            Some(vault) => {
+                require_logged!(
+                    vault.mint == token && vault.owner == ctx.accounts.program_token_authority.key(),
+                    RbxError::InvalidVaultAccount,
+                    "emit_token_accounting",
+                    token,
+                    0,
+                    0
+                );
+                vault.amount
+            }
+            None => 0,
+        };
+
+        // Native SOL is reported from the SOL account alongside any wrapped balance
+        let is_native = token == token::spl_token::native_mint::ID;
+        let (sol_vault_balance, reserved_lamports) = if is_native {
+            (
+                ctx.accounts.program_sol_account.lamports(),
+                ctx.accounts.counters.custodied_lamports,
+            )
+        } else {
+            (0, 0)
+        };
+
+        emit!(TokenAccountingEvent {
+            token,
+            supported: state.supported_tokens.contains(&token),
+            min_deposit: state.get_min_deposit(&token).unwrap_or(0),
+            next_deposit_num: ctx.accounts.counters.next_deposit_num,
+            next_stake_num: ctx.accounts.counters.next_stake_num,
+            vault_balance,
+            sol_vault_balance,
+            reserved_lamports,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    // Wind down the deployment once approved through the timelock. remaining_accounts holds
+    // (program token account, owner token account) pairs for every vault to empty and close.
+    pub fn decommission<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Decommission<'info>>,
+    ) -> Result<()> {
+        let state = &ctx.accounts.state;
+        require_logged!(
+            state.decommission_approved,
+            RbxError::DecommissionNotApproved,
+            "decommission"
+        );
+        require_logged!(
+            ctx.accounts.counters.custodied_lamports == 0,
+            RbxError::OutstandingLiabilities,
+            "decommission"
+        );
+        // Remaining accounts start with the TokenConfig of every supported token, in
+        // supported_tokens order, followed by (vault, destination) pairs
+        let token_count = state.supported_tokens.len();
+        require_logged!(
+            ctx.remaining_accounts.len() >= token_count
+                && (ctx.remaining_accounts.len() - token_count) % 2 == 0,
+            RbxError::InvalidVaultAccount,
+            "decommission"
+        );
+        let (token_configs, vault_pairs) = ctx.remaining_accounts.split_at(token_count);
+        for (token, info) in state.supported_tokens.iter().zip(token_configs) {
+            let (config_key, _) = Pubkey::find_program_address(
+                &[b"token_config".as_ref(), token.as_ref()],
+                &crate::ID,
+            );
+            require_logged!(
+                info.key() == config_key,
+                RbxError::TokenConfigMismatch,
+                "decommission"
+            );
+            let config = Account::<TokenConfig>::try_from(info)?;
+            require_logged!(
+                config.liabilities == 0,
+                RbxError::OutstandingLiabilities,
+                "decommission",
+                *token,
+                config.liabilities,
+                0
+            );
+        }
+
+        let token_seeds = &[b"token_authority".as_ref(), &[state.token_account_bump]];
+        let token_signer = &[&token_seeds[..]];
+
+        for pair in vault_pairs.chunks(2) {
+            let vault = Account::<TokenAccount>::try_from(&pair[0])?;
+            require_logged!(
+                vault.owner == ctx.accounts.program_token_authority.key(),
+                RbxError::InvalidVaultAccount,
+                "decommission"
+            );
+
+            if vault.amount > 0 {
+                token::transfer(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        Transfer {
+                            from: pair[0].clone(),
+                            to: pair[1].clone(),
+                            authority: ctx.accounts.program_token_authority.to_account_info(),
+                        },
+                        token_signer,
+                    ),
+                    vault.amount,
+                )?;
+            }
+
+            token::close_account(CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: pair[0].clone(),
+                    destination: ctx.accounts.owner.to_account_info(),
+                    authority: ctx.accounts.program_token_authority.to_account_info(),
+                },
+                token_signer,
+            ))?;
+        }
+
+        // Move everything left in the SOL account, which closes it
+        let lamports = ctx.accounts.program_sol_account.lamports();
+        if lamports > 0 {
+            let sol_seeds = &[state.sol_account_seed(), &[state.sol_account_bump]];
+            solana_program::program::invoke_signed(
+                &solana_program::system_instruction::transfer(
+                    &ctx.accounts.program_sol_account.key(),
+                    &ctx.accounts.owner.key(),
+                    lamports,
+                ),
+                &[
+                    ctx.accounts.program_sol_account.to_account_info(),
+                    ctx.accounts.owner.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+                &[&sol_seeds[..]],
+            )?;
+        }
+
+        emit!(DecommissionedEvent {
+            owner: ctx.accounts.owner.key(),
+            vaults_closed: (vault_pairs.len() / 2) as u8,
+            lamports,
+        });
+
+        // The state account itself is closed to the owner by the `close` constraint
+        Ok(())
+    }
+
+    // Clear the cached domain separator and recompute it from the current state address
+    pub fn refresh_domain_separator(ctx: Context<RefreshDomainSeparator>) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+
+        state.domain_separator = None;
+        let domain_separator = eip712::get_domain_separator(state);
+
+        emit!(DomainSeparatorRefreshedEvent { domain_separator });
+
+        Ok(())
+    }
+
+    pub fn cancel_operation(ctx: Context<CancelOperation>, operation_index: u8) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+
+        // Only timelock authorities with the proposer role can cancel operations
+        require_logged!(
+            state.has_timelock_role(&ctx.accounts.authority.key(), TIMELOCK_ROLE_PROPOSER),
+            RbxError::UnauthorizedAccess,
+            "cancel_operation"
+        );
+
+        // Check if operation index is valid
+        require_logged!(
+            (operation_index as usize) < state.pending_operations.len(),
+            RbxError::InvalidOperationIndex,
+            "cancel_operation"
+        );
+
+        // Remove the operation from the pending list, keeping its details for the event
+        let operation = state.remove_pending_operation(operation_index as usize);
+
+        emit!(CancelOperationEvent {
+            operation_type: operation.operation_type,
+            authority: ctx.accounts.authority.key(),
+            operation_hash: operation_hash(&operation)?,
+        });
+
+        Ok(())
+    }
+
+    // Cancel the pending operation whose Borsh encoding hashes to `operation_hash`. Unlike an
+    // index, the hash still names the same operation after others are queued or executed
+    pub fn cancel_operation_by_hash(
+        ctx: Context<CancelOperation>,
+        operation_hash: [u8; 32],
+    ) -> Result<()> {
+        let state = &mut ctx.accounts.state;
+
+        require_logged!(
+            state.has_timelock_role(&ctx.accounts.authority.key(), TIMELOCK_ROLE_PROPOSER),
+            RbxError::UnauthorizedAccess,
+            "cancel_operation_by_hash"
+        );
+
+        // Identical operations hash alike, and cancelling either has the same effect
+        let mut index = None;
+        for (i, operation) in state.pending_operations.iter().enumerate() {
+            if crate::operation_hash(operation)? == operation_hash {
+                index = Some(i);
+                break;
+            }
+        }
+        let index = index.ok_or_else(|| {
+            logged_error!(RbxError::InvalidOperationIndex, "cancel_operation_by_hash")
+        })?;
+
+        let operation = state.remove_pending_operation(index);
+
+        emit!(CancelOperationEvent {
+            operation_type: operation.operation_type,
+            authority: ctx.accounts.authority.key(),
+            operation_hash,
+        });
+
+        Ok(())
+    }
+
+    pub fn stake_token(ctx: Context<DepositToken>, amount: u64) -> Result<()> {
+        fund_transfer_in(
+            deposit_token_funding(ctx.accounts),
+            amount,
+            FundingPurpose::Stake,
+            false,
+            "stake_token",
+        )?;
+        Ok(())
+    }
+
+    pub fn stake_native(ctx: Context<DepositNative>, amount: u64) -> Result<()> {
+        require_logged!(
+            ctx.accounts.counters.reentry_lock_status == UNLOCKED,
+            RbxError::ReentrancyDetected,
+            "stake_native",
+            ctx.accounts.wrapped_sol_mint.key(),
+            amount,
+            0
+        );
+        enforce_not_blocked(&ctx.accounts.blacklist_entry, "stake_native")?;
+
+        ctx.accounts.counters.reentry_lock_status = LOCKED;
+
+        // Verify amount meets minimum
+        let state = &ctx.accounts.state;
+        let wrapped_sol = ctx.accounts.wrapped_sol_mint.key();
+
+        let min_deposit = state
+            .get_min_deposit(&wrapped_sol)
+            .ok_or_else(|| logged_error!(RbxError::UnsupportedToken, "stake_native"))?;
+
+        require_logged!(
+            amount > 0 && amount >= min_deposit,
+            RbxError::AmountTooSmall,
+            "stake_native",
+            ctx.accounts.wrapped_sol_mint.key(),
+            amount,
+            0
+        );
+        enforce_deposit_policy(&ctx.accounts.deposit_policy, wrapped_sol, amount, "stake_native")?;
+        check_deposit_precision(&ctx.accounts.token_config, amount, "stake_native")?;
+        check_funding_purpose(&ctx.accounts.token_config, FundingPurpose::Stake, "stake_native")?;
+        record_deposit_inflow(&mut ctx.accounts.token_config, amount, "stake_native")?;
+        require_logged!(
+            amount <= ctx.accounts.user.lamports(),
+            RbxError::InsufficientFunds,
+            "stake_native",
+            ctx.accounts.wrapped_sol_mint.key(),
+            amount,
+            0
+        );
+
+        let stake_num = take_stake_num(&mut ctx.accounts.counters, "stake_native")?;
+        ctx.accounts.counters.custodied_lamports = ctx
+            .accounts
+            .counters
+            .custodied_lamports
+            .checked_add(amount)
+            .ok_or_else(|| logged_error!(RbxError::AmountOverflow, "stake_native"))?;
+
+        // Create stake ID string with _rbx_sol suffix
+        let mut stake_id = String::with_capacity(20); // Pre-allocate to avoid reallocation
+        stake_id.push_str("s_");
+        stake_id.push_str(&stake_num.to_string());
+        stake_id.push_str("_rbx_sol");
+
+        // Transfer SOL from user to program
+        let ix = solana_program::system_instruction::transfer(
+            &ctx.accounts.user.key(),
+            &ctx.accounts.program_sol_account.key(),
+            amount,
+        );
+
+        solana_program::program::invoke(
+            &ix,
+            &[
+                ctx.accounts.user.to_account_info(),
+                ctx.accounts.program_sol_account.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+        )?;
+
+        credit_stake_position(
+            &mut ctx.accounts.stake_position,
+            ctx.accounts.user.key(),
+            wrapped_sol,
+            amount,
+            "stake_native",
+        )?;
+
+        ctx.accounts.token_config.add_liability(amount);
+
+        ctx.accounts.stats.record_stake(wrapped_sol, amount);
+
+        let clock = Clock::get()?;
+        emit_stake_event(
+            &ctx.accounts.state,
+            &event_cpi_authority(&ctx.accounts.event_authority, &ctx.accounts.event_program),
+            StakeEvent {
+                id: stake_id,
+                trader: ctx.accounts.user.key(),
+                amount,
+                token: wrapped_sol,
+                slot: clock.slot,
+                unix_timestamp: clock.unix_timestamp,
+            },
+        )?;
+
+        ctx.accounts.counters.reentry_lock_status = UNLOCKED;
+
+        record_activity(
+            &mut ctx.accounts.activity_counter,
+            ctx.accounts.user.key(),
+            "stake_native",
+        )?;
+
+        Ok(())
+    }
+}
+
+// Digest the withdrawal signer signed, as selected by the scheme and signature version
+fn withdrawal_digest(
+    state: &mut Account<State>,
+    id: u64,
+    amount: u64,
+    token: Pubkey,
+    decimals: u8,
+    trader: Pubkey,
+    recipient: Pubkey,
+    sig_version: u8,
+    scheme: SignatureScheme,
+    relayer_tip: u64,
+) -> Result<[u8; 32]> {
+    let digest = match scheme {
+        SignatureScheme::Eip712 => {
+            // Construct the EIP712 digest
+            let domain_separator = eip712::get_domain_separator(state);
+            // The signature version selects the struct layout and its registered typehash
+            let typehash = state.get_withdrawal_typehash(sig_version);
+            require_withdrawal!(
+                typehash.is_some(),
+                id,
+                trader,
+                REJECT_INVALID_SIGNATURE,
+                RbxError::SignatureVersionDisabled
+            );
+            let typehash = typehash.unwrap();
+
+            // A nonzero tip is only valid under a layout that commits to it
+            require_withdrawal!(
+                sig_version == SIG_VERSION_RELAYED || relayer_tip == 0,
+                id,
+                trader,
+                REJECT_INVALID_SIGNATURE,
+                RbxError::SignatureVersionMismatch
+            );
+
+            let trader_word = encode_trader(&trader, state.trader_encoding);
+            let withdrawal_hash = match (sig_version, state.sign_amounts_e18) {
+                // Match the EVM signing convention of 18-decimal uint256 amounts
+                (SIG_VERSION_WITHDRAWAL, true) => get_withdrawal_hash_e18(
+                    typehash,
+                    id,
+                    token,
+                    trader_word,
+                    to_e18(amount, decimals),
+                ),
+                (SIG_VERSION_WITHDRAWAL, false) => {
+                    get_withdrawal_hash(typehash, id, token, trader_word, amount)
+                }
+                (SIG_VERSION_RECIPIENT, true) => get_recipient_withdrawal_hash_e18(
+                    typehash,
+                    id,
+                    token,
+                    trader_word,
+                    recipient,
+                    to_e18(amount, decimals),
+                ),
+                (SIG_VERSION_RECIPIENT, false) => get_recipient_withdrawal_hash(
+                    typehash,
+                    id,
+                    token,
+                    trader_word,
+                    recipient,
+                    amount,
+                ),
+                (_, true) => get_relayed_withdrawal_hash_e18(
+                    typehash,
+                    id,
+                    token,
+                    trader_word,
+                    to_e18(amount, decimals),
+                    to_e18(relayer_tip, decimals),
+                ),
+                (_, false) => get_relayed_withdrawal_hash(
+                    typehash,
+                    id,
+                    token,
+                    trader_word,
+                    amount,
+                    relayer_tip,
+                ),
+            };
+
+            hash_typed_data(&domain_separator, &withdrawal_hash)
+        }
+        // Borsh payload under a domain tag, for backends without EIP-712 tooling.
+        // It always commits to the tip, so sig_version does not apply.
+        SignatureScheme::SolanaNative => solana_withdrawal_digest(&SolanaWithdrawalMessage {
+            program_state: state.key(),
+            id,
+            token,
+            trader,
+            amount,
+            relayer_tip,
+        }),
+    };
+
+    Ok(digest)
+}
 
 fn process_withdrawal(
-    _program_id: &Pubkey,
+    program_id: &Pubkey,
+    state: &mut Account<State>,
+    mut withdrawal_record: Option<&mut Account<WithdrawalRecord>>,
+    trader_ledger: Option<&mut Account<TraderLedger>>,
+    id: u64,
+    amount: u64,
+    token: Pubkey,
+    decimals: u8,
+    trader: Pubkey,
+    recipient: Pubkey,
+    v: u8,
+    r: [u8; 32],
+    s: [u8; 32],
+    sig_version: u8,
+    scheme: SignatureScheme,
+    relayer_tip: u64,
+) -> Result<u64> {
+    // Validate amount
+    require_withdrawal!(
+        amount > 0,
+        id,
+        trader,
+        REJECT_WRONG_AMOUNT,
+        RbxError::WrongAmount
+    );
+    require_withdrawal!(
+        !state.withdrawals_paused,
+        id,
+        trader,
+        REJECT_PAUSED,
+        RbxError::WithdrawalsPaused
+    );
+
+    // Without a bitmap record the caller has checked the id against the receipt floor and
+    // created its per-id receipt with `init`
+    if let Some(withdrawal_record) = withdrawal_record.as_deref_mut() {
+        // The receipt floor splits the id space, so an id cannot be replayed across schemes
+        require_withdrawal!(
+            state.withdrawal_receipt_floor == 0 || id < state.withdrawal_receipt_floor,
+            id,
+            trader,
+            REJECT_ALREADY_PROCESSED,
+            RbxError::ReplayProtectionMismatch
+        );
+
+        // Shards wholly below the watermark may have been closed, and a re-created
+        // shard would have forgotten which ids it processed
+        require_withdrawal!(
+            id / WITHDRAWALS_PER_ACCOUNT as u64
+                >= state.high_watermark_id / WITHDRAWALS_PER_ACCOUNT as u64,
+            id,
+            trader,
+            REJECT_ALREADY_PROCESSED,
+            RbxError::WithdrawalIdBelowWatermark
+        );
+
+        // The record must be the shard PDA for the id, so bits are never marked in another
+        // shard's record even if an accounts struct derives the wrong seeds
+        let shard = id / WITHDRAWALS_PER_ACCOUNT as u64;
+        let (expected_record, _) = Pubkey::find_program_address(
+            &[b"withdrawal_account".as_ref(), &shard.to_le_bytes()],
+            program_id,
+        );
+        require_withdrawal!(
+            withdrawal_record.key() == expected_record,
+            id,
+            trader,
+            REJECT_INVALID_RECORD,
+            RbxError::WithdrawalRecordMismatch
+        );
+
+        // Initialize the withdrawal record if it's new
+        if withdrawal_record.index == 0 {
+            withdrawal_record.index = shard;
+            // No need to initialize processed_bits as they default to zero
+        }
+        require_withdrawal!(
+            withdrawal_record.index == shard,
+            id,
+            trader,
+            REJECT_INVALID_RECORD,
+            RbxError::WithdrawalRecordMismatch
+        );
+
+        // Check if withdrawal has already been processed
+        require_withdrawal!(
+            !withdrawal_record.is_processed(id),
+            id,
+            trader,
+            REJECT_ALREADY_PROCESSED,
+            RbxError::WithdrawalAlreadyProcessed
+        );
+    }
+
+    // Paying anyone but the trader needs a signature that commits to the recipient
+    require_withdrawal!(
+        recipient == trader
+            || (scheme == SignatureScheme::Eip712 && sig_version == SIG_VERSION_RECIPIENT),
+        id,
+        trader,
+        REJECT_INVALID_SIGNATURE,
+        RbxError::SignatureVersionMismatch
+    );
+
+    let digest = withdrawal_digest(
+        state,
+        id,
+        amount,
+        token,
+        decimals,
+        trader,
+        recipient,
+        sig_version,
+        scheme,
+        relayer_tip,
+    )?;
+
+    // Verify signature, accepting the previous signer during a rotation overlap
+    let recovered_signer = recover_eth_address(&digest, v, &r, &s).map_err(|err| {
+        log_withdrawal_rejection(id, trader, REJECT_INVALID_SIGNATURE);
+        err
+    })?;
+    let sig_result = state.accepts_withdrawal_signer(
+        &token,
+        &recovered_signer,
+        Clock::get()?.unix_timestamp,
+    );
+    require_withdrawal!(
+        sig_result,
+        id,
+        trader,
+        REJECT_INVALID_SIGNATURE,
+        RbxError::InvalidSignature
+    );
+
+    // Resolve the full-balance sentinel against the trader's ledger and debit it. A signed
+    // amount can include trading profit the ledger never saw, so the debit stops at zero
+    let amount = match trader_ledger {
+        Some(ledger) => {
+            require_withdrawal!(
+                ledger.trader == trader && ledger.token == token,
+                id,
+                trader,
+                REJECT_INVALID_LEDGER,
+                RbxError::InvalidTraderLedger
+            );
+            let resolved = if amount == WITHDRAW_ALL {
+                ledger.balance
+            } else {
+                amount
+            };
+            ledger.balance = ledger.balance.saturating_sub(resolved);
+            ledger.last_withdrawal_at = Clock::get()?.unix_timestamp;
+            resolved
+        }
+        None => {
+            require_withdrawal!(
+                amount != WITHDRAW_ALL,
+                id,
+                trader,
+                REJECT_INVALID_LEDGER,
+                RbxError::TraderLedgerRequired
+            );
+            amount
+        }
+    };
+    require_withdrawal!(
+        amount > 0,
+        id,
+        trader,
+        REJECT_WRONG_AMOUNT,
+        RbxError::WrongAmount
+    );
+    // Checked against the resolved amount so full-balance withdrawals are covered too
+    require_withdrawal!(
+        amount >= state.get_min_withdrawal(&token).unwrap_or(0),
+        id,
+        trader,
+        REJECT_WRONG_AMOUNT,
+        RbxError::WithdrawalTooSmall
+    );
+    require_withdrawal!(
+        relayer_tip < amount,
+        id,
+        trader,
+        REJECT_WRONG_AMOUNT,
+        RbxError::RelayerTipTooLarge
+    );
+
+    // Mark the withdrawal as processed
+    if let Some(withdrawal_record) = withdrawal_record {
+        withdrawal_record.mark_processed(id);
+        state.max_bitmap_withdrawal_id = state.max_bitmap_withdrawal_id.max(id);
+
+        // Start the retention period of a shard once its last id is used. Untracked shards
+        // can still be closed by advancing the watermark
+        let index = withdrawal_record.index;
+        if withdrawal_record.is_full()
+            && state.filled_shards.len() < MAX_FILLED_SHARDS
+            && !state.filled_shards.iter().any(|(i, _)| *i == index)
+        {
+            state
+                .filled_shards
+                .push((index, Clock::get()?.unix_timestamp));
+        }
+    }
+
+    // Return the amount to pay out - the calling function will handle the actual transfer
+    Ok(amount)
+}
+
+// Accounts shared by withdraw_token and withdraw_token_existing_record, which differ only
+// in how the withdrawal record is created and in the optional recipient and event CPI
+struct TokenWithdrawalAccounts<'a, 'info> {
+    state: &'a mut Account<'info, State>,
+    counters: &'a mut Account<'info, Counters>,
+    stats: &'a mut Account<'info, Stats>,
+    token_config: &'a mut Account<'info, TokenConfig>,
+    withdrawal_record: &'a mut Account<'info, WithdrawalRecord>,
+    trader_ledger: Option<&'a mut Account<'info, TraderLedger>>,
+    partial_withdrawal: Option<&'a mut Account<'info, PartialWithdrawal>>,
+    mint: &'a AccountInfo<'info>,
+    program_token_account: &'a Account<'info, TokenAccount>,
+    program_token_authority: &'a AccountInfo<'info>,
+    trader_token_account: &'a Account<'info, TokenAccount>,
+    trader: &'a AccountInfo<'info>,
+    recipient: Pubkey,
+    blacklist_entry: &'a AccountInfo<'info>,
+    withdrawal_allowlist: &'a AccountInfo<'info>,
+    token_program: &'a Program<'info, Token>,
+    event_authority: Option<AccountInfo<'info>>,
+}
+
+fn execute_token_withdrawal(
+    program_id: &Pubkey,
+    accounts: TokenWithdrawalAccounts,
+    id: u64,
+    amount: u64,
+    v: u8,
+    r: [u8; 32],
+    s: [u8; 32],
+    sig_version: u8,
+    scheme: SignatureScheme,
+    allow_partial: bool,
+    ix: &str,
+) -> Result<()> {
+    require_logged!(
+        accounts.counters.reentry_lock_status == UNLOCKED,
+        RbxError::ReentrancyDetected,
+        ix,
+        accounts.mint.key(),
+        amount,
+        id
+    );
+    enforce_not_blocked(accounts.blacklist_entry, ix)?;
+    accounts.counters.reentry_lock_status = LOCKED;
+
+    let decimals = mint_decimals(accounts.mint)?;
+
+    // Pay the trader's own or allowlisted account, or the ATA of a recipient named in the
+    // signature
+    let trader = accounts.trader.key();
+    let recipient = accounts.recipient;
+    require_logged!(
+        recipient == trader
+            || accounts.trader_token_account.key()
+                == get_associated_token_address(&recipient, &accounts.mint.key()),
+        RbxError::InvalidRecipientAccount,
+        ix,
+        accounts.mint.key(),
+        amount,
+        id
+    );
+
+    enforce_withdrawal_allowlist(
+        accounts.withdrawal_allowlist,
+        accounts.trader_token_account,
+        recipient,
+        ix,
+    )?;
+
+    // Process common withdrawal logic, resolving the amount actually paid out
+    let amount = process_withdrawal(
+        program_id,
+        accounts.state,
+        Some(accounts.withdrawal_record),
+        accounts.trader_ledger,
+        id,
+        amount,
+        accounts.mint.key(),
+        decimals,
+        trader,
+        recipient,
+        v,
+        r,
+        s,
+        sig_version,
+        scheme,
+        0,
+    )?;
+
+    // Pay what the vault holds and record the rest for a later claim
+    let owed = amount;
+    let available = accounts.program_token_account.amount;
+    let amount = settle_partial_withdrawal(
+        accounts.partial_withdrawal,
+        trader,
+        accounts.mint.key(),
+        owed,
+        available,
+        allow_partial,
+        ix,
+    )?;
+    ensure_vault_liquidity(accounts.mint.key(), available, amount, ix)?;
+
+    // Transfer tokens from program to user
+    let seeds = &[
+        b"token_authority".as_ref(),
+        &[accounts.state.token_account_bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    ensure_vault_not_frozen(&accounts.program_token_account.to_account_info())?;
+    let transfer_ctx = CpiContext::new_with_signer(
+        accounts.token_program.to_account_info(),
+        Transfer {
+            from: accounts.program_token_account.to_account_info(),
+            to: accounts.trader_token_account.to_account_info(),
+            authority: accounts.program_token_authority.to_account_info(),
+        },
+        signer,
+    );
+    token::transfer(transfer_ctx, amount)?;
+
+    // Unlock reentrancy lock
+    accounts.counters.reentry_lock_status = UNLOCKED;
+
+    accounts.token_config.remove_liability(amount);
+
+    accounts.stats.record_withdrawal(accounts.mint.key(), amount);
+
+    let clock = Clock::get()?;
+    emit_withdrawal_event(
+        accounts.state,
+        &accounts.event_authority,
+        WithdrawalEvent {
+            id,
+            trader,
+            recipient,
+            amount,
+            amount_e18: to_e18(amount, decimals),
+            token: accounts.mint.key(),
+            slot: clock.slot,
+            unix_timestamp: clock.unix_timestamp,
+        },
+    )?;
+
+    if owed > amount {
+        emit!(PartialWithdrawalEvent {
+            id,
+            trader,
+            token: accounts.mint.key(),
+            paid: amount,
+            deferred: owed - amount,
+        });
+        // Raised with the withdrawal's own events, so a failed withdrawal never emits it
+        emit!(VaultShortfallEvent {
+            token: accounts.mint.key(),
+            requested: owed,
+            available,
+        });
+    }
+
+    Ok(())
+}
+
+// Accounts shared by withdraw_native and withdraw_native_existing_record
+struct NativeWithdrawalAccounts<'a, 'info> {
+    state: &'a mut Account<'info, State>,
+    counters: &'a mut Account<'info, Counters>,
+    stats: &'a mut Account<'info, Stats>,
+    token_config: &'a mut Account<'info, TokenConfig>,
+    withdrawal_record: &'a mut Account<'info, WithdrawalRecord>,
+    trader_ledger: Option<&'a mut Account<'info, TraderLedger>>,
+    wrapped_sol_mint: &'a AccountInfo<'info>,
+    program_sol_account: &'a SystemAccount<'info>,
+    trader: &'a AccountInfo<'info>,
+    blacklist_entry: &'a AccountInfo<'info>,
+    trader_wsol_account: Option<&'a Account<'info, TokenAccount>>,
+    token_program: Option<&'a Program<'info, Token>>,
+    system_program: &'a Program<'info, System>,
+    event_authority: Option<AccountInfo<'info>>,
+}
+
+fn execute_native_withdrawal(
+    program_id: &Pubkey,
+    accounts: NativeWithdrawalAccounts,
+    id: u64,
+    amount: u64,
+    v: u8,
+    r: [u8; 32],
+    s: [u8; 32],
+    wrap: bool,
+    sig_version: u8,
+    scheme: SignatureScheme,
+    ix: &str,
+) -> Result<()> {
+    let wsol_mint = accounts.wrapped_sol_mint.key();
+    require_logged!(
+        accounts.counters.reentry_lock_status == UNLOCKED,
+        RbxError::ReentrancyDetected,
+        ix,
+        wsol_mint,
+        amount,
+        id
+    );
+    enforce_not_blocked(accounts.blacklist_entry, ix)?;
+    accounts.counters.reentry_lock_status = LOCKED;
+
+    // Process common withdrawal logic, resolving the amount actually paid out
+    let trader = accounts.trader.key();
+    let amount = process_withdrawal(
+        program_id,
+        accounts.state,
+        Some(accounts.withdrawal_record),
+        accounts.trader_ledger,
+        id,
+        amount,
+        wsol_mint,
+        NATIVE_DECIMALS,
+        trader,
+        trader,
+        v,
+        r,
+        s,
+        sig_version,
+        scheme,
+        0,
+    )?;
+
+    // Smart-wallet traders can receive the SOL wrapped in their wSOL account instead
+    let recipient = if wrap {
+        let wsol_account = accounts
+            .trader_wsol_account
+            .ok_or_else(|| logged_error!(RbxError::InvalidWsolAccount, ix))?;
+        require_logged!(
+            wsol_account.mint == token::spl_token::native_mint::ID && wsol_account.owner == trader,
+            RbxError::InvalidWsolAccount,
+            ix,
+            wsol_mint,
+            amount,
+            id
+        );
+        wsol_account.to_account_info()
+    } else {
+        accounts.trader.to_account_info()
+    };
+
+    // Transfer SOL from program to user
+    let seeds = &[
+        accounts.state.sol_account_seed(),
+        &[accounts.state.sol_account_bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    // Keep the SOL account above its rent-exempt minimum and reserve floor
+    ensure_vault_liquidity(wsol_mint, accounts.program_sol_account.lamports(), amount, ix)?;
+    enforce_sol_reserve(accounts.state, accounts.program_sol_account.lamports(), amount, ix)?;
+
+    let transfer_ix = solana_program::system_instruction::transfer(
+        &accounts.program_sol_account.key(),
+        &recipient.key(),
+        amount,
+    );
+
+    solana_program::program::invoke_signed(
+        &transfer_ix,
+        &[
+            accounts.program_sol_account.to_account_info(),
+            recipient.clone(),
+            accounts.system_program.to_account_info(),
+        ],
+        signer,
+    )?;
+
+    if wrap {
+        // Credit the transferred lamports as wSOL tokens
+        let token_program = accounts
+            .token_program
+            .ok_or_else(|| logged_error!(RbxError::InvalidWsolAccount, ix))?;
+        token::sync_native(CpiContext::new(
+            token_program.to_account_info(),
+            SyncNative { account: recipient },
+        ))?;
+    }
+
+    // Withdrawn lamports are no longer owed to traders
+    accounts.counters.custodied_lamports =
+        accounts.counters.custodied_lamports.saturating_sub(amount);
+
+    accounts.counters.reentry_lock_status = UNLOCKED;
+
+    accounts.token_config.remove_liability(amount);
+
+    accounts.stats.record_withdrawal(wsol_mint, amount);
+
+    let clock = Clock::get()?;
+    emit_withdrawal_event(
+        accounts.state,
+        &accounts.event_authority,
+        WithdrawalEvent {
+            id,
+            trader,
+            recipient: trader,
+            amount,
+            amount_e18: to_e18(amount, NATIVE_DECIMALS),
+            token: wsol_mint,
+            slot: clock.slot,
+            unix_timestamp: clock.unix_timestamp,
+        },
+    )?;
+
+    Ok(())
+}
+
+// Returns the amount to transfer now, deferring any shortfall when the caller allows it
+fn settle_partial_withdrawal(
+    partial_withdrawal: Option<&mut Account<PartialWithdrawal>>,
+    trader: Pubkey,
+    token: Pubkey,
+    amount: u64,
+    available: u64,
+    allow_partial: bool,
+    ix: &str,
+) -> Result<u64> {
+    if !allow_partial || amount <= available {
+        return Ok(amount);
+    }
+
+    let partial = partial_withdrawal
+        .ok_or_else(|| logged_error!(RbxError::PartialWithdrawalAccountRequired, ix))?;
+    require_logged!(
+        partial.trader == trader && partial.token == token,
+        RbxError::PartialWithdrawalAccountRequired,
+        ix,
+    );
+    partial.remaining = partial
+        .remaining
+        .checked_add(amount - available)
+        .ok_or_else(|| logged_error!(RbxError::AmountOverflow, ix))?;
+
+    Ok(available)
+}
+
+// Dead-man switch: once the signer's wallet misses its heartbeat for longer than the timeout,
+// filed withdrawal requests can be forced without waiting out forced_withdrawal_delay.
+// A timeout too large to add to the last heartbeat never lapses
+fn heartbeat_lapsed(state: &State, now: i64) -> bool {
+    state.heartbeat_authority != Pubkey::default()
+        && state.heartbeat_timeout > 0
+        && state
+            .last_heartbeat
+            .checked_add(state.heartbeat_timeout)
+            .is_some_and(|deadline| now > deadline)
+}
+
+fn process_forced_withdrawal(
+    state: &Account<State>,
+    withdrawal_request: &Account<WithdrawalRequest>,
+    trader_ledger: &mut Account<TraderLedger>,
+    ix: &str,
+) -> Result<u64> {
+    let now = Clock::get()?.unix_timestamp;
+    let signer_gone = heartbeat_lapsed(state, now);
+
+    // A zero delay means forced withdrawals have not been enabled, unless the signer went silent
+    require_logged!(
+        state.forced_withdrawal_delay > 0 || signer_gone,
+        RbxError::ForcedWithdrawalsDisabled,
+        ix,
+    );
+    let stale_at = withdrawal_request
+        .requested_at
+        .checked_add(state.forced_withdrawal_delay)
+        .ok_or_else(|| logged_error!(RbxError::TimestampOverflow, ix))?;
+    require_logged!(
+        signer_gone || now >= stale_at,
+        RbxError::WithdrawalRequestNotStale,
+        ix,
+    );
+
+    // Any signed withdrawal since the request means the operator answered it
+    require_logged!(
+        trader_ledger.last_withdrawal_at < withdrawal_request.requested_at,
+        RbxError::WithdrawalRequestAnswered,
+        ix,
+    );
+
+    let amount = withdrawal_request.amount.min(trader_ledger.balance);
+    require_logged!(amount > 0, RbxError::InsufficientFunds, ix);
+
+    trader_ledger.balance -= amount;
+    trader_ledger.last_withdrawal_at = now;
+
+    Ok(amount)
+}
+
+fn process_released_withdrawal(
+    state: &mut Account<State>,
+    withdrawal_request: &Account<WithdrawalRequest>,
+    trader_ledger: &mut Account<TraderLedger>,
+    v: u8,
+    r: [u8; 32],
+    s: [u8; 32],
+    ix: &str,
+) -> Result<u64> {
+    // A zero delay means co-signed releases have not been enabled
+    require_logged!(
+        state.withdrawal_release_delay > 0,
+        RbxError::WithdrawalReleaseDisabled,
+        ix,
+    );
+
+    let now = Clock::get()?.unix_timestamp;
+    let ready_at = withdrawal_request
+        .requested_at
+        .checked_add(state.withdrawal_release_delay)
+        .ok_or_else(|| logged_error!(RbxError::TimestampOverflow, ix))?;
+    require_logged!(now >= ready_at, RbxError::WithdrawalReleaseNotReady, ix);
+
+    // Any signed withdrawal since the request means the operator answered it
+    require_logged!(
+        trader_ledger.last_withdrawal_at < withdrawal_request.requested_at,
+        RbxError::WithdrawalRequestAnswered,
+        ix,
+    );
+
+    // The signer approved the requested amount, so it must be covered in full
+    let amount = withdrawal_request.amount;
+    require_logged!(trader_ledger.balance >= amount, RbxError::InsufficientFunds, ix);
+
+    let domain_separator = eip712::get_domain_separator(state);
+    let release_hash = get_withdrawal_release_hash(
+        withdrawal_request.token,
+        encode_trader(&withdrawal_request.trader, state.trader_encoding),
+        amount,
+        withdrawal_request.requested_at,
+    );
+    let digest = hash_typed_data(&domain_separator, &release_hash);
+
+    let recovered_signer = recover_eth_address(&digest, v, &r, &s)?;
+    require_logged!(
+        state.accepts_withdrawal_signer(&withdrawal_request.token, &recovered_signer, now),
+        RbxError::InvalidSignature,
+        ix,
+    );
+
+    trader_ledger.balance -= amount;
+    trader_ledger.last_withdrawal_at = now;
+
+    Ok(amount)
+}
+
+fn reject_withdrawal(id: u64, trader: Pubkey, reason_code: u8, error: RbxError) -> Error {
+    log_withdrawal_rejection(id, trader, reason_code);
+    error!(error)
+}
+
+// A frozen vault would only fail inside the token transfer, so reject it up front with a
+// clear error and an alert. Like WithdrawalRejectedEvent, the event survives in the logs
+fn ensure_vault_not_frozen(vault: &AccountInfo) -> Result<()> {
+    let vault_account = {
+        let data = vault.try_borrow_data()?;
+        TokenAccount::try_deserialize(&mut &data[..])?
+    };
+    if vault_account.is_frozen() {
+        emit!(VaultFrozenEvent {
+            vault: vault.key(),
+            token: vault_account.mint,
+        });
+        return Err(error!(RbxError::VaultFrozen));
+    }
+    Ok(())
+}
+
+// An amount above the vault balance would only fail inside the transfer with an opaque
+// error, so reject it up front
+fn ensure_vault_liquidity(token: Pubkey, available: u64, requested: u64, ix: &str) -> Result<()> {
+    require_logged!(
+        requested <= available,
+        RbxError::InsufficientVaultLiquidity,
+        ix,
+        token,
+        requested,
+        0
+    );
+    Ok(())
+}
+
+fn log_withdrawal_rejection(id: u64, trader: Pubkey, reason_code: u8) {
+    msg!(
+        "{{\"event\":\"WithdrawalRejected\",\"id\":{},\"trader\":\"{}\",\"reason_code\":{}}}",
+        id,
+        trader,
+        reason_code
+    );
+    emit!(WithdrawalRejectedEvent {
+        id,
+        trader,
+        reason_code,
+    });
+}
+
+// Shared by queue_operation and its typed wrappers
+fn queue_pending_operation(
+    state: &mut Account<State>,
+    authority: &Pubkey,
+    operation_type: u8,
+    data: Vec<u8>,
+    auto_executable: bool,
+    ix: &str,
+) -> Result<()> {
+    // Only timelock authorities with the proposer role can queue operations
+    require_logged!(
+        state.has_timelock_role(authority, TIMELOCK_ROLE_PROPOSER),
+        RbxError::UnauthorizedAccess,
+        ix
+    );
+
+    require_logged!(
+        Clock::get()?.unix_timestamp >= state.governance_frozen_until,
+        RbxError::GovernanceFrozen,
+        ix
+    );
+
+    // Validate operation type
+    require_logged!(
+        (1..=MAX_OPERATION_TYPE).contains(&operation_type),
+        RbxError::InvalidOperationType,
+        ix
+    );
+    require_logged!(
+        !auto_executable || CRANK_SAFE_OPERATION_TYPES.contains(&operation_type),
+        RbxError::OperationNotAutoExecutable,
+        ix
+    );
+    require_logged!(
+        data.len() <= MAX_OPERATION_DATA_LEN,
+        RbxError::InvalidOperationData,
+        ix
+    );
+    require_logged!(
+        state.pending_operations.len() < MAX_PENDING_OPERATIONS,
+        RbxError::TooManyPendingOperations,
+        ix
+    );
+
+    let clock = Clock::get()?;
+    let current_time = clock.unix_timestamp;
+    let current_slot = clock.slot;
+
+    // The delay applies to whichever clock was chosen at initialize
+    let (execute_time, execute_slot) = if state.timelock_in_slots {
+        let execute_slot = current_slot
+            .checked_add(state.timelock_delay as u64)
+            .ok_or_else(|| logged_error!(RbxError::TimestampOverflow, ix))?;
+        (current_time, execute_slot)
+    } else {
+        let execute_time = current_time
+            .checked_add(state.timelock_delay)
+            .ok_or_else(|| logged_error!(RbxError::TimestampOverflow, ix))?;
+        (execute_time, current_slot)
+    };
+
+    let operation = TimelockOperation {
+        operation_type,
+        data,
+        queued_at: current_time,
+        can_execute_at: execute_time,
+        queued_slot: current_slot,
+        can_execute_slot: execute_slot,
+        auto_executable,
+    };
+
+    state.pending_operations.push(operation);
+    state.next_operation_id = state
+        .next_operation_id
+        .checked_add(1)
+        .ok_or_else(|| logged_error!(RbxError::CounterOverflow, ix))?;
+    let operation_id = state.next_operation_id;
+    state.pending_operation_ids.push(operation_id);
+
+    emit!(QueueOperationEvent {
+        operation_id,
+        operation_type,
+        execute_time,
+        execute_slot,
+        auto_executable,
+    });
+
+    Ok(())
+}
+
+// Fills the receipt every execute path creates for the operation at index, before the
+// operation is removed from the queue
+fn record_executed_operation(
+    receipt: &mut Account<ExecutedOperationReceipt>,
+    state: &State,
+    index: usize,
+    executor: Pubkey,
+    ix: &str,
+) -> Result<()> {
+    let operation_id = state
+        .operation_id(index)
+        .ok_or_else(|| logged_error!(RbxError::InvalidOperationIndex, ix))?;
+    let operation = &state.pending_operations[index];
+
+    receipt.operation_id = operation_id;
+    receipt.operation_type = operation.operation_type;
+    receipt.data_hash = Keccak256::digest(&operation.data).into();
+    receipt.executor = executor;
+    receipt.executed_at = Clock::get()?.unix_timestamp;
+    Ok(())
+}
+
+// keccak256 of the Borsh-encoded operation, as named by cancel_operation_by_hash
+fn operation_hash(operation: &TimelockOperation) -> Result<[u8; 32]> {
+    Ok(Keccak256::digest(operation.try_to_vec()?).into())
+}
+
+fn execute_pending_operation(
+    state: &mut Account<State>,
+    operation_index: u8,
+    ix: &str,
+) -> Result<()> {
+    // Check if operation index is valid
+    require_logged!(
+        (operation_index as usize) < state.pending_operations.len(),
+        RbxError::InvalidOperationIndex,
+        ix,
+    );
+
+    // Clone the operation to avoid borrow issues
+    let operation = state.pending_operations[operation_index as usize].clone();
+
+    // Check if timelock delay has passed
+    let clock = Clock::get()?;
+    require_logged!(
+        clock.unix_timestamp >= state.governance_frozen_until,
+        RbxError::GovernanceFrozen,
+        ix,
+    );
+    let delay_met = if state.timelock_in_slots {
+        clock.slot >= operation.can_execute_slot
+    } else {
+        clock.unix_timestamp >= operation.can_execute_at
+    };
+    require_logged!(delay_met, RbxError::TimelockDelayNotMet, ix);
+
+    // Execute operation based on type
+    match operation.operation_type {
+        1 => {
+            // Change owner
+            require_logged!(operation.data.len() == 32, RbxError::InvalidOperationData, ix);
+            let new_owner = Pubkey::try_from_slice(&operation.data[0..32])?;
+            state.owner = new_owner;
+
+            emit!(SetOwnerEvent { owner: new_owner });
+        }
+        2 => {
+            // Change signer - which is a 20-byte Ethereum address, optionally followed by a
+            // non-zero byte that skips the overlap when the outgoing key is compromised
+            require_logged!(
+                operation.data.len() == 20 || operation.data.len() == 21,
+                RbxError::InvalidOperationData,
+                ix,
+            );
+            let mut new_signer = [0u8; 20];
+            new_signer.copy_from_slice(&operation.data[0..20]);
+            let skip_overlap = operation.data.get(20).is_some_and(|b| *b != 0);
+
+            // Check if signer is all zeros
+            let is_zero = new_signer.iter().all(|&b| b == 0);
+            require_logged!(!is_zero, RbxError::InvalidSigner, ix);
+
+            // Keep accepting the outgoing key until the overlap window closes
+            if state.signer_rotation_overlap > 0 && !skip_overlap {
+                state.previous_signer = state.withdrawal_signer;
+                state.rotation_deadline = Clock::get()?
+                    .unix_timestamp
+                    .saturating_add(state.signer_rotation_overlap);
+            } else {
+                state.previous_signer = [0u8; 20];
+                state.rotation_deadline = 0;
+            }
+            state.withdrawal_signer = new_signer;
+
+            emit!(SetSignerEvent {
+                signer: new_signer,
+                previous_signer: state.previous_signer,
+                rotation_deadline: state.rotation_deadline,
+            });
+        }
+        3 => {
+            // Set timelock delay, in slots when timelock_in_slots is set
+            require_logged!(operation.data.len() == 8, RbxError::InvalidOperationData, ix);
+            let new_delay = i64::from_le_bytes(operation.data[0..8].try_into().unwrap());
+            require_logged!(new_delay >= 0, RbxError::InvalidTimelockDelay, ix);
+            state.timelock_delay = new_delay;
+
+            emit!(SetTimelockDelayEvent { delay: new_delay });
+        }
+        4 => {
+            // Add timelock authority
+            require_logged!(operation.data.len() == 32, RbxError::InvalidOperationData, ix);
+            let new_authority = Pubkey::try_from_slice(&operation.data[0..32])?;
+
+            // Validate the new authority
+            require_logged!(
+                new_authority != Pubkey::default(),
+                RbxError::InvalidAuthority,
+                ix,
+            );
+
+            // Check if already an authority
+            require_logged!(
+                !state.timelock_authorities.contains(&new_authority),
+                RbxError::AuthorityAlreadyExists,
+                ix,
+            );
+
+            // Check max limit
+            require_logged!(
+                state.timelock_authorities.len() < MAX_AUTHORITIES,
+                RbxError::TooManyAuthorities,
+                ix,
+            );
+
+            // Add the new authority
+            state.timelock_authorities.push(new_authority);
+
+            emit!(AddAuthorityEvent {
+                authority: new_authority
+            });
+        }
+        5 => {
+            // Remove timelock authority
+            require_logged!(operation.data.len() == 32, RbxError::InvalidOperationData, ix);
+            let authority_to_remove = Pubkey::try_from_slice(&operation.data[0..32])?;
+
+            // Prevent removing non-existent authority
+            let position = state
+                .timelock_authorities
+                .iter()
+                .position(|&a| a == authority_to_remove)
+                .ok_or_else(|| logged_error!(RbxError::AuthorityNotFound, ix))?;
+
+            // Prevent removing the last authority
+            require_logged!(
+                state.timelock_authorities.len() > 1,
+                RbxError::CannotRemoveLastAuthority,
+                ix,
+            );
+
+            // Remove the authority
+            state.timelock_authorities.remove(position);
+            state
+                .timelock_authority_roles
+                .retain(|(a, _)| *a != authority_to_remove);
+
+            emit!(RemoveAuthorityEvent {
+                authority: authority_to_remove
+            });
+        }
+        6 => {
+            // Set treasury that receives swept protocol surplus
+            require_logged!(operation.data.len() == 32, RbxError::InvalidOperationData, ix);
+            let new_treasury = Pubkey::try_from_slice(&operation.data[0..32])?;
+            require_logged!(
+                new_treasury != Pubkey::default(),
+                RbxError::InvalidTreasury,
+                ix,
+            );
+            state.treasury = new_treasury;
+
+            emit!(SetTreasuryEvent {
+                treasury: new_treasury
+            });
+        }
+        7 => {
+            // Sign withdrawal amounts as 18-decimal values
+            require_logged!(operation.data.len() == 1, RbxError::InvalidOperationData, ix);
+            let sign_amounts_e18 = operation.data[0] != 0;
+            state.sign_amounts_e18 = sign_amounts_e18;
+
+            emit!(SetAmountEncodingEvent { sign_amounts_e18 });
+        }
+        8 => {
+            // Set maximum batch sizes for deposits and withdrawals
+            require_logged!(operation.data.len() == 2, RbxError::InvalidOperationData, ix);
+            let max_batch_deposits = operation.data[0];
+            let max_batch_withdrawals = operation.data[1];
+            require_logged!(
+                max_batch_deposits > 0 && max_batch_withdrawals > 0,
+                RbxError::InvalidOperationData,
+                ix,
+            );
+            state.max_batch_deposits = max_batch_deposits;
+            state.max_batch_withdrawals = max_batch_withdrawals;
+
+            emit!(SetMaxBatchSizesEvent {
+                max_batch_deposits,
+                max_batch_withdrawals,
+            });
+        }
+        9 => {
+            // Reject mints with a freeze authority in support_token
+            require_logged!(operation.data.len() == 1, RbxError::InvalidOperationData, ix);
+            let reject_freezable_mints = operation.data[0] != 0;
+            state.reject_freezable_mints = reject_freezable_mints;
+
+            emit!(SetRejectFreezableMintsEvent {
+                reject_freezable_mints
+            });
+        }
+        10 => {
+            // Set how long a withdrawal request must go unanswered before a forced claim
+            require_logged!(operation.data.len() == 8, RbxError::InvalidOperationData, ix);
+            let delay = i64::from_le_bytes(operation.data[0..8].try_into().unwrap());
+            require_logged!(delay >= 0, RbxError::InvalidTimelockDelay, ix);
+            state.forced_withdrawal_delay = delay;
+
+            emit!(SetForcedWithdrawalDelayEvent { delay });
+        }
+        11 => {
+            // Enable or disable per-id receipts, the flag optionally followed by the u64 id
+            // receipts start at. Bitmap shards keep every id below it in both modes. The floor is
+            // set once, above every id a shard has marked, so neither scheme sees the other's ids
+            require_logged!(
+                operation.data.len() == 1 || operation.data.len() == 9,
+                RbxError::InvalidOperationData,
+                ix,
+            );
+            let use_withdrawal_receipts = operation.data[0] != 0;
+            if operation.data.len() == 9 {
+                let floor = u64::from_le_bytes(operation.data[1..9].try_into().unwrap());
+                require_logged!(
+                    state.withdrawal_receipt_floor == 0
+                        && floor > state.max_bitmap_withdrawal_id
+                        && floor >= state.high_watermark_id,
+                    RbxError::InvalidOperationData,
+                    ix,
+                );
+                state.withdrawal_receipt_floor = floor;
+            }
+            require_logged!(
+                !use_withdrawal_receipts || state.withdrawal_receipt_floor != 0,
+                RbxError::InvalidOperationData,
+                ix,
+            );
+            state.use_withdrawal_receipts = use_withdrawal_receipts;
+
+            emit!(SetWithdrawalReceiptsEvent {
+                use_withdrawal_receipts,
+                withdrawal_receipt_floor: state.withdrawal_receipt_floor,
+            });
+        }
+        12 => {
+            // Per-token signer override - 32-byte token followed by a 20-byte Ethereum
+            // address, where an all-zero address falls back to the global signer
+            require_logged!(operation.data.len() == 52, RbxError::InvalidOperationData, ix);
+            let token = Pubkey::try_from(&operation.data[0..32]).unwrap();
+            let mut signer = [0u8; 20];
+            signer.copy_from_slice(&operation.data[32..52]);
+
+            if signer.iter().all(|&b| b == 0) {
+                state.remove_withdrawal_signer_override(&token);
+            } else {
+                require_logged!(
+                    state.supported_tokens.contains(&token),
+                    RbxError::UnsupportedToken,
+                    ix,
+                );
+                state.set_withdrawal_signer_override(token, signer);
+            }
+
+            emit!(SetTokenSignerEvent { token, signer });
+        }
+        13 => {
+            // Enable or disable deposit_token_for / deposit_native_for
+            require_logged!(operation.data.len() == 1, RbxError::InvalidOperationData, ix);
+            let enabled = operation.data[0] != 0;
+            state.third_party_deposits_enabled = enabled;
+
+            emit!(SetThirdPartyDepositsEvent { enabled });
+        }
+        14 => {
+            // Add (1) or remove (0) a third-party deposit sponsor - 32-byte pubkey followed by
+            // the flag. An empty sponsor list lets anyone use the *_for deposits when enabled.
+            require_logged!(operation.data.len() == 33, RbxError::InvalidOperationData, ix);
+            let sponsor = Pubkey::try_from_slice(&operation.data[0..32])?;
+            let added = operation.data[32] != 0;
+
+            let position = state.third_party_sponsors.iter().position(|&s| s == sponsor);
+            if added {
+                require_logged!(position.is_none(), RbxError::DuplicateSponsor, ix);
+                require_logged!(
+                    state.third_party_sponsors.len() < MAX_THIRD_PARTY_SPONSORS,
+                    RbxError::TooManySponsors,
+                    ix,
+                );
+                state.third_party_sponsors.push(sponsor);
+            } else {
+                let position =
+                    position.ok_or_else(|| logged_error!(RbxError::SponsorNotFound, ix))?;
+                state.third_party_sponsors.remove(position);
+            }
+
+            emit!(ThirdPartySponsorEvent { sponsor, added });
+        }
+        15 => {
+            // Record the expected BPF upgrade authority for verify_upgrade_authority
+            require_logged!(operation.data.len() == 32, RbxError::InvalidOperationData, ix);
+            let upgrade_authority = Pubkey::try_from_slice(&operation.data[0..32])?;
+            state.expected_upgrade_authority = upgrade_authority;
+
+            emit!(SetUpgradeAuthorityEvent { upgrade_authority });
+        }
+        16 => {
+            // Approve decommissioning, carried out by the owner with `decommission`
+            require_logged!(operation.data.is_empty(), RbxError::InvalidOperationData, ix);
+            state.decommission_approved = true;
+
+            emit!(DecommissionApprovedEvent {});
+        }
+        17 => {
+            // Set the margin engine allowed to lock and release stake collateral
+            require_logged!(operation.data.len() == 32, RbxError::InvalidOperationData, ix);
+            let margin_engine = Pubkey::try_from_slice(&operation.data[0..32])?;
+            state.margin_engine = margin_engine;
+
+            emit!(SetMarginEngineEvent { margin_engine });
+        }
+        18 => {
+            // Set how long the previous signer stays valid after a signer change, in seconds
+            require_logged!(operation.data.len() == 8, RbxError::InvalidOperationData, ix);
+            let overlap = i64::from_le_bytes(operation.data[0..8].try_into().unwrap());
+            require_logged!(overlap >= 0, RbxError::InvalidTimelockDelay, ix);
+            state.signer_rotation_overlap = overlap;
+
+            emit!(SetSignerRotationOverlapEvent { overlap });
+        }
+        19 => {
+            // Register, enable or disable a withdrawal signature version:
+            // 1-byte version, 1-byte enabled flag, then the 32-byte typehash
+            require_logged!(operation.data.len() == 34, RbxError::InvalidOperationData, ix);
+            let version = operation.data[0];
+            let enabled = operation.data[1] != 0;
+            let mut typehash = [0u8; 32];
+            typehash.copy_from_slice(&operation.data[2..34]);
+
+            // Only versions with a struct layout known to process_withdrawal can be registered
+            require_logged!(
+                (SIG_VERSION_WITHDRAWAL..=MAX_SIG_VERSION).contains(&version),
+                RbxError::InvalidSignatureVersion,
+                ix,
+            );
+            require_logged!(typehash != [0u8; 32], RbxError::InvalidSignatureVersion, ix);
+
+            state.set_withdrawal_sig_version(SigVersion {
+                version,
+                typehash,
+                enabled,
+            });
+
+            emit!(SetSignatureVersionEvent {
+                version,
+                typehash,
+                enabled,
+            });
+        }
+        20 => {
+            // Set the minimum withdrawal of a token: 32-byte token, then the u64 amount.
+            // An amount of zero removes the minimum.
+            require_logged!(operation.data.len() == 40, RbxError::InvalidOperationData, ix);
+            let token = Pubkey::try_from_slice(&operation.data[0..32])?;
+            let amount = u64::from_le_bytes(operation.data[32..40].try_into().unwrap());
+
+            if amount == 0 {
+                state.remove_min_withdrawal(&token);
+            } else {
+                require_logged!(
+                    state.supported_tokens.contains(&token),
+                    RbxError::UnsupportedToken,
+                    ix,
+                );
+                state.set_min_withdrawal(token, amount);
+            }
+
+            emit!(SetMinWithdrawalEvent { token, amount });
+        }
+        21 => {
+            // Set the compliance authority allowed to block and unblock addresses
+            require_logged!(operation.data.len() == 32, RbxError::InvalidOperationData, ix);
+            let compliance_authority = Pubkey::try_from_slice(&operation.data[0..32])?;
+            state.compliance_authority = compliance_authority;
+
+            emit!(SetComplianceAuthorityEvent {
+                compliance_authority,
+            });
+        }
+        22 => {
+            // Set how long a withdrawal request must wait before the signer can release it
+            require_logged!(operation.data.len() == 8, RbxError::InvalidOperationData, ix);
+            let delay = i64::from_le_bytes(operation.data[0..8].try_into().unwrap());
+            require_logged!(delay >= 0, RbxError::InvalidTimelockDelay, ix);
+            state.withdrawal_release_delay = delay;
+
+            emit!(SetWithdrawalReleaseDelayEvent { delay });
+        }
+        23 => {
+            // Set the core bridge whose posted VAAs deposit_bridged accepts, and the token
+            // bridge emitter those VAAs must come from
+            require_logged!(operation.data.len() == 66, RbxError::InvalidOperationData, ix);
+            let bridge_program = Pubkey::try_from_slice(&operation.data[0..32])?;
+            let emitter_chain = u16::from_le_bytes(operation.data[32..34].try_into().unwrap());
+            let emitter: [u8; 32] = operation.data[34..66].try_into().unwrap();
+            state.bridge_program = bridge_program;
+            state.bridge_emitter_chain = emitter_chain;
+            state.bridge_emitter = emitter;
+
+            emit!(SetBridgeProgramEvent {
+                bridge_program,
+                emitter_chain,
+                emitter,
+            });
+        }
+        24 => {
+            // Approve clearing a token's pause flags, carried out with `unpause_token`
+            require_logged!(operation.data.len() == 32, RbxError::InvalidOperationData, ix);
+            let token = Pubkey::try_from_slice(&operation.data[0..32])?;
+            require_logged!(
+                state.supported_tokens.contains(&token),
+                RbxError::UnsupportedToken,
+                ix,
+            );
+            if !state.approved_token_unpauses.contains(&token) {
+                state.approved_token_unpauses.push(token);
+            }
+
+            emit!(TokenUnpauseApprovedEvent { token });
+        }
+        25 => {
+            // Approve moving the SOL account to its v2 seeds, carried out with `migrate_sol_vault`
+            require_logged!(operation.data.is_empty(), RbxError::InvalidOperationData, ix);
+            require_logged!(
+                state.sol_account_version == 0,
+                RbxError::SolVaultAlreadyMigrated,
+                ix,
+            );
+            state.sol_vault_migration_approved = true;
+
+            emit!(SolVaultMigrationApprovedEvent {});
+        }
+        26 => {
+            // Set the *_for deposit size that needs an escrow claim, and how long the trader has to accept
+            require_logged!(operation.data.len() == 16, RbxError::InvalidOperationData, ix);
+            let threshold = u64::from_le_bytes(operation.data[0..8].try_into().unwrap());
+            let window = i64::from_le_bytes(operation.data[8..16].try_into().unwrap());
+            require_logged!(window >= 0, RbxError::InvalidTimelockDelay, ix);
+            state.deposit_claim_threshold = threshold;
+            state.deposit_claim_window = window;
+
+            emit!(SetDepositClaimConfigEvent { threshold, window });
+        }
+        27 => {
+            // Keep emitting the legacy deposit, withdrawal and stake events next to their V2 variants
+            require_logged!(operation.data.len() == 1, RbxError::InvalidOperationData, ix);
+            let enabled = operation.data[0] != 0;
+            state.emit_legacy_events = enabled;
+
+            emit!(SetEmitLegacyEventsEvent { enabled });
+        }
+        28 => {
+            // Set the wallet that sends heartbeats and how long it may stay silent, 0 disables the switch
+            require_logged!(operation.data.len() == 40, RbxError::InvalidOperationData, ix);
+            let heartbeat_authority = Pubkey::try_from_slice(&operation.data[0..32])?;
+            let timeout = i64::from_le_bytes(operation.data[32..40].try_into().unwrap());
+            require_logged!(timeout >= 0, RbxError::InvalidTimelockDelay, ix);
+            state.heartbeat_authority = heartbeat_authority;
+            state.heartbeat_timeout = timeout;
+            // Start the new period now rather than from a stale heartbeat
+            state.last_heartbeat = clock.unix_timestamp;
+
+            emit!(SetHeartbeatConfigEvent {
+                heartbeat_authority,
+                timeout,
+            });
+        }
+        29 => {
+            // Set how the trader is encoded in the `address trader` word of signed withdrawals
+            require_logged!(operation.data.len() == 1, RbxError::InvalidOperationData, ix);
+            let trader_encoding = match operation.data[0] {
+                0 => TraderEncoding::SolanaPubkey,
+                1 => TraderEncoding::EvmAddress20,
+                _ => return Err(error!(RbxError::InvalidOperationData)),
+            };
+            state.trader_encoding = trader_encoding;
+
+            emit!(SetTraderEncodingEvent { trader_encoding });
+        }
+        30 => {
+            // Set the roles of a timelock authority; TIMELOCK_ROLES_ALL drops its entry
+            require_logged!(operation.data.len() == 33, RbxError::InvalidOperationData, ix);
+            let authority = Pubkey::try_from_slice(&operation.data[0..32])?;
+            let roles = operation.data[32];
+            require_logged!(
+                roles != 0 && roles & !TIMELOCK_ROLES_ALL == 0,
+                RbxError::InvalidOperationData,
+                ix,
+            );
+            require_logged!(
+                state.timelock_authorities.contains(&authority),
+                RbxError::AuthorityNotFound,
+                ix,
+            );
+
+            state.timelock_authority_roles.retain(|(a, _)| *a != authority);
+            if roles != TIMELOCK_ROLES_ALL {
+                state.timelock_authority_roles.push((authority, roles));
+            }
+
+            // Someone must still be able to both queue and execute, or the timelock is stuck
+            for role in [TIMELOCK_ROLE_PROPOSER, TIMELOCK_ROLE_EXECUTOR] {
+                require_logged!(
+                    state
+                        .timelock_authorities
+                        .iter()
+                        .any(|a| state.has_timelock_role(a, role)),
+                    RbxError::CannotRemoveLastAuthority,
+                    ix,
+                );
+            }
+
+            emit!(SetTimelockRolesEvent { authority, roles });
+        }
+        31 => {
+            // Approve moving an amount of a token from the cold vault, carried out with `rebalance_to_hot`
+            require_logged!(operation.data.len() == 40, RbxError::InvalidOperationData, ix);
+            let token = Pubkey::try_from_slice(&operation.data[0..32])?;
+            let amount = u64::from_le_bytes(operation.data[32..40].try_into().unwrap());
+            require_logged!(
+                state.supported_tokens.contains(&token),
+                RbxError::UnsupportedToken,
+                ix,
+            );
+
+            match state
+                .approved_cold_releases
+                .iter_mut()
+                .find(|(t, _)| t == &token)
+            {
+                Some((_, approved)) => *approved = approved.saturating_add(amount),
+                None => state.approved_cold_releases.push((token, amount)),
+            }
+
+            emit!(ColdReleaseApprovedEvent { token, amount });
+        }
+        32 => {
+            // AdvanceCounters { min_deposit_num, min_stake_num }, applied by `advance_counters`.
+            // 0 leaves a counter as it is
+            require_logged!(operation.data.len() == 16, RbxError::InvalidOperationData, ix);
+            let min_deposit_num = u64::from_le_bytes(operation.data[0..8].try_into().unwrap());
+            let min_stake_num = u64::from_le_bytes(operation.data[8..16].try_into().unwrap());
+            require_logged!(
+                min_deposit_num > 0 || min_stake_num > 0,
+                RbxError::InvalidOperationData,
+                ix,
+            );
+
+            state.approved_min_deposit_num = min_deposit_num;
+            state.approved_min_stake_num = min_stake_num;
+
+            emit!(CounterAdvanceApprovedEvent {
+                min_deposit_num,
+                min_stake_num,
+            });
+        }
+        33 => {
+            // Set how long a full shard is kept before retire_withdrawal_shard, 0 disables it
+            require_logged!(operation.data.len() == 8, RbxError::InvalidOperationData, ix);
+            let retention_period = i64::from_le_bytes(operation.data[0..8].try_into().unwrap());
+            require_logged!(retention_period >= 0, RbxError::InvalidTimelockDelay, ix);
+            state.shard_retention_period = retention_period;
+
+            emit!(SetShardRetentionPeriodEvent { retention_period });
+        }
+        34 => {
+            // Approve an emission schedule for a token: 32-byte token, then keccak256 of the
+            // Borsh-encoded segments, carried out with `set_emission_schedule`
+            require_logged!(operation.data.len() == 64, RbxError::InvalidOperationData, ix);
+            let token = Pubkey::try_from_slice(&operation.data[0..32])?;
+            let mut schedule_hash = [0u8; 32];
+            schedule_hash.copy_from_slice(&operation.data[32..64]);
+            require_logged!(
+                state.supported_tokens.contains(&token),
+                RbxError::UnsupportedToken,
+                ix,
+            );
+
+            // A newer approval for the token replaces an unused one
+            state
+                .approved_emission_schedules
+                .retain(|(t, _)| *t != token);
+            state.approved_emission_schedules.push((token, schedule_hash));
+
+            emit!(EmissionScheduleApprovedEvent {
+                token,
+                schedule_hash,
+            });
+        }
+        35 => {
+            // Change a supported token's min deposit: 32-byte token, then the u64 amount
+            require_logged!(operation.data.len() == 40, RbxError::InvalidOperationData, ix);
+            let token = Pubkey::try_from_slice(&operation.data[0..32])?;
+            let amount = u64::from_le_bytes(operation.data[32..40].try_into().unwrap());
+            require_logged!(
+                state.supported_tokens.contains(&token),
+                RbxError::UnsupportedToken,
+                ix,
+            );
+            state.set_min_deposit(token, amount);
+
+            emit!(SetMinDepositEvent { token, amount });
+        }
+        36 => {
+            // Freeze governance until a deadline, bounded so a freeze cannot be permanent.
+            // Pending operations can still be cancelled while frozen
+            require_logged!(operation.data.len() == 8, RbxError::InvalidOperationData, ix);
+            let frozen_until = i64::from_le_bytes(operation.data[0..8].try_into().unwrap());
+            require_logged!(
+                frozen_until > clock.unix_timestamp
+                    && frozen_until - clock.unix_timestamp <= MAX_GOVERNANCE_FREEZE,
+                RbxError::InvalidOperationData,
+                ix,
+            );
+            state.governance_frozen_until = frozen_until;
+
+            emit!(GovernanceFrozenEvent { frozen_until });
+        }
+        OPERATION_APPROVE_ALLOWED_PURPOSES => {
+            // Approve a token's allowed_purposes mask: 32-byte token, then the mask byte. The
+            // timelock cannot reach token configs, so set_allowed_purposes applies the approval
+            require_logged!(operation.data.len() == 33, RbxError::InvalidOperationData, ix);
+            let token = Pubkey::try_from_slice(&operation.data[0..32])?;
+            let allowed_purposes = operation.data[32];
+            require_logged!(
+                state.supported_tokens.contains(&token),
+                RbxError::UnsupportedToken,
+                ix,
+            );
+            require_logged!(
+                allowed_purposes != 0 && allowed_purposes & !PURPOSES_ALL == 0,
+                RbxError::InvalidOperationData,
+                ix,
+            );
+            // A newer approval for the token replaces an unused one
+            state.approved_allowed_purposes.retain(|(t, _)| *t != token);
+            state.approved_allowed_purposes.push((token, allowed_purposes));
+
+            emit!(AllowedPurposesApprovedEvent {
+                token,
+                allowed_purposes,
+            });
+        }
+        OPERATION_SET_EVENT_MODE => {
+            // Publish deposit, withdrawal and stake events as logs, event CPIs or both
+            require_logged!(operation.data.len() == 1, RbxError::InvalidOperationData, ix);
+            let mode = operation.data[0];
+            require_logged!(mode <= EVENT_MODE_CPI, RbxError::InvalidOperationData, ix);
+            state.event_mode = mode;
+
+            emit!(SetEventModeEvent { mode });
+        }
+        OPERATION_SET_SOL_RESERVE => {
+            // Set the balance native withdrawals must leave in the SOL account, capped so the
+            // reserve cannot be used to lock traders' SOL in
+            require_logged!(operation.data.len() == 8, RbxError::InvalidOperationData, ix);
+            let reserved_lamports = u64::from_le_bytes(operation.data[0..8].try_into().unwrap());
+            require_logged!(
+                reserved_lamports <= MAX_SOL_RESERVE,
+                RbxError::InvalidOperationData,
+                ix,
+            );
+            state.reserved_lamports = reserved_lamports;
+
+            emit!(SetSolReserveEvent { reserved_lamports });
+        }
+        OPERATION_APPROVE_VAULT_ADOPTION => {
+            // Approve registering a vault for a token: 32-byte token, then the 32-byte vault.
+            // Carried out with `adopt_vault_account`, which checks the account itself
+            require_logged!(operation.data.len() == 64, RbxError::InvalidOperationData, ix);
+            let token = Pubkey::try_from_slice(&operation.data[0..32])?;
+            let vault = Pubkey::try_from_slice(&operation.data[32..64])?;
+            require_logged!(
+                state.supported_tokens.contains(&token),
+                RbxError::UnsupportedToken,
+                ix,
+            );
+            // A newer approval for the token replaces an unused one
+            state.approved_vault_adoptions.retain(|(t, _)| *t != token);
+            state.approved_vault_adoptions.push((token, vault));
+
+            emit!(VaultAdoptionApprovedEvent { token, vault });
+        }
+        OPERATION_APPROVE_DEPOSIT_FEE => {
+            // Approve a token's deposit fee: 32-byte token, 2-byte bps, then the 32-byte fee
+            // destination. Carried out with `set_deposit_fee`
+            require_logged!(operation.data.len() == 66, RbxError::InvalidOperationData, ix);
+            let token = Pubkey::try_from_slice(&operation.data[0..32])?;
+            let deposit_fee_bps = u16::from_le_bytes(operation.data[32..34].try_into().unwrap());
+            let fee_destination = Pubkey::try_from_slice(&operation.data[34..66])?;
+            require_logged!(
+                state.supported_tokens.contains(&token),
+                RbxError::UnsupportedToken,
+                ix,
+            );
+            require_logged!(deposit_fee_bps <= MAX_DEPOSIT_FEE_BPS, RbxError::DepositFeeTooHigh, ix);
+            require_logged!(
+                deposit_fee_bps == 0 || fee_destination != Pubkey::default(),
+                RbxError::InvalidFeeDestination,
+                ix,
+            );
+            // A newer approval for the token replaces an unused one
+            state.approved_deposit_fees.retain(|(t, _, _)| *t != token);
+            state
+                .approved_deposit_fees
+                .push((token, deposit_fee_bps, fee_destination));
+
+            emit!(DepositFeeApprovedEvent {
+                token,
+                deposit_fee_bps,
+                fee_destination,
+            });
+        }
+        _ => return Err(error!(RbxError::InvalidOperationType)),
+    }
+
+    // Remove the operation from the pending list
+    state.remove_pending_operation(operation_index as usize);
+
+    emit!(ExecuteOperationEvent {
+        operation_type: operation.operation_type,
+    });
+
+    Ok(())
+}
+
+fn verify_pause_signature(
     state: &mut Account<State>,
-    withdrawal_record: &mut Account<WithdrawalRecord>,
-    id: u64,
+    typehash: [u8; 32],
+    nonce: u64,
+    deadline: i64,
+    v: u8,
+    r: [u8; 32],
+    s: [u8; 32],
+    ix: &str,
+) -> Result<()> {
+    // Nonces must strictly increase so a signed message can't be replayed
+    require_logged!(nonce > state.pause_nonce, RbxError::InvalidNonce, ix);
+    require_logged!(
+        Clock::get()?.unix_timestamp <= deadline,
+        RbxError::SignatureExpired,
+        ix,
+    );
+
+    let domain_separator = eip712::get_domain_separator(state);
+    let struct_hash = get_pause_hash(typehash, nonce, deadline);
+    let digest = hash_typed_data(&domain_separator, &struct_hash);
+
+    let sig_result = verify_secp256k1_signature(&digest, v, &r, &s, &state.withdrawal_signer)?;
+    require_logged!(sig_result, RbxError::InvalidSignature, ix);
+
+    state.pause_nonce = nonce;
+
+    Ok(())
+}
+
+fn enforce_deposit_policy(
+    deposit_policy: &AccountInfo,
+    token: Pubkey,
+    amount: u64,
+    ix: &str,
+) -> Result<()> {
+    let Some(mut policy) = check_deposit_policy(deposit_policy, token, amount, ix)? else {
+        return Ok(());
+    };
+
+    // Approvals are single use
+    policy.approved_token = Pubkey::default();
+    policy.approved_amount = 0;
+
+    let mut data = deposit_policy.try_borrow_mut_data()?;
+    policy.try_serialize(&mut &mut data[..])?;
+
+    Ok(())
+}
+
+// Returns the policy whose approval the deposit would use up, if it needs one
+fn check_deposit_policy(
+    deposit_policy: &AccountInfo,
+    token: Pubkey,
+    amount: u64,
+    ix: &str,
+) -> Result<Option<DepositPolicy>> {
+    // Depositors who never registered a policy are unrestricted
+    if deposit_policy.owner != &crate::ID || deposit_policy.data_is_empty() {
+        return Ok(None);
+    }
+
+    let policy = {
+        let data = deposit_policy.try_borrow_data()?;
+        DepositPolicy::try_deserialize(&mut &data[..])?
+    };
+
+    if amount <= policy.threshold {
+        return Ok(None);
+    }
+
+    require_logged!(
+        policy.approved_token == token && policy.approved_amount == amount,
+        RbxError::DepositApprovalRequired,
+        ix,
+    );
+
+    Ok(Some(policy))
+}
+
+fn enforce_not_blocked(blacklist_entry: &AccountInfo, ix: &str) -> Result<()> {
+    // The entry only exists while the address is blocked
+    require_logged!(
+        blacklist_entry.owner != &crate::ID || blacklist_entry.data_is_empty(),
+        RbxError::AddressBlocked,
+        ix,
+    );
+    Ok(())
+}
+
+// Campaign fee on a token deposit, rounded down
+fn deposit_fee(token_config: &TokenConfig, amount: u64) -> u64 {
+    (amount as u128 * token_config.deposit_fee_bps as u128 / 10_000) as u64
+}
+
+// Send the campaign fee on a token deposit straight to the rewards pool, returning the net
+// amount left to move into the vault and credit to the trader
+fn charge_deposit_fee<'info>(
+    token_config: &TokenConfig,
+    fee_destination: Option<&Account<'info, TokenAccount>>,
+    token_program: &AccountInfo<'info>,
+    from: &AccountInfo<'info>,
+    authority: &AccountInfo<'info>,
+    trader: Pubkey,
+    id: &str,
+    gross_amount: u64,
+    ix: &str,
+) -> Result<u64> {
+    let token = token_config.mint;
+    let fee = deposit_fee(token_config, gross_amount);
+    if fee == 0 {
+        return Ok(gross_amount);
+    }
+
+    let fee_destination =
+        fee_destination.ok_or_else(|| logged_error!(RbxError::InvalidFeeDestination, ix))?;
+    require_logged!(
+        fee_destination.key() == token_config.fee_destination && fee_destination.mint == token,
+        RbxError::InvalidFeeDestination,
+        ix,
+        token,
+        gross_amount,
+        0
+    );
+
+    let fee_ctx = CpiContext::new(
+        token_program.clone(),
+        Transfer {
+            from: from.clone(),
+            to: fee_destination.to_account_info(),
+            authority: authority.clone(),
+        },
+    );
+    token::transfer(fee_ctx, fee)?;
+
+    let net_amount = gross_amount - fee;
+    emit!(DepositFeeEvent {
+        id: id.to_string(),
+        trader,
+        token,
+        gross_amount,
+        fee,
+        net_amount,
+    });
+    Ok(net_amount)
+}
+
+// Hand out the next deposit number, reporting the new counter value for monitoring
+fn take_deposit_num(counters: &mut Counters, ix: &str) -> Result<u64> {
+    let deposit_num = counters.next_deposit_num;
+    counters.next_deposit_num = deposit_num
+        .checked_add(1)
+        .ok_or_else(|| logged_error!(RbxError::CounterOverflow, ix))?;
+    emit!(CounterAdvancedEvent {
+        kind: COUNTER_DEPOSIT_NUM,
+        new_value: counters.next_deposit_num,
+    });
+    Ok(deposit_num)
+}
+
+fn take_stake_num(counters: &mut Counters, ix: &str) -> Result<u64> {
+    let stake_num = counters.next_stake_num;
+    counters.next_stake_num = stake_num
+        .checked_add(1)
+        .ok_or_else(|| logged_error!(RbxError::CounterOverflow, ix))?;
+    emit!(CounterAdvancedEvent {
+        kind: COUNTER_STAKE_NUM,
+        new_value: counters.next_stake_num,
+    });
+    Ok(stake_num)
+}
+
+// Count a deposit against the token's hourly cap, starting a new window once the last one is over
+fn record_deposit_inflow(token_config: &mut TokenConfig, amount: u64, ix: &str) -> Result<()> {
+    if token_config.deposit_cap == 0 {
+        return Ok(());
+    }
+
+    let now = Clock::get()?.unix_timestamp;
+    let window_end = token_config
+        .cap_window_start
+        .checked_add(DEPOSIT_CAP_WINDOW)
+        .ok_or_else(|| logged_error!(RbxError::TimestampOverflow, ix))?;
+    if now >= window_end {
+        token_config.cap_window_start = now;
+        token_config.cap_window_deposited = 0;
+    }
+
+    let deposited = token_config
+        .cap_window_deposited
+        .checked_add(amount)
+        .ok_or_else(|| logged_error!(RbxError::DepositCapExceeded, ix))?;
+    require_logged!(
+        deposited <= token_config.deposit_cap,
+        RbxError::DepositCapExceeded,
+        ix,
+    );
+    token_config.cap_window_deposited = deposited;
+
+    Ok(())
+}
+
+fn check_funding_purpose(
+    token_config: &TokenConfig,
+    purpose: FundingPurpose,
+    ix: &str,
+) -> Result<()> {
+    require_logged!(token_config.allows(purpose), RbxError::PurposeNotAllowed, ix);
+    Ok(())
+}
+
+fn check_deposit_precision(token_config: &TokenConfig, amount: u64, ix: &str) -> Result<()> {
+    // A modulus of 0 or 1 accepts any amount
+    require_logged!(
+        token_config.dust_modulus <= 1 || amount % token_config.dust_modulus == 0,
+        RbxError::InvalidPrecision,
+        ix,
+    );
+    Ok(())
+}
+
+// The event authority, when both it and this program are passed for the event CPI
+fn event_cpi_authority<'info>(
+    event_authority: &Option<UncheckedAccount<'info>>,
+    event_program: &Option<Program<'info, crate::program::Rbx>>,
+) -> Option<AccountInfo<'info>> {
+    match (event_authority, event_program) {
+        (Some(event_authority), Some(_)) => Some(event_authority.to_account_info()),
+        _ => None,
+    }
+}
+
+// Publishes an event according to state.event_mode. The event CPI (what Anchor's emit_cpi!
+// does) carries the event in inner-instruction data, which survives RPC log truncation
+fn emit_event<E: anchor_lang::Event>(
+    state: &State,
+    event_authority: &Option<AccountInfo>,
+    event: &E,
+) -> Result<()> {
+    let event_authority = match event_authority {
+        Some(event_authority) if state.event_mode != EVENT_MODE_LOGS => Some(event_authority),
+        _ => None,
+    };
+    if event_authority.is_none() || state.event_mode == EVENT_MODE_LOGS_AND_CPI {
+        emit!(*event);
+    }
+    if let Some(event_authority) = event_authority {
+        let (expected, bump) = Pubkey::find_program_address(&[EVENT_AUTHORITY_SEED], &crate::ID);
+        require_keys_eq!(
+            event_authority.key(),
+            expected,
+            RbxError::InvalidEventAuthority
+        );
+        let ix = solana_program::instruction::Instruction {
+            program_id: crate::ID,
+            accounts: vec![AccountMeta::new_readonly(expected, true)],
+            data: [
+                &anchor_lang::event::EVENT_IX_TAG_LE[..],
+                &anchor_lang::Event::data(event),
+            ]
+            .concat(),
+        };
+        solana_program::program::invoke_signed(
+            &ix,
+            std::slice::from_ref(event_authority),
+            &[&[EVENT_AUTHORITY_SEED, &[bump]]],
+        )?;
+    }
+    Ok(())
+}
+
+// V2 events are always emitted, the legacy ones only while indexers migrate
+// The trader's profile binding, when passed, must belong to the credited trader
+fn emit_deposit_event(
+    state: &State,
+    profile_binding: &Option<Account<ProfileBinding>>,
+    event_authority: &Option<AccountInfo>,
+    event: DepositEvent,
+    ix: &str,
+) -> Result<()> {
+    let profile_id = match profile_binding {
+        Some(binding) => {
+            require_logged!(
+                binding.wallet == event.trader,
+                RbxError::InvalidProfileBinding,
+                ix,
+            );
+            Some(binding.profile_id)
+        }
+        None => None,
+    };
+
+    emit_event(
+        state,
+        event_authority,
+        &DepositEventV2 {
+            schema_version: EVENT_SCHEMA_VERSION,
+            id: event.id.clone(),
+            trader: event.trader,
+            amount: event.amount,
+            amount_e18: event.amount_e18,
+            token: event.token,
+            slot: event.slot,
+            unix_timestamp: event.unix_timestamp,
+            profile_id,
+        },
+    )?;
+    if state.emit_legacy_events {
+        emit_event(state, event_authority, &event)?;
+    }
+
+    Ok(())
+}
+
+fn emit_withdrawal_event(
+    state: &State,
+    event_authority: &Option<AccountInfo>,
+    event: WithdrawalEvent,
+) -> Result<()> {
+    emit_event(
+        state,
+        event_authority,
+        &WithdrawalEventV2 {
+            schema_version: EVENT_SCHEMA_VERSION,
+            id: event.id,
+            trader: event.trader,
+            recipient: event.recipient,
+            amount: event.amount,
+            amount_e18: event.amount_e18,
+            token: event.token,
+            slot: event.slot,
+            unix_timestamp: event.unix_timestamp,
+        },
+    )?;
+    if state.emit_legacy_events {
+        emit_event(state, event_authority, &event)?;
+    }
+
+    Ok(())
+}
+
+fn emit_stake_event(
+    state: &State,
+    event_authority: &Option<AccountInfo>,
+    event: StakeEvent,
+) -> Result<()> {
+    emit_event(
+        state,
+        event_authority,
+        &StakeEventV2 {
+            schema_version: EVENT_SCHEMA_VERSION,
+            id: event.id.clone(),
+            trader: event.trader,
+            amount: event.amount,
+            token: event.token,
+            slot: event.slot,
+            unix_timestamp: event.unix_timestamp,
+        },
+    )?;
+    if state.emit_legacy_events {
+        emit_event(state, event_authority, &event)?;
+    }
+
+    Ok(())
+}
+
+// Third-party deposits of at least the threshold must go through an escrow claim
+fn requires_deposit_claim(state: &State, amount: u64) -> bool {
+    state.deposit_claim_threshold != 0 && amount >= state.deposit_claim_threshold
+}
+
+fn check_third_party_deposit(state: &State, sponsor: &Pubkey, ix: &str) -> Result<()> {
+    require_logged!(
+        state.third_party_deposits_enabled,
+        RbxError::ThirdPartyDepositsDisabled,
+        ix,
+    );
+    require_logged!(
+        state.third_party_sponsors.is_empty() || state.third_party_sponsors.contains(sponsor),
+        RbxError::UnauthorizedSponsor,
+        ix,
+    );
+    Ok(())
+}
+
+fn enforce_withdrawal_allowlist(
+    withdrawal_allowlist: &AccountInfo,
+    destination: &Account<TokenAccount>,
+    owner: Pubkey,
+    ix: &str,
+) -> Result<()> {
+    // Traders who never opened an allowlist may only withdraw to accounts they own
+    if withdrawal_allowlist.owner != &crate::ID || withdrawal_allowlist.data_is_empty() {
+        require_logged!(destination.owner == owner, RbxError::InvalidRecipientAccount, ix);
+        return Ok(());
+    }
+
+    let allowlist = {
+        let data = withdrawal_allowlist.try_borrow_data()?;
+        WithdrawalAllowlist::try_deserialize(&mut &data[..])?
+    };
+
+    // Once opened, the allowlist alone decides the destination, which may be a third party's
+    let destination = destination.key();
+    let now = Clock::get()?.unix_timestamp;
+    require_logged!(
+        allowlist
+            .entries
+            .iter()
+            .any(|e| e.destination == destination && e.active_at <= now),
+        RbxError::DestinationNotAllowlisted,
+        ix,
+    );
+
+    Ok(())
+}
+
+// Reject batches longer than the configured limit before doing any work
+fn check_batch_size(len: usize, max: u8, ix: &str) -> Result<()> {
+    require_logged!(len > 0, RbxError::EmptyBatch, ix);
+    require_logged!(len <= max as usize, RbxError::BatchTooLarge, ix);
+    Ok(())
+}
+
+fn init_token_config(
+    token_config: &mut Account<TokenConfig>,
+    mint: &Account<Mint>,
+    reject_freezable: bool,
+    ix: &str,
+) -> Result<()> {
+    require_logged!(
+        !(reject_freezable && mint.freeze_authority.is_some()),
+        RbxError::FreezableMint,
+        ix,
+    );
+
+    token_config.mint = mint.key();
+    token_config.decimals = mint.decimals;
+    token_config.token_program = *mint.to_account_info().owner;
+    token_config.status = TokenStatus::Active;
+    token_config.liabilities = 0;
+    token_config.paused_deposits = false;
+    token_config.paused_withdrawals = false;
+    token_config.dust_modulus = 0;
+    token_config.deposit_cap = 0;
+    token_config.cap_window_start = 0;
+    token_config.cap_window_deposited = 0;
+    token_config.hot_float = 0;
+    token_config.deposit_fee_bps = 0;
+    token_config.fee_destination = Pubkey::default();
+    token_config.last_checkpoint_day = 0;
+    token_config.vault = Pubkey::default();
+    token_config.allowed_purposes = PURPOSES_ALL;
+
+    Ok(())
+}
+
+// Lowest balance the SOL account may be left with by a payout
+fn sol_reserve_floor(state: &State) -> Result<u64> {
+    Ok(state.reserved_lamports.max(Rent::get()?.minimum_balance(0)))
+}
+
+fn enforce_sol_reserve(state: &State, balance: u64, amount: u64, ix: &str) -> Result<()> {
+    let floor = sol_reserve_floor(state)?;
+    require_logged!(
+        balance.checked_sub(amount).is_some_and(|rest| rest >= floor),
+        RbxError::SolReserveBreached,
+        ix,
+    );
+    Ok(())
+}
+
+// Forced and escape payouts only keep the SOL account rent exempt, so the reserve cannot
+// hold them up
+fn enforce_sol_rent_floor(balance: u64, amount: u64, ix: &str) -> Result<()> {
+    let floor = Rent::get()?.minimum_balance(0);
+    require_logged!(
+        balance.checked_sub(amount).is_some_and(|rest| rest >= floor),
+        RbxError::SolReserveBreached,
+        ix,
+    );
+    Ok(())
+}
+
+fn credit_stake_position(
+    stake_position: &mut Option<Account<StakePosition>>,
+    trader: Pubkey,
+    token: Pubkey,
     amount: u64,
+    ix: &str,
+) -> Result<()> {
+    if let Some(position) = stake_position {
+        require_logged!(
+            position.trader == trader && position.token == token,
+            RbxError::InvalidStakePosition,
+            ix,
+        );
+        position.staked = position
+            .staked
+            .checked_add(amount)
+            .ok_or_else(|| logged_error!(RbxError::AmountOverflow, ix))?;
+    }
+    Ok(())
+}
+
+// Balance backing a token's liabilities: the SPL vault plus any cold vault, or the SOL account
+// less its rent-exempt minimum, which is not owed to anyone
+fn custodied_balance(
     token: Pubkey,
+    program_token_account: Option<&Account<TokenAccount>>,
+    cold_vault: Option<&Account<TokenAccount>>,
+    program_token_authority: &Pubkey,
+    program_sol_account: &AccountInfo,
+    ix: &str,
+) -> Result<u64> {
+    if token == token::spl_token::native_mint::ID {
+        return Ok(program_sol_account
+            .lamports()
+            .saturating_sub(Rent::get()?.minimum_balance(0)));
+    }
+
+    let vault =
+        program_token_account.ok_or_else(|| logged_error!(RbxError::InvalidVaultAccount, ix))?;
+    require_logged!(
+        vault.mint == token && vault.owner == *program_token_authority,
+        RbxError::InvalidVaultAccount,
+        ix
+    );
+
+    // Tokens rebalanced to the cold vault still back the liabilities
+    let cold_balance = match cold_vault {
+        Some(cold_vault) => {
+            let (cold_authority, _) =
+                Pubkey::find_program_address(&[b"cold_authority"], &crate::ID);
+            require_logged!(
+                cold_vault.mint == token && cold_vault.owner == cold_authority,
+                RbxError::InvalidVaultAccount,
+                ix
+            );
+            cold_vault.amount
+        }
+        None => 0,
+    };
+    Ok(vault.amount.saturating_add(cold_balance))
+}
+
+// "major.minor.patch" as comparable numbers, missing or malformed parts reading as 0
+fn parse_version(version: &str) -> [u16; 3] {
+    let mut parts = [0u16; 3];
+    for (part, value) in parts.iter_mut().zip(version.split('.')) {
+        *part = value.parse().unwrap_or(0);
+    }
+    parts
+}
+
+// Rewrite a 1.0.1 State in the current layout, growing the account to State::SIZE with rent
+// from the payer. The version stays [0, 0, 0] so run_migrations seeds the new fields
+fn convert_legacy_state<'info>(
+    state_info: &AccountInfo<'info>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    ix: &str,
+) -> Result<()> {
+    let state: State = {
+        let data = state_info.try_borrow_data()?;
+        require_logged!(
+            data[..8] == <State as anchor_lang::Discriminator>::DISCRIMINATOR,
+            ErrorCode::AccountDiscriminatorMismatch,
+            ix,
+        );
+        LegacyState::deserialize(&mut &data[8..])
+            .map_err(|_| error!(ErrorCode::AccountDidNotDeserialize))?
+            .into()
+    };
+
+    let space = 8 + State::SIZE;
+    let shortfall = Rent::get()?
+        .minimum_balance(space)
+        .saturating_sub(state_info.lamports());
+    if shortfall > 0 {
+        solana_program::program::invoke(
+            &solana_program::system_instruction::transfer(payer.key, state_info.key, shortfall),
+            &[
+                payer.to_account_info(),
+                state_info.clone(),
+                system_program.to_account_info(),
+            ],
+        )?;
+    }
+    state_info.realloc(space, true)?;
+
+    let mut data = state_info.try_borrow_mut_data()?;
+    state.try_serialize(&mut &mut data[..])?;
+    Ok(())
+}
+
+// In-place migrations from `from`, the version recorded before the upgrade ([0, 0, 0] for
+// deployments predating the record). Every step must be safe to run once per upgrade
+fn run_migrations(state: &mut State, from: [u16; 3]) {
+    // The domain separator is cached; recompute it with the upgraded hashing code
+    state.domain_separator = None;
+
+    if from < [1, 1, 0] {
+        // Fields added in 1.1.0 whose zero value is not the old behaviour. The SOL custody
+        // stays unreconciled until the owner runs reconcile_custodied_lamports
+        state.max_batch_deposits = DEFAULT_MAX_BATCH_SIZE;
+        state.max_batch_withdrawals = DEFAULT_MAX_BATCH_SIZE;
+        state.custody_reconciled = false;
+        // Indexers still read the legacy events until the timelock turns them off
+        state.emit_legacy_events = true;
+        // *_for deposits were open to anyone before the flag existed
+        state.third_party_deposits_enabled = true;
+        // Operations queued before ids existed get one, so every execute path can write
+        // its receipt
+        let untracked = state.pending_operations.len() - state.pending_operation_ids.len();
+        let mut ids = Vec::with_capacity(state.pending_operations.len());
+        for _ in 0..untracked {
+            state.next_operation_id += 1;
+            ids.push(state.next_operation_id);
+        }
+        ids.append(&mut state.pending_operation_ids);
+        state.pending_operation_ids = ids;
+    }
+}
+
+// The mint's vault: the canonical ATA of the token authority unless adopt_vault_account
+// registered another account
+fn vault_address(token_config: &TokenConfig, program_token_authority: &Pubkey) -> Pubkey {
+    if token_config.vault == Pubkey::default() {
+        get_associated_token_address(program_token_authority, &token_config.mint)
+    } else {
+        token_config.vault
+    }
+}
+
+// Accounts of a token deposit or stake. The funder signs the transfer, and its blacklist entry
+// and deposit policy are the ones checked; the trader is credited
+struct TokenFundingAccounts<'a, 'info> {
+    state: &'a Account<'info, State>,
+    counters: &'a mut Account<'info, Counters>,
+    stats: &'a mut Account<'info, Stats>,
+    token_config: &'a mut Account<'info, TokenConfig>,
+    mint: &'a AccountInfo<'info>,
+    program_token_account: &'a AccountInfo<'info>,
+    funder_token_account: &'a AccountInfo<'info>,
+    funder: AccountInfo<'info>,
+    trader: Pubkey,
+    blacklist_entry: &'a AccountInfo<'info>,
+    deposit_policy: &'a AccountInfo<'info>,
+    trader_ledger: &'a mut Option<Account<'info, TraderLedger>>,
+    stake_position: &'a mut Option<Account<'info, StakePosition>>,
+    deposit_receipt: &'a mut Option<Account<'info, DepositReceipt>>,
+    fee_destination: &'a Option<Account<'info, TokenAccount>>,
+    token_program: AccountInfo<'info>,
+    profile_binding: &'a Option<Account<'info, ProfileBinding>>,
+    activity_counter: &'a mut Option<Account<'info, ActivityCounter>>,
+    event_authority: Option<AccountInfo<'info>>,
+}
+
+// Funding accounts of a DepositToken context, where the user funds and is credited
+fn deposit_token_funding<'a, 'info>(
+    accounts: &'a mut DepositToken<'info>,
+) -> TokenFundingAccounts<'a, 'info> {
+    TokenFundingAccounts {
+        state: &accounts.state,
+        counters: &mut accounts.counters,
+        stats: &mut accounts.stats,
+        token_config: &mut accounts.token_config,
+        mint: &accounts.mint,
+        program_token_account: &accounts.program_token_account,
+        funder_token_account: &accounts.user_token_account,
+        funder: accounts.user.to_account_info(),
+        trader: accounts.user.key(),
+        blacklist_entry: &accounts.blacklist_entry,
+        deposit_policy: &accounts.deposit_policy,
+        trader_ledger: &mut accounts.trader_ledger,
+        stake_position: &mut accounts.stake_position,
+        deposit_receipt: &mut accounts.deposit_receipt,
+        fee_destination: &accounts.fee_destination,
+        token_program: accounts.token_program.to_account_info(),
+        profile_binding: &accounts.profile_binding,
+        activity_counter: &mut accounts.activity_counter,
+        event_authority: event_cpi_authority(&accounts.event_authority, &accounts.event_program),
+    }
+}
+
+// Shared body of every SPL token deposit and stake, so every check, the deposit fee and the
+// activity counter apply to all of them. Returns the deposit num or stake num taken for the
+// transfer, and the amount credited after any fee
+fn fund_transfer_in(
+    accounts: TokenFundingAccounts,
+    amount: u64,
+    purpose: FundingPurpose,
+    create_receipt: bool,
+    ix: &str,
+) -> Result<(u64, u64)> {
+    let token = accounts.mint.key();
+    let trader = accounts.trader;
+    require_logged!(
+        accounts.counters.reentry_lock_status == UNLOCKED,
+        RbxError::ReentrancyDetected,
+        ix,
+        token,
+        amount,
+        0
+    );
+    enforce_not_blocked(accounts.blacklist_entry, ix)?;
+    // Receipts only exist for deposits
+    require_logged!(
+        create_receipt == accounts.deposit_receipt.is_some()
+            && (purpose == FundingPurpose::Deposit || !create_receipt),
+        RbxError::InvalidDepositReceipt,
+        ix,
+        token,
+        amount,
+        0
+    );
+
+    accounts.counters.reentry_lock_status = LOCKED;
+
+    // Verify token is supported
+    require_logged!(
+        accounts.state.supported_tokens.contains(&token),
+        RbxError::UnsupportedToken,
+        ix,
+        token,
+        amount,
+        0
+    );
+    let min_deposit = accounts
+        .state
+        .get_min_deposit(&token)
+        .ok_or_else(|| logged_error!(RbxError::UnsupportedToken, ix))?;
+
+    require_logged!(
+        amount > 0 && amount >= min_deposit,
+        RbxError::AmountTooSmall,
+        ix,
+        token,
+        amount,
+        0
+    );
+    enforce_deposit_policy(accounts.deposit_policy, token, amount, ix)?;
+    check_deposit_precision(accounts.token_config, amount, ix)?;
+    check_funding_purpose(accounts.token_config, purpose, ix)?;
+    record_deposit_inflow(accounts.token_config, amount, ix)?;
+
+    // Create the deposit or stake ID string with _rbx_sol suffix
+    let (num, prefix) = match purpose {
+        FundingPurpose::Deposit => (take_deposit_num(accounts.counters, ix)?, "d_"),
+        FundingPurpose::Stake => (take_stake_num(accounts.counters, ix)?, "s_"),
+    };
+    let mut id = String::with_capacity(20); // Pre-allocate to avoid reallocation
+    id.push_str(prefix);
+    id.push_str(&num.to_string());
+    id.push_str("_rbx_sol");
+
+    // Stakes are not charged the campaign fee
+    let amount = match purpose {
+        FundingPurpose::Deposit => charge_deposit_fee(
+            accounts.token_config,
+            accounts.fee_destination.as_ref(),
+            &accounts.token_program,
+            accounts.funder_token_account,
+            &accounts.funder,
+            trader,
+            &id,
+            amount,
+            ix,
+        )?,
+        FundingPurpose::Stake => amount,
+    };
+
+    // Transfer tokens from the funder to program token account
+    ensure_vault_not_frozen(accounts.program_token_account)?;
+    let transfer_ctx = CpiContext::new(
+        accounts.token_program.clone(),
+        Transfer {
+            from: accounts.funder_token_account.clone(),
+            to: accounts.program_token_account.clone(),
+            authority: accounts.funder.clone(),
+        },
+    );
+    token::transfer(transfer_ctx, amount)?;
+
+    match purpose {
+        FundingPurpose::Deposit => {
+            credit_trader_ledger(accounts.trader_ledger, trader, token, amount, ix)?
+        }
+        FundingPurpose::Stake => {
+            credit_stake_position(accounts.stake_position, trader, token, amount, ix)?
+        }
+    }
+
+    accounts.token_config.add_liability(amount);
+
+    let clock = Clock::get()?;
+    match purpose {
+        FundingPurpose::Deposit => {
+            accounts.stats.record_deposit(token, amount);
+
+            // Leave an on-chain proof of the deposit that other programs can read
+            if let Some(receipt) = accounts.deposit_receipt.as_mut() {
+                receipt.deposit_num = num;
+                receipt.trader = trader;
+                receipt.token = token;
+                receipt.amount = amount;
+                receipt.slot = clock.slot;
+            }
+
+            emit_deposit_event(
+                accounts.state,
+                accounts.profile_binding,
+                &accounts.event_authority,
+                DepositEvent {
+                    id,
+                    trader,
+                    amount,
+                    amount_e18: to_e18(amount, mint_decimals(accounts.mint)?),
+                    token,
+                    slot: clock.slot,
+                    unix_timestamp: clock.unix_timestamp,
+                },
+                ix,
+            )?;
+        }
+        FundingPurpose::Stake => {
+            accounts.stats.record_stake(token, amount);
+
+            emit_stake_event(
+                accounts.state,
+                &accounts.event_authority,
+                StakeEvent {
+                    id,
+                    trader,
+                    amount,
+                    token,
+                    slot: clock.slot,
+                    unix_timestamp: clock.unix_timestamp,
+                },
+            )?;
+        }
+    }
+
+    accounts.counters.reentry_lock_status = UNLOCKED;
+
+    record_activity(accounts.activity_counter, trader, ix)?;
+
+    Ok((num, amount))
+}
+
+// Bump a trader's activity counter so pollers can detect changes by fetching one account
+fn record_activity(
+    activity_counter: &mut Option<Account<ActivityCounter>>,
     trader: Pubkey,
-    v: u8,
-    r: [u8; 32],
-    s: [u8; 32],
+    ix: &str,
 ) -> Result<()> {
-    // Validate amount
-    require!(amount > 0, RbxError::WrongAmount);
+    if let Some(counter) = activity_counter {
+        require_logged!(counter.trader == trader, RbxError::InvalidActivityCounter, ix);
+        counter.count = counter
+            .count
+            .checked_add(1)
+            .ok_or_else(|| logged_error!(RbxError::CounterOverflow, ix))?;
+        counter.last_slot = Clock::get()?.slot;
+    }
+    Ok(())
+}
+
+fn credit_trader_ledger(
+    trader_ledger: &mut Option<Account<TraderLedger>>,
+    trader: Pubkey,
+    token: Pubkey,
+    amount: u64,
+    ix: &str,
+) -> Result<()> {
+    if let Some(ledger) = trader_ledger {
+        require_logged!(
+            ledger.trader == trader && ledger.token == token,
+            RbxError::InvalidTraderLedger,
+            ix,
+        );
+        ledger.balance = ledger
+            .balance
+            .checked_add(amount)
+            .ok_or_else(|| logged_error!(RbxError::CounterOverflow, ix))?;
+    }
+    Ok(())
+}
+
+// Read an optional trader ledger from remaining_accounts, where the program id stands in for
+// an omitted one as in Anchor's optional accounts
+fn optional_trader_ledger<'info>(
+    info: &'info AccountInfo<'info>,
+) -> Result<Option<Account<'info, TraderLedger>>> {
+    if info.key() == crate::ID {
+        return Ok(None);
+    }
+    Ok(Some(Account::<TraderLedger>::try_from(info)?))
+}
+
+// Read an optional token account from remaining_accounts, with the same program id placeholder
+fn optional_token_account<'info>(
+    info: &'info AccountInfo<'info>,
+) -> Result<Option<Account<'info, TokenAccount>>> {
+    if info.key() == crate::ID {
+        return Ok(None);
+    }
+    Ok(Some(Account::<TokenAccount>::try_from(info)?))
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + State::SIZE,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub authority: Signer<'info>,
+    pub default_token_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + TokenConfig::SIZE,
+        seeds = [b"token_config".as_ref(), default_token_mint.key().as_ref()],
+        bump
+    )]
+    pub default_token_config: Account<'info, TokenConfig>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + Stats::SIZE,
+        seeds = [b"stats"],
+        bump
+    )]
+    pub stats: Account<'info, Stats>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + Counters::SIZE,
+        seeds = [b"counters"],
+        bump
+    )]
+    pub counters: Account<'info, Counters>,
+    /// CHECK: PDA for token account authority
+    #[account(seeds = [b"token_authority"], bump)]
+    pub program_token_authority: AccountInfo<'info>,
+    /// CHECK: Vault ATA for the default token, created in the instruction
+    #[account(
+        mut,
+        address = get_associated_token_address(&program_token_authority.key(), &default_token_mint.key())
+    )]
+    pub program_token_account: AccountInfo<'info>,
+    /// CHECK: PDA for SOL account
+    #[account(seeds = [b"sol_account"], bump)]
+    pub program_sol_account: AccountInfo<'info>,
+    /// wSOL mint, passed with the other native accounts when `native_min_deposit` is set
+    #[account(address = token::spl_token::native_mint::ID)]
+    pub native_mint: Option<Account<'info, Mint>>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + TokenConfig::SIZE,
+        seeds = [b"token_config".as_ref(), token::spl_token::native_mint::ID.as_ref()],
+        bump
+    )]
+    pub native_token_config: Option<Account<'info, TokenConfig>>,
+    /// CHECK: Vault ATA for wSOL, created in the instruction
+    #[account(
+        mut,
+        address = get_associated_token_address(&program_token_authority.key(), &token::spl_token::native_mint::ID)
+    )]
+    pub native_program_token_account: Option<AccountInfo<'info>>,
+    /// ProgramData of this program. Whoever initializes becomes owner, so its upgrade
+    /// authority must be the owner unless built with `non-upgradeable`
+    #[account(
+        seeds = [crate::ID.as_ref()],
+        bump,
+        seeds::program = solana_program::bpf_loader_upgradeable::ID,
+        constraint = cfg!(feature = "non-upgradeable")
+            || program_data.upgrade_authority_address == Some(owner.key())
+            @ RbxError::NotUpgradeAuthority
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct SupportToken<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    /// Token mint, deserialized so that token accounts and typos are rejected
+    pub token_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + TokenConfig::SIZE,
+        seeds = [b"token_config".as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    /// CHECK: PDA for token account authority
+    #[account(
+        seeds = [b"token_authority".as_ref()],
+        bump = state.token_account_bump
+    )]
+    pub program_token_authority: AccountInfo<'info>,
+    /// CHECK: Vault ATA for the new token, created in the instruction
+    #[account(
+        mut,
+        address = get_associated_token_address(&program_token_authority.key(), &token_mint.key())
+    )]
+    pub program_token_account: AccountInfo<'info>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ReactivateToken<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), token_config.mint.as_ref()],
+        bump
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+}
+
+#[derive(Accounts)]
+pub struct Deposit<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    /// CHECK: Token mint
+    pub mint: AccountInfo<'info>,
+    /// CHECK: Token account
+    #[account(mut)]
+    pub program_token_account: AccountInfo<'info>,
+    /// CHECK: PDA for token account authority
+    #[account(
+        seeds = [b"token_authority"],
+        bump = state.token_account_bump
+    )]
+    pub program_token_authority: AccountInfo<'info>,
+    /// CHECK: User token account
+    #[account(mut)]
+    pub user_token_account: AccountInfo<'info>,
+    pub user: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(token: Pubkey)]
+pub struct UnsupportToken<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), token.as_ref()],
+        bump
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetTokenStatus<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), token_config.mint.as_ref()],
+        bump
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AdoptVaultAccount<'info> {
+    #[account(mut, seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), token_config.mint.as_ref()],
+        bump
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    /// Existing token account to use as the vault, validated in the instruction
+    pub vault_account: Account<'info, TokenAccount>,
+    /// CHECK: PDA for token account authority
+    #[account(
+        seeds = [b"token_authority".as_ref()],
+        bump = state.token_account_bump
+    )]
+    pub program_token_authority: AccountInfo<'info>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RebalanceToCold<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        seeds = [b"token_config".as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    pub mint: Account<'info, Mint>,
+    /// Program's vault for the mint
+    #[account(
+        mut,
+        address = vault_address(&token_config, &program_token_authority.key())
+            @ RbxError::InvalidVaultAccount
+    )]
+    pub program_token_account: Account<'info, TokenAccount>,
+    /// CHECK: PDA for token account authority
+    #[account(
+        seeds = [b"token_authority".as_ref()],
+        bump = state.token_account_bump
+    )]
+    pub program_token_authority: AccountInfo<'info>,
+    /// CHECK: PDA owning the cold vaults, only signs transfers approved through the timelock
+    #[account(seeds = [b"cold_authority"], bump)]
+    pub cold_authority: AccountInfo<'info>,
+    /// CHECK: Cold vault ATA for the mint, created in the instruction if needed
+    #[account(
+        mut,
+        address = get_associated_token_address(&cold_authority.key(), &mint.key())
+    )]
+    pub cold_vault: AccountInfo<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RebalanceToHot<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub mint: Account<'info, Mint>,
+    #[account(
+        seeds = [b"token_config".as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    /// Program's vault for the mint
+    #[account(
+        mut,
+        address = vault_address(&token_config, &program_token_authority.key())
+            @ RbxError::InvalidVaultAccount
+    )]
+    pub program_token_account: Account<'info, TokenAccount>,
+    /// CHECK: PDA for token account authority
+    #[account(
+        seeds = [b"token_authority".as_ref()],
+        bump = state.token_account_bump
+    )]
+    pub program_token_authority: AccountInfo<'info>,
+    /// CHECK: PDA owning the cold vaults
+    #[account(seeds = [b"cold_authority"], bump)]
+    pub cold_authority: AccountInfo<'info>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = cold_authority
+    )]
+    pub cold_vault: Account<'info, TokenAccount>,
+    pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RevokePreviousSigner<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetTokenPause<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), token_config.mint.as_ref()],
+        bump
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterTokenConfig<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub token_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + TokenConfig::SIZE,
+        seeds = [b"token_config".as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DepositToken<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"counters"],
+        bump
+    )]
+    pub counters: Account<'info, Counters>,
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump
+    )]
+    pub stats: Account<'info, Stats>,
+    /// Deposits and stakes are only accepted for active tokens
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), mint.key().as_ref()],
+        bump,
+        constraint = token_config.status == TokenStatus::Active @ RbxError::TokenNotActive,
+        constraint = !token_config.paused_deposits @ RbxError::TokenDepositsPaused
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    /// CHECK: SPL token mint - verified in the instruction
+    pub mint: AccountInfo<'info>,
+    /// CHECK: Canonical vault ATA of the token authority, so a crafted transaction cannot
+    /// route the deposit to another account while the event still credits the user
+    #[account(
+        mut,
+        address = vault_address(&token_config, &program_token_authority.key())
+            @ RbxError::InvalidVaultAccount
+    )]
+    pub program_token_account: AccountInfo<'info>,
+    /// CHECK: PDA for token account authority
+    #[account(
+        seeds = [b"token_authority".as_ref()],
+        bump = state.token_account_bump
+    )]
+    pub program_token_authority: AccountInfo<'info>,
+    #[account(mut)]
+    /// CHECK: User's token account for the specified mint
+    pub user_token_account: AccountInfo<'info>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    /// CHECK: Blacklist PDA of the user, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"blacklist".as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub blacklist_entry: AccountInfo<'info>,
+    /// CHECK: Deposit policy PDA of the user, enforced in the instruction when initialized
+    #[account(
+        mut,
+        seeds = [b"deposit_policy".as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub deposit_policy: AccountInfo<'info>,
+    /// Optional ledger of the credited trader, validated in the instruction
+    #[account(mut)]
+    pub trader_ledger: Option<Account<'info, TraderLedger>>,
+    /// Optional stake position of the staker, credited by stakes
+    #[account(mut)]
+    pub stake_position: Option<Account<'info, StakePosition>>,
+    /// Optional receipt for this deposit, only supported by `deposit_token`
+    #[account(
+        init,
+        payer = user,
+        space = 8 + DepositReceipt::SIZE,
+        seeds = [b"deposit_receipt".as_ref(), &counters.next_deposit_num.to_le_bytes()],
+        bump
+    )]
+    pub deposit_receipt: Option<Account<'info, DepositReceipt>>,
+    /// Rewards pool token account, required while the token charges a deposit fee
+    #[account(mut)]
+    pub fee_destination: Option<Account<'info, TokenAccount>>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    /// Optional profile binding of the credited trader, reported in the deposit event
+    pub profile_binding: Option<Account<'info, ProfileBinding>>,
+    /// Optional activity counter of the credited trader, bumped for off-chain pollers
+    #[account(mut)]
+    pub activity_counter: Option<Account<'info, ActivityCounter>>,
+    /// CHECK: Optional event CPI signer, verified against its PDA when events are emitted
+    pub event_authority: Option<UncheckedAccount<'info>>,
+    /// This program, the target of the event CPI
+    pub event_program: Option<Program<'info, crate::program::Rbx>>,
+    /// CHECK: Blacklist PDA of the credited trader, required by `deposit_token_for` and
+    /// verified in the instruction
+    pub trader_blacklist_entry: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+#[instruction(amount: u64, client_id: [u8; 16])]
+pub struct DepositTokenWithClientId<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"counters"],
+        bump
+    )]
+    pub counters: Account<'info, Counters>,
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump
+    )]
+    pub stats: Account<'info, Stats>,
+    /// Deposits and stakes are only accepted for active tokens
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), mint.key().as_ref()],
+        bump,
+        constraint = token_config.status == TokenStatus::Active @ RbxError::TokenNotActive,
+        constraint = !token_config.paused_deposits @ RbxError::TokenDepositsPaused
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    /// CHECK: SPL token mint - verified in the instruction
+    pub mint: AccountInfo<'info>,
+    /// CHECK: Canonical vault ATA of the token authority, so a crafted transaction cannot
+    /// route the deposit to another account while the event still credits the user
+    #[account(
+        mut,
+        address = vault_address(&token_config, &program_token_authority.key())
+            @ RbxError::InvalidVaultAccount
+    )]
+    pub program_token_account: AccountInfo<'info>,
+    /// CHECK: PDA for token account authority
+    #[account(
+        seeds = [b"token_authority".as_ref()],
+        bump = state.token_account_bump
+    )]
+    pub program_token_authority: AccountInfo<'info>,
+    #[account(mut)]
+    /// CHECK: User's token account for the specified mint
+    pub user_token_account: AccountInfo<'info>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    /// CHECK: Blacklist PDA of the user, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"blacklist".as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub blacklist_entry: AccountInfo<'info>,
+    /// CHECK: Deposit policy PDA of the user, enforced in the instruction when initialized
+    #[account(
+        mut,
+        seeds = [b"deposit_policy".as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub deposit_policy: AccountInfo<'info>,
+    /// Optional ledger of the credited trader, validated in the instruction
+    #[account(mut)]
+    pub trader_ledger: Option<Account<'info, TraderLedger>>,
+    /// Marker of this client_id, whose existence rejects a retried deposit
+    #[account(
+        init,
+        payer = user,
+        space = 8 + ClientDepositMarker::SIZE,
+        seeds = [b"client_deposit".as_ref(), user.key().as_ref(), client_id.as_ref()],
+        bump
+    )]
+    pub client_deposit_marker: Account<'info, ClientDepositMarker>,
+    /// Rewards pool token account, required while the token charges a deposit fee
+    #[account(mut)]
+    pub fee_destination: Option<Account<'info, TokenAccount>>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    /// Optional profile binding of the credited trader, reported in the deposit event
+    pub profile_binding: Option<Account<'info, ProfileBinding>>,
+    /// Optional activity counter of the credited trader, bumped for off-chain pollers
+    #[account(mut)]
+    pub activity_counter: Option<Account<'info, ActivityCounter>>,
+}
+
+#[derive(Accounts)]
+pub struct Heartbeat<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        constraint = state.heartbeat_authority != Pubkey::default()
+            && state.heartbeat_authority == heartbeat_authority.key() @ RbxError::UnauthorizedAccess
+    )]
+    pub state: Account<'info, State>,
+    pub heartbeat_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DepositMulti<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"counters"],
+        bump
+    )]
+    pub counters: Account<'info, Counters>,
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump
+    )]
+    pub stats: Account<'info, Stats>,
+    /// CHECK: PDA for token account authority
+    #[account(
+        seeds = [b"token_authority".as_ref()],
+        bump = state.token_account_bump
+    )]
+    pub program_token_authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    /// CHECK: Blacklist PDA of the user, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"blacklist".as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub blacklist_entry: AccountInfo<'info>,
+    /// CHECK: Deposit policy PDA of the user, enforced in the instruction when initialized
+    #[account(
+        mut,
+        seeds = [b"deposit_policy".as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub deposit_policy: AccountInfo<'info>,
+    pub token_program: Program<'info, Token>,
+    /// Optional profile binding of the credited trader, reported in the deposit event
+    pub profile_binding: Option<Account<'info, ProfileBinding>>,
+    /// Optional activity counter of the credited trader, bumped for off-chain pollers
+    #[account(mut)]
+    pub activity_counter: Option<Account<'info, ActivityCounter>>,
+}
+
+#[derive(Accounts)]
+pub struct DepositBridged<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"counters"],
+        bump
+    )]
+    pub counters: Account<'info, Counters>,
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump
+    )]
+    pub stats: Account<'info, Stats>,
+    /// Deposits and stakes are only accepted for active tokens
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), mint.key().as_ref()],
+        bump,
+        constraint = token_config.status == TokenStatus::Active @ RbxError::TokenNotActive,
+        constraint = !token_config.paused_deposits @ RbxError::TokenDepositsPaused
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    /// CHECK: SPL token mint - verified in the instruction
+    pub mint: AccountInfo<'info>,
+    /// CHECK: Posted VAA of the token bridge transfer, owned by the configured core bridge
+    /// and parsed in the instruction
+    pub bridge_receipt: AccountInfo<'info>,
+    /// CHECK: PDA owning the bridge inbox token accounts
+    #[account(
+        seeds = [b"bridge_authority".as_ref()],
+        bump
+    )]
+    pub bridge_authority: AccountInfo<'info>,
+    /// Token account the bridge delivers into
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = bridge_authority
+    )]
+    pub bridge_inbox: Account<'info, TokenAccount>,
+    /// Program's vault for the mint
+    #[account(
+        mut,
+        address = vault_address(&token_config, &program_token_authority.key())
+            @ RbxError::InvalidVaultAccount
+    )]
+    pub program_token_account: Account<'info, TokenAccount>,
+    /// CHECK: PDA for token account authority
+    #[account(
+        seeds = [b"token_authority".as_ref()],
+        bump = state.token_account_bump
+    )]
+    pub program_token_authority: AccountInfo<'info>,
+    /// CHECK: Blacklist PDA of the destination trader, verified in the instruction
+    pub blacklist_entry: AccountInfo<'info>,
+    /// Optional ledger of the credited trader, validated in the instruction
+    #[account(mut)]
+    pub trader_ledger: Option<Account<'info, TraderLedger>>,
+    /// Marker of the receipt, whose existence rejects a replayed delivery
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + BridgedDepositMarker::SIZE,
+        seeds = [b"bridged_deposit".as_ref(), bridge_receipt.key().as_ref()],
+        bump
+    )]
+    pub bridged_deposit_marker: Account<'info, BridgedDepositMarker>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    /// Optional profile binding of the credited trader, reported in the deposit event
+    pub profile_binding: Option<Account<'info, ProfileBinding>>,
+}
+
+#[derive(Accounts)]
+pub struct DepositTokenAsDelegate<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"counters"],
+        bump
+    )]
+    pub counters: Account<'info, Counters>,
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump
+    )]
+    pub stats: Account<'info, Stats>,
+    /// Deposits and stakes are only accepted for active tokens
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), mint.key().as_ref()],
+        bump,
+        constraint = token_config.status == TokenStatus::Active @ RbxError::TokenNotActive,
+        constraint = !token_config.paused_deposits @ RbxError::TokenDepositsPaused
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    /// CHECK: SPL token mint - verified in the instruction
+    pub mint: AccountInfo<'info>,
+    /// CHECK: Canonical vault ATA of the token authority, so a crafted transaction cannot
+    /// route the deposit to another account while the event still credits the user
+    #[account(
+        mut,
+        address = vault_address(&token_config, &program_token_authority.key())
+            @ RbxError::InvalidVaultAccount
+    )]
+    pub program_token_account: AccountInfo<'info>,
+    /// CHECK: PDA for token account authority
+    #[account(
+        seeds = [b"token_authority".as_ref()],
+        bump = state.token_account_bump
+    )]
+    pub program_token_authority: AccountInfo<'info>,
+    #[account(mut)]
+    /// CHECK: Delegate's token account for the specified mint
+    pub delegate_token_account: AccountInfo<'info>,
+    pub delegate: Signer<'info>,
+    /// CHECK: Principal trader credited with the deposit, bound by the delegate PDA seeds
+    pub trader: AccountInfo<'info>,
+    /// CHECK: Blacklist PDA of the trader, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"blacklist".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub blacklist_entry: AccountInfo<'info>,
+    #[account(
+        seeds = [b"deposit_delegate".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub deposit_delegate: Account<'info, DepositDelegate>,
+    /// CHECK: Deposit policy PDA of the delegate, enforced in the instruction when initialized.
+    /// Like every deposit, the policy of the wallet funding it applies, not the credited trader's
+    #[account(
+        mut,
+        seeds = [b"deposit_policy".as_ref(), delegate.key().as_ref()],
+        bump
+    )]
+    pub deposit_policy: AccountInfo<'info>,
+    /// Optional ledger of the credited trader, validated in the instruction
+    #[account(mut)]
+    pub trader_ledger: Option<Account<'info, TraderLedger>>,
+    /// Rewards pool token account, required while the token charges a deposit fee
+    #[account(mut)]
+    pub fee_destination: Option<Account<'info, TokenAccount>>,
+    pub token_program: Program<'info, Token>,
+    /// Optional profile binding of the credited trader, reported in the deposit event
+    pub profile_binding: Option<Account<'info, ProfileBinding>>,
+    /// Optional activity counter of the credited trader, bumped for off-chain pollers
+    #[account(mut)]
+    pub activity_counter: Option<Account<'info, ActivityCounter>>,
+}
+
+#[derive(Accounts)]
+pub struct OpenDepositDelegate<'info> {
+    #[account(
+        init,
+        payer = trader,
+        space = 8 + DepositDelegate::SIZE,
+        seeds = [b"deposit_delegate".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub deposit_delegate: Account<'info, DepositDelegate>,
+    #[account(mut)]
+    pub trader: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ManageDepositDelegate<'info> {
+    #[account(
+        mut,
+        seeds = [b"deposit_delegate".as_ref(), trader.key().as_ref()],
+        bump,
+        has_one = trader
+    )]
+    pub deposit_delegate: Account<'info, DepositDelegate>,
+    pub trader: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DepositNative<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"counters"],
+        bump
+    )]
+    pub counters: Account<'info, Counters>,
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump
+    )]
+    pub stats: Account<'info, Stats>,
+    /// Deposits and stakes are only accepted for active tokens
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), wrapped_sol_mint.key().as_ref()],
+        bump,
+        constraint = token_config.status == TokenStatus::Active @ RbxError::TokenNotActive,
+        constraint = !token_config.paused_deposits @ RbxError::TokenDepositsPaused
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    /// CHECK: Wrapped SOL mint address for native SOL operations
+    pub wrapped_sol_mint: AccountInfo<'info>,
+    /// CHECK: PDA for program's SOL account
+    #[account(
+        mut,
+        seeds = [state.sol_account_seed()],
+        bump = state.sol_account_bump,
+    )]
+    pub program_sol_account: AccountInfo<'info>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    /// CHECK: Blacklist PDA of the user, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"blacklist".as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub blacklist_entry: AccountInfo<'info>,
+    /// CHECK: Deposit policy PDA of the user, enforced in the instruction when initialized
+    #[account(
+        mut,
+        seeds = [b"deposit_policy".as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub deposit_policy: AccountInfo<'info>,
+    /// Optional ledger of the credited trader, validated in the instruction
+    #[account(mut)]
+    pub trader_ledger: Option<Account<'info, TraderLedger>>,
+    /// Optional stake position of the staker, credited by stakes
+    #[account(mut)]
+    pub stake_position: Option<Account<'info, StakePosition>>,
+    pub system_program: Program<'info, System>,
+    /// Optional profile binding of the credited trader, reported in the deposit event
+    pub profile_binding: Option<Account<'info, ProfileBinding>>,
+    /// CHECK: SPL Memo program, only used by `deposit_native_with_tag`
+    #[account(address = MEMO_PROGRAM_ID)]
+    pub memo_program: Option<AccountInfo<'info>>,
+    /// Optional activity counter of the credited trader, bumped for off-chain pollers
+    #[account(mut)]
+    pub activity_counter: Option<Account<'info, ActivityCounter>>,
+    /// CHECK: Optional event CPI signer, verified against its PDA when events are emitted
+    pub event_authority: Option<UncheckedAccount<'info>>,
+    /// This program, the target of the event CPI
+    pub event_program: Option<Program<'info, crate::program::Rbx>>,
+}
+
+#[derive(Accounts)]
+#[instruction(amount: u64, for_trader: Pubkey, claim_id: u64)]
+pub struct DepositTokenForClaim<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"counters"],
+        bump
+    )]
+    pub counters: Account<'info, Counters>,
+    /// Deposits are only accepted for active tokens
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), mint.key().as_ref()],
+        bump,
+        constraint = token_config.status == TokenStatus::Active @ RbxError::TokenNotActive,
+        constraint = !token_config.paused_deposits @ RbxError::TokenDepositsPaused
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    /// CHECK: SPL token mint - verified in the instruction
+    pub mint: AccountInfo<'info>,
+    /// Program's vault for the mint
+    #[account(
+        mut,
+        address = vault_address(&token_config, &program_token_authority.key())
+            @ RbxError::InvalidVaultAccount
+    )]
+    pub program_token_account: Account<'info, TokenAccount>,
+    /// CHECK: PDA for token account authority
+    #[account(
+        seeds = [b"token_authority"],
+        bump = state.token_account_bump
+    )]
+    pub program_token_authority: AccountInfo<'info>,
+    #[account(mut)]
+    /// CHECK: User's token account for the specified mint
+    pub user_token_account: AccountInfo<'info>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    /// CHECK: Blacklist PDA of the user, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"blacklist".as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub blacklist_entry: AccountInfo<'info>,
+    /// CHECK: Deposit policy PDA of the user, enforced in the instruction when initialized
+    #[account(
+        mut,
+        seeds = [b"deposit_policy".as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub deposit_policy: AccountInfo<'info>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + DepositClaim::SIZE,
+        seeds = [b"deposit_claim".as_ref(), user.key().as_ref(), &claim_id.to_le_bytes()],
+        bump
+    )]
+    pub deposit_claim: Account<'info, DepositClaim>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(amount: u64, for_trader: Pubkey, claim_id: u64)]
+pub struct DepositNativeForClaim<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"counters"],
+        bump
+    )]
+    pub counters: Account<'info, Counters>,
+    /// Deposits are only accepted for active tokens
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), wrapped_sol_mint.key().as_ref()],
+        bump,
+        constraint = token_config.status == TokenStatus::Active @ RbxError::TokenNotActive,
+        constraint = !token_config.paused_deposits @ RbxError::TokenDepositsPaused
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    /// CHECK: Wrapped SOL mint address for native SOL operations
+    pub wrapped_sol_mint: AccountInfo<'info>,
+    /// CHECK: PDA for program's SOL account
+    #[account(
+        mut,
+        seeds = [state.sol_account_seed()],
+        bump = state.sol_account_bump,
+    )]
+    pub program_sol_account: AccountInfo<'info>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    /// CHECK: Blacklist PDA of the user, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"blacklist".as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub blacklist_entry: AccountInfo<'info>,
+    /// CHECK: Deposit policy PDA of the user, enforced in the instruction when initialized
+    #[account(
+        mut,
+        seeds = [b"deposit_policy".as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub deposit_policy: AccountInfo<'info>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + DepositClaim::SIZE,
+        seeds = [b"deposit_claim".as_ref(), user.key().as_ref(), &claim_id.to_le_bytes()],
+        bump
+    )]
+    pub deposit_claim: Account<'info, DepositClaim>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptDeposit<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"counters"],
+        bump
+    )]
+    pub counters: Account<'info, Counters>,
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump
+    )]
+    pub stats: Account<'info, Stats>,
+    #[account(
+        seeds = [b"token_config".as_ref(), deposit_claim.token.as_ref()],
+        bump
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    /// Closed on acceptance, returning its rent to the sponsor who paid it
+    #[account(
+        mut,
+        close = sponsor,
+        seeds = [
+            b"deposit_claim".as_ref(),
+            deposit_claim.sponsor.as_ref(),
+            &deposit_claim.claim_id.to_le_bytes()
+        ],
+        bump,
+        constraint = deposit_claim.trader == trader.key() @ RbxError::UnauthorizedAccess
+    )]
+    pub deposit_claim: Account<'info, DepositClaim>,
+    /// CHECK: Sponsor of the claim, only receives its rent
+    #[account(mut, address = deposit_claim.sponsor)]
+    pub sponsor: AccountInfo<'info>,
+    /// Optional ledger of the credited trader, validated in the instruction
+    #[account(mut)]
+    pub trader_ledger: Option<Account<'info, TraderLedger>>,
+    pub trader: Signer<'info>,
+    /// CHECK: Blacklist PDA of the trader, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"blacklist".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub blacklist_entry: AccountInfo<'info>,
+    /// Optional profile binding of the credited trader, reported in the deposit event
+    pub profile_binding: Option<Account<'info, ProfileBinding>>,
+}
+
+#[derive(Accounts)]
+pub struct ReclaimDeposit<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    #[account(
+        mut,
+        close = sponsor,
+        seeds = [
+            b"deposit_claim".as_ref(),
+            sponsor.key().as_ref(),
+            &deposit_claim.claim_id.to_le_bytes()
+        ],
+        bump,
+        constraint = deposit_claim.token == mint.key() @ RbxError::InvalidToken
+    )]
+    pub deposit_claim: Account<'info, DepositClaim>,
+    /// CHECK: This is a token mint account
+    pub mint: AccountInfo<'info>,
+    /// Program's vault for the mint
+    #[account(
+        mut,
+        address = vault_address(&token_config, &program_token_authority.key())
+            @ RbxError::InvalidVaultAccount
+    )]
+    pub program_token_account: Account<'info, TokenAccount>,
+    /// CHECK: PDA for token account authority
+    #[account(
+        seeds = [b"token_authority"],
+        bump = state.token_account_bump
+    )]
+    pub program_token_authority: AccountInfo<'info>,
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = sponsor
+    )]
+    pub sponsor_token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ReclaimDepositNative<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"counters"],
+        bump
+    )]
+    pub counters: Account<'info, Counters>,
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), wrapped_sol_mint.key().as_ref()],
+        bump
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    #[account(
+        mut,
+        close = sponsor,
+        seeds = [
+            b"deposit_claim".as_ref(),
+            sponsor.key().as_ref(),
+            &deposit_claim.claim_id.to_le_bytes()
+        ],
+        bump,
+        constraint = deposit_claim.token == wrapped_sol_mint.key() @ RbxError::InvalidToken
+    )]
+    pub deposit_claim: Account<'info, DepositClaim>,
+    /// CHECK: Wrapped SOL mint address for native SOL operations
+    pub wrapped_sol_mint: AccountInfo<'info>,
+    /// CHECK: PDA for program's SOL account
+    #[account(
+        mut,
+        seeds = [state.sol_account_seed()],
+        bump = state.sol_account_bump,
+    )]
+    pub program_sol_account: AccountInfo<'info>,
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DepositWsol<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"counters"],
+        bump
+    )]
+    pub counters: Account<'info, Counters>,
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump
+    )]
+    pub stats: Account<'info, Stats>,
+    /// Deposits and stakes are only accepted for active tokens
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), wrapped_sol_mint.key().as_ref()],
+        bump,
+        constraint = token_config.status == TokenStatus::Active @ RbxError::TokenNotActive,
+        constraint = !token_config.paused_deposits @ RbxError::TokenDepositsPaused
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    /// CHECK: Wrapped SOL mint
+    #[account(address = token::spl_token::native_mint::ID)]
+    pub wrapped_sol_mint: AccountInfo<'info>,
+    #[account(
+        mut,
+        token::mint = wrapped_sol_mint,
+        token::authority = user
+    )]
+    pub user_wsol_account: Account<'info, TokenAccount>,
+    /// CHECK: PDA for token account authority
+    #[account(
+        seeds = [b"token_authority".as_ref()],
+        bump = state.token_account_bump
+    )]
+    pub program_token_authority: AccountInfo<'info>,
+    /// CHECK: wSOL vault used when the deposit stays wrapped: the canonical ATA of the token
+    /// authority or the adopted vault, like every other deposit destination
+    #[account(
+        mut,
+        address = vault_address(&token_config, &program_token_authority.key())
+            @ RbxError::InvalidVaultAccount
+    )]
+    pub program_token_account: AccountInfo<'info>,
+    /// CHECK: PDA for program's SOL account
+    #[account(
+        mut,
+        seeds = [state.sol_account_seed()],
+        bump = state.sol_account_bump,
+    )]
+    pub program_sol_account: AccountInfo<'info>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    /// CHECK: Blacklist PDA of the user, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"blacklist".as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub blacklist_entry: AccountInfo<'info>,
+    /// CHECK: Deposit policy PDA of the user, enforced in the instruction when initialized
+    #[account(
+        mut,
+        seeds = [b"deposit_policy".as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub deposit_policy: AccountInfo<'info>,
+    /// Optional ledger of the credited trader, validated in the instruction
+    #[account(mut)]
+    pub trader_ledger: Option<Account<'info, TraderLedger>>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    /// Optional profile binding of the credited trader, reported in the deposit event
+    pub profile_binding: Option<Account<'info, ProfileBinding>>,
+}
+
+#[derive(Accounts)]
+#[instruction(id: u64, amount: u64, v: u8, r: [u8; 32], s: [u8; 32])]
+pub struct WithdrawToken<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"counters"],
+        bump
+    )]
+    pub counters: Account<'info, Counters>,
+
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump
+    )]
+    pub stats: Account<'info, Stats>,
+
+    /// Withdrawals stay open for retired tokens so traders can always exit
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), mint.key().as_ref()],
+        bump,
+        constraint = token_config.status != TokenStatus::Paused @ RbxError::TokenPaused,
+        constraint = !token_config.paused_withdrawals @ RbxError::TokenWithdrawalsPaused
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+
+    /// Created on demand with the init-if-needed feature, otherwise it must already exist
+    #[cfg_attr(
+        feature = "init-if-needed",
+        account(
+            init_if_needed,
+            payer = payer,
+            space = 8 + WithdrawalRecord::SIZE,
+            seeds = [b"withdrawal_account".as_ref(), &(id / WITHDRAWALS_PER_ACCOUNT as u64).to_le_bytes()],
+            bump
+        )
+    )]
+    #[cfg_attr(
+        not(feature = "init-if-needed"),
+        account(
+            mut,
+            seeds = [b"withdrawal_account".as_ref(), &(id / WITHDRAWALS_PER_ACCOUNT as u64).to_le_bytes()],
+            bump
+        )
+    )]
+    pub withdrawal_record: Account<'info, WithdrawalRecord>,
+
+    /// CHECK: This is a token mint account
+    pub mint: AccountInfo<'info>,
+
+    /// Program's vault for the mint
+    #[account(
+        mut,
+        address = vault_address(&token_config, &program_token_authority.key())
+            @ RbxError::InvalidVaultAccount
+    )]
+    pub program_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: This is the PDA that signs for the program
+    #[account(
+        seeds = [b"token_authority".as_ref()],
+        bump = state.token_account_bump
+    )]
+    pub program_token_authority: AccountInfo<'info>,
+
+    /// Payout account for the mint: the trader's own or allowlisted account, or the recipient's
+    /// associated token account when the signature names a recipient. Checked in the instruction
+    #[account(
+        mut,
+        token::mint = mint
+    )]
+    pub trader_token_account: Account<'info, TokenAccount>,
+    /// CHECK: Custody wallet named in the signed withdrawal, defaults to the trader
+    pub recipient: Option<AccountInfo<'info>>,
+
+    /// CHECK: Trader account that will receive the tokens (doesn't need to sign)
+    pub trader: AccountInfo<'info>,
+    /// CHECK: Blacklist PDA of the trader, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"blacklist".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub blacklist_entry: AccountInfo<'info>,
+
+    /// Optional ledger of the trader, required for full-balance withdrawals
+    #[account(mut)]
+    pub trader_ledger: Option<Account<'info, TraderLedger>>,
+
+    /// CHECK: Withdrawal allowlist PDA of the trader, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"withdrawal_allowlist".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub withdrawal_allowlist: AccountInfo<'info>,
+
+    /// Remainder of partially paid withdrawals, required when `allow_partial` defers an amount
+    #[account(
+        mut,
+        seeds = [b"partial_withdrawal".as_ref(), trader.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub partial_withdrawal: Option<Account<'info, PartialWithdrawal>>,
+
+    /// The account that signs the transaction and pays for account creation
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// Optional activity counter of the trader, bumped for off-chain pollers
+    #[account(mut)]
+    pub activity_counter: Option<Account<'info, ActivityCounter>>,
+    /// CHECK: Optional event CPI signer, verified against its PDA when events are emitted
+    pub event_authority: Option<UncheckedAccount<'info>>,
+    /// This program, the target of the event CPI
+    pub event_program: Option<Program<'info, crate::program::Rbx>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(id: u64)]
+pub struct PostWithdrawalTicket<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+
+    /// Created on demand with the init-if-needed feature, otherwise it must already exist
+    #[cfg_attr(
+        feature = "init-if-needed",
+        account(
+            init_if_needed,
+            payer = poster,
+            space = 8 + WithdrawalRecord::SIZE,
+            seeds = [b"withdrawal_account".as_ref(), &(id / WITHDRAWALS_PER_ACCOUNT as u64).to_le_bytes()],
+            bump
+        )
+    )]
+    #[cfg_attr(
+        not(feature = "init-if-needed"),
+        account(
+            mut,
+            seeds = [b"withdrawal_account".as_ref(), &(id / WITHDRAWALS_PER_ACCOUNT as u64).to_le_bytes()],
+            bump
+        )
+    )]
+    pub withdrawal_record: Account<'info, WithdrawalRecord>,
+
+    #[account(
+        init,
+        payer = poster,
+        space = 8 + WithdrawalTicket::SIZE,
+        seeds = [b"withdrawal_ticket".as_ref(), &id.to_le_bytes()],
+        bump
+    )]
+    pub withdrawal_ticket: Account<'info, WithdrawalTicket>,
+
+    /// Tickets are not posted for paused tokens, matching `withdraw_token`
+    #[account(
+        seeds = [b"token_config".as_ref(), mint.key().as_ref()],
+        bump,
+        constraint = token_config.status != TokenStatus::Paused @ RbxError::TokenPaused,
+        constraint = !token_config.paused_withdrawals @ RbxError::TokenWithdrawalsPaused
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+
+    /// CHECK: This is a token mint account
+    pub mint: AccountInfo<'info>,
+
+    /// CHECK: Trader named in the signed withdrawal (doesn't need to sign)
+    pub trader: AccountInfo<'info>,
+    /// CHECK: Blacklist PDA of the trader, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"blacklist".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub blacklist_entry: AccountInfo<'info>,
+
+    /// Optional ledger of the trader, required for full-balance withdrawals
+    #[account(mut)]
+    pub trader_ledger: Option<Account<'info, TraderLedger>>,
+
+    /// Operator posting the ticket, refunded the ticket rent when it is claimed
+    #[account(mut)]
+    pub poster: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimTicket<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"counters"],
+        bump
+    )]
+    pub counters: Account<'info, Counters>,
+
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump
+    )]
+    pub stats: Account<'info, Stats>,
+
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), mint.key().as_ref()],
+        bump,
+        constraint = token_config.status != TokenStatus::Paused @ RbxError::TokenPaused,
+        constraint = !token_config.paused_withdrawals @ RbxError::TokenWithdrawalsPaused
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+
+    #[account(
+        mut,
+        close = poster,
+        seeds = [b"withdrawal_ticket".as_ref(), &withdrawal_ticket.id.to_le_bytes()],
+        bump,
+        has_one = trader,
+        has_one = poster,
+        constraint = withdrawal_ticket.token == mint.key() @ RbxError::InvalidToken
+    )]
+    pub withdrawal_ticket: Account<'info, WithdrawalTicket>,
+
+    /// CHECK: This is a token mint account, bound by the ticket
+    pub mint: AccountInfo<'info>,
+
+    /// Program's vault for the mint
+    #[account(
+        mut,
+        address = vault_address(&token_config, &program_token_authority.key())
+            @ RbxError::InvalidVaultAccount
+    )]
+    pub program_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: This is the PDA that signs for the program
+    #[account(
+        seeds = [b"token_authority".as_ref()],
+        bump = state.token_account_bump
+    )]
+    pub program_token_authority: AccountInfo<'info>,
+
+    /// Payout account for the mint: the trader's own, or a destination on the trader's
+    /// withdrawal allowlist. Checked in the instruction
+    #[account(
+        mut,
+        token::mint = mint
+    )]
+    pub trader_token_account: Account<'info, TokenAccount>,
+
+    pub trader: Signer<'info>,
+    /// CHECK: Blacklist PDA of the trader, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"blacklist".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub blacklist_entry: AccountInfo<'info>,
+
+    /// CHECK: Withdrawal allowlist PDA of the trader, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"withdrawal_allowlist".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub withdrawal_allowlist: AccountInfo<'info>,
+
+    /// CHECK: Poster of the ticket, receives its rent
+    #[account(mut)]
+    pub poster: AccountInfo<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(id: u64, amount: u64, v: u8, r: [u8; 32], s: [u8; 32])]
+pub struct WithdrawNative<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+
+    #[account(
+        mut,
+        seeds = [b"counters"],
+        bump
+    )]
+    pub counters: Account<'info, Counters>,
+
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump
+    )]
+    pub stats: Account<'info, Stats>,
+
+    /// Withdrawals stay open for retired tokens so traders can always exit
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), wrapped_sol_mint.key().as_ref()],
+        bump,
+        constraint = token_config.status != TokenStatus::Paused @ RbxError::TokenPaused,
+        constraint = !token_config.paused_withdrawals @ RbxError::TokenWithdrawalsPaused
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+
+    /// Created on demand with the init-if-needed feature, otherwise it must already exist
+    #[cfg_attr(
+        feature = "init-if-needed",
+        account(
+            init_if_needed,
+            payer = payer,
+            space = 8 + WithdrawalRecord::SIZE,
+            seeds = [b"withdrawal_account".as_ref(), &(id / WITHDRAWALS_PER_ACCOUNT as u64).to_le_bytes()],
+            bump
+        )
+    )]
+    #[cfg_attr(
+        not(feature = "init-if-needed"),
+        account(
+            mut,
+            seeds = [b"withdrawal_account".as_ref(), &(id / WITHDRAWALS_PER_ACCOUNT as u64).to_le_bytes()],
+            bump
+        )
+    )]
+    pub withdrawal_record: Account<'info, WithdrawalRecord>,
+
+    /// CHECK: Wrapped SOL mint
+    pub wrapped_sol_mint: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [state.sol_account_seed()],
+        bump = state.sol_account_bump
+    )]
+    pub program_sol_account: SystemAccount<'info>,
+
+    /// CHECK: Trader account that will receive the SOL (doesn't need to sign)
+    #[account(mut)]
+    pub trader: AccountInfo<'info>,
+    /// CHECK: Blacklist PDA of the trader, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"blacklist".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub blacklist_entry: AccountInfo<'info>,
+
+    /// Optional ledger of the trader, required for full-balance withdrawals
+    #[account(mut)]
+    pub trader_ledger: Option<Account<'info, TraderLedger>>,
+
+    /// Trader's wSOL ATA, required when the withdrawal is paid out wrapped
+    #[account(
+        mut,
+        associated_token::mint = wrapped_sol_mint,
+        associated_token::authority = trader
+    )]
+    pub trader_wsol_account: Option<Account<'info, TokenAccount>>,
+
+    /// The account that signs the transaction and pays for account creation
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    /// Optional activity counter of the trader, bumped for off-chain pollers
+    #[account(mut)]
+    pub activity_counter: Option<Account<'info, ActivityCounter>>,
+    /// CHECK: Optional event CPI signer, verified against its PDA when events are emitted
+    pub event_authority: Option<UncheckedAccount<'info>>,
+    /// This program, the target of the event CPI
+    pub event_program: Option<Program<'info, crate::program::Rbx>>,
+
+    pub token_program: Option<Program<'info, Token>>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterDepositPolicy<'info> {
+    #[account(
+        init,
+        payer = trader,
+        space = 8 + DepositPolicy::SIZE,
+        seeds = [b"deposit_policy".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub deposit_policy: Account<'info, DepositPolicy>,
+    #[account(mut)]
+    pub trader: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BindProfile<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        init,
+        payer = wallet,
+        space = 8 + ProfileBinding::SIZE,
+        seeds = [b"profile_binding".as_ref(), wallet.key().as_ref()],
+        bump
+    )]
+    pub profile_binding: Account<'info, ProfileBinding>,
+    #[account(mut)]
+    pub wallet: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateDepositPolicy<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"deposit_policy".as_ref(), deposit_policy.trader.as_ref()],
+        bump
+    )]
+    pub deposit_policy: Account<'info, DepositPolicy>,
+    pub submitter: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveDeposit<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"deposit_policy".as_ref(), deposit_policy.trader.as_ref()],
+        bump
+    )]
+    pub deposit_policy: Account<'info, DepositPolicy>,
+    pub submitter: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(token: Pubkey)]
+pub struct RequestWithdrawal<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+    /// Exists for every token ever supported, so retired tokens can still be requested
+    #[account(
+        seeds = [b"token_config".as_ref(), token.as_ref()],
+        bump
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    pub trader: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FileWithdrawalRequest<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        init,
+        payer = trader,
+        space = 8 + WithdrawalRequest::SIZE,
+        seeds = [b"withdrawal_request".as_ref(), trader.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub withdrawal_request: Account<'info, WithdrawalRequest>,
+    /// CHECK: Token mint being requested
+    pub mint: AccountInfo<'info>,
+    /// Exists for every token ever supported, so retired tokens can still be requested
+    #[account(
+        seeds = [b"token_config".as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    #[account(mut)]
+    pub trader: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ForceWithdrawToken<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"counters"],
+        bump
+    )]
+    pub counters: Account<'info, Counters>,
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump
+    )]
+    pub stats: Account<'info, Stats>,
+    #[account(
+        mut,
+        close = trader,
+        seeds = [b"withdrawal_request".as_ref(), trader.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub withdrawal_request: Account<'info, WithdrawalRequest>,
+    #[account(
+        mut,
+        seeds = [b"trader_ledger".as_ref(), trader.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub trader_ledger: Account<'info, TraderLedger>,
+    /// CHECK: This is a token mint account
+    pub mint: AccountInfo<'info>,
+    /// Tracks the liability reduced by the payout
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    /// Program's vault for the mint
+    #[account(
+        mut,
+        address = vault_address(&token_config, &program_token_authority.key())
+            @ RbxError::InvalidVaultAccount
+    )]
+    pub program_token_account: Account<'info, TokenAccount>,
+    /// CHECK: This is the PDA that signs for the program
+    #[account(
+        seeds = [b"token_authority".as_ref()],
+        bump = state.token_account_bump
+    )]
+    pub program_token_authority: AccountInfo<'info>,
+    /// Payout account for the mint: the trader's own, or a destination on the trader's
+    /// withdrawal allowlist. Checked in the instruction
+    #[account(
+        mut,
+        token::mint = mint
+    )]
+    pub trader_token_account: Account<'info, TokenAccount>,
+    /// CHECK: Withdrawal allowlist PDA of the trader, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"withdrawal_allowlist".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub withdrawal_allowlist: AccountInfo<'info>,
+    #[account(mut)]
+    pub trader: Signer<'info>,
+    /// CHECK: Blacklist PDA of the trader, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"blacklist".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub blacklist_entry: AccountInfo<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ForceWithdrawNative<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"counters"],
+        bump
+    )]
+    pub counters: Account<'info, Counters>,
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump
+    )]
+    pub stats: Account<'info, Stats>,
+    #[account(
+        mut,
+        close = trader,
+        seeds = [b"withdrawal_request".as_ref(), trader.key().as_ref(), wrapped_sol_mint.key().as_ref()],
+        bump
+    )]
+    pub withdrawal_request: Account<'info, WithdrawalRequest>,
+    #[account(
+        mut,
+        seeds = [b"trader_ledger".as_ref(), trader.key().as_ref(), wrapped_sol_mint.key().as_ref()],
+        bump
+    )]
+    pub trader_ledger: Account<'info, TraderLedger>,
+    /// CHECK: Wrapped SOL mint
+    pub wrapped_sol_mint: AccountInfo<'info>,
+    /// Tracks the liability reduced by the payout
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), wrapped_sol_mint.key().as_ref()],
+        bump
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    #[account(
+        mut,
+        seeds = [state.sol_account_seed()],
+        bump = state.sol_account_bump
+    )]
+    pub program_sol_account: SystemAccount<'info>,
+    #[account(mut)]
+    pub trader: Signer<'info>,
+    /// CHECK: Blacklist PDA of the trader, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"blacklist".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub blacklist_entry: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(address: Pubkey)]
+pub struct BlockAddress<'info> {
+    #[account(
+        seeds = [b"state"],
+        bump,
+        constraint = state.compliance_authority != Pubkey::default()
+            && state.compliance_authority == compliance_authority.key() @ RbxError::UnauthorizedAccess
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        init,
+        payer = compliance_authority,
+        space = 8 + BlacklistEntry::SIZE,
+        seeds = [b"blacklist".as_ref(), address.as_ref()],
+        bump
+    )]
+    pub blacklist_entry: Account<'info, BlacklistEntry>,
+    #[account(mut)]
+    pub compliance_authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(address: Pubkey)]
+pub struct UnblockAddress<'info> {
+    #[account(
+        seeds = [b"state"],
+        bump,
+        constraint = state.compliance_authority != Pubkey::default()
+            && state.compliance_authority == compliance_authority.key() @ RbxError::UnauthorizedAccess
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        close = compliance_authority,
+        seeds = [b"blacklist".as_ref(), address.as_ref()],
+        bump
+    )]
+    pub blacklist_entry: Account<'info, BlacklistEntry>,
+    #[account(mut)]
+    pub compliance_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct OpenWithdrawalAllowlist<'info> {
+    #[account(
+        init,
+        payer = trader,
+        space = 8 + WithdrawalAllowlist::SIZE,
+        seeds = [b"withdrawal_allowlist".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub withdrawal_allowlist: Account<'info, WithdrawalAllowlist>,
+    #[account(mut)]
+    pub trader: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ManageWithdrawalAllowlist<'info> {
+    #[account(
+        mut,
+        seeds = [b"withdrawal_allowlist".as_ref(), trader.key().as_ref()],
+        bump,
+        has_one = trader
+    )]
+    pub withdrawal_allowlist: Account<'info, WithdrawalAllowlist>,
+    pub trader: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(deposit_num: u64)]
+pub struct AttestDeposit<'info> {
+    #[account(
+        seeds = [b"deposit_receipt".as_ref(), &deposit_num.to_le_bytes()],
+        bump
+    )]
+    pub deposit_receipt: Account<'info, DepositReceipt>,
+}
+
+#[derive(Accounts)]
+#[instruction(deposit_num: u64)]
+pub struct CloseDepositReceipt<'info> {
+    #[account(
+        mut,
+        close = trader,
+        has_one = trader,
+        seeds = [b"deposit_receipt".as_ref(), &deposit_num.to_le_bytes()],
+        bump
+    )]
+    pub deposit_receipt: Account<'info, DepositReceipt>,
+    #[account(mut)]
+    pub trader: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct OpenStakePosition<'info> {
+    #[account(
+        init,
+        payer = trader,
+        space = 8 + StakePosition::SIZE,
+        seeds = [b"stake_position".as_ref(), trader.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub stake_position: Account<'info, StakePosition>,
+    /// CHECK: Token mint of the stakes
+    pub mint: AccountInfo<'info>,
+    #[account(mut)]
+    pub trader: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitEmissionSchedule<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + EmissionSchedule::SIZE,
+        seeds = [b"emission_schedule".as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub schedule: Account<'info, EmissionSchedule>,
+    pub mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetEmissionSchedule<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"emission_schedule".as_ref(), schedule.token.as_ref()],
+        bump
+    )]
+    pub schedule: Account<'info, EmissionSchedule>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetAllowedPurposes<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), token_config.mint.as_ref()],
+        bump
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetDepositFee<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), token_config.mint.as_ref()],
+        bump
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GetEmittedRewards<'info> {
+    #[account(
+        seeds = [b"emission_schedule".as_ref(), schedule.token.as_ref()],
+        bump
+    )]
+    pub schedule: Account<'info, EmissionSchedule>,
+}
+
+#[derive(Accounts)]
+pub struct DecreaseStake<'info> {
+    #[account(
+        mut,
+        seeds = [b"stake_position".as_ref(), trader.key().as_ref(), stake_position.token.as_ref()],
+        bump,
+        has_one = trader
+    )]
+    pub stake_position: Account<'info, StakePosition>,
+    pub trader: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ManageCollateral<'info> {
+    #[account(
+        seeds = [b"state"],
+        bump,
+        constraint = state.margin_engine != Pubkey::default()
+            && state.margin_engine == margin_engine.key() @ RbxError::UnauthorizedAccess
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"stake_position".as_ref(), stake_position.trader.as_ref(), stake_position.token.as_ref()],
+        bump
+    )]
+    pub stake_position: Account<'info, StakePosition>,
+    pub margin_engine: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct OpenTraderLedger<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + TraderLedger::SIZE,
+        seeds = [b"trader_ledger".as_ref(), trader.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub trader_ledger: Account<'info, TraderLedger>,
+    /// CHECK: Token mint the ledger tracks
+    pub mint: AccountInfo<'info>,
+    /// CHECK: Trader whose balance the ledger tracks
+    pub trader: AccountInfo<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct OpenActivityCounter<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + ActivityCounter::SIZE,
+        seeds = [b"activity_counter".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub activity_counter: Account<'info, ActivityCounter>,
+    /// CHECK: Trader whose activity is counted
+    pub trader: AccountInfo<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct OpenPartialWithdrawal<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + PartialWithdrawal::SIZE,
+        seeds = [b"partial_withdrawal".as_ref(), trader.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub partial_withdrawal: Account<'info, PartialWithdrawal>,
+    /// CHECK: Trader owed the remainders
+    pub trader: AccountInfo<'info>,
+    /// CHECK: Token mint of the withdrawals
+    pub mint: AccountInfo<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimPartialWithdrawal<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"counters"],
+        bump
+    )]
+    pub counters: Account<'info, Counters>,
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump
+    )]
+    pub stats: Account<'info, Stats>,
+    #[account(
+        mut,
+        seeds = [b"partial_withdrawal".as_ref(), trader.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub partial_withdrawal: Account<'info, PartialWithdrawal>,
+    /// CHECK: This is a token mint account
+    pub mint: AccountInfo<'info>,
+    /// Tracks the liability reduced by the payout
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    /// Program's vault for the mint
+    #[account(
+        mut,
+        address = vault_address(&token_config, &program_token_authority.key())
+            @ RbxError::InvalidVaultAccount
+    )]
+    pub program_token_account: Account<'info, TokenAccount>,
+    /// CHECK: This is the PDA that signs for the program
+    #[account(
+        seeds = [b"token_authority".as_ref()],
+        bump = state.token_account_bump
+    )]
+    pub program_token_authority: AccountInfo<'info>,
+    /// Payout account for the mint: the trader's own, or a destination on the trader's
+    /// withdrawal allowlist. Checked in the instruction
+    #[account(
+        mut,
+        token::mint = mint
+    )]
+    pub trader_token_account: Account<'info, TokenAccount>,
+    /// CHECK: Withdrawal allowlist PDA of the trader, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"withdrawal_allowlist".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub withdrawal_allowlist: AccountInfo<'info>,
+    pub trader: Signer<'info>,
+    /// CHECK: Blacklist PDA of the trader, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"blacklist".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub blacklist_entry: AccountInfo<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(index: u64)]
+pub struct CreateWithdrawalRecord<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + WithdrawalRecord::SIZE,
+        seeds = [b"withdrawal_account".as_ref(), &index.to_le_bytes()],
+        bump
+    )]
+    pub withdrawal_record: Account<'info, WithdrawalRecord>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(index: u64)]
+pub struct CreateWithdrawalRecordSponsored<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    /// CHECK: Created in the instruction with lamports from the rent treasury
+    #[account(
+        mut,
+        seeds = [b"withdrawal_account".as_ref(), &index.to_le_bytes()],
+        bump
+    )]
+    pub withdrawal_record: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"rent_treasury"],
+        bump
+    )]
+    pub rent_treasury: SystemAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct TopUpRentTreasury<'info> {
+    #[account(mut, seeds = [b"state"], bump, has_one = owner)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"rent_treasury"],
+        bump
+    )]
+    pub rent_treasury: SystemAccount<'info>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AdvanceWithdrawalWatermark<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(index: u64)]
+pub struct GcWithdrawalRecord<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        close = treasury,
+        seeds = [b"withdrawal_account".as_ref(), &index.to_le_bytes()],
+        bump
+    )]
+    pub withdrawal_record: Account<'info, WithdrawalRecord>,
+    /// CHECK: Must match the treasury configured through the timelock
+    #[account(mut, address = state.treasury)]
+    pub treasury: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(index: u64)]
+pub struct RetireWithdrawalShard<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        close = treasury,
+        seeds = [b"withdrawal_account".as_ref(), &index.to_le_bytes()],
+        bump
+    )]
+    pub withdrawal_record: Account<'info, WithdrawalRecord>,
+    /// CHECK: Must match the treasury configured through the timelock
+    #[account(mut, address = state.treasury)]
+    pub treasury: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(id: u64, amount: u64, v: u8, r: [u8; 32], s: [u8; 32])]
+pub struct WithdrawTokenExistingRecord<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"counters"],
+        bump
+    )]
+    pub counters: Account<'info, Counters>,
+
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump
+    )]
+    pub stats: Account<'info, Stats>,
+
+    /// Withdrawals stay open for retired tokens so traders can always exit
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), mint.key().as_ref()],
+        bump,
+        constraint = token_config.status != TokenStatus::Paused @ RbxError::TokenPaused,
+        constraint = !token_config.paused_withdrawals @ RbxError::TokenWithdrawalsPaused
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"withdrawal_account".as_ref(), &(id / WITHDRAWALS_PER_ACCOUNT as u64).to_le_bytes()],
+        bump
+    )]
+    pub withdrawal_record: Account<'info, WithdrawalRecord>,
+
+    /// CHECK: This is a token mint account
+    pub mint: AccountInfo<'info>,
+
+    /// Program's vault for the mint
+    #[account(
+        mut,
+        address = vault_address(&token_config, &program_token_authority.key())
+            @ RbxError::InvalidVaultAccount
+    )]
+    pub program_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: This is the PDA that signs for the program
+    #[account(
+        seeds = [b"token_authority".as_ref()],
+        bump = state.token_account_bump
+    )]
+    pub program_token_authority: AccountInfo<'info>,
+
+    /// Payout account for the mint: the trader's own, or a destination on the trader's
+    /// withdrawal allowlist. Checked in the instruction
+    #[account(
+        mut,
+        token::mint = mint
+    )]
+    pub trader_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Trader account that will receive the tokens (doesn't need to sign)
+    pub trader: AccountInfo<'info>,
+    /// CHECK: Blacklist PDA of the trader, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"blacklist".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub blacklist_entry: AccountInfo<'info>,
+
+    /// Optional ledger of the trader, required for full-balance withdrawals
+    #[account(mut)]
+    pub trader_ledger: Option<Account<'info, TraderLedger>>,
+
+    /// CHECK: Withdrawal allowlist PDA of the trader, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"withdrawal_allowlist".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub withdrawal_allowlist: AccountInfo<'info>,
+
+    /// Remainder of partially paid withdrawals, required when `allow_partial` defers an amount
+    #[account(
+        mut,
+        seeds = [b"partial_withdrawal".as_ref(), trader.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub partial_withdrawal: Option<Account<'info, PartialWithdrawal>>,
+
+    /// The account that signs the transaction
+    pub payer: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(id: u64)]
+pub struct WithdrawTokenRelayed<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"counters"],
+        bump
+    )]
+    pub counters: Account<'info, Counters>,
+
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump
+    )]
+    pub stats: Account<'info, Stats>,
+
+    /// Withdrawals stay open for retired tokens so traders can always exit
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), mint.key().as_ref()],
+        bump,
+        constraint = token_config.status != TokenStatus::Paused @ RbxError::TokenPaused,
+        constraint = !token_config.paused_withdrawals @ RbxError::TokenWithdrawalsPaused
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"withdrawal_account".as_ref(), &(id / WITHDRAWALS_PER_ACCOUNT as u64).to_le_bytes()],
+        bump
+    )]
+    pub withdrawal_record: Account<'info, WithdrawalRecord>,
+
+    /// CHECK: This is a token mint account
+    pub mint: AccountInfo<'info>,
+
+    /// Program's vault for the mint
+    #[account(
+        mut,
+        address = vault_address(&token_config, &program_token_authority.key())
+            @ RbxError::InvalidVaultAccount
+    )]
+    pub program_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: This is the PDA that signs for the program
+    #[account(
+        seeds = [b"token_authority".as_ref()],
+        bump = state.token_account_bump
+    )]
+    pub program_token_authority: AccountInfo<'info>,
+
+    /// Payout account for the mint: the trader's own, or a destination on the trader's
+    /// withdrawal allowlist. Checked in the instruction
+    #[account(
+        mut,
+        token::mint = mint
+    )]
+    pub trader_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Trader account that will receive the tokens (doesn't need to sign)
+    pub trader: AccountInfo<'info>,
+    /// CHECK: Blacklist PDA of the trader, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"blacklist".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub blacklist_entry: AccountInfo<'info>,
+
+    /// Optional ledger of the trader, required for full-balance withdrawals
+    #[account(mut)]
+    pub trader_ledger: Option<Account<'info, TraderLedger>>,
+
+    /// CHECK: Withdrawal allowlist PDA of the trader, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"withdrawal_allowlist".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub withdrawal_allowlist: AccountInfo<'info>,
+
+    /// The relayer submitting the transaction, paid the tip
+    pub payer: Signer<'info>,
+
+    /// Relayer's token account for the mint, receiving the tip
+    #[account(
+        mut,
+        constraint = relayer_token_account.mint == mint.key() @ RbxError::InvalidRelayerAccount,
+        constraint = relayer_token_account.owner == payer.key() @ RbxError::InvalidRelayerAccount
+    )]
+    pub relayer_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(id: u64, amount: u64, v: u8, r: [u8; 32], s: [u8; 32])]
+pub struct WithdrawTokenNonced<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [b"counters"],
+        bump
+    )]
+    pub counters: Account<'info, Counters>,
+
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump
+    )]
+    pub stats: Account<'info, Stats>,
+
+    /// Withdrawals stay open for retired tokens so traders can always exit
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), mint.key().as_ref()],
+        bump,
+        constraint = token_config.status != TokenStatus::Paused @ RbxError::TokenPaused,
+        constraint = !token_config.paused_withdrawals @ RbxError::TokenWithdrawalsPaused
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + WithdrawalReceipt::SIZE,
+        seeds = [b"withdrawal_receipt".as_ref(), &id.to_le_bytes()],
+        bump
+    )]
+    pub withdrawal_receipt: Account<'info, WithdrawalReceipt>,
+
+    /// CHECK: This is a token mint account
+    pub mint: AccountInfo<'info>,
+
+    /// Program's vault for the mint
+    #[account(
+        mut,
+        address = vault_address(&token_config, &program_token_authority.key())
+            @ RbxError::InvalidVaultAccount
+    )]
+    pub program_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: This is the PDA that signs for the program
+    #[account(
+        seeds = [b"token_authority".as_ref()],
+        bump = state.token_account_bump
+    )]
+    pub program_token_authority: AccountInfo<'info>,
+
+    /// Payout account for the mint: the trader's own, or a destination on the trader's
+    /// withdrawal allowlist. Checked in the instruction
+    #[account(
+        mut,
+        token::mint = mint
+    )]
+    pub trader_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: Trader account that will receive the tokens (doesn't need to sign)
+    pub trader: AccountInfo<'info>,
+    /// CHECK: Blacklist PDA of the trader, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"blacklist".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub blacklist_entry: AccountInfo<'info>,
+
+    /// Optional ledger of the trader, required for full-balance withdrawals
+    #[account(mut)]
+    pub trader_ledger: Option<Account<'info, TraderLedger>>,
+
+    /// CHECK: Withdrawal allowlist PDA of the trader, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"withdrawal_allowlist".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub withdrawal_allowlist: AccountInfo<'info>,
+
+    /// The account that signs the transaction and pays for the receipt
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(id: u64, amount: u64, v: u8, r: [u8; 32], s: [u8; 32])]
+pub struct WithdrawNativeExistingRecord<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+
+    #[account(
+        mut,
+        seeds = [b"counters"],
+        bump
+    )]
+    pub counters: Account<'info, Counters>,
+
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump
+    )]
+    pub stats: Account<'info, Stats>,
+
+    /// Withdrawals stay open for retired tokens so traders can always exit
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), wrapped_sol_mint.key().as_ref()],
+        bump,
+        constraint = token_config.status != TokenStatus::Paused @ RbxError::TokenPaused,
+        constraint = !token_config.paused_withdrawals @ RbxError::TokenWithdrawalsPaused
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"withdrawal_account".as_ref(), &(id / WITHDRAWALS_PER_ACCOUNT as u64).to_le_bytes()],
+        bump
+    )]
+    pub withdrawal_record: Account<'info, WithdrawalRecord>,
+
+    /// CHECK: Wrapped SOL mint
+    pub wrapped_sol_mint: AccountInfo<'info>,
+
+    #[account(
+        mut,
+        seeds = [state.sol_account_seed()],
+        bump = state.sol_account_bump
+    )]
+    pub program_sol_account: SystemAccount<'info>,
+
+    /// CHECK: Trader account that will receive the SOL (doesn't need to sign)
+    #[account(mut)]
+    pub trader: AccountInfo<'info>,
+    /// CHECK: Blacklist PDA of the trader, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"blacklist".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub blacklist_entry: AccountInfo<'info>,
+
+    /// Optional ledger of the trader, required for full-balance withdrawals
+    #[account(mut)]
+    pub trader_ledger: Option<Account<'info, TraderLedger>>,
+
+    /// Trader's wSOL ATA, required when the withdrawal is paid out wrapped
+    #[account(
+        mut,
+        associated_token::mint = wrapped_sol_mint,
+        associated_token::authority = trader
+    )]
+    pub trader_wsol_account: Option<Account<'info, TokenAccount>>,
+
+    /// The account that signs the transaction
+    pub payer: Signer<'info>,
+
+    pub token_program: Option<Program<'info, Token>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(token_payload: WithdrawalPayload, native_payload: WithdrawalPayload)]
+pub struct WithdrawMulti<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+
+    #[account(
+        mut,
+        seeds = [b"counters"],
+        bump
+    )]
+    pub counters: Account<'info, Counters>,
+
+    #[account(
+        mut,
+        seeds = [b"stats"],
+        bump
+    )]
+    pub stats: Account<'info, Stats>,
+
+    #[account(
+        mut,
+        seeds = [b"withdrawal_account".as_ref(), &(token_payload.id / WITHDRAWALS_PER_ACCOUNT as u64).to_le_bytes()],
+        bump
+    )]
+    pub withdrawal_record: Account<'info, WithdrawalRecord>,
+
+    /// CHECK: This is a token mint account
+    pub mint: AccountInfo<'info>,
+
+    /// Withdrawals stay open for retired tokens so traders can always exit
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), mint.key().as_ref()],
+        bump,
+        constraint = token_config.status != TokenStatus::Paused @ RbxError::TokenPaused,
+        constraint = !token_config.paused_withdrawals @ RbxError::TokenWithdrawalsPaused
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+
+    /// Program's vault for the mint
+    #[account(
+        mut,
+        address = vault_address(&token_config, &program_token_authority.key())
+            @ RbxError::InvalidVaultAccount
+    )]
+    pub program_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: This is the PDA that signs for the program
+    #[account(
+        seeds = [b"token_authority".as_ref()],
+        bump = state.token_account_bump
+    )]
+    pub program_token_authority: AccountInfo<'info>,
 
-    // Initialize the withdrawal record if it's new
-    if withdrawal_record.index == 0 {
-        withdrawal_record.index = id / WITHDRAWALS_PER_ACCOUNT as u64;
-        // No need to initialize processed_bits as they default to zero
-    }
+    /// Payout account for the mint: the trader's own, or a destination on the trader's
+    /// withdrawal allowlist. Checked in the instruction
+    #[account(
+        mut,
+        token::mint = mint
+    )]
+    pub trader_token_account: Account<'info, TokenAccount>,
 
-    // Check if withdrawal has already been processed
-    require!(
-        !withdrawal_record.is_processed(id),
-        RbxError::WithdrawalAlreadyProcessed
-    );
+    /// CHECK: Trader account that will receive the tokens and SOL (doesn't need to sign)
+    #[account(mut)]
+    pub trader: AccountInfo<'info>,
+    /// CHECK: Blacklist PDA of the trader, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"blacklist".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub blacklist_entry: AccountInfo<'info>,
 
-    // Construct the EIP712 digest
-    let domain_separator = get_domain_separator(state);
-    let withdrawal_hash = get_withdrawal_hash(id, token, trader, amount);
+    /// Optional token ledger of the trader, required for full-balance withdrawals
+    #[account(mut)]
+    pub trader_ledger: Option<Account<'info, TraderLedger>>,
 
-    // Create a prefixed message following EIP-712 spec
-    let mut message = Vec::with_capacity(66); // 2 bytes prefix + 32 bytes domain_separator + 32 bytes withdrawal_hash
-    message.push(0x19);
-    message.push(0x01);
-    message.extend_from_slice(&domain_separator);
-    message.extend_from_slice(&withdrawal_hash);
+    /// CHECK: Withdrawal allowlist PDA of the trader, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"withdrawal_allowlist".as_ref(), trader.key().as_ref()],
+        bump
+    )]
+    pub withdrawal_allowlist: AccountInfo<'info>,
 
-    let digest = keccak256(&message);
+    /// The account that signs the transaction
+    pub payer: Signer<'info>,
 
-    // Verify signature
-    let sig_result = verify_secp256k1_signature(&digest, v, &r, &s, &state.withdrawal_signer)?;
-    require!(sig_result, RbxError::InvalidSignature);
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
 
-    // Mark the withdrawal as processed
-    withdrawal_record.mark_processed(id);
+#[derive(Accounts)]
+pub struct AssertSlotWindow<'info> {
+    pub clock: Sysvar<'info, Clock>,
+}
 
-    // Return success - the calling function will handle the actual transfer
-    Ok(())
+#[derive(Accounts)]
+#[instruction(id: u64)]
+pub struct IsWithdrawalProcessed<'info> {
+    /// CHECK: Withdrawal record shard for the id, which may not exist yet
+    #[account(
+        seeds = [b"withdrawal_account".as_ref(), &(id / WITHDRAWALS_PER_ACCOUNT as u64).to_le_bytes()],
+        bump
+    )]
+    pub withdrawal_record: AccountInfo<'info>,
 }
 
 #[derive(Accounts)]
-pub struct Initialize<'info> {
+pub struct GetVersion<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+}
+
+#[derive(Accounts)]
+pub struct GetEip712VerifyingContract<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+}
+
+#[derive(Accounts)]
+pub struct ChangeSigner<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        has_one = owner
+    )]
+    pub state: Account<'info, State>,
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct QueueOperation<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(operation_id: u64)]
+pub struct ExecuteOperationById<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
     #[account(
         init,
-        payer = owner,
-        space = 8 + State::SIZE,
+        payer = authority,
+        space = 8 + ExecutedOperationReceipt::SIZE,
+        seeds = [b"executed_operation".as_ref(), &operation_id.to_le_bytes()],
+        bump
+    )]
+    pub receipt: Account<'info, ExecutedOperationReceipt>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(operation_index: u8)]
+pub struct ExecuteOperation<'info> {
+    #[account(
+        mut,
         seeds = [b"state"],
         bump
     )]
     pub state: Account<'info, State>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + ExecutedOperationReceipt::SIZE,
+        seeds = [
+            b"executed_operation".as_ref(),
+            &state
+                .operation_id(operation_index as usize)
+                .unwrap_or_default()
+                .to_le_bytes()
+        ],
+        bump
+    )]
+    pub receipt: Account<'info, ExecutedOperationReceipt>,
     #[account(mut)]
-    pub owner: Signer<'info>,
     pub authority: Signer<'info>,
-    /// CHECK: This is a token mint account
-    pub default_token_mint: AccountInfo<'info>,
-    /// CHECK: PDA for token account authority
-    #[account(seeds = [b"token_authority"], bump)]
-    pub program_token_authority: AccountInfo<'info>,
-    /// CHECK: PDA for SOL account
-    #[account(seeds = [b"sol_account"], bump)]
-    pub program_sol_account: AccountInfo<'info>,
+    // Include any other accounts needed for specific operations
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
 }
 
 #[derive(Accounts)]
-pub struct SupportToken<'info> {
+#[instruction(operation_index: u8)]
+pub struct ExecuteMatured<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        init,
+        payer = cranker,
+        space = 8 + ExecutedOperationReceipt::SIZE,
+        seeds = [
+            b"executed_operation".as_ref(),
+            &state
+                .operation_id(operation_index as usize)
+                .unwrap_or_default()
+                .to_le_bytes()
+        ],
+        bump
+    )]
+    pub receipt: Account<'info, ExecutedOperationReceipt>,
+    #[account(mut)]
+    pub cranker: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(operation_index: u8)]
+pub struct ExecuteOperationViaGovernance<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        init,
+        payer = native_treasury,
+        space = 8 + ExecutedOperationReceipt::SIZE,
+        seeds = [
+            b"executed_operation".as_ref(),
+            &state
+                .operation_id(operation_index as usize)
+                .unwrap_or_default()
+                .to_le_bytes()
+        ],
+        bump
+    )]
+    pub receipt: Account<'info, ExecutedOperationReceipt>,
+    /// CHECK: Governance account, must be owned by the governance program
+    #[account(owner = governance_program.key())]
+    pub governance: AccountInfo<'info>,
+    /// Native treasury PDA of the governance, signing via the governance program. It pays
+    /// for the receipt
+    #[account(
+        mut,
+        seeds = [b"native-treasury".as_ref(), governance.key().as_ref()],
+        bump,
+        seeds::program = governance_program.key()
+    )]
+    pub native_treasury: Signer<'info>,
+    /// CHECK: SPL Governance program instance the DAO is deployed on
+    #[account(executable)]
+    pub governance_program: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetTimelockDelay<'info> {
     #[account(
         mut,
         seeds = [b"state"],
@@ -963,288 +11132,475 @@ pub struct SupportToken<'info> {
     )]
     pub state: Account<'info, State>,
     pub authority: Signer<'info>,
-    /// CHECK: This is a token mint account
-    pub token_mint: AccountInfo<'info>,
-    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct Deposit<'info> {
+pub struct SetTimelockAuthority<'info> {
     #[account(
         mut,
         seeds = [b"state"],
         bump
     )]
     pub state: Account<'info, State>,
-    /// CHECK: Token mint
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetConfig<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+}
+
+#[derive(Accounts)]
+#[instruction(id: u64, amount: u64, token: Pubkey)]
+pub struct ValidateWithdrawalSignature<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+    #[account(
+        seeds = [b"token_config".as_ref(), token.as_ref()],
+        bump
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+}
+
+#[derive(Accounts)]
+pub struct SimulateDeposit<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+    #[account(seeds = [b"counters"], bump)]
+    pub counters: Account<'info, Counters>,
+    #[account(
+        seeds = [b"token_config".as_ref(), mint.key().as_ref()],
+        bump,
+        constraint = token_config.status == TokenStatus::Active @ RbxError::TokenNotActive,
+        constraint = !token_config.paused_deposits @ RbxError::TokenDepositsPaused
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    pub mint: Account<'info, Mint>,
+    #[account(
+        token::mint = mint,
+        token::authority = user
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+    /// CHECK: Depositor being simulated, need not sign as nothing moves
+    pub user: AccountInfo<'info>,
+    /// CHECK: Blacklist PDA of the user, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"blacklist".as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub blacklist_entry: AccountInfo<'info>,
+    /// CHECK: Deposit policy PDA of the user, enforced in the instruction when initialized
+    #[account(
+        seeds = [b"deposit_policy".as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub deposit_policy: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(id: u64)]
+pub struct SimulateWithdrawal<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+    #[account(seeds = [b"counters"], bump)]
+    pub counters: Account<'info, Counters>,
+    #[account(
+        seeds = [b"token_config".as_ref(), mint.key().as_ref()],
+        bump,
+        constraint = token_config.status != TokenStatus::Paused @ RbxError::TokenPaused,
+        constraint = !token_config.paused_withdrawals @ RbxError::TokenWithdrawalsPaused
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    /// CHECK: Token mint, only used for its key
     pub mint: AccountInfo<'info>,
-    /// CHECK: Token account
-    #[account(mut)]
-    pub program_token_account: AccountInfo<'info>,
-    /// CHECK: PDA for token account authority
+    /// Bitmap shard of the id, omitted when it has not been created yet
     #[account(
-        seeds = [b"token_authority"],
-        bump = state.token_account_bump
+        seeds = [b"withdrawal_account".as_ref(), &(id / WITHDRAWALS_PER_ACCOUNT as u64).to_le_bytes()],
+        bump
     )]
-    pub program_token_authority: AccountInfo<'info>,
-    /// CHECK: User token account
-    #[account(mut)]
-    pub user_token_account: AccountInfo<'info>,
-    pub user: Signer<'info>,
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub withdrawal_record: Option<Account<'info, WithdrawalRecord>>,
+    /// CHECK: Receipt PDA of the id, must not exist yet when receipts are in use
+    #[account(
+        seeds = [b"withdrawal_receipt".as_ref(), &id.to_le_bytes()],
+        bump
+    )]
+    pub withdrawal_receipt: AccountInfo<'info>,
+    /// Optional ledger of the trader, required for full-balance withdrawals
+    pub trader_ledger: Option<Account<'info, TraderLedger>>,
+}
+
+#[derive(Accounts)]
+pub struct GetPendingOperations<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+}
+
+#[derive(Accounts)]
+pub struct GetWithdrawalSigner<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+}
+
+#[derive(Accounts)]
+pub struct GetOwner<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+}
+
+#[derive(Accounts)]
+pub struct GetNextStakeNum<'info> {
+    #[account(seeds = [b"counters"], bump)]
+    pub counters: Account<'info, Counters>,
+}
+
+#[derive(Accounts)]
+pub struct GetNextDepositNum<'info> {
+    #[account(seeds = [b"counters"], bump)]
+    pub counters: Account<'info, Counters>,
+}
+
+#[derive(Accounts)]
+pub struct GetTimelockDelay<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+}
+
+#[derive(Accounts)]
+pub struct GetDomainSeparator<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+}
+
+#[derive(Accounts)]
+pub struct SweepSolSurplus<'info> {
+    #[account(
+        seeds = [b"state"],
+        bump
+    )]
+    pub state: Account<'info, State>,
+    #[account(seeds = [b"counters"], bump)]
+    pub counters: Account<'info, Counters>,
+    #[account(
+        mut,
+        seeds = [state.sol_account_seed()],
+        bump = state.sol_account_bump
+    )]
+    pub program_sol_account: SystemAccount<'info>,
+    /// CHECK: Must match the treasury configured through the timelock
+    #[account(mut, address = state.treasury)]
+    pub treasury: AccountInfo<'info>,
+    pub authority: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct UnsupportToken<'info> {
+pub struct MigrateSolVault<'info> {
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        constraint = state.sol_account_version == 0 @ RbxError::SolVaultAlreadyMigrated
+    )]
+    pub state: Account<'info, State>,
+    #[account(
+        mut,
+        seeds = [SOL_ACCOUNT_SEED],
+        bump = state.sol_account_bump
+    )]
+    pub old_sol_account: SystemAccount<'info>,
     #[account(
         mut,
-        seeds = [b"state"],
+        seeds = [SOL_ACCOUNT_SEED_V2],
         bump
     )]
-    pub state: Account<'info, State>,
+    pub new_sol_account: SystemAccount<'info>,
     pub authority: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct DepositToken<'info> {
+pub struct EmergencyPause<'info> {
     #[account(
         mut,
         seeds = [b"state"],
         bump
     )]
     pub state: Account<'info, State>,
-    /// CHECK: SPL token mint - verified in the instruction
-    pub mint: AccountInfo<'info>,
-    #[account(mut)]
-    /// CHECK: Program's token account for the specified mint
-    pub program_token_account: AccountInfo<'info>,
-    /// CHECK: PDA for token account authority
-    pub program_token_authority: AccountInfo<'info>,
-    #[account(mut)]
-    /// CHECK: User's token account for the specified mint
-    pub user_token_account: AccountInfo<'info>,
-    pub user: Signer<'info>,
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
+    /// Anyone may relay a message signed by the withdrawal signer
+    pub submitter: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct DepositNative<'info> {
+pub struct EmitTokenAccounting<'info> {
+    #[account(seeds = [b"state"], bump)]
+    pub state: Account<'info, State>,
+    #[account(seeds = [b"counters"], bump)]
+    pub counters: Account<'info, Counters>,
+    /// CHECK: PDA for token account authority
     #[account(
-        mut,
-        seeds = [b"state"],
-        bump
+        seeds = [b"token_authority".as_ref()],
+        bump = state.token_account_bump
     )]
-    pub state: Account<'info, State>,
-    /// CHECK: Wrapped SOL mint address for native SOL operations
-    pub wrapped_sol_mint: AccountInfo<'info>,
-    /// CHECK: PDA for program's SOL account
+    pub program_token_authority: AccountInfo<'info>,
+    /// Program's token account for the token, validated in the instruction
+    pub program_token_account: Option<Account<'info, TokenAccount>>,
     #[account(
-        mut,
-        seeds = [b"sol_account"],
-        bump = state.sol_account_bump,
+        seeds = [state.sol_account_seed()],
+        bump = state.sol_account_bump
     )]
-    pub program_sol_account: AccountInfo<'info>,
-    #[account(mut)]
-    pub user: Signer<'info>,
-    pub system_program: Program<'info, System>,
+    pub program_sol_account: SystemAccount<'info>,
 }
 
 #[derive(Accounts)]
-#[instruction(id: u64, amount: u64, v: u8, r: [u8; 32], s: [u8; 32])]
-pub struct WithdrawToken<'info> {
+pub struct Decommission<'info> {
     #[account(
         mut,
         seeds = [b"state"],
-        bump
+        bump,
+        has_one = owner,
+        close = owner
     )]
     pub state: Account<'info, State>,
-
     #[account(
-        init_if_needed,
-        payer = payer,
-        space = 8 + WithdrawalRecord::SIZE,
-        seeds = [b"withdrawal_account".as_ref(), &(id / WITHDRAWALS_PER_ACCOUNT as u64).to_le_bytes()],
-        bump
+        mut,
+        seeds = [b"counters"],
+        bump,
+        close = owner
     )]
-    pub withdrawal_record: Account<'info, WithdrawalRecord>,
-
-    /// CHECK: This is a token mint account
-    pub mint: AccountInfo<'info>,
-
+    pub counters: Account<'info, Counters>,
     #[account(mut)]
-    /// CHECK: Program's token account for the specified mint
-    pub program_token_account: AccountInfo<'info>,
-
-    /// CHECK: This is the PDA that signs for the program
+    pub owner: Signer<'info>,
+    /// CHECK: This is the PDA that signs for the program's token accounts
     #[account(
         seeds = [b"token_authority".as_ref()],
         bump = state.token_account_bump
     )]
     pub program_token_authority: AccountInfo<'info>,
-
-    #[account(mut)]
-    /// CHECK: Trader's token account for the specified mint
-    pub trader_token_account: AccountInfo<'info>,
-
-    /// CHECK: Trader account that will receive the tokens (doesn't need to sign)
-    pub trader: AccountInfo<'info>,
-
-    /// The account that signs the transaction and pays for account creation
-    #[account(mut)]
-    pub payer: Signer<'info>,
-
+    #[account(
+        mut,
+        seeds = [state.sol_account_seed()],
+        bump = state.sol_account_bump
+    )]
+    pub program_sol_account: SystemAccount<'info>,
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-#[instruction(id: u64, amount: u64, v: u8, r: [u8; 32], s: [u8; 32])]
-pub struct WithdrawNative<'info> {
+pub struct RefreshDomainSeparator<'info> {
     #[account(
         mut,
         seeds = [b"state"],
-        bump
+        bump,
+        has_one = owner
     )]
     pub state: Account<'info, State>,
+    pub owner: Signer<'info>,
+}
 
-    #[account(
-        init_if_needed,
-        payer = payer,
-        space = 8 + WithdrawalRecord::SIZE,
-        seeds = [b"withdrawal_account".as_ref(), &(id / WITHDRAWALS_PER_ACCOUNT as u64).to_le_bytes()],
-        bump
-    )]
-    pub withdrawal_record: Account<'info, WithdrawalRecord>,
-
-    /// CHECK: Wrapped SOL mint
-    pub wrapped_sol_mint: AccountInfo<'info>,
+#[derive(Accounts)]
+pub struct GetStats<'info> {
+    #[account(seeds = [b"stats"], bump)]
+    pub stats: Account<'info, Stats>,
+}
 
+#[derive(Accounts)]
+pub struct TopUpSolAccount<'info> {
+    #[account(seeds = [b"state"], bump, has_one = owner)]
+    pub state: Account<'info, State>,
     #[account(
         mut,
-        seeds = [b"sol_account".as_ref()],
+        seeds = [state.sol_account_seed()],
         bump = state.sol_account_bump
     )]
     pub program_sol_account: SystemAccount<'info>,
-
-    /// CHECK: Trader account that will receive the SOL (doesn't need to sign)
-    #[account(mut)]
-    pub trader: AccountInfo<'info>,
-
-    /// The account that signs the transaction and pays for account creation
     #[account(mut)]
-    pub payer: Signer<'info>,
-
+    pub owner: Signer<'info>,
     pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
-}
-
-#[derive(Accounts)]
-pub struct GetVersion {}
-
-#[derive(Accounts)]
-pub struct GetEip712VerifyingContract<'info> {
-    #[account(seeds = [b"state"], bump)]
-    pub state: Account<'info, State>,
 }
 
 #[derive(Accounts)]
-pub struct ChangeSigner<'info> {
+pub struct AdvanceCounters<'info> {
     #[account(
         mut,
         seeds = [b"state"],
-        bump,
-        has_one = owner
+        bump
     )]
     pub state: Account<'info, State>,
-    pub owner: Signer<'info>,
-}
-
-#[derive(Accounts)]
-pub struct QueueOperation<'info> {
     #[account(
         mut,
-        seeds = [b"state"],
+        seeds = [b"counters"],
         bump
     )]
-    pub state: Account<'info, State>,
+    pub counters: Account<'info, Counters>,
     pub authority: Signer<'info>,
-    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct ExecuteOperation<'info> {
+pub struct InitCounters<'info> {
+    #[account(seeds = [b"state"], bump, has_one = owner)]
+    pub state: Account<'info, State>,
     #[account(
-        mut,
-        seeds = [b"state"],
+        init,
+        payer = owner,
+        space = 8 + Counters::SIZE,
+        seeds = [b"counters"],
         bump
     )]
-    pub state: Account<'info, State>,
-    pub authority: Signer<'info>,
-    // Include any other accounts needed for specific operations
+    pub counters: Account<'info, Counters>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct SetTimelockDelay<'info> {
+pub struct ReconcileCustodiedLamports<'info> {
+    #[account(mut, seeds = [b"state"], bump, has_one = owner)]
+    pub state: Account<'info, State>,
+    #[account(mut, seeds = [b"counters"], bump)]
+    pub counters: Account<'info, Counters>,
     #[account(
-        mut,
-        seeds = [b"state"],
-        bump
+        seeds = [state.sol_account_seed()],
+        bump = state.sol_account_bump
     )]
-    pub state: Account<'info, State>,
-    pub authority: Signer<'info>,
-    pub system_program: Program<'info, System>,
+    pub program_sol_account: SystemAccount<'info>,
+    pub owner: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct SetTimelockAuthority<'info> {
+pub struct InitStats<'info> {
     #[account(
-        mut,
-        seeds = [b"state"],
+        init,
+        payer = payer,
+        space = 8 + Stats::SIZE,
+        seeds = [b"stats"],
         bump
     )]
-    pub state: Account<'info, State>,
-    pub authority: Signer<'info>,
+    pub stats: Account<'info, Stats>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct GetWithdrawalSigner<'info> {
-    #[account(seeds = [b"state"], bump)]
-    pub state: Account<'info, State>,
+pub struct PostUpgradeCheck<'info> {
+    /// CHECK: State PDA, loaded in the instruction after any 1.0.1 layout is converted
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        owner = crate::ID
+    )]
+    pub state: AccountInfo<'info>,
+    /// Pays the rent for growing a 1.0.1 State
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct GetOwner<'info> {
+pub struct VerifyUpgradeAuthority<'info> {
     #[account(seeds = [b"state"], bump)]
     pub state: Account<'info, State>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::Rbx>,
+    pub program_data: Account<'info, ProgramData>,
 }
 
 #[derive(Accounts)]
-pub struct GetNextStakeNum<'info> {
+pub struct AssertSolvency<'info> {
     #[account(seeds = [b"state"], bump)]
     pub state: Account<'info, State>,
+    #[account(
+        seeds = [b"token_config".as_ref(), token_config.mint.as_ref()],
+        bump
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    /// CHECK: PDA for token account authority
+    #[account(
+        seeds = [b"token_authority".as_ref()],
+        bump = state.token_account_bump
+    )]
+    pub program_token_authority: AccountInfo<'info>,
+    /// Program's token account for the mint, required for SPL tokens
+    pub program_token_account: Option<Account<'info, TokenAccount>>,
+    /// Cold vault of the mint, once the token has been rebalanced to cold
+    pub cold_vault: Option<Account<'info, TokenAccount>>,
+    #[account(
+        seeds = [state.sol_account_seed()],
+        bump = state.sol_account_bump
+    )]
+    pub program_sol_account: SystemAccount<'info>,
 }
 
 #[derive(Accounts)]
-pub struct GetNextDepositNum<'info> {
+pub struct RegisterLookupTableAccounts<'info> {
     #[account(seeds = [b"state"], bump)]
     pub state: Account<'info, State>,
+    #[account(seeds = [b"stats"], bump)]
+    pub stats: Account<'info, Stats>,
+    #[account(seeds = [b"counters"], bump)]
+    pub counters: Account<'info, Counters>,
+    /// CHECK: PDA for token account authority
+    #[account(
+        seeds = [b"token_authority".as_ref()],
+        bump = state.token_account_bump
+    )]
+    pub program_token_authority: AccountInfo<'info>,
+    /// CHECK: Program SOL account, only its address is registered
+    #[account(
+        seeds = [state.sol_account_seed()],
+        bump = state.sol_account_bump
+    )]
+    pub program_sol_account: AccountInfo<'info>,
+    /// CHECK: Address lookup table owned by the lookup table program, checked by its extend
+    #[account(mut, owner = solana_program::address_lookup_table::program::ID)]
+    pub lookup_table: AccountInfo<'info>,
+    pub table_authority: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: Address lookup table program
+    #[account(address = solana_program::address_lookup_table::program::ID)]
+    pub address_lookup_table_program: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct GetTimelockDelay<'info> {
+pub struct Checkpoint<'info> {
     #[account(seeds = [b"state"], bump)]
     pub state: Account<'info, State>,
+    #[account(seeds = [b"stats"], bump)]
+    pub stats: Account<'info, Stats>,
+    #[account(seeds = [b"counters"], bump)]
+    pub counters: Account<'info, Counters>,
+    #[account(
+        mut,
+        seeds = [b"token_config".as_ref(), token_config.mint.as_ref()],
+        bump
+    )]
+    pub token_config: Account<'info, TokenConfig>,
+    /// CHECK: PDA for token account authority
+    #[account(
+        seeds = [b"token_authority".as_ref()],
+        bump = state.token_account_bump
+    )]
+    pub program_token_authority: AccountInfo<'info>,
+    /// Program's token account for the mint, required for SPL tokens
+    pub program_token_account: Option<Account<'info, TokenAccount>>,
+    /// Cold vault of the mint, once the token has been rebalanced to cold
+    pub cold_vault: Option<Account<'info, TokenAccount>>,
+    #[account(
+        seeds = [state.sol_account_seed()],
+        bump = state.sol_account_bump
+    )]
+    pub program_sol_account: SystemAccount<'info>,
 }
 
 #[derive(Accounts)]
-pub struct GetDomainSeparator<'info> {
+pub struct GetMaxBatchSizes<'info> {
     #[account(seeds = [b"state"], bump)]
     pub state: Account<'info, State>,
 }
@@ -1261,365 +11617,132 @@ pub struct CancelOperation<'info> {
     pub system_program: Program<'info, System>,
 }
 
-#[account]
-pub struct State {
-    pub owner: Pubkey,
-    pub withdrawal_signer: [u8; 20],
-    pub next_deposit_num: u64,
-    pub next_stake_num: u64,
-    pub reentry_lock_status: u8,
-    pub token_account_bump: u8,
-    pub sol_account_bump: u8,
-    pub supported_tokens: Vec<Pubkey>,
-    pub min_deposits: Vec<(Pubkey, u64)>,
-    pub timelock_authorities: Vec<Pubkey>,
-    pub timelock_delay: i64,
-    pub pending_operations: Vec<TimelockOperation>,
-    pub domain_separator: Option<[u8; 32]>, // Cached domain separator
-}
-
-impl State {
-    pub const SIZE: usize = 8 +  // discriminator
-        32 + // owner
-        20 + // withdrawal_signer
-        8 +  // next_deposit_num
-        8 +  // next_stake_num
-        1 +  // reentry_lock_status
-        1 +  // token_account_bump
-        1 +  // sol_account_bump
-        4 + (32 * MAX_SUPPORTED_TOKENS) + // Vec<Pubkey> for supported_tokens
-        4 + (40 * MAX_SUPPORTED_TOKENS) + // Vec<(Pubkey, u64)> for min_deposits
-        4 + (32 * MAX_AUTHORITIES) + // Vec<Pubkey> for timelock_authorities        
-        8 +  // timelock_delay
-        4 + (100 * 10) + // Vec<TimelockOperation> - estimated for 10 pending operations with ~100 bytes each
-        1 + 32; // Option<[u8; 32]> for cached domain separator
-
-    // Helper methods for min_deposits
-    pub fn get_min_deposit(&self, token: &Pubkey) -> Option<u64> {
-        self.min_deposits
-            .iter()
-            .find(|(t, _)| t == token)
-            .map(|(_, amount)| *amount)
-    }
-
-    pub fn set_min_deposit(&mut self, token: Pubkey, amount: u64) {
-        if let Some(idx) = self.min_deposits.iter().position(|(t, _)| t == &token) {
-            self.min_deposits[idx] = (token, amount);
-        } else {
-            self.min_deposits.push((token, amount));
-        }
-    }
-
-    pub fn remove_min_deposit(&mut self, token: &Pubkey) -> bool {
-        if let Some(idx) = self.min_deposits.iter().position(|(t, _)| t == token) {
-            self.min_deposits.remove(idx);
-            true
-        } else {
-            false
-        }
-    }
-}
-
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct TimelockOperation {
-    pub operation_type: u8,  // 1 = change_owner, 2 = change_signer, etc.
-    pub data: Vec<u8>,       // Serialized operation parameters
-    pub queued_at: i64,      // Timestamp when operation was queued
-    pub can_execute_at: i64, // Timestamp when operation becomes executable
-}
-
-#[account]
-pub struct WithdrawalRecord {
-    pub index: u64,
-    pub processed_bits: [u8; WITHDRAWAL_BITMAP_SIZE],
+fn mint_decimals(mint: &AccountInfo) -> Result<u8> {
+    let data = mint.try_borrow_data()?;
+    let mint = Mint::try_deserialize(&mut &data[..]).map_err(|_| error!(RbxError::InvalidToken))?;
+    Ok(mint.decimals)
 }
 
-impl WithdrawalRecord {
-    // Account size includes 8 bytes for anchor discriminator + index (8 bytes) + bitmap
-    pub const SIZE: usize = 8 + 8 + WITHDRAWAL_BITMAP_SIZE;
+// Typed wrappers over the generated CPI functions for downstream Anchor programs,
+// returning the deposit number instead of raw return data
+#[cfg(feature = "cpi")]
+pub mod deposit_cpi {
+    use super::*;
 
-    pub fn is_processed(&self, id: u64) -> bool {
-        let bit_index = (id % WITHDRAWALS_PER_ACCOUNT as u64) as usize;
-        let byte_index = bit_index / 8;
-        let bit_position = bit_index % 8;
-        (self.processed_bits[byte_index] & (1 << bit_position)) != 0
+    pub fn deposit_token<'a, 'b, 'c, 'info>(
+        ctx: CpiContext<'a, 'b, 'c, 'info, cpi::accounts::DepositToken<'info>>,
+        amount: u64,
+        create_receipt: bool,
+    ) -> Result<u64> {
+        Ok(cpi::deposit_token(ctx, amount, create_receipt)?.get())
     }
 
-    pub fn mark_processed(&mut self, id: u64) {
-        let bit_index = (id % WITHDRAWALS_PER_ACCOUNT as u64) as usize;
-        let byte_index = bit_index / 8;
-        let bit_position = bit_index % 8;
-        self.processed_bits[byte_index] |= 1 << bit_position;
+    pub fn deposit_native<'a, 'b, 'c, 'info>(
+        ctx: CpiContext<'a, 'b, 'c, 'info, cpi::accounts::DepositNative<'info>>,
+        amount: u64,
+    ) -> Result<u64> {
+        Ok(cpi::deposit_native(ctx, amount)?.get())
     }
-}
-
-#[event]
-pub struct DepositEvent {
-    #[index]
-    pub id: String,
-    #[index]
-    pub trader: Pubkey,
-    pub amount: u64,
-    pub token: Pubkey,
-}
-
-#[event]
-pub struct StakeEvent {
-    #[index]
-    pub id: String,
-    #[index]
-    pub trader: Pubkey,
-    pub amount: u64,
-    pub token: Pubkey,
-}
-
-#[event]
-pub struct WithdrawalEvent {
-    #[index]
-    pub id: u64,
-    #[index]
-    pub trader: Pubkey,
-    pub amount: u64,
-    pub token: Pubkey,
-}
 
-#[event]
-pub struct SupportTokenEvent {
-    #[index]
-    pub token: Pubkey,
-    pub min_deposit: u64,
-}
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use anchor_lang::solana_program::entrypoint::ProgramResult;
+        use anchor_lang::solana_program::instruction::Instruction;
+        use anchor_lang::solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+        use anchor_lang::Discriminator;
+
+        // Stands in for the runtime: checks the invoked instruction and answers with the
+        // deposit number the program would set as return data
+        struct DepositProgram;
+
+        impl SyscallStubs for DepositProgram {
+            fn sol_invoke_signed(
+                &self,
+                instruction: &Instruction,
+                _account_infos: &[AccountInfo],
+                _signers_seeds: &[&[&[u8]]],
+            ) -> ProgramResult {
+                assert_eq!(instruction.program_id, crate::ID);
+                assert!(
+                    instruction.data.starts_with(&instruction::DepositToken::DISCRIMINATOR)
+                        || instruction.data.starts_with(&instruction::DepositNative::DISCRIMINATOR)
+                );
+                Ok(())
+            }
 
-#[event]
-pub struct UnsupportTokenEvent {
-    #[index]
-    pub token: Pubkey,
-}
-
-#[event]
-pub struct SetSignerEvent {
-    #[index]
-    pub signer: [u8; 20],
-}
-
-#[event]
-pub struct QueueOperationEvent {
-    pub operation_type: u8,
-    pub execute_time: i64,
-}
-
-#[event]
-pub struct ExecuteOperationEvent {
-    pub operation_type: u8,
-}
-
-#[event]
-pub struct CancelOperationEvent {
-    pub operation_type: u8,
-    pub authority: Pubkey,
-}
-
-#[event]
-pub struct SetTimelockDelayEvent {
-    pub delay: i64,
-}
-
-#[event]
-pub struct SetTimelockAuthorityEvent {
-    pub authority: Pubkey,
-}
-
-#[event]
-pub struct InitializeEvent {
-    pub owner: Pubkey,
-    pub signer: [u8; 20],
-    pub timelock_authorities: Vec<Pubkey>,
-    pub timelock_delay: i64,
-    pub default_token: Pubkey,
-    pub min_deposit: u64,
-}
-
-#[event]
-pub struct SetOwnerEvent {
-    #[index]
-    pub owner: Pubkey,
-}
-
-#[event]
-pub struct AddAuthorityEvent {
-    #[index]
-    pub authority: Pubkey,
-}
-
-#[event]
-pub struct RemoveAuthorityEvent {
-    #[index]
-    pub authority: Pubkey,
-}
-
-#[error_code]
-pub enum RbxError {
-    #[msg("Amount too small")]
-    AmountTooSmall,
-    #[msg("Invalid signature")]
-    InvalidSignature,
-    #[msg("Already processed")]
-    AlreadyProcessed,
-    #[msg("Wrong amount")]
-    WrongAmount,
-    #[msg("Unsupported token")]
-    UnsupportedToken,
-    #[msg("Too many tokens")]
-    TooManyTokens,
-    #[msg("Invalid token mint")]
-    InvalidToken,
-    #[msg("Invalid signature format")]
-    InvalidSignatureFormat,
-    #[msg("Reentrancy detected")]
-    ReentrancyDetected,
-    #[msg("Insufficient funds")]
-    InsufficientFunds,
-    #[msg("Invalid signer")]
-    InvalidSigner,
-    #[msg("Unauthorized access")]
-    UnauthorizedAccess,
-    #[msg("Invalid operation index")]
-    InvalidOperationIndex,
-    #[msg("Timelock delay not met")]
-    TimelockDelayNotMet,
-    #[msg("Invalid operation type")]
-    InvalidOperationType,
-    #[msg("Invalid operation data")]
-    InvalidOperationData,
-    #[msg("Invalid timelock delay")]
-    InvalidTimelockDelay,
-    #[msg("Invalid authority")]
-    InvalidAuthority,
-    #[msg("Withdrawal already processed")]
-    WithdrawalAlreadyProcessed,
-    #[msg("Authority already exists")]
-    AuthorityAlreadyExists,
-    #[msg("Authority not found")]
-    AuthorityNotFound,
-    #[msg("Cannot remove the last authority")]
-    CannotRemoveLastAuthority,
-    #[msg("No authorities provided")]
-    NoAuthoritiesProvided,
-    #[msg("Too many authorities")]
-    TooManyAuthorities,
-    #[msg("Duplicate authority")]
-    DuplicateAuthority,
-}
-
-// Helper functions for EIP712 signature verification
-
-fn get_domain_separator(state: &mut Account<State>) -> [u8; 32] {
-    // First check if we have a cached value in the state
-    if let Some(cached) = state.domain_separator {
-        return cached;
-    }
-
-    // If no cached value, compute it
-
-    // Compute the domain separator components
-    let name_hash = keccak256(DOMAIN_NAME);
-    let version_hash = keccak256(DOMAIN_VERSION);
-    // Use fixed chain ID value 0x534f4c414e41 (hex for "SOLANA" in ASCII)
-    let chain_id: u64 = 0x534f4c414e41;
-    // Need to pad to 32 bytes (pad with zeros)
-    let mut chain_id_bytes = [0u8; 32];
-    chain_id_bytes[24..32].copy_from_slice(&chain_id.to_be_bytes());
-    let contract_bytes = state.key().to_bytes();
-
-    // Perform the hashing
-    let mut hasher = Keccak256::new();
-    hasher.update(EIP712_DOMAIN_TYPEHASH);
-    hasher.update(name_hash);
-    hasher.update(version_hash);
-    hasher.update(&chain_id_bytes);
-    hasher.update(&contract_bytes);
-
-    let result = hasher.finalize().into();
-
-    // Cache the result in the state for future use
-    state.domain_separator = Some(result);
-
-    result
-}
-
-fn get_withdrawal_hash(id: u64, token: Pubkey, trader: Pubkey, amount: u64) -> [u8; 32] {
-    let mut hasher = Keccak256::new();
-    hasher.update(WITHDRAWAL_TYPEHASH);
-    hasher.update(&id.to_be_bytes());
-    hasher.update(token.to_bytes());
-    hasher.update(trader.to_bytes());
-    hasher.update(&amount.to_be_bytes());
-    hasher.finalize().into()
-}
-
-fn verify_secp256k1_signature(
-    digest: &[u8; 32],
-    v: u8,
-    r: &[u8; 32],
-    s: &[u8; 32],
-    expected_signer: &[u8; 20],
-) -> Result<bool> {
-    // Adjust recovery ID for Ethereum compatibility (v should be 27 or 28)
-    let recovery_id = if v >= 27 { v - 27 } else { v };
-
-    // Validate recovery_id is either 0 or 1
-    require!(recovery_id <= 1, RbxError::InvalidSignatureFormat);
-
-    // Combine r and s into a single signature array
-    let mut signature = [0u8; 64];
-    signature[0..32].copy_from_slice(r);
-    signature[32..64].copy_from_slice(s);
-
-    let recovered_pubkey = match secp256k1_recover(digest, recovery_id, &signature) {
-        Ok(pubkey) => pubkey,
-        Err(err) => {
-            // Convert the error to a string and include it in the error message
-            msg!("Signature recovery error: {:?}", err);
-            return Err(error!(RbxError::InvalidSignature));
+            fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+                Some((crate::ID, 42u64.to_le_bytes().to_vec()))
+            }
         }
-    };
-
-    let recovered_signer_address = derive_eth_address(&recovered_pubkey.to_bytes());
-
-    // Compare the Ethereum addresses directly
-    let result = recovered_signer_address == *expected_signer;
-
-    Ok(result)
-}
-
-// Function to derive an Ethereum address from a public key
-fn derive_eth_address(pubkey: &[u8]) -> [u8; 20] {
-    // First we need to ensure we have the uncompressed public key without the prefix byte
-    let key_to_hash = if pubkey.len() == 64 {
-        pubkey
-    } else if pubkey.len() == 65 && (pubkey[0] == 0x04 || pubkey[0] == 0x00) {
-        &pubkey[1..]
-    } else {
-        &pubkey[pubkey.len() - 64..]
-    };
-
-    // Hash the public key with Keccak256
-    let mut hasher = Keccak256::new();
-    hasher.update(key_to_hash);
-    let hash = hasher.finalize();
-
-    // Take the last 20 bytes of the hash result
-    let mut address = [0u8; 20];
-    address.copy_from_slice(&hash[12..32]);
 
-    address
-}
-
-fn keccak256(data: &[u8]) -> [u8; 32] {
-    let mut hasher = sha3::Keccak256::new();
-    hasher.update(data);
-    let result = hasher.finalize();
-    let mut output = [0u8; 32];
-    output.copy_from_slice(&result);
-    output
+        #[test]
+        fn wrappers_return_the_deposit_num() {
+            set_syscall_stubs(Box::new(DepositProgram));
+
+            let key = Pubkey::new_unique();
+            let (mut lamports, mut program_lamports) = (0, 0);
+            let (mut data, mut program_data) = (vec![], vec![]);
+            let info = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &key, false, 0);
+            let program = AccountInfo::new(
+                &crate::ID,
+                false,
+                false,
+                &mut program_lamports,
+                &mut program_data,
+                &crate::ID,
+                true,
+                0,
+            );
+
+            let accounts = cpi::accounts::DepositToken {
+                state: info.clone(),
+                counters: info.clone(),
+                stats: info.clone(),
+                token_config: info.clone(),
+                mint: info.clone(),
+                program_token_account: info.clone(),
+                program_token_authority: info.clone(),
+                user_token_account: info.clone(),
+                user: info.clone(),
+                blacklist_entry: info.clone(),
+                deposit_policy: info.clone(),
+                trader_ledger: None,
+                stake_position: None,
+                deposit_receipt: None,
+                fee_destination: None,
+                token_program: info.clone(),
+                system_program: info.clone(),
+                profile_binding: None,
+                activity_counter: None,
+                event_authority: None,
+                event_program: None,
+                trader_blacklist_entry: None,
+            };
+            let num = deposit_token(CpiContext::new(program.clone(), accounts), 1_000, false);
+            assert_eq!(num.unwrap(), 42);
+
+            let accounts = cpi::accounts::DepositNative {
+                state: info.clone(),
+                counters: info.clone(),
+                stats: info.clone(),
+                token_config: info.clone(),
+                wrapped_sol_mint: info.clone(),
+                program_sol_account: info.clone(),
+                user: info.clone(),
+                blacklist_entry: info.clone(),
+                deposit_policy: info.clone(),
+                trader_ledger: None,
+                stake_position: None,
+                system_program: info.clone(),
+                profile_binding: None,
+                memo_program: None,
+                activity_counter: None,
+                event_authority: None,
+                event_program: None,
+            };
+            let num = deposit_native(CpiContext::new(program, accounts), 1_000);
+            assert_eq!(num.unwrap(), 42);
+        }
+    }
 }
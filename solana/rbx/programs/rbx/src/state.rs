@@ -0,0 +1,1016 @@
+use anchor_lang::prelude::*;
+
+use crate::eip712::{
+    RECIPIENT_WITHDRAWAL_TYPEHASH, RELAYED_WITHDRAWAL_TYPEHASH, WITHDRAWAL_TYPEHASH,
+};
+use crate::*;
+
+#[account]
+#[derive(Default)]
+pub struct State {
+    pub owner: Pubkey,
+    pub withdrawal_signer: [u8; 20],
+    pub next_deposit_num: u64, // Superseded by Counters, kept for layout compatibility
+    pub next_stake_num: u64,   // Superseded by Counters, kept for layout compatibility
+    pub reentry_lock_status: u8, // Superseded by Counters, kept for layout compatibility
+    pub token_account_bump: u8,
+    pub sol_account_bump: u8,
+    pub supported_tokens: Vec<Pubkey>,
+    pub min_deposits: Vec<(Pubkey, u64)>,
+    pub timelock_authorities: Vec<Pubkey>,
+    pub timelock_delay: i64,
+    pub pending_operations: Vec<TimelockOperation>,
+    pub domain_separator: Option<[u8; 32]>, // Cached domain separator
+    pub custodied_lamports: u64, // Superseded by Counters, kept for layout compatibility
+    pub custody_reconciled: bool, // Counters.custodied_lamports is known; SOL surplus may be swept
+    pub treasury: Pubkey,
+    pub withdrawals_paused: bool,
+    pub pause_nonce: u64, // Last nonce used by a signed pause/unpause message
+    pub sign_amounts_e18: bool, // Withdrawal signatures cover 18-decimal amounts
+    pub max_batch_deposits: u8,
+    pub max_batch_withdrawals: u8,
+    pub reject_freezable_mints: bool,
+    pub forced_withdrawal_delay: i64, // 0 disables forced withdrawals
+    pub use_withdrawal_receipts: bool, // Enables withdraw_token_nonced for ids from withdrawal_receipt_floor
+    pub withdrawal_receipt_floor: u64, // Receipts take ids from here up, bitmaps those below. 0 = unset
+    pub max_bitmap_withdrawal_id: u64, // Highest id marked in a bitmap shard
+    pub withdrawal_signer_overrides: Vec<(Pubkey, [u8; 20])>, // Per-token signers replacing withdrawal_signer
+    pub timelock_in_slots: bool, // timelock_delay is measured in slots instead of seconds
+    pub third_party_deposits_enabled: bool,
+    pub third_party_sponsors: Vec<Pubkey>, // If non-empty, the only signers allowed to use *_for deposits
+    pub high_watermark_id: u64, // All withdrawal ids below this are finished
+    pub expected_upgrade_authority: Pubkey,
+    pub decommission_approved: bool, // Set by the timelock before the owner can decommission
+    pub margin_engine: Pubkey, // Authority allowed to lock stake collateral
+    pub previous_signer: [u8; 20], // Outgoing withdrawal signer, valid until rotation_deadline
+    pub rotation_deadline: i64,
+    pub signer_rotation_overlap: i64, // 0 makes signer changes take effect immediately
+    pub withdrawal_sig_versions: Vec<SigVersion>, // Overrides of the built-in withdrawal typehashes
+    pub reserved_lamports: u64, // Floor native payouts may not take the SOL account below
+    pub min_withdrawals: Vec<(Pubkey, u64)>,
+    pub compliance_authority: Pubkey, // Authority allowed to block addresses, unset disables blocking
+    pub withdrawal_release_delay: i64, // 0 disables signer-released withdrawal requests
+    pub bridge_program: Pubkey, // Owner of receipts accepted by deposit_bridged, unset disables it
+    pub bridge_emitter_chain: u16, // Wormhole chain of the token bridge deposit_bridged accepts
+    pub bridge_emitter: [u8; 32],  // Emitter address of that token bridge
+    pub approved_token_unpauses: Vec<Pubkey>, // Tokens whose pause flags unpause_token may clear
+    pub sol_account_version: u8, // 0 = SOL_ACCOUNT_SEED, 1 = SOL_ACCOUNT_SEED_V2
+    pub sol_vault_migration_approved: bool, // Set by the timelock before migrate_sol_vault
+    pub deposit_claim_threshold: u64, // *_for deposits of at least this go through a claim, 0 disables claims
+    pub deposit_claim_window: i64,    // Seconds the named trader has to accept a claim
+    pub emit_legacy_events: bool, // Emit DepositEvent etc. alongside their V2 variants
+    pub heartbeat_authority: Pubkey, // Signer hot wallet calling `heartbeat`, unset disables the switch
+    pub heartbeat_timeout: i64, // Silence after which forced withdrawals skip their delay, 0 disables
+    pub last_heartbeat: i64,
+    pub trader_encoding: TraderEncoding,
+    pub rent_treasury_funded: u64, // Lamports the owner has put into the rent treasury
+    pub sponsored_rent: u64,       // Lamports the rent treasury has spent on withdrawal records
+    pub timelock_authority_roles: Vec<(Pubkey, u8)>, // Role flags of restricted timelock authorities
+    pub approved_cold_releases: Vec<(Pubkey, u64)>, // Amounts rebalance_to_hot may still move per token
+    pub approved_min_deposit_num: u64, // Floor advance_counters may raise next_deposit_num to, 0 if none
+    pub approved_min_stake_num: u64,   // Floor advance_counters may raise next_stake_num to, 0 if none
+    pub shard_retention_period: i64,   // Seconds a full shard is kept before retiring, 0 disables
+    pub filled_shards: Vec<(u64, i64)>, // Fully processed shards and when their last id was used
+    pub next_operation_id: u64,         // Last id given to a queued timelock operation
+    pub pending_operation_ids: Vec<u64>, // Ids of the trailing pending_operations queued with one
+    pub approved_emission_schedules: Vec<(Pubkey, [u8; 32])>, // Token and hash of approved segments
+    pub governance_frozen_until: i64, // No timelock operation is queued or executed before this
+    pub program_version: [u16; 3], // Version last migrated to by post_upgrade_check
+    pub event_mode: u8, // EVENT_MODE_*: events as program logs, event CPIs or both
+    pub approved_allowed_purposes: Vec<(Pubkey, u8)>, // Token and approved allowed_purposes mask
+    pub approved_vault_adoptions: Vec<(Pubkey, Pubkey)>, // Token and the vault it may adopt
+    pub approved_deposit_fees: Vec<(Pubkey, u16, Pubkey)>, // Token, fee bps and fee destination
+}
+
+impl State {
+    pub const SIZE: usize = 8 +  // discriminator
+        32 + // owner
+        20 + // withdrawal_signer
+        8 +  // next_deposit_num
+        8 +  // next_stake_num
+        1 +  // reentry_lock_status
+        1 +  // token_account_bump
+        1 +  // sol_account_bump
+        4 + (32 * MAX_SUPPORTED_TOKENS) + // Vec<Pubkey> for supported_tokens
+        4 + (40 * MAX_SUPPORTED_TOKENS) + // Vec<(Pubkey, u64)> for min_deposits
+        4 + (32 * MAX_AUTHORITIES) + // Vec<Pubkey> for timelock_authorities        
+        8 +  // timelock_delay
+        4 + (TimelockOperation::SIZE * MAX_PENDING_OPERATIONS) + // Vec<TimelockOperation>
+        1 + 32 + // Option<[u8; 32]> for cached domain separator
+        8 +  // custodied_lamports
+        1 +  // custody_reconciled
+        32 + // treasury
+        1 +  // withdrawals_paused
+        8 +  // pause_nonce
+        1 +  // sign_amounts_e18
+        1 +  // max_batch_deposits
+        1 +  // max_batch_withdrawals
+        1 +  // reject_freezable_mints
+        8 +  // forced_withdrawal_delay
+        1 +  // use_withdrawal_receipts
+        8 +  // withdrawal_receipt_floor
+        8 +  // max_bitmap_withdrawal_id
+        4 + (52 * MAX_SUPPORTED_TOKENS) + // Vec<(Pubkey, [u8; 20])> for withdrawal_signer_overrides
+        1 +  // timelock_in_slots
+        1 +  // third_party_deposits_enabled
+        4 + (32 * MAX_THIRD_PARTY_SPONSORS) + // Vec<Pubkey> for third_party_sponsors
+        8 +  // high_watermark_id
+        32 + // expected_upgrade_authority
+        1 +  // decommission_approved
+        32 + // margin_engine
+        20 + // previous_signer
+        8 +  // rotation_deadline
+        8 +  // signer_rotation_overlap
+        4 + (34 * MAX_SIG_VERSION as usize) + // Vec<SigVersion> for withdrawal_sig_versions
+        8 +  // reserved_lamports
+        4 + (40 * MAX_SUPPORTED_TOKENS) + // Vec<(Pubkey, u64)> for min_withdrawals
+        32 + // compliance_authority
+        8 +  // withdrawal_release_delay
+        32 + // bridge_program
+        2 +  // bridge_emitter_chain
+        32 + // bridge_emitter
+        4 + (32 * MAX_SUPPORTED_TOKENS) + // Vec<Pubkey> for approved_token_unpauses
+        1 +  // sol_account_version
+        1 +  // sol_vault_migration_approved
+        8 +  // deposit_claim_threshold
+        8 +  // deposit_claim_window
+        1 +  // emit_legacy_events
+        32 + // heartbeat_authority
+        8 +  // heartbeat_timeout
+        8 +  // last_heartbeat
+        1 +  // trader_encoding
+        8 +  // rent_treasury_funded
+        8 +  // sponsored_rent
+        4 + (33 * MAX_AUTHORITIES) + // Vec<(Pubkey, u8)> for timelock_authority_roles
+        4 + (40 * MAX_SUPPORTED_TOKENS) + // Vec<(Pubkey, u64)> for approved_cold_releases
+        8 +  // approved_min_deposit_num
+        8 +  // approved_min_stake_num
+        8 +  // shard_retention_period
+        4 + (16 * MAX_FILLED_SHARDS) + // Vec<(u64, i64)> for filled_shards
+        8 +  // next_operation_id
+        4 + (8 * MAX_PENDING_OPERATIONS) + // Vec<u64> for pending_operation_ids, one per pending operation
+        4 + (64 * MAX_SUPPORTED_TOKENS) + // Vec<(Pubkey, [u8; 32])> for approved_emission_schedules
+        8 +  // governance_frozen_until
+        6 +  // program_version
+        1 +  // event_mode
+        4 + (33 * MAX_SUPPORTED_TOKENS) + // Vec<(Pubkey, u8)> for approved_allowed_purposes
+        4 + (64 * MAX_SUPPORTED_TOKENS) + // Vec<(Pubkey, Pubkey)> for approved_vault_adoptions
+        4 + (66 * MAX_SUPPORTED_TOKENS); // Vec<(Pubkey, u16, Pubkey)> for approved_deposit_fees
+
+    // Seed of the program SOL account; sol_account_bump belongs to the same version
+    pub fn sol_account_seed(&self) -> &'static [u8] {
+        match self.sol_account_version {
+            0 => SOL_ACCOUNT_SEED,
+            _ => SOL_ACCOUNT_SEED_V2,
+        }
+    }
+
+    // Helper methods for min_deposits
+    pub fn get_min_deposit(&self, token: &Pubkey) -> Option<u64> {
+        self.min_deposits
+            .iter()
+            .find(|(t, _)| t == token)
+            .map(|(_, amount)| *amount)
+    }
+
+    pub fn set_min_deposit(&mut self, token: Pubkey, amount: u64) {
+        if let Some(idx) = self.min_deposits.iter().position(|(t, _)| t == &token) {
+            self.min_deposits[idx] = (token, amount);
+        } else {
+            self.min_deposits.push((token, amount));
+        }
+    }
+
+    pub fn remove_min_deposit(&mut self, token: &Pubkey) -> bool {
+        if let Some(idx) = self.min_deposits.iter().position(|(t, _)| t == token) {
+            self.min_deposits.remove(idx);
+            true
+        } else {
+            false
+        }
+    }
+
+    // Helper methods for min_withdrawals
+    pub fn get_min_withdrawal(&self, token: &Pubkey) -> Option<u64> {
+        self.min_withdrawals
+            .iter()
+            .find(|(t, _)| t == token)
+            .map(|(_, amount)| *amount)
+    }
+
+    pub fn set_min_withdrawal(&mut self, token: Pubkey, amount: u64) {
+        if let Some(idx) = self.min_withdrawals.iter().position(|(t, _)| t == &token) {
+            self.min_withdrawals[idx] = (token, amount);
+        } else {
+            self.min_withdrawals.push((token, amount));
+        }
+    }
+
+    pub fn remove_min_withdrawal(&mut self, token: &Pubkey) -> bool {
+        if let Some(idx) = self.min_withdrawals.iter().position(|(t, _)| t == token) {
+            self.min_withdrawals.remove(idx);
+            true
+        } else {
+            false
+        }
+    }
+
+    // Authorities without a roles entry hold every role
+    pub fn has_timelock_role(&self, authority: &Pubkey, role: u8) -> bool {
+        if !self.timelock_authorities.contains(authority) {
+            return false;
+        }
+        let roles = self
+            .timelock_authority_roles
+            .iter()
+            .find(|(a, _)| a == authority)
+            .map_or(TIMELOCK_ROLES_ALL, |(_, roles)| *roles);
+        roles & role != 0
+    }
+
+    // Operations queued before ids existed have none, and always precede those that do
+    pub fn operation_id(&self, index: usize) -> Option<u64> {
+        let untracked = self.pending_operations.len() - self.pending_operation_ids.len();
+        index
+            .checked_sub(untracked)
+            .and_then(|i| self.pending_operation_ids.get(i).copied())
+    }
+
+    pub fn operation_index(&self, operation_id: u64) -> Option<usize> {
+        let untracked = self.pending_operations.len() - self.pending_operation_ids.len();
+        self.pending_operation_ids
+            .iter()
+            .position(|id| *id == operation_id)
+            .map(|i| i + untracked)
+    }
+
+    pub fn remove_pending_operation(&mut self, index: usize) -> TimelockOperation {
+        let untracked = self.pending_operations.len() - self.pending_operation_ids.len();
+        if index >= untracked {
+            self.pending_operation_ids.remove(index - untracked);
+        }
+        self.pending_operations.remove(index)
+    }
+
+    // A signer is accepted if it is the token's signer, or the previous global
+    // signer while the rotation overlap is open. Overrides rotate without overlap.
+    pub fn accepts_withdrawal_signer(&self, token: &Pubkey, signer: &[u8; 20], now: i64) -> bool {
+        let current = self.get_withdrawal_signer(token);
+        if *signer == current {
+            return true;
+        }
+        current == self.withdrawal_signer
+            && self.previous_signer != [0u8; 20]
+            && now < self.rotation_deadline
+            && *signer == self.previous_signer
+    }
+
+    // Registered entries replace the built-in typehash of a version or disable it
+    pub fn get_withdrawal_typehash(&self, version: u8) -> Option<[u8; 32]> {
+        match self.withdrawal_sig_versions.iter().find(|v| v.version == version) {
+            Some(entry) => entry.enabled.then_some(entry.typehash),
+            None => match version {
+                SIG_VERSION_WITHDRAWAL => Some(WITHDRAWAL_TYPEHASH),
+                SIG_VERSION_RELAYED => Some(RELAYED_WITHDRAWAL_TYPEHASH),
+                SIG_VERSION_RECIPIENT => Some(RECIPIENT_WITHDRAWAL_TYPEHASH),
+                _ => None,
+            },
+        }
+    }
+
+    pub fn set_withdrawal_sig_version(&mut self, entry: SigVersion) {
+        if let Some(idx) = self
+            .withdrawal_sig_versions
+            .iter()
+            .position(|v| v.version == entry.version)
+        {
+            self.withdrawal_sig_versions[idx] = entry;
+        } else {
+            self.withdrawal_sig_versions.push(entry);
+        }
+    }
+
+    // Helper methods for withdrawal_signer_overrides
+    pub fn get_withdrawal_signer(&self, token: &Pubkey) -> [u8; 20] {
+        self.withdrawal_signer_overrides
+            .iter()
+            .find(|(t, _)| t == token)
+            .map(|(_, signer)| *signer)
+            .unwrap_or(self.withdrawal_signer)
+    }
+
+    pub fn set_withdrawal_signer_override(&mut self, token: Pubkey, signer: [u8; 20]) {
+        if let Some(idx) = self
+            .withdrawal_signer_overrides
+            .iter()
+            .position(|(t, _)| t == &token)
+        {
+            self.withdrawal_signer_overrides[idx] = (token, signer);
+        } else {
+            self.withdrawal_signer_overrides.push((token, signer));
+        }
+    }
+
+    pub fn remove_withdrawal_signer_override(&mut self, token: &Pubkey) -> bool {
+        if let Some(idx) = self
+            .withdrawal_signer_overrides
+            .iter()
+            .position(|(t, _)| t == token)
+        {
+            self.withdrawal_signer_overrides.remove(idx);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// Everything the deposit path writes, kept small so deposits leave State untouched
+#[account]
+pub struct Counters {
+    pub next_deposit_num: u64,
+    pub next_stake_num: u64,
+    pub custodied_lamports: u64, // Lamports in the SOL account owed to traders
+    pub reentry_lock_status: u8, // The one reentrancy lock, for deposits and withdrawals alike
+}
+
+impl Counters {
+    pub const SIZE: usize = 8 + 8 + 8 + 1;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TokenStats {
+    pub token: Pubkey,
+    pub deposit_count: u64,
+    pub deposit_volume: u128,
+    pub withdrawal_count: u64,
+    pub withdrawal_volume: u128,
+    pub stake_count: u64,
+    pub stake_volume: u128,
+}
+
+impl TokenStats {
+    pub const SIZE: usize = 32 + (8 + 16) * 3;
+}
+
+#[account]
+pub struct Stats {
+    pub tokens: Vec<TokenStats>,
+}
+
+impl Stats {
+    pub const SIZE: usize = 4 + TokenStats::SIZE * MAX_SUPPORTED_TOKENS;
+
+    // Entry for the token, created on first use. Totals stop being tracked for new
+    // tokens once the table is full rather than failing the deposit or withdrawal,
+    // and saturate for the same reason.
+    fn entry(&mut self, token: Pubkey) -> Option<&mut TokenStats> {
+        let position = match self.tokens.iter().position(|t| t.token == token) {
+            Some(position) => position,
+            None if self.tokens.len() < MAX_SUPPORTED_TOKENS => {
+                self.tokens.push(TokenStats {
+                    token,
+                    deposit_count: 0,
+                    deposit_volume: 0,
+                    withdrawal_count: 0,
+                    withdrawal_volume: 0,
+                    stake_count: 0,
+                    stake_volume: 0,
+                });
+                self.tokens.len() - 1
+            }
+            None => return None,
+        };
+        self.tokens.get_mut(position)
+    }
+
+    pub fn record_deposit(&mut self, token: Pubkey, amount: u64) {
+        if let Some(entry) = self.entry(token) {
+            entry.deposit_count = entry.deposit_count.saturating_add(1);
+            entry.deposit_volume = entry.deposit_volume.saturating_add(amount as u128);
+        }
+    }
+
+    pub fn record_withdrawal(&mut self, token: Pubkey, amount: u64) {
+        if let Some(entry) = self.entry(token) {
+            entry.withdrawal_count = entry.withdrawal_count.saturating_add(1);
+            entry.withdrawal_volume = entry.withdrawal_volume.saturating_add(amount as u128);
+        }
+    }
+
+    pub fn record_stake(&mut self, token: Pubkey, amount: u64) {
+        if let Some(entry) = self.entry(token) {
+            entry.stake_count = entry.stake_count.saturating_add(1);
+            entry.stake_volume = entry.stake_volume.saturating_add(amount as u128);
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TokenMinDeposit {
+    pub token: Pubkey,
+    pub min_deposit: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ConfigView {
+    pub owner: Pubkey,
+    pub withdrawal_signer: [u8; 20],
+    pub timelock_delay: i64,
+    pub timelock_in_slots: bool,
+    pub supported_tokens: Vec<TokenMinDeposit>,
+    pub timelock_authorities: Vec<Pubkey>,
+    pub version: String,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SignatureCheckView {
+    pub valid: bool, // Recovered signer is accepted for the token right now
+    pub recovered_signer: [u8; 20],
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct WithdrawalSimulationView {
+    pub amount: u64,      // Amount that would be paid out, resolved against the ledger
+    pub digest: [u8; 32], // Digest the signature was checked against
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct LookupTableEntry {
+    pub address: Pubkey,
+    pub index: u8, // Position in the lookup table, as used by v0 transaction account indexes
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PendingOperationView {
+    pub index: u8, // Position in pending_operations, as taken by execute_operation
+    pub operation_id: Option<u64>, // As taken by execute_operation_by_id, none if queued before ids
+    pub operation_type: u8,
+    pub queued_at: i64,
+    pub can_execute_at: i64,
+    pub data_hash: [u8; 32], // keccak256 of the operation data, see get_pending_operation
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SigVersion {
+    pub version: u8,
+    pub typehash: [u8; 32],
+    pub enabled: bool,
+}
+
+// How a trader pubkey fills the `address trader` word of EIP-712 withdrawal structs
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TraderEncoding {
+    #[default]
+    SolanaPubkey, // The full 32-byte pubkey
+    EvmAddress20, // keccak256(pubkey)[12..32], left-padded to 32 bytes
+}
+
+// How the withdrawal signer produced the digest it signed
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureScheme {
+    Eip712,       // EIP-712 typed data, layout selected by sig_version
+    SolanaNative, // sha256 over a domain tag and the Borsh-serialized SolanaWithdrawalMessage
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct WithdrawalPayload {
+    pub id: u64,
+    pub amount: u64,
+    pub v: u8,
+    pub r: [u8; 32],
+    pub s: [u8; 32],
+    pub sig_version: u8,
+    pub scheme: SignatureScheme,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TimelockOperation {
+    pub operation_type: u8,    // 1 = change_owner, 2 = change_signer, etc.
+    pub data: Vec<u8>,         // Serialized operation parameters
+    pub queued_at: i64,        // Timestamp when operation was queued
+    pub can_execute_at: i64,   // Timestamp when operation becomes executable
+    pub queued_slot: u64,      // Slot when operation was queued
+    pub can_execute_slot: u64, // Slot when operation becomes executable in slot mode
+    pub auto_executable: bool, // Anyone may execute it once matured
+}
+
+impl TimelockOperation {
+    pub const SIZE: usize = 1 + 4 + MAX_OPERATION_DATA_LEN + 8 + 8 + 8 + 8 + 1;
+}
+
+// State as written by 1.0.1, the last release before program_version was recorded. It
+// carries State's discriminator, and post_upgrade_check converts it in place
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyState {
+    pub owner: Pubkey,
+    pub withdrawal_signer: [u8; 20],
+    pub next_deposit_num: u64,
+    pub next_stake_num: u64,
+    pub reentry_lock_status: u8,
+    pub token_account_bump: u8,
+    pub sol_account_bump: u8,
+    pub supported_tokens: Vec<Pubkey>,
+    pub min_deposits: Vec<(Pubkey, u64)>,
+    pub timelock_authorities: Vec<Pubkey>,
+    pub timelock_delay: i64,
+    pub pending_operations: Vec<LegacyTimelockOperation>,
+    pub domain_separator: Option<[u8; 32]>,
+}
+
+impl LegacyState {
+    // Space 1.0.1 allocated after the discriminator, which its size constant also counted
+    pub const SIZE: usize = 8 +
+        32 + // owner
+        20 + // withdrawal_signer
+        8 +  // next_deposit_num
+        8 +  // next_stake_num
+        1 +  // reentry_lock_status
+        1 +  // token_account_bump
+        1 +  // sol_account_bump
+        4 + (32 * MAX_SUPPORTED_TOKENS) + // supported_tokens
+        4 + (40 * MAX_SUPPORTED_TOKENS) + // min_deposits
+        4 + (32 * MAX_AUTHORITIES) + // timelock_authorities
+        8 +  // timelock_delay
+        4 + (100 * 10) + // pending_operations
+        1 + 32; // domain_separator
+}
+
+impl From<LegacyState> for State {
+    // Fields added since 1.0.1 start at their defaults; run_migrations seeds those that need more
+    fn from(legacy: LegacyState) -> Self {
+        Self {
+            owner: legacy.owner,
+            withdrawal_signer: legacy.withdrawal_signer,
+            next_deposit_num: legacy.next_deposit_num,
+            next_stake_num: legacy.next_stake_num,
+            reentry_lock_status: legacy.reentry_lock_status,
+            token_account_bump: legacy.token_account_bump,
+            sol_account_bump: legacy.sol_account_bump,
+            supported_tokens: legacy.supported_tokens,
+            min_deposits: legacy.min_deposits,
+            timelock_authorities: legacy.timelock_authorities,
+            timelock_delay: legacy.timelock_delay,
+            pending_operations: legacy
+                .pending_operations
+                .into_iter()
+                .map(TimelockOperation::from)
+                .collect(),
+            domain_separator: legacy.domain_separator,
+            ..Default::default()
+        }
+    }
+}
+
+// TimelockOperation as queued by 1.0.1, before slots and auto execution were recorded.
+// post_upgrade_check rewrites queued operations into the current layout
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct LegacyTimelockOperation {
+    pub operation_type: u8,
+    pub data: Vec<u8>,
+    pub queued_at: i64,
+    pub can_execute_at: i64,
+}
+
+impl From<LegacyTimelockOperation> for TimelockOperation {
+    // 1.0.1 only had time-based delays, so the slot deadlines are never consulted
+    fn from(operation: LegacyTimelockOperation) -> Self {
+        Self {
+            operation_type: operation.operation_type,
+            data: operation.data,
+            queued_at: operation.queued_at,
+            can_execute_at: operation.can_execute_at,
+            queued_slot: 0,
+            can_execute_slot: 0,
+            auto_executable: false,
+        }
+    }
+}
+
+// Left by every execute path, so an operation id executes at most once and executed
+// governance actions can be enumerated on-chain
+#[account]
+pub struct ExecutedOperationReceipt {
+    pub operation_id: u64,
+    pub operation_type: u8,
+    pub data_hash: [u8; 32], // keccak256 of the operation data
+    pub executor: Pubkey,
+    pub executed_at: i64,
+}
+
+impl ExecutedOperationReceipt {
+    pub const SIZE: usize = 8 + 1 + 32 + 32 + 8;
+}
+
+#[account]
+pub struct WithdrawalRecord {
+    pub index: u64,
+    pub processed_bits: [u8; WITHDRAWAL_BITMAP_SIZE],
+}
+
+impl WithdrawalRecord {
+    // Account size includes 8 bytes for anchor discriminator + index (8 bytes) + bitmap
+    pub const SIZE: usize = 8 + 8 + WITHDRAWAL_BITMAP_SIZE;
+
+    pub fn is_processed(&self, id: u64) -> bool {
+        let bit_index = (id % WITHDRAWALS_PER_ACCOUNT as u64) as usize;
+        let byte_index = bit_index / 8;
+        let bit_position = bit_index % 8;
+        (self.processed_bits[byte_index] & (1 << bit_position)) != 0
+    }
+
+    pub fn mark_processed(&mut self, id: u64) {
+        let bit_index = (id % WITHDRAWALS_PER_ACCOUNT as u64) as usize;
+        let byte_index = bit_index / 8;
+        let bit_position = bit_index % 8;
+        self.processed_bits[byte_index] |= 1 << bit_position;
+    }
+
+    // Every id in the shard has been processed
+    pub fn is_full(&self) -> bool {
+        self.processed_bits.iter().all(|byte| *byte == u8::MAX)
+    }
+}
+
+// What a token transfer into the vault through deposit_v2 funds
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum FundingPurpose {
+    Deposit, // Credits the trader's exchange balance
+    Stake,   // Credits the trader's stake
+}
+
+impl FundingPurpose {
+    // Bit of the purpose in TokenConfig.allowed_purposes
+    pub fn bit(self) -> u8 {
+        match self {
+            FundingPurpose::Deposit => PURPOSE_DEPOSIT,
+            FundingPurpose::Stake => PURPOSE_STAKE,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum TokenStatus {
+    Active,  // Deposits, stakes and withdrawals allowed
+    Paused,  // Nothing allowed
+    Retired, // Withdrawals only
+}
+
+#[account]
+pub struct TokenConfig {
+    pub mint: Pubkey,
+    pub decimals: u8,
+    pub token_program: Pubkey,
+    pub status: TokenStatus,
+    pub liabilities: u64,
+    pub paused_deposits: bool,    // Set by pause_token, cleared through the timelock
+    pub paused_withdrawals: bool, // Set by pause_token, cleared through the timelock
+    pub dust_modulus: u64,        // Deposits must be multiples of this, 0 accepts any amount
+    pub deposit_cap: u64,         // Max deposit volume per DEPOSIT_CAP_WINDOW, 0 is uncapped
+    pub cap_window_start: i64,
+    pub cap_window_deposited: u64, // Deposited since cap_window_start
+    pub hot_float: u64,            // Hot vault balance kept by rebalance_to_cold, 0 disables it
+    pub deposit_fee_bps: u16,      // Campaign fee on deposit_token, 0 charges nothing
+    pub fee_destination: Pubkey,   // Token account receiving the deposit fee
+    pub last_checkpoint_day: i64,  // Unix day of the last checkpoint, 0 before the first
+    pub vault: Pubkey,             // Adopted vault account, default for the canonical ATA
+    pub allowed_purposes: u8,      // PURPOSE_* bits the token may fund, 0 allows every purpose
+}
+
+impl TokenConfig {
+    pub const SIZE: usize = 32 + 1 + 32 + 1 + 8 + 1 + 1 + 8 + 8 + 8 + 8 + 8 + 2 + 32 + 8 + 32 + 1;
+
+    // Configs created before allowed_purposes existed read 0 and stay unrestricted
+    pub fn allows(&self, purpose: FundingPurpose) -> bool {
+        self.allowed_purposes == 0 || self.allowed_purposes & purpose.bit() != 0
+    }
+
+    pub fn add_liability(&mut self, amount: u64) {
+        self.liabilities = self.liabilities.saturating_add(amount);
+    }
+
+    pub fn remove_liability(&mut self, amount: u64) {
+        self.liabilities = self.liabilities.saturating_sub(amount);
+    }
+}
+
+#[account]
+pub struct TraderLedger {
+    pub trader: Pubkey,
+    pub token: Pubkey,
+    pub balance: u64, // Deposits minus withdrawals recorded on-chain
+    pub last_withdrawal_at: i64,
+}
+
+impl TraderLedger {
+    pub const SIZE: usize = 32 + 32 + 8 + 8;
+}
+
+#[account]
+pub struct DepositDelegate {
+    pub trader: Pubkey,
+    pub delegates: Vec<Pubkey>, // Wallets allowed to deposit on the trader's behalf
+}
+
+impl DepositDelegate {
+    pub const SIZE: usize = 32 + 4 + 32 * MAX_DEPOSIT_DELEGATES;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct AllowlistEntry {
+    pub destination: Pubkey, // Token account withdrawals may be paid into
+    pub active_at: i64,      // Timestamp from which the destination may be used
+}
+
+#[account]
+pub struct WithdrawalAllowlist {
+    pub trader: Pubkey,
+    pub entries: Vec<AllowlistEntry>,
+}
+
+impl WithdrawalAllowlist {
+    pub const SIZE: usize = 32 + 4 + (32 + 8) * MAX_ALLOWLIST_ENTRIES;
+}
+
+#[account]
+pub struct BlacklistEntry {
+    pub address: Pubkey,
+    pub blocked_at: i64,
+}
+
+impl BlacklistEntry {
+    pub const SIZE: usize = 32 + 8;
+}
+
+// Header of a Wormhole `PostedVAAV1` account, written by the core bridge once the
+// guardian signatures over the message have been verified
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PostedVaa {
+    pub vaa_version: u8,
+    pub consistency_level: u8,
+    pub vaa_time: u32,
+    pub vaa_signature_account: Pubkey,
+    pub submission_time: u32,
+    pub nonce: u32,
+    pub sequence: u64,
+    pub emitter_chain: u16,
+    pub emitter_address: [u8; 32],
+    pub payload: Vec<u8>,
+}
+
+// Token bridge transfer-with-payload (payload id 3) whose payload names the destination trader
+#[derive(Clone)]
+pub struct BridgedDepositPayload {
+    pub emitter_chain: u16,
+    pub emitter_address: [u8; 32],
+    pub sequence: u64,
+    pub amount: u64, // Normalized to at most 8 decimals by the token bridge
+    pub token_address: [u8; 32],
+    pub token_chain: u16,
+    pub to: Pubkey,
+    pub to_chain: u16,
+    pub trader: Pubkey,
+}
+
+impl BridgedDepositPayload {
+    pub const VAA_MAGIC: &'static [u8; 3] = b"vaa";
+    pub const TRANSFER_WITH_PAYLOAD: u8 = 3;
+    // id + amount + token_address + token_chain + to + to_chain + from_address + trader
+    pub const SIZE: usize = 1 + 32 + 32 + 2 + 32 + 2 + 32 + 32;
+
+    pub fn from_receipt(receipt: &AccountInfo) -> Result<Self> {
+        let data = receipt.try_borrow_data()?;
+        require!(
+            data.len() > Self::VAA_MAGIC.len() && &data[..3] == Self::VAA_MAGIC,
+            RbxError::InvalidBridgeReceipt
+        );
+        let vaa = PostedVaa::deserialize(&mut &data[3..])
+            .map_err(|_| error!(RbxError::InvalidBridgeReceipt))?;
+
+        // Token bridge payloads are big-endian, unlike the Borsh-encoded header
+        let p = &vaa.payload;
+        require!(
+            p.len() >= Self::SIZE && p[0] == Self::TRANSFER_WITH_PAYLOAD,
+            RbxError::InvalidBridgeReceipt
+        );
+        // Amounts are uint256, but normalized transfers always fit in the low 8 bytes
+        require!(
+            p[1..25].iter().all(|b| *b == 0),
+            RbxError::InvalidBridgeReceipt
+        );
+        let amount = u64::from_be_bytes(p[25..33].try_into().unwrap());
+
+        Ok(Self {
+            emitter_chain: vaa.emitter_chain,
+            emitter_address: vaa.emitter_address,
+            sequence: vaa.sequence,
+            amount,
+            token_address: p[33..65].try_into().unwrap(),
+            token_chain: u16::from_be_bytes(p[65..67].try_into().unwrap()),
+            to: Pubkey::new_from_array(p[67..99].try_into().unwrap()),
+            to_chain: u16::from_be_bytes(p[99..101].try_into().unwrap()),
+            trader: Pubkey::new_from_array(p[133..165].try_into().unwrap()),
+        })
+    }
+
+    // Undo the token bridge's normalization to 8 decimals
+    pub fn denormalized_amount(&self, decimals: u8) -> Result<u64> {
+        if decimals <= 8 {
+            return Ok(self.amount);
+        }
+        10u64
+            .checked_pow((decimals - 8) as u32)
+            .and_then(|scale| self.amount.checked_mul(scale))
+            .ok_or(error!(RbxError::InvalidBridgeReceipt))
+    }
+}
+
+#[account]
+pub struct BridgedDepositMarker {
+    pub receipt: Pubkey,
+    pub emitter_chain: u16,
+    pub sequence: u64,
+    pub trader: Pubkey,
+    pub token: Pubkey,
+    pub amount: u64,
+    pub deposit_num: u64,
+}
+
+impl BridgedDepositMarker {
+    pub const SIZE: usize = 32 + 2 + 8 + 32 + 32 + 8 + 8;
+}
+
+#[account]
+pub struct DepositReceipt {
+    pub deposit_num: u64,
+    pub trader: Pubkey,
+    pub token: Pubkey,
+    pub amount: u64,
+    pub slot: u64, // Slot in which the deposit was made
+}
+
+impl DepositReceipt {
+    pub const SIZE: usize = 8 + 32 + 32 + 8 + 8;
+}
+
+// Records the deposit made under a client-chosen id, keyed by user and client_id
+#[account]
+pub struct ClientDepositMarker {
+    pub client_id: [u8; 16],
+    pub trader: Pubkey,
+    pub token: Pubkey,
+    pub amount: u64,
+    pub deposit_num: u64,
+}
+
+impl ClientDepositMarker {
+    pub const SIZE: usize = 16 + 32 + 32 + 8 + 8;
+}
+
+// Never closed: its existence is what marks the id as processed
+#[account]
+pub struct WithdrawalReceipt {
+    pub id: u64,
+    pub trader: Pubkey,
+    pub token: Pubkey,
+    pub amount: u64,
+}
+
+impl WithdrawalReceipt {
+    pub const SIZE: usize = 8 + 32 + 32 + 8;
+}
+
+#[account]
+pub struct StakePosition {
+    pub trader: Pubkey,
+    pub token: Pubkey,
+    pub staked: u64, // Total staked since the position was opened
+    pub locked: u64, // Portion locked as collateral by the margin engine
+}
+
+impl StakePosition {
+    pub const SIZE: usize = 32 + 32 + 8 + 8;
+}
+
+// Rewards are emitted at `rate_per_second` from `start` until the next segment starts
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct EmissionSegment {
+    pub start: i64,
+    pub rate_per_second: u64,
+}
+
+// Piecewise-linear reward emission of a stake token, set through the timelock
+#[account]
+pub struct EmissionSchedule {
+    pub token: Pubkey,
+    pub segments: Vec<EmissionSegment>, // Sorted by start, the last one runs indefinitely
+}
+
+impl EmissionSchedule {
+    pub const SIZE: usize = 32 + 4 + (16 * MAX_EMISSION_SEGMENTS);
+
+    // Total emitted over [from, to), taking each segment's rate for the time it overlaps
+    pub fn emitted_between(&self, from: i64, to: i64) -> u128 {
+        let mut emitted = 0u128;
+        for (i, segment) in self.segments.iter().enumerate() {
+            let end = self.segments.get(i + 1).map_or(i64::MAX, |next| next.start);
+            let overlap_start = from.max(segment.start);
+            let overlap_end = to.min(end);
+            if overlap_end > overlap_start {
+                let seconds = (overlap_end as i128 - overlap_start as i128) as u128;
+                emitted = emitted.saturating_add(seconds * segment.rate_per_second as u128);
+            }
+        }
+        emitted
+    }
+}
+
+#[account]
+pub struct PartialWithdrawal {
+    pub trader: Pubkey,
+    pub token: Pubkey,
+    pub remaining: u64, // Signed amounts the vault could not cover yet
+}
+
+impl PartialWithdrawal {
+    pub const SIZE: usize = 32 + 32 + 8;
+}
+
+#[account]
+pub struct DepositClaim {
+    pub sponsor: Pubkey,
+    pub claim_id: u64,
+    pub trader: Pubkey, // Only this trader can accept the deposit
+    pub token: Pubkey,
+    pub amount: u64,
+    pub expires_at: i64, // After this the sponsor can reclaim the deposit
+}
+
+impl DepositClaim {
+    pub const SIZE: usize = 32 + 8 + 32 + 32 + 8 + 8;
+}
+
+#[account]
+pub struct WithdrawalRequest {
+    pub trader: Pubkey,
+    pub token: Pubkey,
+    pub amount: u64,
+    pub requested_at: i64,
+}
+
+impl WithdrawalRequest {
+    pub const SIZE: usize = 32 + 32 + 8 + 8;
+}
+
+// Bumped on each deposit, withdrawal and stake of a trader, so pollers can watch one account
+#[account]
+pub struct ActivityCounter {
+    pub trader: Pubkey,
+    pub count: u64,
+    pub last_slot: u64,
+}
+
+impl ActivityCounter {
+    pub const SIZE: usize = 32 + 8 + 8;
+}
+
+// Withdrawal verified and posted ahead of time, redeemed by the trader with claim_ticket
+#[account]
+pub struct WithdrawalTicket {
+    pub id: u64,
+    pub trader: Pubkey,
+    pub token: Pubkey,
+    pub amount: u64,
+    pub poster: Pubkey, // Refunded the rent on claim
+    pub posted_at: i64,
+}
+
+impl WithdrawalTicket {
+    pub const SIZE: usize = 8 + 32 + 32 + 8 + 32 + 8;
+}
+
+// Off-chain profile id of a wallet, attested by the backend signer
+#[account]
+pub struct ProfileBinding {
+    pub wallet: Pubkey,
+    pub profile_id: u64,
+    pub bound_at: i64,
+}
+
+impl ProfileBinding {
+    pub const SIZE: usize = 32 + 8 + 8;
+}
+
+#[account]
+pub struct DepositPolicy {
+    pub trader: Pubkey,
+    pub approver: [u8; 20], // Ethereum address of the trader-registered approver
+    pub threshold: u64,     // Deposits above this amount need an approval
+    pub nonce: u64,
+    pub approved_token: Pubkey,
+    pub approved_amount: u64,
+}
+
+impl DepositPolicy {
+    pub const SIZE: usize = 32 + 20 + 8 + 8 + 32 + 8;
+}
@@ -1,7 +1,7 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { PublicKey, Keypair } from "@solana/web3.js";
-import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { TOKEN_PROGRAM_ID, getAssociatedTokenAddressSync } from "@solana/spl-token";
 import BN from "bn.js";
 
 async function main() {
@@ -70,7 +70,8 @@ async function main() {
         minDeposit,
         timelockDelay,
         withdrawalSigner,
-        initialAuthorities
+        initialAuthorities,
+        false // timelock delay in seconds, not slots
       )
       .accounts({
         state: statePda,
@@ -78,6 +79,7 @@ async function main() {
         authority: provider.wallet.publicKey,
         defaultTokenMint: wrappedSol,
         programTokenAuthority: tokenAuthority,
+        programTokenAccount: getAssociatedTokenAddressSync(wrappedSol, tokenAuthority, true),
         programSolAccount: solAccount,
         systemProgram: anchor.web3.SystemProgram.programId,
         tokenProgram: TOKEN_PROGRAM_ID,
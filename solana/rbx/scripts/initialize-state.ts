@@ -1,7 +1,7 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { PublicKey, Keypair } from "@solana/web3.js";
-import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { TOKEN_PROGRAM_ID, getAssociatedTokenAddressSync } from "@solana/spl-token";
 
 // Initialize a new state account for the RBX program
 
@@ -76,7 +76,8 @@ async function main() {
         minDeposit,
         timelockDelay,
         Array.from(withdrawalSignerBytes),
-        initialAuthorities
+        initialAuthorities,
+        false // timelock delay in seconds, not slots
       )
       .accounts({
         state: statePda,
@@ -84,6 +85,7 @@ async function main() {
         authority: provider.wallet.publicKey,
         defaultTokenMint: defaultTokenMint,
         programTokenAuthority: tokenAuthority,
+        programTokenAccount: getAssociatedTokenAddressSync(defaultTokenMint, tokenAuthority, true),
         programSolAccount: solAccount,
         systemProgram: anchor.web3.SystemProgram.programId,
         tokenProgram: TOKEN_PROGRAM_ID,
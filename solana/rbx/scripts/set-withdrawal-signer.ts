@@ -122,7 +122,8 @@ async function main() {
         const tx = await program.methods
             .queueOperation(
                 new BN(2), // 2 = Change signer operation type
-                Buffer.from(newSignerBytes)
+                Buffer.from(newSignerBytes),
+                false
             )
             .accounts({
                 state: statePda,
@@ -10,7 +10,7 @@ import { BN } from "bn.js";
 import * as dotenv from "dotenv";
 import path from "path";
 import { ethers } from "ethers";
-import { signWithdrawal } from "../tests/utils.ts";
+import { signWithdrawal, SIG_VERSION_WITHDRAWAL, SIGNATURE_SCHEME_EIP712 } from "../tests/utils.ts";
 
 async function main() {
     // Load environment variables from .env file
@@ -112,7 +112,10 @@ async function main() {
                 withdrawAmount,
                 v,
                 r,
-                s
+                s,
+                false, // pay out native SOL
+                SIG_VERSION_WITHDRAWAL,
+                SIGNATURE_SCHEME_EIP712
             )
             .accounts({
                 state: statePda,
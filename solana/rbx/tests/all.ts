@@ -26,6 +26,8 @@ import { runDepositForTests } from "./deposit-for-operations.ts";
 import { runStakeTests } from "./stake-operations.ts";
 import { runWithdrawalTests } from "./withdraw-operations.ts";
 import { runTimelockTests } from "./timelock-operations.ts";
+import { runDurableNonceTests } from "./durable-nonce-operations.ts";
+import { runComputeBudgetTests } from "./compute-budget.ts";
 
 /**
  * Interface for a TimelockOperation
@@ -35,6 +37,8 @@ interface TimelockOperation {
   data: Buffer;
   queuedAt: number;
   canExecuteAt: number;
+  queuedSlot: number;
+  canExecuteSlot: number;
 }
 
 /**
@@ -171,6 +175,19 @@ describe("RBX Protocol Tests", () => {
     );
   });
 
+  it("Run compute budget tests", async () => {
+    await runComputeBudgetTests(
+      program,
+      admin,
+      user,
+      statePda,
+      tokenAuthPda,
+      solAccountPda,
+      mint,
+      userTokenAccount
+    );
+  });
+
   it("Run stake tests", async () => {
     await runStakeTests(
       program,
@@ -198,6 +215,18 @@ describe("RBX Protocol Tests", () => {
     );
   });
 
+  it("Run durable nonce withdrawal tests", async () => {
+    await runDurableNonceTests(
+      program,
+      admin,
+      user,
+      signerWallet,
+      statePda,
+      tokenAuthPda,
+      mint
+    );
+  });
+
   it("Run timelock operation tests", async () => {
     await runTimelockTests(
       program,
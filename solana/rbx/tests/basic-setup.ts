@@ -9,12 +9,15 @@ import {
     TOKEN_PROGRAM_ID,
     createMint,
     getOrCreateAssociatedTokenAccount,
+    getAssociatedTokenAddressSync,
     mintTo
 } from "@solana/spl-token";
 import { assert } from "chai";
 import { BN } from "bn.js";
 import { getEthereumAddressBytes, getVerifyingContractFromProgram } from "./utils.ts";
 
+const BPF_LOADER_UPGRADEABLE_ID = new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111");
+
 export async function runBasicSetupTests(
     program: anchor.Program,
     admin: Keypair,
@@ -79,7 +82,9 @@ export async function runBasicSetupTests(
                     new BN(1_000_000),              // Min deposit
                     new BN(5),                     // Timelock delay
                     Array.from(signerAddressBytes), // 20-byte withdrawal signer as array
-                    [timelockAuthority.publicKey]   // array of timelock authority accounts
+                    [timelockAuthority.publicKey],  // array of timelock authority accounts
+                    false,                          // Timelock delay in seconds, not slots
+                    null                            // No native SOL support at deployment
                 )
                 .accounts({
                     state: statePda,
@@ -87,7 +92,15 @@ export async function runBasicSetupTests(
                     authority: timelockAuthority.publicKey,
                     defaultTokenMint: mint,
                     programTokenAuthority: tokenAuthPda,
+                    programTokenAccount: getAssociatedTokenAddressSync(mint, tokenAuthPda, true),
                     programSolAccount: solAccountPda,
+                    nativeMint: null,
+                    nativeTokenConfig: null,
+                    nativeProgramTokenAccount: null,
+                    programData: PublicKey.findProgramAddressSync(
+                        [program.programId.toBuffer()],
+                        BPF_LOADER_UPGRADEABLE_ID
+                    )[0],
                     systemProgram: SystemProgram.programId,
                     tokenProgram: TOKEN_PROGRAM_ID,
                 })
@@ -135,6 +148,7 @@ export async function runBasicSetupTests(
                 state: statePda,
                 authority: timelockAuthority.publicKey,
                 tokenMint: wrappedSolMint,
+                programTokenAccount: getAssociatedTokenAddressSync(wrappedSolMint, tokenAuthPda, true),
                 tokenProgram: TOKEN_PROGRAM_ID,
             })
             .signers([timelockAuthority])
@@ -226,7 +240,9 @@ export async function runBasicSetupTestsWithMocha(
                         new BN(1_000_000),              // Min deposit
                         new BN(5),                     // Timelock delay
                         Array.from(signerAddressBytes), // 20-byte withdrawal signer as array
-                        [timelockAuthority.publicKey]   // array of timelock authority accounts
+                        [timelockAuthority.publicKey],  // array of timelock authority accounts
+                        false,                          // Timelock delay in seconds, not slots
+                        null                            // No native SOL support at deployment
                     )
                     .accounts({
                         state: statePda,
@@ -234,7 +250,15 @@ export async function runBasicSetupTestsWithMocha(
                         authority: timelockAuthority.publicKey,
                         defaultTokenMint: mint,
                         programTokenAuthority: tokenAuthPda,
+                        programTokenAccount: getAssociatedTokenAddressSync(mint, tokenAuthPda, true),
                         programSolAccount: solAccountPda,
+                        nativeMint: null,
+                        nativeTokenConfig: null,
+                        nativeProgramTokenAccount: null,
+                        programData: PublicKey.findProgramAddressSync(
+                            [program.programId.toBuffer()],
+                            BPF_LOADER_UPGRADEABLE_ID
+                        )[0],
                         systemProgram: SystemProgram.programId,
                         tokenProgram: TOKEN_PROGRAM_ID,
                     })
@@ -301,6 +325,7 @@ export async function runBasicSetupTestsWithMocha(
                         state: statePda,
                         authority: timelockAuthority.publicKey,
                         tokenMint: wrappedSolMint,
+                        programTokenAccount: getAssociatedTokenAddressSync(wrappedSolMint, tokenAuthPda, true),
                         tokenProgram: TOKEN_PROGRAM_ID,
                     })
                     .signers([timelockAuthority])
@@ -0,0 +1,92 @@
+import * as anchor from "@coral-xyz/anchor";
+import {
+    Keypair,
+    PublicKey,
+    SystemProgram
+} from "@solana/web3.js";
+import {
+    TOKEN_PROGRAM_ID,
+    NATIVE_MINT,
+    getOrCreateAssociatedTokenAccount,
+} from "@solana/spl-token";
+import { expect } from "chai";
+import { BN } from "bn.js";
+import { ensureTraderLedger } from "./utils.ts";
+
+// Deposits only write the small Counters account, so they should stay well under this
+const DEPOSIT_TOKEN_CU_CEILING = 60_000;
+const DEPOSIT_NATIVE_CU_CEILING = 40_000;
+
+async function computeUnitsConsumed(program: anchor.Program, signature: string): Promise<number> {
+    const connection = program.provider.connection;
+    await connection.confirmTransaction(signature, "confirmed");
+    const tx = await connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+    });
+    return tx!.meta!.computeUnitsConsumed!;
+}
+
+export async function runComputeBudgetTests(
+    program: anchor.Program,
+    admin: Keypair,
+    user: Keypair,
+    statePda: PublicKey,
+    tokenAuthPda: PublicKey,
+    solAccountPda: PublicKey,
+    mint: PublicKey,
+    userTokenAccount: PublicKey
+) {
+    console.log("Running compute budget tests...");
+
+    describe("deposit compute budget", () => {
+        it("Measures compute units for a token deposit", async () => {
+            const programTokenAccount = await getOrCreateAssociatedTokenAccount(
+                program.provider.connection,
+                admin,
+                mint,
+                tokenAuthPda,
+                true
+            ).then(account => account.address);
+
+            const signature = await program.methods
+                .depositToken(new BN(1_000_000), false)
+                .accounts({
+                    traderLedger: await ensureTraderLedger(program, user.publicKey, mint),
+                    state: statePda,
+                    mint: mint,
+                    programTokenAccount: programTokenAccount,
+                    programTokenAuthority: tokenAuthPda,
+                    userTokenAccount: userTokenAccount,
+                    user: user.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([user])
+                .rpc();
+
+            const units = await computeUnitsConsumed(program, signature);
+            console.log("deposit_token compute units:", units);
+            expect(units).to.be.lessThan(DEPOSIT_TOKEN_CU_CEILING);
+        });
+
+        it("Measures compute units for a native SOL deposit", async () => {
+            const signature = await program.methods
+                .depositNative(new BN(100_000_000))
+                .accounts({
+                    traderLedger: await ensureTraderLedger(program, user.publicKey, NATIVE_MINT),
+                    state: statePda,
+                    wrappedSolMint: NATIVE_MINT,
+                    programSolAccount: solAccountPda,
+                    user: user.publicKey,
+                    systemProgram: SystemProgram.programId,
+                })
+                .signers([user])
+                .rpc();
+
+            const units = await computeUnitsConsumed(program, signature);
+            console.log("deposit_native compute units:", units);
+            expect(units).to.be.lessThan(DEPOSIT_NATIVE_CU_CEILING);
+        });
+    });
+}
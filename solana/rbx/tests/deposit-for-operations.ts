@@ -11,6 +11,7 @@ import {
 } from "@solana/spl-token";
 import { assert, expect } from "chai";
 import { BN } from "bn.js";
+import { ensureTraderLedger, getBlacklistPda } from "./utils.ts";
 
 export async function runDepositForTests(
     program: anchor.Program,
@@ -80,12 +81,14 @@ export async function runDepositForTests(
                 const tx = await program.methods
                     .depositTokenFor(depositAmount, beneficiary.publicKey)
                     .accounts({
+                        traderLedger: await ensureTraderLedger(program, beneficiary.publicKey, mint),
                         state: statePda,
                         mint: mint,
                         programTokenAccount: programTokenAccount,
                         programTokenAuthority: tokenAuthPda,
                         userTokenAccount: userTokenAccount,
                         user: user.publicKey,
+                        traderBlacklistEntry: getBlacklistPda(program.programId, beneficiary.publicKey),
                         tokenProgram: TOKEN_PROGRAM_ID,
                         systemProgram: SystemProgram.programId,
                     })
@@ -145,6 +148,7 @@ export async function runDepositForTests(
                 const tx = await program.methods
                     .depositNativeFor(solDepositAmount, beneficiary.publicKey)
                     .accounts({
+                        traderLedger: await ensureTraderLedger(program, beneficiary.publicKey, wrappedSolMint),
                         state: statePda,
                         wrappedSolMint: wrappedSolMint,
                         programSolAccount: solAccountPda,
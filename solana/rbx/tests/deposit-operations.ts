@@ -11,6 +11,7 @@ import {
 } from "@solana/spl-token";
 import { assert, expect } from "chai";
 import { BN } from "bn.js";
+import { ensureTraderLedger } from "./utils.ts";
 
 export async function runDepositTests(
     program: anchor.Program,
@@ -61,8 +62,9 @@ export async function runDepositTests(
             try {
                 // Call the deposit_token instruction
                 const tx = await program.methods
-                    .depositToken(depositAmount)
+                    .depositToken(depositAmount, false)
                     .accounts({
+                        traderLedger: await ensureTraderLedger(program, user.publicKey, mint),
                         state: statePda,
                         mint: mint,
                         programTokenAccount: programTokenAccount,
@@ -104,6 +106,42 @@ export async function runDepositTests(
             }
         });
 
+        it("Rejects a deposit into a token account other than the vault", async () => {
+            // An ATA of the same mint owned by someone else, as a malicious transaction would use
+            const attacker = Keypair.generate();
+            const attackerTokenAccount = await getOrCreateAssociatedTokenAccount(
+                program.provider.connection,
+                admin, // payer
+                mint,
+                attacker.publicKey
+            ).then(account => account.address);
+
+            try {
+                await program.methods
+                    .depositToken(new BN(1_000_000), false)
+                    .accounts({
+                        traderLedger: await ensureTraderLedger(program, user.publicKey, mint),
+                        state: statePda,
+                        mint: mint,
+                        programTokenAccount: attackerTokenAccount,
+                        programTokenAuthority: tokenAuthPda,
+                        userTokenAccount: userTokenAccount,
+                        user: user.publicKey,
+                        tokenProgram: TOKEN_PROGRAM_ID,
+                        systemProgram: SystemProgram.programId,
+                    })
+                    .signers([user])
+                    .rpc();
+                assert.fail("Deposit into a non-vault token account should fail");
+            } catch (e: any) {
+                expect(e.message).to.include("InvalidVaultAccount");
+            }
+
+            // Nothing reached the attacker's account
+            const attackerBalance = await program.provider.connection.getTokenAccountBalance(attackerTokenAccount);
+            expect(attackerBalance.value.amount).to.equal("0");
+        });
+
         it("Deposits native SOL", async () => {
             console.log("Testing native SOL deposit...");
 
@@ -125,6 +163,7 @@ export async function runDepositTests(
                 const tx = await program.methods
                     .depositNative(solDepositAmount)
                     .accounts({
+                        traderLedger: await ensureTraderLedger(program, user.publicKey, wrappedSolMint),
                         state: statePda,
                         wrappedSolMint: wrappedSolMint,
                         programSolAccount: solAccountPda,
@@ -0,0 +1,285 @@
+import * as anchor from "@coral-xyz/anchor";
+import {
+    Keypair,
+    PublicKey,
+    SystemProgram,
+    SYSVAR_RENT_PUBKEY,
+    LAMPORTS_PER_SOL,
+    NONCE_ACCOUNT_LENGTH,
+    NonceAccount,
+    Transaction
+} from "@solana/web3.js";
+import {
+    TOKEN_PROGRAM_ID,
+    getOrCreateAssociatedTokenAccount
+} from "@solana/spl-token";
+import { assert, expect } from "chai";
+import { BN } from "bn.js";
+import { signWithdrawal, SIG_VERSION_WITHDRAWAL, SIGNATURE_SCHEME_EIP712, ensureTraderLedger } from "./utils.ts";
+
+export async function runDurableNonceTests(
+    program: anchor.Program,
+    admin: Keypair,
+    user: Keypair,
+    signerWallet: any,
+    statePda: PublicKey,
+    tokenAuthPda: PublicKey,
+    mint: PublicKey
+) {
+    console.log("Running durable nonce withdrawal tests...");
+    describe("durable nonce withdrawals", () => {
+        it("Submits an offline-built withdrawal using a durable nonce", async () => {
+            const connection = program.provider.connection;
+
+            // Create a nonce account controlled by the relayer (user)
+            const nonceAccount = Keypair.generate();
+            const rentExempt = await connection.getMinimumBalanceForRentExemption(NONCE_ACCOUNT_LENGTH);
+            const createNonceTx = new Transaction().add(
+                SystemProgram.createNonceAccount({
+                    fromPubkey: user.publicKey,
+                    noncePubkey: nonceAccount.publicKey,
+                    authorizedPubkey: user.publicKey,
+                    lamports: rentExempt,
+                })
+            );
+            await anchor.web3.sendAndConfirmTransaction(connection, createNonceTx, [user, nonceAccount]);
+
+            const nonceInfo = await connection.getAccountInfo(nonceAccount.publicKey);
+            const nonce = NonceAccount.fromAccountData(nonceInfo!.data).nonce;
+            console.log("Durable nonce:", nonce);
+
+            const recipient = Keypair.generate();
+            await connection.confirmTransaction(
+                await connection.requestAirdrop(recipient.publicKey, LAMPORTS_PER_SOL)
+            );
+
+            const programTokenAccount = await getOrCreateAssociatedTokenAccount(
+                connection,
+                admin,
+                mint,
+                tokenAuthPda,
+                true
+            ).then(account => account.address);
+
+            const recipientTokenAccount = await getOrCreateAssociatedTokenAccount(
+                connection,
+                admin,
+                mint,
+                recipient.publicKey
+            ).then(account => account.address);
+
+            const withdrawalId = 77_001;
+            const withdrawalAmount = new BN(1_000);
+
+            const { v, r, s } = await signWithdrawal(signerWallet, statePda, {
+                id: withdrawalId,
+                token: mint,
+                trader: recipient.publicKey,
+                amount: withdrawalAmount.toString(),
+            });
+
+            const withdrawalRecordAccount = PublicKey.findProgramAddressSync(
+                [
+                    Buffer.from("withdrawal_account"),
+                    new BN(Math.floor(withdrawalId / 4000)).toArrayLike(Buffer, 'le', 8)
+                ],
+                program.programId
+            )[0];
+
+            // Bound the slots in which the offline transaction may land
+            const currentSlot = await connection.getSlot();
+            const boundsIx = await program.methods
+                .assertSlotWindow(new BN(currentSlot), new BN(currentSlot + 1_000))
+                .accounts({})
+                .instruction();
+
+            const withdrawIx = await program.methods
+                .withdrawToken(new BN(withdrawalId), withdrawalAmount, v, r, s, SIG_VERSION_WITHDRAWAL, SIGNATURE_SCHEME_EIP712, false)
+                .accounts({
+                    state: statePda,
+                    withdrawalRecord: withdrawalRecordAccount,
+                    mint: mint,
+                    programTokenAccount: programTokenAccount,
+                    programTokenAuthority: tokenAuthPda,
+                    traderTokenAccount: recipientTokenAccount,
+                    trader: recipient.publicKey,
+                    traderLedger: await ensureTraderLedger(program, recipient.publicKey, mint),
+                    payer: user.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                    rent: SYSVAR_RENT_PUBKEY,
+                })
+                .instruction();
+
+            // Build the transaction against the durable nonce instead of a recent blockhash
+            const tx = new Transaction();
+            tx.add(
+                SystemProgram.nonceAdvance({
+                    noncePubkey: nonceAccount.publicKey,
+                    authorizedPubkey: user.publicKey,
+                })
+            );
+            tx.add(boundsIx);
+            tx.add(withdrawIx);
+            tx.recentBlockhash = nonce;
+            tx.feePayer = user.publicKey;
+            tx.sign(user);
+
+            const processedBefore = await program.methods
+                .isWithdrawalProcessed(new BN(withdrawalId))
+                .accounts({ withdrawalRecord: withdrawalRecordAccount })
+                .view();
+            expect(processedBefore).to.equal(false);
+
+            const initialRecipientBalance = parseInt(
+                (await connection.getTokenAccountBalance(recipientTokenAccount)).value.amount
+            );
+
+            const signature = await connection.sendRawTransaction(tx.serialize());
+            await connection.confirmTransaction(signature);
+            console.log("Durable nonce withdrawal signature:", signature);
+
+            const finalRecipientBalance = parseInt(
+                (await connection.getTokenAccountBalance(recipientTokenAccount)).value.amount
+            );
+            expect(finalRecipientBalance - initialRecipientBalance).to.equal(withdrawalAmount.toNumber());
+
+            // The relayer can tell a retry is unnecessary without resubmitting
+            const processedAfter = await program.methods
+                .isWithdrawalProcessed(new BN(withdrawalId))
+                .accounts({ withdrawalRecord: withdrawalRecordAccount })
+                .view();
+            expect(processedAfter).to.equal(true);
+
+            // Resubmitting the same signed transaction fails since the nonce has advanced
+            try {
+                await connection.sendRawTransaction(tx.serialize());
+                assert.fail("Replayed durable nonce transaction should fail");
+            } catch (e: any) {
+                console.log("Replay rejected as expected:", e.message);
+            }
+        });
+
+        // Builds a signed withdrawal to a fresh recipient, bundled behind a slot window check
+        async function buildWindowedWithdrawal(withdrawalId: number, minSlot: number, maxSlot: number) {
+            const connection = program.provider.connection;
+            const recipient = Keypair.generate();
+            await connection.confirmTransaction(
+                await connection.requestAirdrop(recipient.publicKey, LAMPORTS_PER_SOL)
+            );
+
+            const programTokenAccount = await getOrCreateAssociatedTokenAccount(
+                connection,
+                admin,
+                mint,
+                tokenAuthPda,
+                true
+            ).then(account => account.address);
+
+            const recipientTokenAccount = await getOrCreateAssociatedTokenAccount(
+                connection,
+                admin,
+                mint,
+                recipient.publicKey
+            ).then(account => account.address);
+
+            const withdrawalAmount = new BN(1_000);
+            const { v, r, s } = await signWithdrawal(signerWallet, statePda, {
+                id: withdrawalId,
+                token: mint,
+                trader: recipient.publicKey,
+                amount: withdrawalAmount.toString(),
+            });
+
+            const withdrawalRecordAccount = PublicKey.findProgramAddressSync(
+                [
+                    Buffer.from("withdrawal_account"),
+                    new BN(Math.floor(withdrawalId / 4000)).toArrayLike(Buffer, 'le', 8)
+                ],
+                program.programId
+            )[0];
+
+            const boundsIx = await program.methods
+                .assertSlotWindow(new BN(minSlot), new BN(maxSlot))
+                .accounts({})
+                .instruction();
+
+            const withdrawIx = await program.methods
+                .withdrawToken(new BN(withdrawalId), withdrawalAmount, v, r, s, SIG_VERSION_WITHDRAWAL, SIGNATURE_SCHEME_EIP712, false)
+                .accounts({
+                    state: statePda,
+                    withdrawalRecord: withdrawalRecordAccount,
+                    mint: mint,
+                    programTokenAccount: programTokenAccount,
+                    programTokenAuthority: tokenAuthPda,
+                    traderTokenAccount: recipientTokenAccount,
+                    trader: recipient.publicKey,
+                    traderLedger: await ensureTraderLedger(program, recipient.publicKey, mint),
+                    payer: user.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                    rent: SYSVAR_RENT_PUBKEY,
+                })
+                .instruction();
+
+            const tx = new Transaction().add(boundsIx).add(withdrawIx);
+            return { tx, recipientTokenAccount, withdrawalRecordAccount, withdrawalAmount };
+        }
+
+        it("Lands a withdrawal inside its slot window", async () => {
+            const connection = program.provider.connection;
+            const currentSlot = await connection.getSlot();
+            const withdrawalId = 77_002;
+            const { tx, recipientTokenAccount, withdrawalRecordAccount, withdrawalAmount } =
+                await buildWindowedWithdrawal(withdrawalId, currentSlot, currentSlot + 1_000);
+
+            const initialBalance = parseInt(
+                (await connection.getTokenAccountBalance(recipientTokenAccount)).value.amount
+            );
+
+            await anchor.web3.sendAndConfirmTransaction(connection, tx, [user]);
+
+            const finalBalance = parseInt(
+                (await connection.getTokenAccountBalance(recipientTokenAccount)).value.amount
+            );
+            expect(finalBalance - initialBalance).to.equal(withdrawalAmount.toNumber());
+
+            const processed = await program.methods
+                .isWithdrawalProcessed(new BN(withdrawalId))
+                .accounts({ withdrawalRecord: withdrawalRecordAccount })
+                .view();
+            expect(processed).to.equal(true);
+        });
+
+        it("Rejects a withdrawal outside its slot window", async () => {
+            const connection = program.provider.connection;
+            const currentSlot = await connection.getSlot();
+            const withdrawalId = 77_003;
+            const { tx, recipientTokenAccount, withdrawalRecordAccount } =
+                await buildWindowedWithdrawal(withdrawalId, 0, Math.max(currentSlot - 10, 0));
+
+            const initialBalance = parseInt(
+                (await connection.getTokenAccountBalance(recipientTokenAccount)).value.amount
+            );
+
+            try {
+                await anchor.web3.sendAndConfirmTransaction(connection, tx, [user]);
+                assert.fail("Expected slot window violation");
+            } catch (e: any) {
+                expect(e.message + (e.logs ?? []).join("\n")).to.include("SlotWindowViolated");
+            }
+
+            // The failed window check reverts the bundled withdrawal as well
+            const finalBalance = parseInt(
+                (await connection.getTokenAccountBalance(recipientTokenAccount)).value.amount
+            );
+            expect(finalBalance).to.equal(initialBalance);
+
+            const processed = await program.methods
+                .isWithdrawalProcessed(new BN(withdrawalId))
+                .accounts({ withdrawalRecord: withdrawalRecordAccount })
+                .view();
+            expect(processed).to.equal(false);
+        });
+    });
+}
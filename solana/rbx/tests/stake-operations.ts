@@ -11,6 +11,7 @@ import {
 } from "@solana/spl-token";
 import { assert, expect } from "chai";
 import { BN } from "bn.js";
+import { ensureTraderLedger } from "./utils.ts";
 
 export async function runStakeTests(
     program: anchor.Program,
@@ -63,6 +64,7 @@ export async function runStakeTests(
                 const tx = await program.methods
                     .stakeToken(stakeAmount)
                     .accounts({
+                        traderLedger: await ensureTraderLedger(program, user.publicKey, mint),
                         state: statePda,
                         mint: mint,
                         programTokenAccount: programTokenAccount,
@@ -125,6 +127,7 @@ export async function runStakeTests(
                 const tx = await program.methods
                     .stakeNative(solStakeAmount)
                     .accounts({
+                        traderLedger: await ensureTraderLedger(program, user.publicKey, wrappedSolMint),
                         state: statePda,
                         wrappedSolMint: wrappedSolMint,
                         programSolAccount: solAccountPda,
@@ -7,6 +7,7 @@ import {
 } from "@solana/web3.js";
 import {
     TOKEN_PROGRAM_ID,
+    NATIVE_MINT,
     getOrCreateAssociatedTokenAccount
 } from "@solana/spl-token";
 import { assert, expect } from "chai";
@@ -14,7 +15,12 @@ import { BN } from "bn.js";
 import {
     fetchStateAccount,
     waitForTimelock,
-    generateEthereumAddress
+    generateEthereumAddress,
+    ensureTraderLedger,
+    getExecutedOperationReceiptPda,
+    getBlacklistPda,
+    SIG_VERSION_WITHDRAWAL,
+    SIGNATURE_SCHEME_EIP712
 } from "./utils.ts";
 
 export async function runTimelockTests(
@@ -43,7 +49,7 @@ export async function runTimelockTests(
             const operationData = newAuthority.publicKey.toBytes();
 
             await program.methods
-                .queueOperation(new BN(4), Buffer.from(operationData)) // 4 = Add timelock authority
+                .queueOperation(new BN(4), Buffer.from(operationData), false) // 4 = Add timelock authority
                 .accounts({
                     state: statePda,
                     authority: timelockAuthority.publicKey,
@@ -64,6 +70,7 @@ export async function runTimelockTests(
                     .executeOperation(new BN(0))
                     .accounts({
                         state: statePda,
+                        receipt: await getExecutedOperationReceiptPda(program, statePda, 0),
                         authority: timelockAuthority.publicKey,
                     })
                     .signers([timelockAuthority])
@@ -82,6 +89,7 @@ export async function runTimelockTests(
                 .executeOperation(new BN(0))
                 .accounts({
                     state: statePda,
+                    receipt: await getExecutedOperationReceiptPda(program, statePda, 0),
                     authority: timelockAuthority.publicKey,
                 })
                 .signers([timelockAuthority])
@@ -109,7 +117,7 @@ export async function runTimelockTests(
 
             // Queue the timelock operation to update withdrawal signer
             await program.methods
-                .queueOperation(new BN(2), newWithdrawalSigner) // 2 = Change signer
+                .queueOperation(new BN(2), newWithdrawalSigner, false) // 2 = Change signer
                 .accounts({
                     state: statePda,
                     authority: timelockAuthority.publicKey,
@@ -141,6 +149,7 @@ export async function runTimelockTests(
                 .executeOperation(new BN(operationIndex))
                 .accounts({
                     state: statePda,
+                    receipt: await getExecutedOperationReceiptPda(program, statePda, operationIndex),
                     authority: timelockAuthority.publicKey,
                 })
                 .signers([timelockAuthority])
@@ -176,7 +185,7 @@ export async function runTimelockTests(
             try {
                 // Attempt to queue the operation with unauthorized account
                 await program.methods
-                    .queueOperation(new BN(2), newWithdrawalSigner) // 2 = Change signer
+                    .queueOperation(new BN(2), newWithdrawalSigner, false) // 2 = Change signer
                     .accounts({
                         state: statePda,
                         authority: unauthorizedAccount.publicKey, // Using unauthorized account
@@ -218,7 +227,8 @@ export async function runTimelockTests(
                 const tx = await program.methods
                     .queueOperation(
                         new BN(3), // 3 = Set timelock delay operation type
-                        Buffer.from(delayBytes)
+                        Buffer.from(delayBytes),
+                        false
                     )
                     .accounts({
                         state: statePda,
@@ -251,6 +261,7 @@ export async function runTimelockTests(
                     .executeOperation(new BN(operationIndex))
                     .accounts({
                         state: statePda,
+                        receipt: await getExecutedOperationReceiptPda(program, statePda, operationIndex),
                         authority: timelockAuthority.publicKey,
                     })
                     .signers([timelockAuthority])
@@ -313,7 +324,8 @@ export async function runTimelockTests(
             await program.methods
                 .queueOperation(
                     new BN(3), // 3 = Set timelock delay operation type
-                    Buffer.from(delayBytes)
+                    Buffer.from(delayBytes),
+                    false
                 )
                 .accounts({
                     state: statePda,
@@ -344,6 +356,7 @@ export async function runTimelockTests(
                     .executeOperation(new BN(operationIndex))
                     .accounts({
                         state: statePda,
+                        receipt: await getExecutedOperationReceiptPda(program, statePda, operationIndex),
                         authority: unauthorizedAccount.publicKey, // Using unauthorized account
                     })
                     .signers([unauthorizedAccount])
@@ -364,6 +377,7 @@ export async function runTimelockTests(
                 .executeOperation(new BN(operationIndex))
                 .accounts({
                     state: statePda,
+                    receipt: await getExecutedOperationReceiptPda(program, statePda, operationIndex),
                     authority: timelockAuthority.publicKey,
                 })
                 .signers([timelockAuthority])
@@ -385,7 +399,8 @@ export async function runTimelockTests(
             await program.methods
                 .queueOperation(
                     new BN(3), // 3 = Set timelock delay operation type
-                    Buffer.from(delayBytes)
+                    Buffer.from(delayBytes),
+                    false
                 )
                 .accounts({
                     state: statePda,
@@ -454,7 +469,8 @@ export async function runTimelockTests(
             await program.methods
                 .queueOperation(
                     new BN(3), // 3 = Set timelock delay operation type
-                    Buffer.from(delayBytes)
+                    Buffer.from(delayBytes),
+                    false
                 )
                 .accounts({
                     state: statePda,
@@ -520,5 +536,373 @@ export async function runTimelockTests(
 
             expect(operationStillExists).to.be.false;
         });
+
+        it("Rejects withdraw_multi above the withdrawal batch limit", async () => {
+            // Queue, wait out and execute operation 8 with the given batch limits
+            const setMaxBatchSizes = async (deposits: number, withdrawals: number) => {
+                await program.methods
+                    .queueOperation(new BN(8), Buffer.from([deposits, withdrawals]), false) // 8 = Set max batch sizes
+                    .accounts({
+                        state: statePda,
+                        authority: timelockAuthority.publicKey,
+                    })
+                    .signers([timelockAuthority])
+                    .rpc();
+
+                const state = await fetchStateAccount(program, statePda);
+                const operationIndex = state.pendingOperations.findIndex(op => op.operationType === 8);
+                await waitForTimelock(state);
+
+                await program.methods
+                    .executeOperation(new BN(operationIndex))
+                    .accounts({
+                        state: statePda,
+                        receipt: await getExecutedOperationReceiptPda(program, statePda, operationIndex),
+                        authority: timelockAuthority.publicKey,
+                    })
+                    .signers([timelockAuthority])
+                    .rpc();
+            };
+
+            await setMaxBatchSizes(4, 1);
+
+            // Both ids sit in the shard created by the earlier withdrawal of id 12345. The batch
+            // limit is checked before the signatures, so placeholder signatures suffice
+            const payload = (id: number) => ({
+                id: new BN(id),
+                amount: new BN(1),
+                v: 27,
+                r: Array(32).fill(0),
+                s: Array(32).fill(0),
+                sigVersion: SIG_VERSION_WITHDRAWAL,
+                scheme: SIGNATURE_SCHEME_EIP712,
+            });
+            const withdrawalRecord = PublicKey.findProgramAddressSync(
+                [Buffer.from("withdrawal_account"), new BN(3).toArrayLike(Buffer, "le", 8)],
+                program.programId
+            )[0];
+            const programTokenAccount = await getOrCreateAssociatedTokenAccount(
+                provider.connection,
+                admin,
+                mint,
+                tokenAuthPda,
+                true
+            ).then(account => account.address);
+            const programSolAccount = PublicKey.findProgramAddressSync(
+                [Buffer.from("sol_account")],
+                program.programId
+            )[0];
+            const nativeTokenConfig = PublicKey.findProgramAddressSync(
+                [Buffer.from("token_config"), NATIVE_MINT.toBuffer()],
+                program.programId
+            )[0];
+
+            try {
+                await program.methods
+                    .withdrawMulti(payload(12346), payload(12347))
+                    .accounts({
+                        state: statePda,
+                        withdrawalRecord,
+                        mint,
+                        programTokenAccount,
+                        programTokenAuthority: tokenAuthPda,
+                        traderTokenAccount: userTokenAccount,
+                        trader: user.publicKey,
+                        traderLedger: await ensureTraderLedger(program, user.publicKey, mint),
+                        payer: user.publicKey,
+                        tokenProgram: TOKEN_PROGRAM_ID,
+                        systemProgram: SystemProgram.programId,
+                    })
+                    // Native leg; both ids share a shard, so no second withdrawal record
+                    .remainingAccounts([
+                        { pubkey: NATIVE_MINT, isWritable: false, isSigner: false },
+                        { pubkey: nativeTokenConfig, isWritable: true, isSigner: false },
+                        { pubkey: programSolAccount, isWritable: true, isSigner: false },
+                        { pubkey: await ensureTraderLedger(program, user.publicKey, NATIVE_MINT), isWritable: true, isSigner: false },
+                    ])
+                    .signers([user])
+                    .rpc();
+                assert.fail("withdraw_multi should exceed a withdrawal batch limit of 1");
+            } catch (e: any) {
+                console.log("withdraw_multi failed as expected with error:", e.message);
+                assert.ok(e.message.includes("BatchTooLarge"), "Error should be BatchTooLarge");
+            } finally {
+                await setMaxBatchSizes(4, 4);
+            }
+        });
+
+        it("Charges an approved deposit fee on every token deposit variant", async () => {
+            const depositAmount = new BN(1_000_000);
+            const feeBps = 100; // 1%
+            const expectedFee = (depositAmount.toNumber() * feeBps) / 10_000;
+            const tokenConfig = PublicKey.findProgramAddressSync(
+                [Buffer.from("token_config"), mint.toBuffer()],
+                program.programId
+            )[0];
+            const programTokenAccount = await getOrCreateAssociatedTokenAccount(
+                provider.connection,
+                admin,
+                mint,
+                tokenAuthPda,
+                true
+            ).then(account => account.address);
+            const feeDestination = await getOrCreateAssociatedTokenAccount(
+                provider.connection,
+                admin,
+                mint,
+                Keypair.generate().publicKey
+            ).then(account => account.address);
+
+            // Queue, wait out and execute operation 41, approving the fee for set_deposit_fee
+            const approveDepositFee = async (bps: number, destination: PublicKey) => {
+                const bpsBytes = Buffer.alloc(2);
+                bpsBytes.writeUInt16LE(bps);
+                await program.methods
+                    .queueOperation(
+                        new BN(41), // 41 = Approve deposit fee
+                        Buffer.concat([mint.toBuffer(), bpsBytes, destination.toBuffer()]),
+                        false
+                    )
+                    .accounts({
+                        state: statePda,
+                        authority: timelockAuthority.publicKey,
+                    })
+                    .signers([timelockAuthority])
+                    .rpc();
+
+                const state = await fetchStateAccount(program, statePda);
+                const operationIndex = state.pendingOperations.findIndex(op => op.operationType === 41);
+                await waitForTimelock(state);
+
+                await program.methods
+                    .executeOperation(new BN(operationIndex))
+                    .accounts({
+                        state: statePda,
+                        receipt: await getExecutedOperationReceiptPda(program, statePda, operationIndex),
+                        authority: timelockAuthority.publicKey,
+                    })
+                    .signers([timelockAuthority])
+                    .rpc();
+            };
+            const setDepositFee = (bps: number, destination: PublicKey) =>
+                program.methods
+                    .setDepositFee(bps, destination)
+                    .accounts({
+                        state: statePda,
+                        tokenConfig,
+                        authority: timelockAuthority.publicKey,
+                    })
+                    .signers([timelockAuthority])
+                    .rpc();
+            const balance = async (account: PublicKey) =>
+                parseInt((await provider.connection.getTokenAccountBalance(account)).value.amount);
+            // Run a deposit and check the fee reached the rewards pool and the net the vault
+            const expectFeeCharged = async (variant: string, deposit: () => Promise<string>) => {
+                const feeBefore = await balance(feeDestination);
+                const vaultBefore = await balance(programTokenAccount);
+                await deposit();
+                expect(await balance(feeDestination) - feeBefore, variant).to.equal(expectedFee);
+                expect(await balance(programTokenAccount) - vaultBefore, variant).to.equal(
+                    depositAmount.toNumber() - expectedFee
+                );
+            };
+
+            try {
+                await setDepositFee(feeBps, feeDestination);
+                assert.fail("set_deposit_fee should require a timelock approval");
+            } catch (e: any) {
+                console.log("set_deposit_fee failed as expected with error:", e.message);
+                assert.ok(e.message.includes("DepositFeeNotApproved"), "Error should be DepositFeeNotApproved");
+            }
+
+            await approveDepositFee(feeBps, feeDestination);
+            await setDepositFee(feeBps, feeDestination);
+
+            try {
+                const beneficiary = Keypair.generate();
+                await expectFeeCharged("deposit_token_for", () =>
+                    program.methods
+                        .depositTokenFor(depositAmount, beneficiary.publicKey)
+                        .accounts({
+                            state: statePda,
+                            mint,
+                            programTokenAccount,
+                            programTokenAuthority: tokenAuthPda,
+                            userTokenAccount,
+                            user: user.publicKey,
+                            feeDestination,
+                            traderBlacklistEntry: getBlacklistPda(program.programId, beneficiary.publicKey),
+                            tokenProgram: TOKEN_PROGRAM_ID,
+                            systemProgram: SystemProgram.programId,
+                        })
+                        .signers([user])
+                        .rpc()
+                );
+
+                // The user deposits on behalf of a principal that named it as a delegate
+                const principal = Keypair.generate();
+                await provider.connection.confirmTransaction(
+                    await provider.connection.requestAirdrop(principal.publicKey, 0.1 * LAMPORTS_PER_SOL)
+                );
+                await program.methods
+                    .openDepositDelegate()
+                    .accounts({ trader: principal.publicKey })
+                    .signers([principal])
+                    .rpc();
+                await program.methods
+                    .addDepositDelegate(user.publicKey)
+                    .accounts({ trader: principal.publicKey })
+                    .signers([principal])
+                    .rpc();
+                await expectFeeCharged("deposit_token_as_delegate", () =>
+                    program.methods
+                        .depositTokenAsDelegate(depositAmount)
+                        .accounts({
+                            state: statePda,
+                            mint,
+                            programTokenAccount,
+                            programTokenAuthority: tokenAuthPda,
+                            delegateTokenAccount: userTokenAccount,
+                            delegate: user.publicKey,
+                            trader: principal.publicKey,
+                            feeDestination,
+                            tokenProgram: TOKEN_PROGRAM_ID,
+                        })
+                        .signers([user])
+                        .rpc()
+                );
+
+                const clientId = Array.from(Keypair.generate().publicKey.toBytes().slice(0, 16));
+                await expectFeeCharged("deposit_token_with_client_id", () =>
+                    program.methods
+                        .depositTokenWithClientId(depositAmount, clientId)
+                        .accounts({
+                            state: statePda,
+                            mint,
+                            programTokenAccount,
+                            programTokenAuthority: tokenAuthPda,
+                            userTokenAccount,
+                            user: user.publicKey,
+                            feeDestination,
+                            tokenProgram: TOKEN_PROGRAM_ID,
+                            systemProgram: SystemProgram.programId,
+                        })
+                        .signers([user])
+                        .rpc()
+                );
+
+                await expectFeeCharged("deposit_multi", () =>
+                    program.methods
+                        .depositMulti([depositAmount])
+                        .accounts({
+                            state: statePda,
+                            programTokenAuthority: tokenAuthPda,
+                            user: user.publicKey,
+                            tokenProgram: TOKEN_PROGRAM_ID,
+                        })
+                        // One leg without a trader ledger
+                        .remainingAccounts([
+                            { pubkey: mint, isWritable: false, isSigner: false },
+                            { pubkey: tokenConfig, isWritable: true, isSigner: false },
+                            { pubkey: userTokenAccount, isWritable: true, isSigner: false },
+                            { pubkey: programTokenAccount, isWritable: true, isSigner: false },
+                            { pubkey: program.programId, isWritable: false, isSigner: false },
+                            { pubkey: feeDestination, isWritable: true, isSigner: false },
+                        ])
+                        .signers([user])
+                        .rpc()
+                );
+            } finally {
+                await approveDepositFee(0, PublicKey.default);
+                await setDepositFee(0, PublicKey.default);
+            }
+        });
+
+        it("Rejects deposit_token_for crediting a blocked trader", async () => {
+            const blockedTrader = Keypair.generate();
+            const traderBlacklistEntry = getBlacklistPda(program.programId, blockedTrader.publicKey);
+            const programTokenAccount = await getOrCreateAssociatedTokenAccount(
+                provider.connection,
+                admin,
+                mint,
+                tokenAuthPda,
+                true
+            ).then(account => account.address);
+            const depositFor = (blacklistEntry: PublicKey | null) =>
+                program.methods
+                    .depositTokenFor(new BN(1_000_000), blockedTrader.publicKey)
+                    .accounts({
+                        state: statePda,
+                        mint,
+                        programTokenAccount,
+                        programTokenAuthority: tokenAuthPda,
+                        userTokenAccount,
+                        user: user.publicKey,
+                        traderBlacklistEntry: blacklistEntry,
+                        tokenProgram: TOKEN_PROGRAM_ID,
+                        systemProgram: SystemProgram.programId,
+                    })
+                    .signers([user])
+                    .rpc();
+
+            // Operation 21 makes the timelock authority the compliance authority
+            await program.methods
+                .queueOperation(new BN(21), timelockAuthority.publicKey.toBuffer(), false) // 21 = Set compliance authority
+                .accounts({
+                    state: statePda,
+                    authority: timelockAuthority.publicKey,
+                })
+                .signers([timelockAuthority])
+                .rpc();
+            const state = await fetchStateAccount(program, statePda);
+            const operationIndex = state.pendingOperations.findIndex(op => op.operationType === 21);
+            await waitForTimelock(state);
+            await program.methods
+                .executeOperation(new BN(operationIndex))
+                .accounts({
+                    state: statePda,
+                    receipt: await getExecutedOperationReceiptPda(program, statePda, operationIndex),
+                    authority: timelockAuthority.publicKey,
+                })
+                .signers([timelockAuthority])
+                .rpc();
+
+            await program.methods
+                .blockAddress(blockedTrader.publicKey)
+                .accounts({
+                    state: statePda,
+                    complianceAuthority: timelockAuthority.publicKey,
+                })
+                .signers([timelockAuthority])
+                .rpc();
+
+            try {
+                try {
+                    await depositFor(traderBlacklistEntry);
+                    assert.fail("deposit_token_for should reject a blocked trader");
+                } catch (e: any) {
+                    console.log("deposit_token_for failed as expected with error:", e.message);
+                    assert.ok(e.message.includes("AddressBlocked"), "Error should be AddressBlocked");
+                }
+
+                // Omitting the entry does not get around the check
+                try {
+                    await depositFor(null);
+                    assert.fail("deposit_token_for should require the trader's blacklist entry");
+                } catch (e: any) {
+                    console.log("deposit_token_for failed as expected with error:", e.message);
+                    assert.ok(e.message.includes("InvalidBlacklistEntry"), "Error should be InvalidBlacklistEntry");
+                }
+            } finally {
+                await program.methods
+                    .unblockAddress(blockedTrader.publicKey)
+                    .accounts({
+                        state: statePda,
+                        complianceAuthority: timelockAuthority.publicKey,
+                    })
+                    .signers([timelockAuthority])
+                    .rpc();
+            }
+        });
     });
 } 
\ No newline at end of file
@@ -4,6 +4,12 @@ import * as crypto from 'crypto';
 import { BN } from 'bn.js';
 import * as anchor from "@coral-xyz/anchor";
 
+// Withdrawal signature version of the original Withdrawal(id, token, trader, amount) struct
+export const SIG_VERSION_WITHDRAWAL = 1;
+
+// SignatureScheme::Eip712, the scheme produced by signWithdrawal
+export const SIGNATURE_SCHEME_EIP712 = { eip712: {} };
+
 /**
  * Interface for a TimelockOperation
  */
@@ -12,6 +18,8 @@ export interface TimelockOperation {
     data: Buffer;
     queuedAt: number;
     canExecuteAt: number;
+    queuedSlot: number;
+    canExecuteSlot: number;
 }
 
 /**
@@ -150,11 +158,21 @@ export function deserializeStateAccount(data: Buffer): StateAccount {
         const canExecuteAt = new BN(buffer.subarray(offset, offset + 8), 'le').toNumber();
         offset += 8;
 
+        // queued_slot: u64 (8 bytes)
+        const queuedSlot = new BN(buffer.subarray(offset, offset + 8), 'le').toNumber();
+        offset += 8;
+
+        // can_execute_slot: u64 (8 bytes)
+        const canExecuteSlot = new BN(buffer.subarray(offset, offset + 8), 'le').toNumber();
+        offset += 8;
+
         pendingOperations.push({
             operationType,
             data: Buffer.from(data),
             queuedAt,
-            canExecuteAt
+            canExecuteAt,
+            queuedSlot,
+            canExecuteSlot
         });
     }
 
@@ -353,4 +371,66 @@ export async function waitForTimelock(state: StateAccount) {
     const waitTime = 1000 + state.timelockDelay.toNumber() * 1000;
     console.log(`Waiting for ${waitTime / 1000} seconds`);
     await new Promise(resolve => setTimeout(resolve, waitTime));
-} 
\ No newline at end of file
+} 
+/**
+ * PDA of the trader's on-chain balance ledger for a token
+ */
+export function getTraderLedgerPda(programId: PublicKey, trader: PublicKey, mint: PublicKey): PublicKey {
+    return PublicKey.findProgramAddressSync(
+        [Buffer.from("trader_ledger"), trader.toBuffer(), mint.toBuffer()],
+        programId
+    )[0];
+}
+
+/**
+ * PDA of the address's blacklist entry, which only exists while the address is blocked
+ */
+export function getBlacklistPda(programId: PublicKey, address: PublicKey): PublicKey {
+    return PublicKey.findProgramAddressSync(
+        [Buffer.from("blacklist"), address.toBuffer()],
+        programId
+    )[0];
+}
+
+/**
+ * Open the trader's ledger for a token unless it exists, paid by the provider wallet.
+ * Deposits and withdrawals require the ledger
+ */
+export async function ensureTraderLedger(
+    program: anchor.Program,
+    trader: PublicKey,
+    mint: PublicKey
+): Promise<PublicKey> {
+    const traderLedger = getTraderLedgerPda(program.programId, trader, mint);
+    const existing = await program.provider.connection.getAccountInfo(traderLedger);
+    if (!existing) {
+        await program.methods
+            .openTraderLedger()
+            .accounts({
+                traderLedger,
+                mint,
+                trader,
+                payer: program.provider.publicKey,
+            })
+            .rpc();
+    }
+    return traderLedger;
+}
+
+/**
+ * PDA of the receipt written when the pending operation at operationIndex is executed,
+ * keyed by the operation's id
+ */
+export async function getExecutedOperationReceiptPda(
+    program: anchor.Program,
+    statePda: PublicKey,
+    operationIndex: number
+): Promise<PublicKey> {
+    const state: any = await program.account.state.fetch(statePda);
+    const untracked = state.pendingOperations.length - state.pendingOperationIds.length;
+    const operationId = state.pendingOperationIds[operationIndex - untracked] ?? new BN(0);
+    return PublicKey.findProgramAddressSync(
+        [Buffer.from("executed_operation"), operationId.toArrayLike(Buffer, "le", 8)],
+        program.programId
+    )[0];
+}
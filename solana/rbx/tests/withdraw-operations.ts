@@ -14,7 +14,7 @@ import {
 } from "@solana/spl-token";
 import { assert, expect } from "chai";
 import { BN } from "bn.js";
-import { signWithdrawal, getEthereumAddressBytes } from "./utils.ts";
+import { signWithdrawal, getEthereumAddressBytes, SIG_VERSION_WITHDRAWAL, SIGNATURE_SCHEME_EIP712, ensureTraderLedger } from "./utils.ts";
 
 export async function runWithdrawalTests(
     program: anchor.Program,
@@ -121,9 +121,13 @@ export async function runWithdrawalTests(
                         withdrawalAmount,
                         v,
                         r,
-                        s
+                        s,
+                        SIG_VERSION_WITHDRAWAL,
+                        SIGNATURE_SCHEME_EIP712,
+                        false
                     )
                     .accounts({
+                        traderLedger: await ensureTraderLedger(program, recipient.publicKey, mint),
                         state: statePda,
                         withdrawalRecord: withdrawalRecordAccount,
                         mint: mint,
@@ -171,9 +175,13 @@ export async function runWithdrawalTests(
                             withdrawalAmount,
                             v,
                             r,
-                            s
+                            s,
+                            SIG_VERSION_WITHDRAWAL,
+                            SIGNATURE_SCHEME_EIP712,
+                            false
                         )
                         .accounts({
+                            traderLedger: await ensureTraderLedger(program, recipient.publicKey, mint),
                             state: statePda,
                             withdrawalRecord: withdrawalRecordAccount,
                             mint: mint,
@@ -269,9 +277,13 @@ export async function runWithdrawalTests(
                         withdrawalAmount,
                         v,
                         r,
-                        s
+                        s,
+                        false, // pay out native SOL
+                        SIG_VERSION_WITHDRAWAL,
+                        SIGNATURE_SCHEME_EIP712
                     )
                     .accounts({
+                        traderLedger: await ensureTraderLedger(program, user.publicKey, wrappedSolMint),
                         state: statePda,
                         withdrawalRecord: withdrawalAccount,
                         wrappedSolMint: wrappedSolMint,
@@ -442,9 +454,13 @@ export async function runWithdrawalTests(
                         withdrawalAmount,
                         v,
                         r,
-                        s
+                        s,
+                        SIG_VERSION_WITHDRAWAL,
+                        SIGNATURE_SCHEME_EIP712,
+                        false
                     )
                     .accounts({
+                        traderLedger: await ensureTraderLedger(program, user.publicKey, mint),
                         state: statePda,
                         withdrawalRecord: withdrawalAccount,
                         mint: mint,
@@ -485,9 +501,13 @@ export async function runWithdrawalTests(
                             withdrawalAmount,
                             v,
                             r,
-                            s
+                            s,
+                            SIG_VERSION_WITHDRAWAL,
+                            SIGNATURE_SCHEME_EIP712,
+                            false
                         )
                         .accounts({
+                            traderLedger: await ensureTraderLedger(program, user.publicKey, mint),
                             state: statePda,
                             withdrawalRecord: withdrawalAccount,
                             mint: mint,
@@ -540,6 +560,157 @@ export async function runWithdrawalTests(
             }
         });
 
+        it("Rejects a withdrawal record from another shard and records the shard index", async () => {
+            const programTokenAccount = await getOrCreateAssociatedTokenAccount(
+                program.provider.connection,
+                admin, // payer
+                mint,
+                tokenAuthPda,
+                true // allowOwnerOffCurve
+            ).then(account => account.address);
+
+            const withdrawalId = 90123; // Unique ID for this test, in shard 22
+            const shard = Math.floor(withdrawalId / 4000);
+            const withdrawalAmount = new BN(100_000); // 0.1 tokens
+
+            const { v, r, s } = await signWithdrawal(
+                signerWallet,
+                statePda,
+                {
+                    id: withdrawalId,
+                    token: mint,
+                    trader: user.publicKey,
+                    amount: withdrawalAmount.toString(),
+                }
+            );
+
+            const recordForShard = (index: number) => PublicKey.findProgramAddressSync(
+                [
+                    Buffer.from("withdrawal_account"),
+                    new BN(index).toArrayLike(Buffer, 'le', 8)
+                ],
+                program.programId
+            )[0];
+
+            const withdraw = (withdrawalRecord: PublicKey) => program.methods
+                .withdrawToken(
+                    new BN(withdrawalId),
+                    withdrawalAmount,
+                    v,
+                    r,
+                    s,
+                    SIG_VERSION_WITHDRAWAL,
+                    SIGNATURE_SCHEME_EIP712,
+                    false
+                )
+                .accounts({
+                    traderLedger: await ensureTraderLedger(program, user.publicKey, mint),
+                    state: statePda,
+                    withdrawalRecord,
+                    mint: mint,
+                    programTokenAccount: programTokenAccount,
+                    programTokenAuthority: tokenAuthPda,
+                    traderTokenAccount: userTokenAccount,
+                    trader: user.publicKey,
+                    payer: user.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                    rent: SYSVAR_RENT_PUBKEY,
+                })
+                .signers([user])
+                .rpc();
+
+            // The next shard's record must not have the id marked in it
+            try {
+                await withdraw(recordForShard(shard + 1));
+                assert.fail("Withdrawal with another shard's record should fail");
+            } catch (e: any) {
+                const logs = (e.logs || []).join('\n') + e.message;
+                assert.ok(
+                    logs.includes("WithdrawalRecordMismatch") || logs.includes("ConstraintSeeds"),
+                    "Error should reject the mismatched withdrawal record"
+                );
+            }
+
+            await withdraw(recordForShard(shard));
+
+            const record = await (program.account as any).withdrawalRecord.fetch(recordForShard(shard));
+            assert.equal(record.index.toNumber(), shard, "Record index should match the id's shard");
+        });
+
+        it("Pays a signed withdrawal larger than the trader's deposits", async () => {
+            const programTokenAccount = await getOrCreateAssociatedTokenAccount(
+                program.provider.connection,
+                admin, // payer
+                mint,
+                tokenAuthPda,
+                true // allowOwnerOffCurve
+            ).then(account => account.address);
+
+            // The ledger only tracks deposits, so a payout of trading profit exceeds it
+            const traderLedger = await ensureTraderLedger(program, user.publicKey, mint);
+            const ledgerBefore: any = await (program.account as any).traderLedger.fetch(traderLedger);
+            const withdrawalId = 94001; // Unique ID for this test, in shard 23
+            const withdrawalAmount = ledgerBefore.balance.add(new BN(200_000));
+
+            const { v, r, s } = await signWithdrawal(
+                signerWallet,
+                statePda,
+                {
+                    id: withdrawalId,
+                    token: mint,
+                    trader: user.publicKey,
+                    amount: withdrawalAmount.toString(),
+                }
+            );
+
+            const balanceBefore = parseInt(
+                (await program.provider.connection.getTokenAccountBalance(userTokenAccount)).value.amount
+            );
+
+            await program.methods
+                .withdrawToken(
+                    new BN(withdrawalId),
+                    withdrawalAmount,
+                    v,
+                    r,
+                    s,
+                    SIG_VERSION_WITHDRAWAL,
+                    SIGNATURE_SCHEME_EIP712,
+                    false
+                )
+                .accounts({
+                    traderLedger,
+                    state: statePda,
+                    withdrawalRecord: PublicKey.findProgramAddressSync(
+                        [
+                            Buffer.from("withdrawal_account"),
+                            new BN(Math.floor(withdrawalId / 4000)).toArrayLike(Buffer, 'le', 8)
+                        ],
+                        program.programId
+                    )[0],
+                    mint: mint,
+                    programTokenAccount: programTokenAccount,
+                    programTokenAuthority: tokenAuthPda,
+                    traderTokenAccount: userTokenAccount,
+                    trader: user.publicKey,
+                    payer: user.publicKey,
+                    tokenProgram: TOKEN_PROGRAM_ID,
+                    systemProgram: SystemProgram.programId,
+                    rent: SYSVAR_RENT_PUBKEY,
+                })
+                .signers([user])
+                .rpc();
+
+            const balanceAfter = parseInt(
+                (await program.provider.connection.getTokenAccountBalance(userTokenAccount)).value.amount
+            );
+            expect(balanceAfter - balanceBefore).to.equal(withdrawalAmount.toNumber());
+
+            const ledgerAfter: any = await (program.account as any).traderLedger.fetch(traderLedger);
+            assert.equal(ledgerAfter.balance.toNumber(), 0, "Ledger debit should stop at zero");
+        });
+
         it("Allows a different account to sign for a trader's withdrawal", async () => {
             console.log("Testing withdrawal with different signer than recipient...");
 
@@ -595,8 +766,9 @@ export async function runWithdrawalTests(
 
                     // Deposit tokens
                     const depositTx = await program.methods
-                        .depositToken(new BN(1_000_000)) // Deposit 1 token
+                        .depositToken(new BN(1_000_000), false) // Deposit 1 token
                         .accounts({
+                            traderLedger: await ensureTraderLedger(program, user.publicKey, mint),
                             state: statePda,
                             mint: mint,
                             programTokenAccount: programTokenAccount,
@@ -660,9 +832,13 @@ export async function runWithdrawalTests(
                         withdrawalAmount,
                         v,
                         r,
-                        s
+                        s,
+                        SIG_VERSION_WITHDRAWAL,
+                        SIGNATURE_SCHEME_EIP712,
+                        false
                     )
                     .accounts({
+                        traderLedger: await ensureTraderLedger(program, recipient.publicKey, mint),
                         state: statePda,
                         withdrawalRecord: withdrawalAccount,
                         mint: mint,
@@ -758,9 +934,13 @@ export async function runWithdrawalTests(
                         withdrawalAmount,
                         v,
                         r,
-                        s
+                        s,
+                        false, // pay out native SOL
+                        SIG_VERSION_WITHDRAWAL,
+                        SIGNATURE_SCHEME_EIP712
                     )
                     .accounts({
+                        traderLedger: await ensureTraderLedger(program, recipient.publicKey, wrappedSolMint),
                         state: statePda,
                         withdrawalRecord: withdrawalAccount,
                         wrappedSolMint: wrappedSolMint,
@@ -928,9 +1108,13 @@ export async function runWithdrawalTests(
                             withdrawalAmount,
                             v, // Original signature
                             r, // Original signature
-                            s  // Original signature
+                            s, // Original signature
+                            SIG_VERSION_WITHDRAWAL,
+                            SIGNATURE_SCHEME_EIP712,
+                            false
                         )
                         .accounts({
+                            traderLedger: await ensureTraderLedger(program, withdrawalTrader, withdrawalToken),
                             state: statePda,
                             withdrawalRecord: withdrawalAccount,
                             mint: withdrawalToken,
@@ -1071,8 +1255,9 @@ export async function runWithdrawalTests(
 
                 // Deposit tokens to program
                 const depositTx = await program.methods
-                    .depositToken(new BN(4_000_000)) // Deposit 4 tokens
+                    .depositToken(new BN(4_000_000), false) // Deposit 4 tokens
                     .accounts({
+                        traderLedger: await ensureTraderLedger(program, user.publicKey, mint),
                         state: statePda,
                         mint: mint,
                         programTokenAccount: programTokenAccount,
@@ -1148,9 +1333,13 @@ export async function runWithdrawalTests(
                         amount,
                         v,
                         r,
-                        s
+                        s,
+                        SIG_VERSION_WITHDRAWAL,
+                        SIGNATURE_SCHEME_EIP712,
+                        false
                     )
                     .accounts({
+                        traderLedger: await ensureTraderLedger(program, recipient.publicKey, mint),
                         state: statePda,
                         withdrawalRecord: withdrawalAccount,
                         mint: mint,